@@ -0,0 +1,18 @@
+#![no_main]
+
+use csvpeek_rs::filter::{record_matches, Operator, ResolvedFilter};
+use csvpeek_rs::input::{parse_csv_from_reader, ReadOptions};
+use libfuzzer_sys::fuzz_target;
+
+// Feeds arbitrary bytes through the CSV loader (sniffing the delimiter) and filters every record it yields.
+fuzz_target!(|data: &[u8]| {
+    let Ok((headers, records)) = parse_csv_from_reader(data, &ReadOptions::default(), true) else {
+        return;
+    };
+    let filters: Vec<ResolvedFilter> = (0..headers.len())
+        .map(|column| ResolvedFilter { column, op: Operator::GtEq, value: headers[column].clone(), natural: column % 2 == 1 })
+        .collect();
+    for record in &records {
+        let _ = record_matches(record, &filters);
+    }
+});
@@ -0,0 +1,25 @@
+#![no_main]
+
+use csvpeek_rs::filter::{parse_filter_arg, value_matches, Operator};
+use libfuzzer_sys::fuzz_target;
+
+// Parses arbitrary filter expressions and, when they are accepted, evaluates them
+// against a few representative cells. Ordering operators must stay complementary.
+fuzz_target!(|data: &str| {
+    let Ok((_, op, value)) = parse_filter_arg(data) else {
+        return;
+    };
+    for cell in ["", "0", "-1.5", "NaN", "inf", "abc", " 42 ", data] {
+        let matched = value_matches(cell, op, &value);
+        let complement = match op {
+            Operator::Eq => Operator::NotEq,
+            Operator::NotEq => Operator::Eq,
+            Operator::Lt => Operator::GtEq,
+            Operator::GtEq => Operator::Lt,
+            Operator::Gt => Operator::LtEq,
+            Operator::LtEq => Operator::Gt,
+            Operator::Custom(_) => return,
+        };
+        assert_ne!(matched, value_matches(cell, complement, &value));
+    }
+});
@@ -0,0 +1,178 @@
+//! User configuration file with default settings, read from `~/.config/csvpeek/config.toml`
+//! or the file given with `--config`. Options given on the command line always win.
+//!
+//! ```toml
+//! delimiter = ";"
+//! null_value = "NULL"
+//! color = "never"
+//!
+//! [datasets.sales]
+//! path = "~/data/sales.csv"
+//! columns = ["Region", "Total"]
+//! ```
+
+use crate::input::{parse_delimiter_arg, parse_encoding_arg};
+use crate::output::{ColorChoice, Layout};
+use crate::{CsvPeekError, Result};
+use clap::ValueEnum;
+use encoding_rs::Encoding;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Default settings from a configuration file. `None` leaves the built-in default in place.
+#[derive(Debug, Default)]
+pub struct Config {
+    pub delimiter: Option<u8>,
+    pub encoding: Option<&'static Encoding>,
+    pub null_value: Option<String>,
+    pub color: Option<ColorChoice>,
+    pub layout: Option<Layout>,
+    pub no_pager: Option<bool>,
+    pub datasets: Vec<Dataset>,
+}
+
+/// Settings for one named data file, applied when that file is read with `-f`.
+#[derive(Debug)]
+pub struct Dataset {
+    pub name: String,
+    pub path: PathBuf,
+    pub columns: Vec<String>,
+    pub delimiter: Option<u8>,
+}
+
+/// Location of the default configuration file: `$XDG_CONFIG_HOME/csvpeek/config.toml`,
+/// falling back to `~/.config/csvpeek/config.toml`.
+pub fn default_config_path() -> Option<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("csvpeek").join("config.toml"))
+}
+
+/// Reads a configuration file. Relative dataset paths are resolved against its directory.
+pub fn load_config(path: &Path) -> Result<Config> {
+    let text = fs::read_to_string(path).map_err(|e| CsvPeekError::from(e).in_file(path.display()))?;
+    Config::parse(&text, path)
+}
+
+impl Config {
+    /// Parses the TOML text of the configuration file at `path`.
+    pub fn parse(text: &str, path: &Path) -> Result<Config> {
+        let invalid = |message: String| CsvPeekError::Usage(format!("Invalid config '{}': {}", path.display(), message));
+        let table: toml::Table = text.parse().map_err(|e: toml::de::Error| invalid(e.message().to_string()))?;
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        let mut config = Config::default();
+        for (key, value) in table {
+            let text = || string_setting(&key, &value);
+            let applied = match key.as_str() {
+                "delimiter" => text().and_then(|s| parse_delimiter_arg(&s)).map(|delimiter| config.delimiter = Some(delimiter)),
+                "encoding" => text().and_then(|s| parse_encoding_arg(&s)).map(|encoding| config.encoding = Some(encoding)),
+                "null_value" => text().map(|null_value| config.null_value = Some(null_value)),
+                "color" => text().and_then(|s| ColorChoice::from_str(&s, true)).map(|color| config.color = Some(color)),
+                "layout" => text().and_then(|s| Layout::from_str(&s, true)).map(|layout| config.layout = Some(layout)),
+                "no_pager" => match value {
+                    toml::Value::Boolean(no_pager) => {
+                        config.no_pager = Some(no_pager);
+                        Ok(())
+                    }
+                    ref other => Err(format!("'no_pager' must be true or false, found {}.", other.type_str())),
+                },
+                "datasets" => match value {
+                    toml::Value::Table(ref datasets) => datasets.iter()
+                        .map(|(name, dataset)| parse_dataset(name, dataset, base_dir))
+                        .collect::<std::result::Result<Vec<_>, _>>()
+                        .map(|datasets| config.datasets = datasets),
+                    ref other => Err(format!("'datasets' must be a table, found {}.", other.type_str())),
+                },
+                _ => Err(format!("unknown setting '{}'.", key)),
+            };
+            applied.map_err(invalid)?;
+        }
+        Ok(config)
+    }
+
+    /// The dataset whose path refers to the same file as `path`, if any.
+    pub fn dataset_for(&self, path: &Path) -> Option<&Dataset> {
+        let canonical = fs::canonicalize(path).ok();
+        self.datasets.iter().find(|dataset| match (&canonical, fs::canonicalize(&dataset.path)) {
+            (Some(canonical), Ok(dataset_path)) => *canonical == dataset_path,
+            _ => dataset.path == path,
+        })
+    }
+}
+
+fn string_setting(key: &str, value: &toml::Value) -> std::result::Result<String, String> {
+    match value {
+        toml::Value::String(text) => Ok(text.clone()),
+        other => Err(format!("'{}' must be a string, found {}.", key, other.type_str())),
+    }
+}
+
+fn parse_dataset(name: &str, value: &toml::Value, base_dir: &Path) -> std::result::Result<Dataset, String> {
+    let toml::Value::Table(table) = value else {
+        return Err(format!("dataset '{}' must be a table, found {}.", name, value.type_str()));
+    };
+    let mut path = None;
+    let mut columns = Vec::new();
+    let mut delimiter = None;
+    for (key, value) in table {
+        match key.as_str() {
+            "path" => path = Some(resolve_path(&string_setting(key, value)?, base_dir)),
+            "columns" => {
+                let toml::Value::Array(items) = value else {
+                    return Err(format!("'columns' of dataset '{}' must be an array of column names.", name));
+                };
+                columns = items.iter().map(|item| string_setting(key, item)).collect::<std::result::Result<_, _>>()?;
+            }
+            "delimiter" => delimiter = Some(parse_delimiter_arg(&string_setting(key, value)?)?),
+            _ => return Err(format!("unknown setting '{}' in dataset '{}'.", key, name)),
+        }
+    }
+    let path = path.ok_or_else(|| format!("dataset '{}' has no 'path'.", name))?;
+    Ok(Dataset { name: name.to_string(), path, columns, delimiter })
+}
+
+/// Expands a leading `~/` to the home directory and resolves relative paths against `base_dir`.
+fn resolve_path(path: &str, base_dir: &Path) -> PathBuf {
+    if let (Some(rest), Some(home)) = (path.strip_prefix("~/"), env::var_os("HOME")) {
+        return PathBuf::from(home).join(rest);
+    }
+    base_dir.join(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let text = "delimiter = \";\"\nnull_value = \"NULL\"\ncolor = \"never\"\nno_pager = true\n\n\
+                    [datasets.sales]\npath = \"data/sales.csv\"\ncolumns = [\"Region\", \"Total\"]\ndelimiter = \"tab\"\n";
+        let config = Config::parse(text, Path::new("/etc/csvpeek/config.toml")).unwrap();
+        assert_eq!(config.delimiter, Some(b';'));
+        assert_eq!(config.null_value.as_deref(), Some("NULL"));
+        assert_eq!(config.color, Some(ColorChoice::Never));
+        assert_eq!(config.layout, None);
+        assert_eq!(config.no_pager, Some(true));
+        assert_eq!(config.datasets.len(), 1);
+        let sales = &config.datasets[0];
+        assert_eq!(sales.name, "sales");
+        assert_eq!(sales.path, PathBuf::from("/etc/csvpeek/data/sales.csv"));
+        assert_eq!(sales.columns, ["Region", "Total"]);
+        assert_eq!(sales.delimiter, Some(b'\t'));
+        assert_eq!(config.dataset_for(Path::new("/etc/csvpeek/data/sales.csv")).map(|d| d.name.as_str()), Some("sales"));
+        assert!(config.dataset_for(Path::new("other.csv")).is_none());
+    }
+
+    #[test]
+    fn test_parse_config_errors() {
+        let path = Path::new("config.toml");
+        let err = Config::parse("colour = \"never\"", path).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid config 'config.toml': unknown setting 'colour'.");
+        assert!(Config::parse("delimiter = 1", path).is_err());
+        assert!(Config::parse("color = \"sometimes\"", path).is_err());
+        assert!(Config::parse("[datasets.sales]\ncolumns = [\"A\"]", path).unwrap_err().to_string().contains("has no 'path'"));
+    }
+}
@@ -0,0 +1,280 @@
+//! Schema drift across the sources of a merge, for `--schema-report`: which source has which
+//! columns, how the columns change from one source to the next, and what the merge rules do
+//! with each source. Also the header diff of two inputs behind `--compare-headers`.
+
+use crate::error::CsvPeekError;
+use crate::eq_ignore_case;
+use crate::input::{extend_union, CsvSource, MergeOptions, MergeStrategy, ReadOptions};
+use std::fmt;
+
+/// What the merge rules do with a source.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeFate {
+    /// Its headers are the main headers.
+    Main,
+    /// Its headers match the merged headers.
+    Merged,
+    /// Its headers only rename the merged ones and are aligned by position.
+    ByPosition,
+    /// Merged into the union of the columns, appending these new ones.
+    Union(Vec<String>),
+    /// Left out of the merge, for this reason.
+    Skipped(String),
+    /// Fails a `--strict` merge, for this reason.
+    Aborts(String),
+}
+
+impl MergeFate {
+    /// Whether the source's records end up in the merged data.
+    pub fn is_merged(&self) -> bool {
+        !matches!(self, MergeFate::Skipped(_) | MergeFate::Aborts(_))
+    }
+}
+
+impl fmt::Display for MergeFate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeFate::Main => write!(f, "main headers"),
+            MergeFate::Merged => write!(f, "merged"),
+            MergeFate::ByPosition => write!(f, "merged by position"),
+            MergeFate::Union(added) if added.is_empty() => write!(f, "merged"),
+            MergeFate::Union(added) => write!(f, "merged, adds {}", added.join(", ")),
+            MergeFate::Skipped(reason) => write!(f, "skipped: {}", reason),
+            MergeFate::Aborts(reason) => write!(f, "fails --strict: {}", reason),
+        }
+    }
+}
+
+/// How a source's columns differ from those of the previous readable source.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ColumnChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// `(old name, new name)` pairs: a column that disappeared where a new one took its place.
+    pub renamed: Vec<(String, String)>,
+}
+
+impl ColumnChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.renamed.is_empty()
+    }
+}
+
+impl fmt::Display for ColumnChanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if !self.renamed.is_empty() {
+            let renames: Vec<String> = self.renamed.iter().map(|(from, to)| format!("{} -> {}", from, to)).collect();
+            parts.push(format!("renamed {}", renames.join(", ")));
+        }
+        if !self.added.is_empty() {
+            parts.push(format!("added {}", self.added.join(", ")));
+        }
+        if !self.removed.is_empty() {
+            parts.push(format!("removed {}", self.removed.join(", ")));
+        }
+        write!(f, "{}", parts.join("; "))
+    }
+}
+
+/// Compares the headers of two sources. A removed column counts as renamed when a new column
+/// sits at the same position.
+pub fn column_changes(previous: &[String], current: &[String]) -> ColumnChanges {
+    let mut added: Vec<String> = current.iter().filter(|h| !previous.contains(h)).cloned().collect();
+    let mut changes = ColumnChanges::default();
+    for (idx, old) in previous.iter().enumerate() {
+        if current.contains(old) {
+            continue;
+        }
+        match current.get(idx).and_then(|new| added.iter().position(|a| a == new)) {
+            Some(pos) => changes.renamed.push((old.clone(), added.remove(pos))),
+            None => changes.removed.push(old.clone()),
+        }
+    }
+    changes.added = added;
+    changes
+}
+
+/// One source of the report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceSchema {
+    pub source: String,
+    /// The headers after `--header-map` renames, or `None` if they could not be read.
+    pub headers: Option<Vec<String>>,
+    /// Changes from the previous source with headers; `None` for the first one.
+    pub changes: Option<ColumnChanges>,
+    pub fate: MergeFate,
+}
+
+/// The schema drift of a set of sources, in merge order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaReport {
+    /// Every column name, in order of first appearance.
+    pub columns: Vec<String>,
+    pub sources: Vec<SourceSchema>,
+}
+
+/// Reads the headers of every source and works out, without reading any records, what
+/// `merge_csv_sources` would do with each of them under `merge`.
+pub fn schema_report(sources: &[CsvSource], main_header_source: Option<&CsvSource>, read: &ReadOptions, merge: &MergeOptions) -> SchemaReport {
+    let loaded: Vec<Result<Vec<String>, String>> = sources.iter()
+        .map(|source| match source.load(read, false) {
+            Ok((headers, _)) => Ok(merge.rename_headers(headers)),
+            Err(CsvPeekError::MissingHeaders) => Err("no header row".to_string()),
+            Err(e) => Err(e.to_string()),
+        })
+        .collect();
+    let main = match main_header_source {
+        Some(main) => sources.iter().position(|source| source == main),
+        None => loaded.iter().position(Result::is_ok),
+    };
+    let mut merged = main.and_then(|idx| loaded[idx].clone().ok());
+
+    let mut columns: Vec<String> = Vec::new();
+    let mut previous: Option<&Vec<String>> = None;
+    let mut report = Vec::with_capacity(sources.len());
+    for (idx, (source, headers)) in sources.iter().zip(&loaded).enumerate() {
+        let (fate, changes) = match headers {
+            Err(reason) => {
+                let reason = format!("unreadable ({})", reason);
+                (if merge.strict { MergeFate::Aborts(reason) } else { MergeFate::Skipped(reason) }, None)
+            }
+            Ok(headers) => {
+                extend_union(&mut columns, headers);
+                let changes = previous.map(|previous| column_changes(previous, headers));
+                previous = Some(headers);
+                let fate = match &mut merged {
+                    _ if Some(idx) == main => MergeFate::Main,
+                    None => MergeFate::Skipped("no main headers".to_string()),
+                    Some(merged) if headers == merged => MergeFate::Merged,
+                    Some(merged) if merge.by_position && headers.len() == merged.len() => MergeFate::ByPosition,
+                    Some(merged) if merge.strategy == MergeStrategy::Union => MergeFate::Union(extend_union(merged, headers)),
+                    Some(_) if merge.strict => MergeFate::Aborts("headers differ from the main headers".to_string()),
+                    Some(_) => MergeFate::Skipped("headers differ from the main headers".to_string()),
+                };
+                (fate, changes)
+            }
+        };
+        report.push(SourceSchema { source: source.to_string(), headers: headers.as_ref().ok().cloned(), changes, fate });
+    }
+    SchemaReport { columns, sources: report }
+}
+
+/// How the headers of one input differ from those of another, for `--compare-headers`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HeaderDiff {
+    /// Expected columns the other input lacks.
+    pub missing: Vec<String>,
+    /// Columns of the other input that were not expected.
+    pub extra: Vec<String>,
+    /// Columns both have, but in a different order relative to the other shared columns:
+    /// the expected name with its 1-based positions in the expected and the other headers.
+    pub reordered: Vec<(String, usize, usize)>,
+    /// Columns whose names differ only in case, as `(expected, other)` pairs.
+    pub case_differs: Vec<(String, String)>,
+}
+
+impl HeaderDiff {
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.reordered.is_empty() && self.case_differs.is_empty()
+    }
+}
+
+/// Diffs the `other` headers against the `expected` ones. Names are paired exactly first and
+/// then ignoring case, so a column that only changed case is reported as such rather than as
+/// missing and extra.
+pub fn compare_headers(expected: &[String], other: &[String]) -> HeaderDiff {
+    let mut pairs: Vec<Option<usize>> = expected.iter().map(|name| other.iter().position(|o| o == name)).collect();
+    for (idx, name) in expected.iter().enumerate() {
+        if pairs[idx].is_none() {
+            pairs[idx] = (0..other.len()).find(|&o| !pairs.contains(&Some(o)) && eq_ignore_case(&other[o], name));
+        }
+    }
+    let mut diff = HeaderDiff::default();
+    for (name, pair) in expected.iter().zip(&pairs) {
+        match pair {
+            None => diff.missing.push(name.clone()),
+            Some(o) if other[*o] != *name => diff.case_differs.push((name.clone(), other[*o].clone())),
+            Some(_) => {}
+        }
+    }
+    diff.extra = (0..other.len()).filter(|o| !pairs.contains(&Some(*o))).map(|o| other[o].clone()).collect();
+
+    // Compare the order of the shared columns only, so that a missing column does not make
+    // every later one look moved.
+    let shared: Vec<(usize, usize)> = pairs.iter().enumerate().filter_map(|(idx, pair)| pair.map(|o| (idx, o))).collect();
+    let mut other_order: Vec<usize> = shared.iter().map(|&(_, o)| o).collect();
+    other_order.sort_unstable();
+    for (rank, &(idx, o)) in shared.iter().enumerate() {
+        if other_order[rank] != o {
+            diff.reordered.push((expected[idx].clone(), idx + 1, o + 1));
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_column_changes() {
+        let changes = column_changes(&names(&["id", "name", "email"]), &names(&["id", "full_name", "phone", "created"]));
+        assert_eq!(changes.renamed, [("name".to_string(), "full_name".to_string()), ("email".to_string(), "phone".to_string())]);
+        assert_eq!(changes.added, ["created"]);
+        assert!(changes.removed.is_empty());
+        assert_eq!(changes.to_string(), "renamed name -> full_name, email -> phone; added created");
+
+        let changes = column_changes(&names(&["id", "name", "email"]), &names(&["id", "email"]));
+        assert_eq!(changes.removed, ["name"]);
+        assert!(changes.added.is_empty() && changes.renamed.is_empty());
+        assert!(column_changes(&names(&["a", "b"]), &names(&["b", "a"])).is_empty());
+    }
+
+    #[test]
+    fn test_compare_headers() {
+        assert!(compare_headers(&names(&["id", "name"]), &names(&["id", "name"])).is_empty());
+
+        let diff = compare_headers(&names(&["id", "name", "email", "city"]), &names(&["ID", "city", "name", "phone"]));
+        assert_eq!(diff.missing, ["email"]);
+        assert_eq!(diff.extra, ["phone"]);
+        assert_eq!(diff.case_differs, [("id".to_string(), "ID".to_string())]);
+        assert_eq!(diff.reordered, [("name".to_string(), 2, 3), ("city".to_string(), 4, 2)]);
+
+        let shifted = compare_headers(&names(&["a", "b", "c"]), &names(&["b", "c"]));
+        assert_eq!(shifted.missing, ["a"]);
+        assert!(shifted.reordered.is_empty());
+    }
+
+    #[test]
+    fn test_schema_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [("a.csv", "id,name\n1,x\n"), ("b.csv", "id,name,email\n"), ("c.csv", ""), ("d.csv", "id,full_name\n")];
+        for (name, content) in files {
+            std::fs::write(dir.path().join(name), content).unwrap();
+        }
+        let sources: Vec<CsvSource> = files.iter().map(|(name, _)| CsvSource::File(dir.path().join(name))).collect();
+        let read = ReadOptions::default();
+
+        let report = schema_report(&sources, None, &read, &MergeOptions::default());
+        assert_eq!(report.columns, ["id", "name", "email", "full_name"]);
+        let fates: Vec<String> = report.sources.iter().map(|source| source.fate.to_string()).collect();
+        assert_eq!(fates[0], "main headers");
+        assert_eq!(fates[1], "skipped: headers differ from the main headers");
+        assert!(fates[2].starts_with("skipped: unreadable"));
+        assert_eq!(report.sources[1].changes.as_ref().unwrap().added, ["email"]);
+        assert_eq!(report.sources[3].changes.as_ref().unwrap().to_string(), "renamed name -> full_name; removed email");
+
+        let union = MergeOptions { strategy: MergeStrategy::Union, by_position: true, ..MergeOptions::default() };
+        let report = schema_report(&sources, None, &read, &union);
+        assert_eq!(report.sources[1].fate, MergeFate::Union(names(&["email"])));
+        assert_eq!(report.sources[3].fate, MergeFate::Union(names(&["full_name"])));
+
+        let by_position = MergeOptions { by_position: true, ..MergeOptions::default() };
+        assert_eq!(schema_report(&sources, None, &read, &by_position).sources[3].fate, MergeFate::ByPosition);
+    }
+}
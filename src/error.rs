@@ -0,0 +1,158 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Result type used throughout the csvpeek-rs library.
+pub type Result<T> = std::result::Result<T, CsvPeekError>;
+
+/// How many duplicate keys a `NotUnique` error lists.
+const MAX_DUPLICATE_SAMPLES: usize = 5;
+
+/// Everything that can go wrong while loading, merging or querying CSV data.
+#[derive(Debug)]
+pub enum CsvPeekError {
+    /// Reading a file, directory or stdin failed.
+    Io { path: Option<PathBuf>, source: io::Error },
+    /// A record could not be parsed. `file` and `line` are filled in when known.
+    Parse { file: Option<String>, line: Option<u64>, message: String },
+    /// The input has no header row.
+    MissingHeaders,
+    /// A directory or archive did not contain any CSV files.
+    NoSources { origin: String },
+    /// No source in a merge provided usable headers (`main_headers` is `None`),
+    /// or none of them matched the main headers.
+    SchemaMismatch { origin: String, main_headers: Option<Vec<String>> },
+    /// A source's headers differ from the main headers in a `--strict` merge.
+    HeaderMismatch { file: String, expected: Vec<String>, found: Vec<String> },
+    /// A column named on the command line does not exist in the headers.
+    /// `role` says what the column was used for, e.g. "Filter" or "Display".
+    ColumnNotFound { role: String, column: String, headers: Vec<String> },
+    /// A zip archive could not be opened or one of its members could not be read.
+    Archive { path: PathBuf, message: String },
+    /// An Excel or OpenDocument workbook could not be read, or an Excel workbook could not be written.
+    Workbook { path: PathBuf, message: String },
+    /// A SQLite database could not be opened, read or written.
+    Sqlite { path: PathBuf, message: String },
+    /// Fetching CSV data over HTTP(S) failed.
+    Http { url: String, message: String },
+    /// `--assert-unique` found key values shared by several rows: each duplicate key (its
+    /// cells, one per column) with the number of rows holding it, in order of appearance.
+    NotUnique { columns: Vec<String>, duplicates: Vec<(Vec<String>, usize)> },
+    /// `--assert` checks did not hold: each failed check with the value it was evaluated to,
+    /// e.g. "max(Price) <= 10000 (got 12000)".
+    AssertionFailed { failures: Vec<String> },
+    /// Invalid option values or combinations.
+    Usage(String),
+}
+
+impl CsvPeekError {
+    /// Attaches the name of the file being read to errors that do not carry one yet.
+    pub fn in_file(self, name: impl fmt::Display) -> Self {
+        match self {
+            CsvPeekError::Io { path: None, source } => CsvPeekError::Io { path: Some(PathBuf::from(name.to_string())), source },
+            CsvPeekError::Parse { file: None, line, message } => CsvPeekError::Parse { file: Some(name.to_string()), line, message },
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for CsvPeekError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvPeekError::Io { path: Some(path), source } => write!(f, "Could not read '{}': {}", path.display(), source),
+            CsvPeekError::Io { path: None, source } => write!(f, "{}", source),
+            CsvPeekError::Parse { file, line, message } => {
+                write!(f, "CSV parse error")?;
+                if let Some(file) = file {
+                    write!(f, " in '{}'", file)?;
+                }
+                if let Some(line) = line {
+                    write!(f, " at line {}", line)?;
+                }
+                write!(f, ": {}", message)
+            }
+            CsvPeekError::MissingHeaders => write!(f, "CSV data is missing headers or is empty."),
+            CsvPeekError::NoSources { origin } => write!(f, "No CSV files found in {}.", origin),
+            CsvPeekError::SchemaMismatch { origin, main_headers: None } => {
+                write!(f, "Could not determine main headers from any suitable file in {}.", origin)
+            }
+            CsvPeekError::SchemaMismatch { origin, main_headers: Some(headers) } => {
+                write!(f, "No CSV files matching main headers ({:?}) found/processed in {}.", headers, origin)
+            }
+            CsvPeekError::HeaderMismatch { file, expected, found } => {
+                write!(f, "Headers in file '{}' do not match main headers: expected {:?}, found {:?}.", file, expected, found)
+            }
+            CsvPeekError::ColumnNotFound { role, column, headers } => {
+                write!(f, "{} column '{}' not found in CSV file headers: {:?}", role, column, headers)
+            }
+            CsvPeekError::Archive { path, message } => write!(f, "Could not read zip archive '{}': {}", path.display(), message),
+            CsvPeekError::Workbook { path, message } => write!(f, "Workbook error in '{}': {}", path.display(), message),
+            CsvPeekError::Sqlite { path, message } => write!(f, "SQLite error in '{}': {}", path.display(), message),
+            CsvPeekError::Http { url, message } => write!(f, "Could not fetch '{}': {}", url, message),
+            CsvPeekError::NotUnique { columns, duplicates } => {
+                let samples: Vec<String> = duplicates.iter().take(MAX_DUPLICATE_SAMPLES)
+                    .map(|(values, rows)| format!("'{}' ({} rows)", values.join(", "), rows))
+                    .collect();
+                write!(f, "Column(s) {} are not unique: {} value(s) occur in more than one row: {}", columns.join(", "), duplicates.len(), samples.join("; "))?;
+                if duplicates.len() > MAX_DUPLICATE_SAMPLES {
+                    write!(f, " and {} more", duplicates.len() - MAX_DUPLICATE_SAMPLES)?;
+                }
+                Ok(())
+            }
+            CsvPeekError::AssertionFailed { failures } => write!(f, "Assertion failed: {}", failures.join("; ")),
+            CsvPeekError::Usage(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for CsvPeekError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CsvPeekError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CsvPeekError {
+    fn from(source: io::Error) -> Self {
+        CsvPeekError::Io { path: None, source }
+    }
+}
+
+impl From<csv::Error> for CsvPeekError {
+    fn from(err: csv::Error) -> Self {
+        let line = err.position().map(|pos| pos.line());
+        let description = err.to_string();
+        match err.into_kind() {
+            csv::ErrorKind::Io(source) => CsvPeekError::Io { path: None, source },
+            csv::ErrorKind::UnequalLengths { expected_len, len, .. } => CsvPeekError::Parse {
+                file: None,
+                line,
+                message: format!("found record with {} fields, but the previous record has {} fields", len, expected_len),
+            },
+            csv::ErrorKind::Utf8 { err, .. } => CsvPeekError::Parse { file: None, line, message: format!("invalid UTF-8: {}", err) },
+            _ => CsvPeekError::Parse { file: None, line, message: description },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_error_carries_line_and_file() {
+        let data = "A,B\n1,2\n3\n";
+        let mut reader = csv::Reader::from_reader(data.as_bytes());
+        let err = reader.records().find_map(|r| r.err()).expect("ragged row should fail");
+        match CsvPeekError::from(err).in_file("data.csv") {
+            CsvPeekError::Parse { file, line, .. } => {
+                assert_eq!(file.as_deref(), Some("data.csv"));
+                assert_eq!(line, Some(3));
+            }
+            other => panic!("expected Parse error, got {:?}", other),
+        }
+    }
+}
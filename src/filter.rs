@@ -0,0 +1,624 @@
+use crate::eq_ignore_case;
+use chrono::{DateTime, Days, Months, NaiveDate, NaiveDateTime};
+use csv::StringRecord;
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::RwLock;
+
+/// Comparison operator of a `--filter` expression.
+#[derive(Debug, Clone, Copy)]
+pub enum Operator {
+    Eq, NotEq, Lt, Gt, LtEq, GtEq,
+    /// An operator added with [`register_operator`], written `COLUMN[NAME]VALUE`.
+    Custom(&'static dyn FilterOperator),
+}
+
+impl PartialEq for Operator {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Operator::Custom(a), Operator::Custom(b)) => a.name() == b.name(),
+            (Operator::Custom(_), _) | (_, Operator::Custom(_)) => false,
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+}
+
+/// A domain-specific filter predicate, such as CIDR containment for IP addresses or a semver
+/// range check. Once registered with [`register_operator`], filters can use it by name:
+/// `--filter "Ip[cidr]10.0.0.0/8"` calls `matches(cell, "10.0.0.0/8")` for every row.
+pub trait FilterOperator: fmt::Debug + Send + Sync {
+    /// Name used between the brackets of a filter expression.
+    fn name(&self) -> &str;
+
+    /// Checks the filter value when the filter is parsed, so that mistakes are reported
+    /// before any data is read.
+    fn check_value(&self, _value: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// True when the cell satisfies the filter value.
+    fn matches(&self, cell: &str, value: &str) -> bool;
+}
+
+static OPERATORS: RwLock<Vec<&'static dyn FilterOperator>> = RwLock::new(Vec::new());
+
+/// Makes a custom operator available to filter expressions. A later registration with the
+/// same name, ignoring ASCII case, replaces the earlier one.
+pub fn register_operator(operator: Box<dyn FilterOperator>) {
+    let operator: &'static dyn FilterOperator = Box::leak(operator);
+    let mut operators = OPERATORS.write().unwrap_or_else(|e| e.into_inner());
+    operators.retain(|registered| !registered.name().eq_ignore_ascii_case(operator.name()));
+    operators.push(operator);
+}
+
+/// Looks up a registered custom operator by name (case-insensitively).
+pub fn find_operator(name: &str) -> Option<&'static dyn FilterOperator> {
+    let operators = OPERATORS.read().unwrap_or_else(|e| e.into_inner());
+    operators.iter().copied().find(|operator| operator.name().eq_ignore_ascii_case(name))
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operator::Eq => write!(f, "="),
+            Operator::NotEq => write!(f, "!="),
+            Operator::Lt => write!(f, "<"),
+            Operator::Gt => write!(f, ">"),
+            Operator::LtEq => write!(f, "<="),
+            Operator::GtEq => write!(f, ">="),
+            Operator::Custom(operator) => write!(f, "[{}]", operator.name()),
+        }
+    }
+}
+
+/// Parses a `COLUMN<OP>VALUE` filter expression into its column name, operator and value.
+/// `COLUMN[NAME]VALUE` uses the custom operator registered as NAME; when no operator has that
+/// name the brackets are part of the column name, as in `Weight[kg]>10`.
+pub fn parse_filter_arg(s: &str) -> Result<(String, Operator, String), String> {
+    if let Some(custom) = parse_custom_filter(s) {
+        return custom;
+    }
+    let (key_str_full, op, val_str_full) = if let Some((k, v)) = s.split_once("!=") {
+        (k, Operator::NotEq, v)
+    } else if let Some((k, v)) = s.split_once(">=") {
+        (k, Operator::GtEq, v)
+    } else if let Some((k, v)) = s.split_once("<=") {
+        (k, Operator::LtEq, v)
+    } else if let Some((k, v)) = s.split_once('=') {
+        (k, Operator::Eq, v)
+    } else if let Some((k, v)) = s.split_once('>') {
+        (k, Operator::Gt, v)
+    } else if let Some((k, v)) = s.split_once('<') {
+        (k, Operator::Lt, v)
+    } else {
+        return Err(format!(
+            "Invalid filter format: Operator (e.g., =, !=, >, <, >=, <=) missing or unrecognized in '{}'. Expected COLUMN<OP>VALUE.", s
+        ));
+    };
+
+    let key = key_str_full.trim();
+
+    if key.is_empty() {
+        return Err(format!("Invalid filter format: Column name cannot be empty in '{}'. Expected COLUMN<OP>VALUE.", s));
+    }
+
+    if key.chars().any(|c| "<>=!".contains(c)) {
+        return Err(format!(
+            "Invalid filter format: Column name '{}' is malformed (contains operator characters) in filter string '{}'.", key, s
+        ));
+    }
+    
+    Ok((key.to_string(), op, val_str_full.trim().to_string()))
+}
+
+/// Parses `COLUMN[NAME]VALUE`. Returns `None` if the expression has no `[NAME]` part naming a
+/// registered operator.
+fn parse_custom_filter(s: &str) -> Option<Result<(String, Operator, String), String>> {
+    let (key, rest) = s.split_once('[')?;
+    let (name, value) = rest.split_once(']')?;
+    let key = key.trim();
+    if key.is_empty() || key.chars().any(|c| "<>=!".contains(c)) {
+        return None;
+    }
+    let operator = find_operator(name)?;
+    let value = value.trim();
+    Some(operator.check_value(value)
+        .map(|()| (key.to_string(), Operator::Custom(operator), value.to_string()))
+        .map_err(|e| format!("Invalid value '{}' for filter operator [{}]: {}", value, name, e)))
+}
+
+/// A filter whose column has been resolved to an index in the headers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedFilter {
+    pub column: usize,
+    pub op: Operator,
+    pub value: String,
+    /// Compare text in natural order (see [`natural_cmp`]) rather than lexicographically.
+    pub natural: bool,
+}
+
+static DECIMAL_COMMA: AtomicBool = AtomicBool::new(false);
+
+/// Makes numbers read with a decimal comma and dots between thousands, such as "1.234,56"
+/// (see `--decimal-comma`).
+pub fn set_decimal_comma(enabled: bool) {
+    DECIMAL_COMMA.store(enabled, AtomicOrdering::Relaxed);
+}
+
+/// Parses a cell as a number for ordering comparisons and aggregates, in decimal or scientific
+/// notation ("1200", "-0.5", "1.2e9", "1.2E+9"), or with `--decimal-comma` as "-0,5",
+/// "1.234,56" or "1,2e9". NaN is rejected so that ordering operators stay consistent (`a < b`
+/// is always the negation of `a >= b`), and so are "inf" and values too large for a float,
+/// which are text like any other word.
+pub fn parse_number(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let number = if DECIMAL_COMMA.load(AtomicOrdering::Relaxed) {
+        from_decimal_comma(value)?.parse::<f64>().ok()
+    } else {
+        value.parse::<f64>().ok()
+    };
+    number.filter(|n| n.is_finite())
+}
+
+/// Rewrites a number written with a decimal comma ("-1.234,5e3") with a decimal point
+/// ("-1234.5e3"). `None` when dots do not group the integer digits in threes, so that "3.14"
+/// is not taken for 314.
+fn from_decimal_comma(value: &str) -> Option<String> {
+    let (mantissa, exponent) = value.split_at(value.find(['e', 'E']).unwrap_or(value.len()));
+    let (sign, unsigned) = mantissa.split_at(usize::from(mantissa.starts_with(['-', '+'])));
+    let (int, fraction) = unsigned.split_once(',').unwrap_or((unsigned, ""));
+    let groups: Vec<&str> = int.split('.').collect();
+    if groups.len() > 1 && (!(1..=3).contains(&groups[0].len()) || groups[1..].iter().any(|group| group.len() != 3)) {
+        return None;
+    }
+    Some(format!("{}{}.{}{}", sign, groups.concat(), fraction, exponent))
+}
+
+/// Number of digits after the decimal point (or comma, with `--decimal-comma`) of a number
+/// as written, e.g. 2 for "1.50" and 0 for "7" or "1e3".
+pub fn fraction_digits(value: &str) -> usize {
+    let separator = if DECIMAL_COMMA.load(AtomicOrdering::Relaxed) { ',' } else { '.' };
+    let mantissa = value.trim().split(['e', 'E']).next().unwrap_or_default();
+    mantissa.split_once(separator).map_or(0, |(_, fraction)| fraction.len())
+}
+
+impl Operator {
+    /// Whether the operator orders values (`<`, `>`, `<=`, `>=`) rather than matching them.
+    pub fn is_ordering(self) -> bool {
+        matches!(self, Operator::Lt | Operator::Gt | Operator::LtEq | Operator::GtEq)
+    }
+}
+
+/// The cells of `filter`'s column that an ordering filter against a number compares as text
+/// because they are not numbers, when the column holds numbers too: how many there are and
+/// the first of them. `None` when every comparison is numeric, or every one is textual.
+pub fn mixed_numeric_cells<'a>(records: &'a [StringRecord], filter: &ResolvedFilter) -> Option<(usize, &'a str)> {
+    if !filter.op.is_ordering() || parse_number(&filter.value).is_none() {
+        return None;
+    }
+    let cells = records.iter().filter_map(|record| record.get(filter.column)).filter(|cell| !cell.trim().is_empty());
+    let (mut numbers, mut texts, mut first_text) = (0, 0, None);
+    for cell in cells {
+        if parse_number(cell).is_some() {
+            numbers += 1;
+        } else {
+            texts += 1;
+            first_text.get_or_insert(cell);
+        }
+    }
+    first_text.filter(|_| numbers > 0).map(|text| (texts, text))
+}
+
+/// Compares two strings with runs of digits compared by their value, so that "item2" sorts
+/// before "item10" and "1.9.0" before "1.10.0". Strings that only differ in leading zeros are
+/// ordered lexicographically.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (mut left, mut right) = (a, b);
+    loop {
+        match (left.chars().next(), right.chars().next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x_run, x_rest) = left.split_at(digits(left));
+                let (y_run, y_rest) = right.split_at(digits(right));
+                let (x_run, y_run) = (x_run.trim_start_matches('0'), y_run.trim_start_matches('0'));
+                let by_value = x_run.len().cmp(&y_run.len()).then_with(|| x_run.cmp(y_run));
+                if by_value != Ordering::Equal {
+                    return by_value;
+                }
+                (left, right) = (x_rest, y_rest);
+            }
+            (Some(x), Some(y)) if x != y => return x.cmp(&y),
+            (Some(x), Some(_)) => (left, right) = (&left[x.len_utf8()..], &right[x.len_utf8()..]),
+        }
+    }
+}
+
+/// Compares a single cell against a filter value.
+/// `=` and `!=` are case-insensitive; ordering operators compare numerically when both
+/// sides parse as numbers and fall back to a lexicographical comparison otherwise.
+pub fn value_matches(cell: &str, op: Operator, filter_value: &str) -> bool {
+    ordered_value_matches(cell, op, filter_value, false)
+}
+
+/// Like [`value_matches`], but ordering operators fall back to [`natural_cmp`] instead of a
+/// lexicographical comparison when `natural` is set.
+pub fn ordered_value_matches(cell: &str, op: Operator, filter_value: &str, natural: bool) -> bool {
+    match op {
+        Operator::Eq => eq_ignore_case(cell, filter_value),
+        Operator::NotEq => !eq_ignore_case(cell, filter_value),
+        Operator::Lt | Operator::Gt | Operator::LtEq | Operator::GtEq => {
+            if let (Some(record_num), Some(filter_num)) = (parse_number(cell), parse_number(filter_value)) {
+                match op {
+                    Operator::Lt => record_num < filter_num,
+                    Operator::Gt => record_num > filter_num,
+                    Operator::LtEq => record_num <= filter_num,
+                    Operator::GtEq => record_num >= filter_num,
+                    _ => false, 
+                }
+            } else { 
+                let ordering = if natural { natural_cmp(cell, filter_value) } else { cell.cmp(filter_value) };
+                match op {
+                    Operator::Lt => ordering.is_lt(),
+                    Operator::Gt => ordering.is_gt(),
+                    Operator::LtEq => ordering.is_le(),
+                    Operator::GtEq => ordering.is_ge(),
+                    _ => false, 
+                }
+            }
+        }
+        Operator::Custom(operator) => operator.matches(cell, filter_value),
+    }
+}
+
+/// True when the record satisfies every filter (AND semantics). A missing cell never matches.
+pub fn record_matches(record: &StringRecord, filters: &[ResolvedFilter]) -> bool {
+    filters.iter().all(|filter| {
+        record.get(filter.column).is_some_and(|cell| ordered_value_matches(cell, filter.op, &filter.value, filter.natural))
+    })
+}
+
+/// True when none of the filtered cells is missing or empty. With `--empty-as-null`, records
+/// must also pass this check, so an empty cell matches no filter.
+pub fn filtered_cells_present(record: &StringRecord, filters: &[ResolvedFilter]) -> bool {
+    filters.iter().all(|filter| record.get(filter.column).is_some_and(|cell| !cell.trim().is_empty()))
+}
+
+/// Deterministic hash-based sampling predicate from `--where-hash`, e.g. `id%100<5`:
+/// the cell is hashed, reduced modulo `modulus`, and the bucket compared against `threshold`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HashSample {
+    pub modulus: u64,
+    pub op: Operator,
+    pub threshold: u64,
+}
+
+impl HashSample {
+    pub fn matches(&self, cell: &str) -> bool {
+        let bucket = stable_hash(cell) % self.modulus;
+        match self.op {
+            Operator::Eq => bucket == self.threshold,
+            Operator::NotEq => bucket != self.threshold,
+            Operator::Lt => bucket < self.threshold,
+            Operator::Gt => bucket > self.threshold,
+            Operator::LtEq => bucket <= self.threshold,
+            Operator::GtEq => bucket >= self.threshold,
+            Operator::Custom(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for HashSample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "% {} {} {}", self.modulus, self.op, self.threshold)
+    }
+}
+
+/// 64-bit FNV-1a hash. Unlike `std`'s hasher it is stable across platforms and releases,
+/// so hash-based samples are the same for everyone running the same query.
+pub fn stable_hash(value: &str) -> u64 {
+    value.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3))
+}
+
+/// Parses a `COLUMN%MODULUS<OP>VALUE` sampling expression such as `id%100<5`.
+pub fn parse_where_hash_arg(s: &str) -> Result<(String, HashSample), String> {
+    let expected = format!("Invalid hash sample '{}'. Expected COLUMN%MODULUS<OP>VALUE, e.g. \"id%100<5\".", s);
+    let (key, op, value) = parse_filter_arg(s).map_err(|_| expected.clone())?;
+    if let Operator::Custom(_) = op {
+        return Err(expected);
+    }
+    let (column, modulus) = key.rsplit_once('%').ok_or_else(|| expected.clone())?;
+    let column = column.trim();
+    let modulus: u64 = modulus.trim().parse().map_err(|_| expected.clone())?;
+    let threshold: u64 = value.parse().map_err(|_| expected.clone())?;
+    if column.is_empty() || modulus == 0 {
+        return Err(expected);
+    }
+    Ok((column.to_string(), HashSample { modulus, op, threshold }))
+}
+
+/// Parses a date or timestamp cell. With `format` (a chrono/strftime pattern such as "%d/%m/%Y")
+/// the cell must match it; otherwise it must start with an ISO 8601 date, "YYYY-MM-DD",
+/// optionally followed by a time.
+pub fn parse_date(cell: &str, format: Option<&str>) -> Option<NaiveDate> {
+    let cell = cell.trim();
+    match format {
+        Some(format) => NaiveDateTime::parse_from_str(cell, format).map(|datetime| datetime.date())
+            .or_else(|_| NaiveDate::parse_from_str(cell, format))
+            .ok(),
+        None => {
+            let (date, rest) = cell.split_at_checked(10)?;
+            if !(rest.is_empty() || rest.starts_with(['T', 't', ' '])) {
+                return None;
+            }
+            NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+        }
+    }
+}
+
+/// Parses a `--since` or `--until` date: YYYY-MM-DD, "today", "yesterday", or "N days ago"
+/// (also weeks, months and years). Relative dates count back from today in UTC.
+pub fn parse_date_bound(s: &str) -> Result<NaiveDate, String> {
+    let today = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .and_then(|elapsed| DateTime::from_timestamp(elapsed.as_secs() as i64, 0))
+        .map(|now| now.date_naive())
+        .ok_or_else(|| "The system clock is not set.".to_string())?;
+    resolve_date_bound(s, today)
+}
+
+fn resolve_date_bound(s: &str, today: NaiveDate) -> Result<NaiveDate, String> {
+    let invalid = || format!("Invalid date '{}'. Expected YYYY-MM-DD, \"today\", \"yesterday\" or e.g. \"30 days ago\".", s);
+    let lower = s.trim().to_ascii_lowercase();
+    match lower.as_str() {
+        "today" => return Ok(today),
+        "yesterday" => return today.checked_sub_days(Days::new(1)).ok_or_else(invalid),
+        _ => {}
+    }
+    let Some(amount) = lower.strip_suffix(" ago") else {
+        return parse_date(s, None).ok_or_else(invalid);
+    };
+    let (count, unit) = amount.split_once(' ').ok_or_else(invalid)?;
+    let count: u32 = count.parse().map_err(|_| invalid())?;
+    match unit.trim().trim_end_matches('s') {
+        "day" => today.checked_sub_days(Days::new(count.into())),
+        "week" => today.checked_sub_days(Days::new(u64::from(count) * 7)),
+        "month" => today.checked_sub_months(Months::new(count)),
+        "year" => count.checked_mul(12).and_then(|months| today.checked_sub_months(Months::new(months))),
+        _ => None,
+    }
+    .ok_or_else(invalid)
+}
+
+/// The `--since`/`--until` range of `--date-column`, both ends included.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DateRange {
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+}
+
+impl DateRange {
+    /// True when the cell is a date (see [`parse_date`]) within the range.
+    pub fn matches(&self, cell: &str, format: Option<&str>) -> bool {
+        parse_date(cell, format).is_some_and(|date| {
+            self.since.is_none_or(|since| date >= since) && self.until.is_none_or(|until| date <= until)
+        })
+    }
+}
+
+impl fmt::Display for DateRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.since, self.until) {
+            (Some(since), Some(until)) => write!(f, "from {} to {}", since, until),
+            (Some(since), None) => write!(f, "since {}", since),
+            (None, Some(until)) => write!(f, "until {}", until),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_filter_arg_valid_ops() {
+        assert_eq!(parse_filter_arg("Col=Val"), Ok(("Col".to_string(), Operator::Eq, "Val".to_string())));
+        assert_eq!(parse_filter_arg("Col!=Val"), Ok(("Col".to_string(), Operator::NotEq, "Val".to_string())));
+        assert_eq!(parse_filter_arg("Col>Val"), Ok(("Col".to_string(), Operator::Gt, "Val".to_string())));
+        assert_eq!(parse_filter_arg("Col<Val"), Ok(("Col".to_string(), Operator::Lt, "Val".to_string())));
+        assert_eq!(parse_filter_arg("Col>=Val"), Ok(("Col".to_string(), Operator::GtEq, "Val".to_string())));
+        assert_eq!(parse_filter_arg("Col<=Val"), Ok(("Col".to_string(), Operator::LtEq, "Val".to_string())));
+        assert_eq!(parse_filter_arg("  Col  >=  Val  "), Ok(("Col".to_string(), Operator::GtEq, "Val".to_string())));
+    }
+
+    #[test]
+    fn test_parse_filter_arg_invalid_ops_or_format() {
+        assert!(parse_filter_arg("ColVal").is_err()); 
+        assert!(parse_filter_arg("Col<>Val").is_err());
+        assert_eq!(parse_filter_arg("Col><Val"), Ok(("Col".to_string(), Operator::Gt, "<Val".to_string())));
+    }
+
+     #[test]
+     fn test_parse_filter_arg_empty_key_error() {
+         let result = parse_filter_arg("=Value");
+         assert!(result.is_err());
+         if let Err(e) = result {
+             assert!(e.contains("Column name cannot be empty"));
+         }
+
+         let result_op = parse_filter_arg(">=Value"); 
+         assert!(result_op.is_err());
+         if let Err(e) = result_op {
+             assert!(e.contains("Column name cannot be empty"));
+         }
+     }
+
+    #[test]
+    fn test_parse_filter_arg_empty_value_is_ok() {
+         assert_eq!(parse_filter_arg("Col="), Ok(("Col".to_string(), Operator::Eq, "".to_string())));
+         assert_eq!(parse_filter_arg("Col>="), Ok(("Col".to_string(), Operator::GtEq, "".to_string())));
+    }
+
+    #[test]
+    fn test_value_matches_numeric_then_lexicographic() {
+        assert!(value_matches("10", Operator::Gt, "9"));
+        assert!(!value_matches("10", Operator::Lt, "9"));
+        assert!(value_matches("beta", Operator::Gt, "alpha"));
+        assert!(value_matches("LONDON", Operator::Eq, "london"));
+        assert!(value_matches("Göteborg", Operator::Eq, "GÖTEBORG"));
+        assert!(!value_matches("ÅRE", Operator::NotEq, "åre"));
+        assert!(value_matches("Straße", Operator::Eq, "STRASSE"));
+        assert!(value_matches("ΟΔΟΣ", Operator::Eq, "οδος"));
+        assert!(crate::contains_ignore_case("Hauptstraße 5", "STRASSE"));
+        assert_eq!(crate::fold_case("Σίσυφος"), "σίσυφοσ");
+        assert!(value_matches("Paris", Operator::NotEq, "london"));
+        assert!(value_matches("1.2e9", Operator::Gt, "999999999"));
+        assert!(value_matches("5E-3", Operator::Lt, "0.01"));
+        assert!(value_matches("-1.2E+3", Operator::LtEq, "-1200"));
+        assert_eq!(parse_number("inf"), None);
+        assert_eq!(parse_number("1e400"), None);
+        assert_eq!(parse_number(" 1.5e2 "), Some(150.0));
+    }
+
+    #[test]
+    fn test_from_decimal_comma() {
+        let convert = |value| from_decimal_comma(value).and_then(|number| number.parse::<f64>().ok());
+        assert_eq!(convert("2,75"), Some(2.75));
+        assert_eq!(convert("-1.234,56"), Some(-1234.56));
+        assert_eq!(convert("+12.345.678"), Some(12345678.0));
+        assert_eq!(convert("1,2e9"), Some(1.2e9));
+        assert_eq!(convert(",5"), Some(0.5));
+        assert_eq!(convert("1.234"), Some(1234.0));
+        assert_eq!(convert("3.14"), None);
+        assert_eq!(convert("1234.567"), None);
+        assert_eq!(convert("1,2,3"), None);
+        assert_eq!(convert("n/a"), None);
+        assert_eq!(convert(","), None);
+        assert_eq!(fraction_digits("12.50"), 2);
+        assert_eq!(fraction_digits("1.5e3"), 1);
+        assert_eq!(fraction_digits("7"), 0);
+    }
+
+    #[test]
+    fn test_mixed_numeric_cells() {
+        let records: Vec<StringRecord> = ["1.2e9", "n/a", "", "500", "unknown"].iter().map(|cell| StringRecord::from(vec![*cell])).collect();
+        let filter = |op, value: &str| ResolvedFilter { column: 0, op, value: value.to_string(), natural: false };
+        assert_eq!(mixed_numeric_cells(&records, &filter(Operator::Gt, "1e3")), Some((2, "n/a")));
+        assert_eq!(mixed_numeric_cells(&records, &filter(Operator::Gt, "m")), None);
+        assert_eq!(mixed_numeric_cells(&records, &filter(Operator::Eq, "500")), None);
+        assert_eq!(mixed_numeric_cells(&records[..1], &filter(Operator::Lt, "7")), None);
+        assert_eq!(mixed_numeric_cells(&records[1..3], &filter(Operator::Lt, "7")), None);
+    }
+
+    #[test]
+    fn test_parse_where_hash_arg() {
+        assert_eq!(
+            parse_where_hash_arg("id%100<5"),
+            Ok(("id".to_string(), HashSample { modulus: 100, op: Operator::Lt, threshold: 5 }))
+        );
+        assert!(parse_where_hash_arg("id<5").is_err());
+        assert!(parse_where_hash_arg("id%0<5").is_err());
+        assert!(parse_where_hash_arg("id%10<x").is_err());
+    }
+
+    #[derive(Debug)]
+    struct Prefix;
+
+    impl FilterOperator for Prefix {
+        fn name(&self) -> &str {
+            "prefix"
+        }
+
+        fn check_value(&self, value: &str) -> Result<(), String> {
+            if value.is_empty() { Err("the prefix is empty".to_string()) } else { Ok(()) }
+        }
+
+        fn matches(&self, cell: &str, value: &str) -> bool {
+            cell.starts_with(value)
+        }
+    }
+
+    #[test]
+    fn test_custom_operator() {
+        register_operator(Box::new(Prefix));
+        let (column, op, value) = parse_filter_arg("Ip [PREFIX] 10.0.").unwrap();
+        assert_eq!((column.as_str(), value.as_str()), ("Ip", "10.0."));
+        assert_eq!(op.to_string(), "[prefix]");
+        assert!(value_matches("10.0.3.4", op, &value));
+        assert!(!value_matches("192.168.0.1", op, &value));
+        assert_eq!(parse_filter_arg("Ip[prefix]").unwrap_err(), "Invalid value '' for filter operator [prefix]: the prefix is empty");
+        assert!(parse_filter_arg("Ip[nope]x").unwrap_err().contains("Operator (e.g., =, !=, >, <, >=, <=) missing"));
+        // Brackets that do not name an operator belong to the column name.
+        assert_eq!(parse_filter_arg("Weight[kg]>10"), Ok(("Weight[kg]".to_string(), Operator::Gt, "10".to_string())));
+        assert!(parse_where_hash_arg("id%10[prefix]1").is_err());
+        // Brackets in values of the built-in operators are left alone.
+        assert_eq!(parse_filter_arg("Tag=[x]"), Ok(("Tag".to_string(), Operator::Eq, "[x]".to_string())));
+    }
+
+    #[derive(Debug)]
+    struct Ends(&'static str, bool);
+
+    impl FilterOperator for Ends {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn matches(&self, cell: &str, value: &str) -> bool {
+            if self.1 { cell.ends_with(value) } else { cell.starts_with(value) }
+        }
+    }
+
+    #[test]
+    fn test_reregistering_operator_ignores_case() {
+        register_operator(Box::new(Ends("Ends", false)));
+        register_operator(Box::new(Ends("ENDS", true)));
+        let registered = OPERATORS.read().unwrap().iter().filter(|operator| operator.name().eq_ignore_ascii_case("ends")).count();
+        assert_eq!(registered, 1);
+        let (_, op, value) = parse_filter_arg("File[ends].csv").unwrap();
+        assert_eq!(op.to_string(), "[ENDS]");
+        assert!(value_matches("data.csv", op, &value));
+    }
+
+    #[test]
+    fn test_stable_hash_is_fnv1a() {
+        assert_eq!(stable_hash(""), 0xcbf29ce484222325);
+        assert_eq!(stable_hash("a"), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn test_date_range() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        assert_eq!(resolve_date_bound("2024-01-01", today), Ok(date(2024, 1, 1)));
+        assert_eq!(resolve_date_bound("Yesterday", today), Ok(date(2024, 3, 30)));
+        assert_eq!(resolve_date_bound("30 days ago", today), Ok(date(2024, 3, 1)));
+        assert_eq!(resolve_date_bound("2 weeks ago", today), Ok(date(2024, 3, 17)));
+        assert_eq!(resolve_date_bound("1 month ago", today), Ok(date(2024, 2, 29)));
+        assert_eq!(resolve_date_bound("1 year ago", today), Ok(date(2023, 3, 31)));
+        assert!(resolve_date_bound("soon", today).is_err());
+        assert!(resolve_date_bound("3 fortnights ago", today).is_err());
+
+        let range = DateRange { since: Some(date(2024, 1, 1)), until: Some(date(2024, 3, 31)) };
+        assert!(range.matches("2024-03-31T23:59:59", None));
+        assert!(!range.matches("2023-12-31", None));
+        assert!(!range.matches("", None));
+        assert!(range.matches("01/02/2024", Some("%d/%m/%Y")));
+        assert_eq!(range.to_string(), "from 2024-01-01 to 2024-03-31");
+    }
+
+    #[test]
+    fn test_natural_order() {
+        assert_eq!(natural_cmp("item2", "item10"), Ordering::Less);
+        assert_eq!(natural_cmp("1.10.0", "1.9.0"), Ordering::Greater);
+        assert_eq!(natural_cmp("v1.2", "v1.2.1"), Ordering::Less);
+        assert_eq!(natural_cmp("a01", "a1"), Ordering::Less);
+        assert_eq!(natural_cmp("b1", "a2"), Ordering::Greater);
+        assert!(ordered_value_matches("item10", Operator::Gt, "item9", true));
+        assert!(!value_matches("item10", Operator::Gt, "item9"));
+    }
+}
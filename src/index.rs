@@ -0,0 +1,268 @@
+//! Sidecar indexes for equality filters on large files. `csvpeek-rs index build -f big.csv
+//! --on CustomerID` records where the rows with each value of the column start, in
+//! `big.csv.csvpeek-index`; later queries with `--filter CustomerID=...` seek straight to them.
+//!
+//! An index is only used while the data file keeps the size and modification time it had when
+//! the index was built, and with the same delimiter, comment character and skipped rows.
+
+use crate::input::{sniff_header_delimiter, ReadOptions};
+use crate::{eq_ignore_case, find_column, fold_case, CsvPeekError, Result};
+use csv::StringRecord;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const INDEX_VERSION: u64 = 3;
+
+/// Where a record starts: its byte offset in the file and its physical line number.
+pub type RecordStart = (u64, u64);
+
+/// The indexed columns of one data file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileIndex {
+    /// Size and modification time (in nanoseconds since the epoch) of the indexed file.
+    pub size: u64,
+    pub modified: u64,
+    pub delimiter: u8,
+    pub comment: Option<u8>,
+    pub skip_rows: usize,
+    pub headers: Vec<String>,
+    /// Indexed column name -> normalized value -> starts of the records with that value.
+    pub columns: BTreeMap<String, BTreeMap<String, Vec<RecordStart>>>,
+}
+
+/// Path of the sidecar index of `data_path`.
+pub fn index_path(data_path: &Path) -> PathBuf {
+    let mut name = data_path.as_os_str().to_owned();
+    name.push(".csvpeek-index");
+    PathBuf::from(name)
+}
+
+/// Values are indexed trimmed and case-folded, as equality filters ignore case. The rows an
+/// index finds are filtered again, so it may find more than `--ascii-case` filters keep.
+pub fn index_key(value: &str) -> String {
+    fold_case(value.trim())
+}
+
+/// Size and modification time of a file, as recorded in its index.
+fn file_stamp(path: &Path) -> Result<(u64, u64)> {
+    let metadata = fs::metadata(path).map_err(|e| CsvPeekError::from(e).in_file(path.display()))?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map_or(0, |age| age.as_nanos() as u64);
+    Ok((metadata.len(), modified))
+}
+
+/// Opens a data file positioned after the skipped rows and resolves its delimiter.
+/// Returns the reader, the number of bytes skipped and the delimiter.
+fn open_data(data_path: &Path, options: &ReadOptions) -> Result<(io::BufReader<fs::File>, u64, u8)> {
+    if options.encoding.is_some() {
+        return Err(CsvPeekError::Usage("Indexes cannot be used with --encoding; convert the file to UTF-8 first.".to_string()));
+    }
+    let file = fs::File::open(data_path).map_err(|e| CsvPeekError::from(e).in_file(data_path.display()))?;
+    let mut buffered = io::BufReader::new(file);
+    let mut skipped = 0;
+    let mut line = Vec::new();
+    for _ in 0..options.skip_rows {
+        line.clear();
+        let read = buffered.read_until(b'\n', &mut line)?;
+        if read == 0 {
+            break;
+        }
+        skipped += read as u64;
+    }
+    let delimiter = match options.for_name(&data_path.display().to_string()).delimiter {
+        Some(delimiter) => delimiter,
+        None => sniff_header_delimiter(buffered.fill_buf()?, options.comment),
+    };
+    Ok((buffered, skipped, delimiter))
+}
+
+fn reader_builder(delimiter: u8, options: &ReadOptions) -> csv::ReaderBuilder {
+    let mut builder = csv::ReaderBuilder::new();
+    builder.delimiter(delimiter).comment(options.comment).flexible(true).trim(options.trim);
+    builder
+}
+
+/// Indexes `columns` of a data file and writes (or extends) its sidecar index.
+/// Returns the index and the number of records read.
+pub fn build_index(data_path: &Path, columns: &[String], options: &ReadOptions) -> Result<(FileIndex, usize)> {
+    let (size, modified) = file_stamp(data_path)?;
+    let (buffered, skipped, delimiter) = open_data(data_path, options)?;
+    let mut reader = reader_builder(delimiter, options).from_reader(buffered);
+    let headers: Vec<String> = reader.headers().map_err(|e| CsvPeekError::from(e).in_file(data_path.display()))?
+        .iter().map(String::from).collect();
+    let indices = columns.iter()
+        .map(|column| find_column(&headers, column, "Index"))
+        .collect::<Result<Vec<usize>>>()?;
+    let mut built: Vec<BTreeMap<String, Vec<RecordStart>>> = vec![BTreeMap::new(); indices.len()];
+    let mut record = StringRecord::new();
+    let mut rows = 0;
+    while reader.read_record(&mut record).map_err(|e| CsvPeekError::from(e).in_file(data_path.display()))? {
+        let position = record.position().expect("records read from a reader have a position");
+        let start = (skipped + position.byte(), position.line() + options.skip_rows as u64);
+        for (&idx, values) in indices.iter().zip(&mut built) {
+            values.entry(index_key(record.get(idx).unwrap_or_default())).or_default().push(start);
+        }
+        rows += 1;
+    }
+
+    let path = index_path(data_path);
+    let mut index = match load_index(&path)? {
+        Some(existing) if existing.size == size && existing.modified == modified && existing.delimiter == delimiter
+            && existing.comment == options.comment && existing.skip_rows == options.skip_rows => existing,
+        _ => FileIndex {
+            size, modified, delimiter, comment: options.comment, skip_rows: options.skip_rows, headers: headers.clone(), columns: BTreeMap::new(),
+        },
+    };
+    for (&idx, values) in indices.iter().zip(built) {
+        index.columns.insert(headers[idx].clone(), values);
+    }
+    fs::write(&path, index.to_json().to_string()).map_err(|e| CsvPeekError::from(e).in_file(path.display()))?;
+    Ok((index, rows))
+}
+
+/// Reads an index file. Returns `None` if there is none.
+pub fn load_index(path: &Path) -> Result<Option<FileIndex>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(CsvPeekError::from(e).in_file(path.display())),
+    };
+    let invalid = || CsvPeekError::Usage(format!("Invalid index file '{}'; rebuild it with csvpeek-rs index build.", path.display()));
+    let json: Value = serde_json::from_str(&text).map_err(|_| invalid())?;
+    FileIndex::from_json(&json).map(Some).ok_or_else(invalid)
+}
+
+impl FileIndex {
+    /// Whether the index still describes `data_path` read with `options`.
+    pub fn is_current(&self, data_path: &Path, options: &ReadOptions) -> Result<bool> {
+        let stamp = file_stamp(data_path)?;
+        let delimiter = options.for_name(&data_path.display().to_string()).delimiter;
+        Ok(stamp == (self.size, self.modified)
+            && delimiter.is_none_or(|delimiter| delimiter == self.delimiter)
+            && options.comment == self.comment
+            && options.skip_rows == self.skip_rows
+            && options.encoding.is_none())
+    }
+
+    /// The indexed column matching `column` case-insensitively, with its values.
+    pub fn column(&self, column: &str) -> Option<&BTreeMap<String, Vec<RecordStart>>> {
+        self.columns.iter().find(|(name, _)| eq_ignore_case(name, column)).map(|(_, values)| values)
+    }
+
+    /// Starts of the records whose indexed `column` equals `value` (ignoring case), or
+    /// `None` if the column is not indexed.
+    pub fn lookup(&self, column: &str, value: &str) -> Option<&[RecordStart]> {
+        self.column(column).map(|values| values.get(&index_key(value)).map_or(&[][..], Vec::as_slice))
+    }
+
+    /// Reads the records starting at `starts` from the indexed data file. Records with the
+    /// wrong number of fields are handled as `ReadOptions::flexible` and `skip_bad_rows` say.
+    pub fn read_records(&self, data_path: &Path, starts: &[RecordStart], options: &ReadOptions) -> Result<Vec<StringRecord>> {
+        let mut file = fs::File::open(data_path).map_err(|e| CsvPeekError::from(e).in_file(data_path.display()))?;
+        let mut records = Vec::with_capacity(starts.len());
+        for &(byte, line) in starts {
+            file.seek(SeekFrom::Start(byte))?;
+            let mut reader = reader_builder(self.delimiter, options).has_headers(false).from_reader(&mut file);
+            let mut record = StringRecord::new();
+            if !reader.read_record(&mut record).map_err(|e| CsvPeekError::from(e).in_file(data_path.display()))? {
+                continue;
+            }
+            let width = self.headers.len();
+            if record.len() != width {
+                if options.skip_bad_rows {
+                    continue;
+                }
+                if !options.flexible {
+                    return Err(CsvPeekError::Parse {
+                        file: Some(data_path.display().to_string()),
+                        line: Some(line),
+                        message: format!("found record with {} fields, but the header has {} fields", record.len(), width),
+                    });
+                }
+                record.truncate(width);
+                while record.len() < width {
+                    record.push_field("");
+                }
+            }
+            let mut position = csv::Position::new();
+            position.set_byte(byte).set_line(line);
+            record.set_position(Some(position));
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "version": INDEX_VERSION,
+            "size": self.size,
+            "modified": self.modified,
+            "delimiter": self.delimiter,
+            "comment": self.comment,
+            "skip_rows": self.skip_rows,
+            "headers": self.headers,
+            "columns": self.columns,
+        })
+    }
+
+    fn from_json(json: &Value) -> Option<FileIndex> {
+        if json["version"].as_u64()? != INDEX_VERSION {
+            return None;
+        }
+        let byte = |value: &Value| value.as_u64().and_then(|n| u8::try_from(n).ok());
+        let columns = json["columns"].as_object()?.iter()
+            .map(|(column, values)| {
+                let values = values.as_object()?.iter()
+                    .map(|(key, starts)| {
+                        let starts = starts.as_array()?.iter()
+                            .map(|start| Some((start[0].as_u64()?, start[1].as_u64()?)))
+                            .collect::<Option<Vec<RecordStart>>>()?;
+                        Some((key.clone(), starts))
+                    })
+                    .collect::<Option<BTreeMap<_, _>>>()?;
+                Some((column.clone(), values))
+            })
+            .collect::<Option<BTreeMap<_, _>>>()?;
+        Some(FileIndex {
+            size: json["size"].as_u64()?,
+            modified: json["modified"].as_u64()?,
+            delimiter: byte(&json["delimiter"])?,
+            comment: if json["comment"].is_null() { None } else { Some(byte(&json["comment"])?) },
+            skip_rows: usize::try_from(json["skip_rows"].as_u64()?).ok()?,
+            headers: json["headers"].as_array()?.iter().map(|h| h.as_str().map(String::from)).collect::<Option<_>>()?,
+            columns,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_and_lookup_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("orders.csv");
+        fs::write(&data, "# export\nid,customer\n1,ACME\n2,\"Big\nCo\"\n3,acme \n").unwrap();
+        let options = ReadOptions { skip_rows: 1, ..ReadOptions::default() };
+        let (index, rows) = build_index(&data, &["CUSTOMER".to_string()], &options).unwrap();
+        assert_eq!(rows, 3);
+        assert!(index.is_current(&data, &options).unwrap());
+        assert_eq!(load_index(&index_path(&data)).unwrap().as_ref(), Some(&index));
+
+        let starts = index.lookup("customer", "Acme").unwrap();
+        assert_eq!(starts.iter().map(|&(_, line)| line).collect::<Vec<_>>(), [3, 6]);
+        let records = index.read_records(&data, starts, &options).unwrap();
+        assert_eq!(records.iter().map(|r| r.get(0).unwrap()).collect::<Vec<_>>(), ["1", "3"]);
+        let big = index.read_records(&data, index.lookup("customer", "big\nco").unwrap(), &options).unwrap();
+        assert_eq!(big[0].get(1), Some("Big\nCo"));
+        assert_eq!(index.lookup("customer", "nobody"), Some(&[][..]));
+        assert_eq!(index.lookup("id", "1"), None);
+
+        fs::write(&data, "id,customer\n").unwrap();
+        assert!(!index.is_current(&data, &options).unwrap());
+    }
+}
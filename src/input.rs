@@ -0,0 +1,1162 @@
+use crate::{eq_ignore_case, find_column};
+use crate::error::{CsvPeekError, Result};
+use crate::json::{is_json, json_as_csv};
+use crate::sqlite::{is_database, query_as_csv};
+use crate::workbook::{is_workbook, sheet_as_csv};
+use clap::ValueEnum;
+use csv::{ByteRecord, StringRecord};
+use encoding_rs::Encoding;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Read};
+use std::path::{Path, PathBuf};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressFinish, ProgressStyle};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::time::{Instant, SystemTime};
+
+/// Headers and records of a loaded CSV input.
+pub type CsvData = (Vec<String>, Vec<StringRecord>);
+
+static FIXTURE_PATHS: AtomicBool = AtomicBool::new(false);
+
+/// Switches path rendering to a platform-independent form for golden-file tests
+/// (see `--render-fixture`): paths below the current directory are shown relative
+/// to it, and `/` is always used as the separator.
+pub fn set_fixture_paths(enabled: bool) {
+    FIXTURE_PATHS.store(enabled, Ordering::Relaxed);
+}
+
+/// Renders a path for user-facing messages.
+pub fn display_path(path: &Path) -> String {
+    if !FIXTURE_PATHS.load(Ordering::Relaxed) {
+        return path.display().to_string();
+    }
+    let relative = std::env::current_dir()
+        .ok()
+        .and_then(|cwd| path.strip_prefix(cwd).ok().map(Path::to_path_buf))
+        .unwrap_or_else(|| path.to_path_buf());
+    relative.to_string_lossy().replace('\\', "/")
+}
+
+/// How CSV text is parsed, shared by every input source.
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    /// Field delimiter. `None` lets each source pick one: `,` for `.csv` files and stdin, tab for
+    /// `.tsv`/`.tab` files, and a guess from the header line for other files (e.g. `.txt`).
+    pub delimiter: Option<u8>,
+    /// Character encoding the input is transcoded from before parsing; `None` reads UTF-8 as is.
+    pub encoding: Option<&'static Encoding>,
+    /// Lines starting with this byte are skipped, both before the header row and between records.
+    pub comment: Option<u8>,
+    /// Number of physical lines (e.g. a title banner) discarded before the header row.
+    pub skip_rows: usize,
+    /// Pad records with too few fields and truncate those with too many instead of failing.
+    pub flexible: bool,
+    /// Drop records whose field count differs from the header instead of failing.
+    pub skip_bad_rows: bool,
+    /// Which of headers and fields have leading and trailing whitespace removed.
+    pub trim: csv::Trim,
+    /// Report the rows and bytes read and the time taken for each input on stderr.
+    pub verbose: bool,
+    /// Whether local files are memory-mapped instead of read.
+    pub mmap: MmapMode,
+    /// When set, only these columns (matched ignoring case, as [`crate::find_column`] does) are
+    /// copied out of each record; the other fields are left empty, keeping their positions.
+    /// Cuts allocations on wide files.
+    pub columns: Option<Vec<String>>,
+    /// When set, a column of this name is prepended to each source, holding the 1-based number
+    /// of every record within that source (before any are skipped or filtered).
+    pub row_number: Option<String>,
+    /// Sheet read from Excel and OpenDocument workbooks, by name; `None` reads the first sheet.
+    pub sheet: Option<String>,
+    /// Table read from SQLite databases; `None` reads the only table of the database.
+    pub table: Option<String>,
+    /// Query whose result is read from SQLite databases instead of a table.
+    pub sqlite_query: Option<String>,
+    /// When set, bad records are logged here (with file and line) and skipped instead of
+    /// failing the load; see `--report-errors`.
+    pub problems: Option<ProblemLog>,
+    /// When set, bytes read from local files advance this progress bar.
+    pub progress: Option<ReadProgress>,
+    /// Name of the input being parsed, used in warnings and logged problems. Set by `for_name`.
+    pub source: Option<String>,
+}
+
+impl ReadOptions {
+    /// Prepares the options for an input called `name` (a path or URL): records the name and
+    /// resolves the delimiter from its extension. The delimiter stays unset when the extension
+    /// does not imply one, so the parser sniffs it.
+    pub fn for_name(&self, name: &str) -> ReadOptions {
+        let mut options = self.clone();
+        options.source = Some(name.to_string());
+        if options.delimiter.is_none() {
+            let extension = Path::new(name).extension().map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+            options.delimiter = match extension.as_deref() {
+                Some("csv") => Some(b','),
+                Some("tsv") | Some("tab") => Some(b'\t'),
+                _ => None,
+            };
+        }
+        options
+    }
+}
+
+/// Collects the problems found while loading with `ReadOptions::problems`, across every source.
+#[derive(Debug, Clone, Default)]
+pub struct ProblemLog(Arc<Mutex<Vec<CsvPeekError>>>);
+
+impl ProblemLog {
+    pub fn push(&self, problem: CsvPeekError) {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(problem);
+    }
+
+    /// Takes the problems logged so far, in the order they were found.
+    pub fn take(&self) -> Vec<CsvPeekError> {
+        std::mem::take(&mut self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+}
+
+/// Progress bar on stderr for long reads, shared by the files of one load: bytes read out of
+/// their total size, and which file is being read. It is cleared once every copy is dropped.
+#[derive(Debug, Clone)]
+pub struct ReadProgress {
+    bar: ProgressBar,
+    files: usize,
+    opened: Arc<AtomicUsize>,
+}
+
+impl ReadProgress {
+    /// A bar for reading `files` files of `total_bytes` bytes together.
+    pub fn new(total_bytes: u64, files: usize) -> Self {
+        Self::with_target(total_bytes, files, ProgressDrawTarget::stderr())
+    }
+
+    fn with_target(total_bytes: u64, files: usize, target: ProgressDrawTarget) -> Self {
+        let style = ProgressStyle::with_template("{spinner} [{elapsed_precise}] {wide_bar} {bytes}/{total_bytes} {msg}")
+            .expect("progress template is valid");
+        let bar = ProgressBar::with_draw_target(Some(total_bytes), target)
+            .with_style(style)
+            .with_finish(ProgressFinish::AndClear);
+        ReadProgress { bar, files, opened: Arc::new(AtomicUsize::new(0)) }
+    }
+
+    /// Shows `name` as the file being read and counts the bytes read through the returned reader.
+    fn track<R: Read>(&self, name: &str, reader: R) -> impl Read {
+        let opened = self.opened.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.files > 1 {
+            self.bar.set_message(format!("file {}/{}: {}", opened.min(self.files), self.files, name));
+        } else {
+            self.bar.set_message(name.to_string());
+        }
+        self.bar.wrap_read(reader)
+    }
+}
+
+/// Parses a `--delimiter` value: a single ASCII character, or `\t`/`tab` for a tab.
+pub fn parse_delimiter_arg(s: &str) -> std::result::Result<u8, String> {
+    match s {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ if s.len() == 1 && s.is_ascii() => Ok(s.as_bytes()[0]),
+        _ => Err(format!("Invalid delimiter '{}'. Expected a single ASCII character or \\t.", s)),
+    }
+}
+
+/// Parses a `--comment-char` value: a single ASCII character.
+pub fn parse_comment_char_arg(s: &str) -> std::result::Result<u8, String> {
+    match s.as_bytes() {
+        [byte] if byte.is_ascii() => Ok(*byte),
+        _ => Err(format!("Invalid comment character '{}'. Expected a single ASCII character.", s)),
+    }
+}
+
+/// Parses an `--encoding` label such as `latin1`, `windows-1252` or `utf-16le`.
+pub fn parse_encoding_arg(s: &str) -> std::result::Result<&'static Encoding, String> {
+    Encoding::for_label(s.trim().as_bytes()).ok_or_else(|| format!("Unknown encoding '{}'. Use a label such as utf-8, latin1, windows-1252 or utf-16le.", s))
+}
+
+/// Candidate delimiters for sniffing, in order of preference on ties.
+const SNIFF_DELIMITERS: [u8; 4] = [b',', b'\t', b';', b'|'];
+
+/// Guesses the delimiter of a header line: the candidate occurring most often outside quotes.
+pub fn sniff_delimiter(header_line: &[u8]) -> u8 {
+    let mut counts = [0usize; SNIFF_DELIMITERS.len()];
+    let mut in_quotes = false;
+    for &byte in header_line.iter().take_while(|&&b| b != b'\n') {
+        if byte == b'"' {
+            in_quotes = !in_quotes;
+        } else if !in_quotes {
+            if let Some(i) = SNIFF_DELIMITERS.iter().position(|&d| d == byte) {
+                counts[i] += 1;
+            }
+        }
+    }
+    let best = (0..counts.len()).rev().max_by_key(|&i| counts[i]).unwrap_or(0);
+    if counts[best] == 0 { b',' } else { SNIFF_DELIMITERS[best] }
+}
+
+/// Guesses the delimiter from the first line of `data` that is not a comment.
+pub fn sniff_header_delimiter(data: &[u8], comment: Option<u8>) -> u8 {
+    let header_line = data
+        .split(|&b| b == b'\n')
+        .find(|line| comment.is_none_or(|comment| line.first() != Some(&comment)))
+        .unwrap_or_default();
+    sniff_delimiter(header_line)
+}
+
+/// Records of one CSV input, read one at a time. Bad records are handled as `ReadOptions` says:
+/// ragged rows are padded, truncated or skipped, and with a problem log parse errors are logged
+/// and skipped. The ragged-row warning and the `--verbose` summary are printed when the stream
+/// is exhausted or dropped.
+pub struct RecordStream<'r> {
+    reader: csv::Reader<io::BufReader<Box<dyn Read + 'r>>>,
+    headers: Vec<String>,
+    /// Number of fields in the input's header row, before a row number column is prepended.
+    width: usize,
+    /// Which fields are copied out of each record, from `ReadOptions::columns`.
+    keep: Option<Vec<bool>>,
+    raw_record: ByteRecord,
+    options: ReadOptions,
+    started: Instant,
+    rows: usize,
+    ragged_rows: usize,
+    first_ragged_line: Option<u64>,
+    /// Whether any record was requested, so header-only reads stay silent.
+    reading: bool,
+    finished: bool,
+}
+
+impl<'r> RecordStream<'r> {
+    /// Opens a stream and reads the header row. Without an explicit delimiter, it is sniffed
+    /// from the header line.
+    pub fn new<R: Read + 'r>(reader_source: R, options: &ReadOptions) -> Result<Self> {
+        let started = Instant::now();
+        let decoded: Box<dyn Read + 'r> = match options.encoding {
+            Some(encoding) => Box::new(DecodeReaderBytesBuilder::new().encoding(Some(encoding)).build(reader_source)),
+            None => Box::new(reader_source),
+        };
+        let mut buffered = io::BufReader::new(decoded);
+        let mut skipped_line = Vec::new();
+        for _ in 0..options.skip_rows {
+            skipped_line.clear();
+            if buffered.read_until(b'\n', &mut skipped_line)? == 0 {
+                break;
+            }
+        }
+        let delimiter = match options.delimiter {
+            Some(delimiter) => delimiter,
+            None => sniff_header_delimiter(buffered.fill_buf()?, options.comment),
+        };
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .comment(options.comment)
+            .flexible(options.flexible || options.skip_bad_rows)
+            .trim(options.trim)
+            .from_reader(buffered);
+        let headers = match reader.headers() {
+            Ok(headers) => headers.iter().map(String::from).collect::<Vec<String>>(),
+            Err(err) => return Err(parse_error(err, options)),
+        };
+        if headers.is_empty() {
+            return Err(CsvPeekError::MissingHeaders);
+        }
+        let keep = options.columns.as_ref()
+            .map(|columns| headers.iter().map(|h| columns.iter().any(|c| eq_ignore_case(c, h))).collect());
+        let width = headers.len();
+        let headers = options.row_number.iter().cloned().chain(headers).collect();
+        Ok(RecordStream {
+            reader,
+            headers,
+            width,
+            keep,
+            raw_record: ByteRecord::new(),
+            options: options.clone(),
+            started,
+            rows: 0,
+            ragged_rows: 0,
+            first_ragged_line: None,
+            reading: false,
+            finished: false,
+        })
+    }
+
+    pub fn headers(&self) -> &[String] {
+        &self.headers
+    }
+
+    /// Reads the next record, copying out only the kept fields when columns are projected.
+    /// Only the kept fields have to be valid UTF-8.
+    fn read_record(&mut self, record: &mut StringRecord) -> Result<bool> {
+        let Some(keep) = &self.keep else {
+            return self.reader.read_record(record).map_err(|err| parse_error(err, &self.options));
+        };
+        if !self.reader.read_byte_record(&mut self.raw_record).map_err(|err| parse_error(err, &self.options))? {
+            return Ok(false);
+        }
+        let mut projected = ByteRecord::with_capacity(0, self.raw_record.len());
+        for (i, field) in self.raw_record.iter().enumerate() {
+            projected.push_field(if keep.get(i).copied().unwrap_or(false) { field } else { b"" });
+        }
+        let position = self.raw_record.position().cloned();
+        projected.set_position(position.clone());
+        *record = StringRecord::from_byte_record(projected).map_err(|err| CsvPeekError::Parse {
+            file: None,
+            line: position.map(|pos| pos.line() + self.options.skip_rows as u64),
+            message: format!("invalid UTF-8: {}", err.utf8_error()),
+        })?;
+        Ok(true)
+    }
+
+    fn finish(&mut self) {
+        if self.finished || !self.reading {
+            return;
+        }
+        self.finished = true;
+        let options = &self.options;
+        if self.ragged_rows > 0 {
+            let action = if options.skip_bad_rows { "Skipped" } else { "Padded or truncated" };
+            let source = options.source.as_ref().map(|name| format!(" in '{}'", name)).unwrap_or_default();
+            let first = self.first_ragged_line.map(|line| format!(" (first at line {})", line)).unwrap_or_default();
+            crate::log_warn!("{} {} row(s){} whose number of fields differs from the header{}.", action, self.ragged_rows, source, first);
+        }
+        if options.verbose {
+            crate::log_debug!(
+                "read {} row(s) and {} byte(s) from {} in {:.2?}.",
+                self.rows,
+                self.reader.position().byte(),
+                options.source.as_deref().unwrap_or("input"),
+                self.started.elapsed()
+            );
+        }
+    }
+
+    /// Counts the remaining records without decoding their fields. Bad records are handled as
+    /// when they are read, except that fields are not checked for valid UTF-8.
+    pub fn count_records(mut self) -> Result<usize> {
+        self.reading = true;
+        let mut record = ByteRecord::new();
+        loop {
+            match self.reader.read_byte_record(&mut record) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) => match self.problem(parse_error(err, &self.options)) {
+                    Some(problem) => return Err(problem),
+                    None => continue,
+                },
+            }
+            if self.keeps(record.len(), record.position()) {
+                self.rows += 1;
+            }
+        }
+        self.finish();
+        Ok(self.rows)
+    }
+
+    /// Logs a read error if there is a problem log, or ends the stream and returns it.
+    fn problem(&mut self, err: CsvPeekError) -> Option<CsvPeekError> {
+        let problem = match &self.options.source {
+            Some(name) => err.in_file(name),
+            None => err,
+        };
+        match &self.options.problems {
+            Some(problems) => {
+                problems.push(problem);
+                None
+            }
+            None => {
+                self.finished = true;
+                Some(problem)
+            }
+        }
+    }
+
+    /// Notes a record with `len` fields if its width differs from the header, and says whether
+    /// it is kept (padded or truncated) rather than skipped.
+    fn keeps(&mut self, len: usize, position: Option<&csv::Position>) -> bool {
+        if len == self.width {
+            return true;
+        }
+        self.ragged_rows += 1;
+        self.first_ragged_line = self.first_ragged_line.or(position.map(|pos| pos.line() + self.options.skip_rows as u64));
+        !self.options.skip_bad_rows
+    }
+}
+
+impl Iterator for RecordStream<'_> {
+    type Item = Result<StringRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.reading = true;
+        loop {
+            let mut record = StringRecord::new();
+            match self.read_record(&mut record) {
+                Ok(true) => {}
+                Ok(false) => {
+                    self.finish();
+                    return None;
+                }
+                Err(err) => match self.problem(err) {
+                    Some(problem) => return Some(Err(problem)),
+                    None => continue,
+                },
+            }
+            if !self.keeps(record.len(), record.position()) {
+                continue;
+            }
+            record.truncate(self.width);
+            while record.len() < self.width {
+                record.push_field("");
+            }
+            if self.options.row_number.is_some() {
+                // Record 0 is the header row.
+                let number = record.position().map_or(0, csv::Position::record);
+                let mut numbered = StringRecord::with_capacity(record.as_slice().len() + 20, record.len() + 1);
+                numbered.push_field(&number.to_string());
+                numbered.extend(record.iter());
+                numbered.set_position(record.position().cloned());
+                record = numbered;
+            }
+            self.rows += 1;
+            return Some(Ok(record));
+        }
+    }
+}
+
+impl Drop for RecordStream<'_> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// Converts a parse error, reporting physical line numbers that count the skipped rows.
+fn parse_error(err: csv::Error, options: &ReadOptions) -> CsvPeekError {
+    match CsvPeekError::from(err) {
+        CsvPeekError::Parse { file, line, message } => CsvPeekError::Parse { file, line: line.map(|l| l + options.skip_rows as u64), message },
+        other => other,
+    }
+}
+
+/// Parses CSV data with the given options. Without an explicit delimiter, it is sniffed from the header line.
+pub fn parse_csv_from_reader<'r, R: Read + 'r>(reader_source: R, options: &ReadOptions, load_records: bool) -> Result<CsvData> {
+    collect_stream(RecordStream::new(reader_source, options)?, load_records)
+}
+
+fn collect_stream(mut stream: RecordStream, load_records: bool) -> Result<CsvData> {
+    let headers = stream.headers().to_vec();
+    if !load_records {
+        return Ok((headers, Vec::new()));
+    }
+    let records = stream.by_ref().collect::<Result<Vec<StringRecord>>>()?;
+    Ok((headers, records))
+}
+
+pub fn load_data_from_csv(filepath: &Path, options: &ReadOptions, load_records: bool) -> Result<CsvData> {
+    collect_stream(open_csv_stream(filepath, options)?, load_records)
+}
+
+/// Opens a CSV file for reading record by record, memory-mapping it if `ReadOptions::mmap` says so.
+/// Files that are not delimited text are converted to CSV text first; see `is_converted`.
+pub fn open_csv_stream(filepath: &Path, options: &ReadOptions) -> Result<RecordStream<'static>> {
+    if is_converted(filepath) {
+        let mut options = options.for_name(&filepath.display().to_string());
+        options.delimiter = Some(b',');
+        options.encoding = None;
+        let converted = if is_workbook(filepath) {
+            sheet_as_csv(filepath, options.sheet.as_deref())
+        } else if is_database(filepath) {
+            query_as_csv(filepath, options.table.as_deref(), options.sqlite_query.as_deref())
+        } else {
+            json_as_csv(filepath)
+        };
+        return converted.and_then(|csv| RecordStream::new(io::Cursor::new(csv), &options)).map_err(|e| e.in_file(filepath.display()));
+    }
+    let file = fs::File::open(filepath).map_err(|e| CsvPeekError::from(e).in_file(filepath.display()))?;
+    let options = options.for_name(&filepath.display().to_string());
+    let stream = if options.mmap.applies_to(&file) {
+        // Safety: the mapping is only read. If another process truncates the file while it is
+        // mapped, reading past the new end kills the process with SIGBUS; `--mmap never` avoids
+        // that for files that may change, as its help says.
+        let map = unsafe { memmap2::Mmap::map(&file) }.map_err(|e| CsvPeekError::from(e).in_file(filepath.display()))?;
+        match &options.progress {
+            Some(progress) => RecordStream::new(progress.track(&display_path(filepath), io::Cursor::new(map)), &options),
+            None => RecordStream::new(io::Cursor::new(map), &options),
+        }
+    } else {
+        match &options.progress {
+            Some(progress) => RecordStream::new(progress.track(&display_path(filepath), file), &options),
+            None => RecordStream::new(file, &options),
+        }
+    };
+    stream.map_err(|e| e.in_file(filepath.display()))
+}
+
+/// True for local files that are read by converting them to CSV text in memory rather than
+/// parsed as delimited text: workbooks (through `ReadOptions::sheet`), SQLite databases
+/// (through `table` and `sqlite_query`) and JSON files. They cannot be indexed or memory-mapped.
+pub fn is_converted(path: &Path) -> bool {
+    is_workbook(path) || is_database(path) || is_json(path)
+}
+
+pub fn load_data_from_stdin(options: &ReadOptions, load_records: bool) -> Result<CsvData> {
+    collect_stream(open_stdin_stream(options)?, load_records)
+}
+
+/// Opens stdin for reading record by record; the delimiter defaults to a comma.
+pub fn open_stdin_stream(options: &ReadOptions) -> Result<RecordStream<'static>> {
+    let mut options = options.clone();
+    options.delimiter.get_or_insert(b',');
+    RecordStream::new(io::stdin().lock(), &options)
+}
+
+/// True when a `-f` argument is an http:// or https:// URL rather than a local path.
+pub fn is_url(spec: &str) -> bool {
+    let lower = spec.to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// Streams CSV data from an HTTP(S) URL. Each entry of `request_headers` is a
+/// `Name: Value` pair sent with the request, e.g. an `Authorization` token.
+pub fn load_data_from_url(url: &str, request_headers: &[String], options: &ReadOptions, load_records: bool) -> Result<CsvData> {
+    collect_stream(open_url_stream(url, request_headers, options)?, load_records)
+}
+
+/// Fetches a URL for reading record by record as the response body arrives.
+pub fn open_url_stream(url: &str, request_headers: &[String], options: &ReadOptions) -> Result<RecordStream<'static>> {
+    let body = open_url(url, request_headers)?;
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let mut options = options.for_name(path);
+    options.source = Some(url.to_string());
+    RecordStream::new(body, &options).map_err(|e| e.in_file(url))
+}
+
+/// Sends a GET request with the given `Name: Value` headers and returns the response body.
+pub fn open_url(url: &str, request_headers: &[String]) -> Result<impl Read + Send + 'static> {
+    let mut request = ureq::get(url);
+    for header in request_headers {
+        let (name, value) = header.split_once(':').ok_or_else(|| {
+            CsvPeekError::Usage(format!("Invalid header '{}'. Expected NAME: VALUE.", header))
+        })?;
+        request = request.set(name.trim(), value.trim());
+    }
+    let response = request.call().map_err(|e| CsvPeekError::Http { url: url.to_string(), message: e.to_string() })?;
+    Ok(response.into_reader())
+}
+
+/// A single CSV input that can take part in a merge: a file on disk or a member of a zip archive.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CsvSource {
+    File(PathBuf),
+    ZipMember { archive: PathBuf, member: String },
+}
+
+impl CsvSource {
+    pub fn load(&self, options: &ReadOptions, load_records: bool) -> Result<CsvData> {
+        match self {
+            CsvSource::File(path) => load_data_from_csv(path, options, load_records),
+            CsvSource::ZipMember { archive, member } => load_data_from_zip_member(archive, member, options, load_records),
+        }
+    }
+
+    /// Opens the source for reading record by record.
+    pub fn open_stream(&self, options: &ReadOptions) -> Result<RecordStream<'static>> {
+        match self {
+            CsvSource::File(path) => open_csv_stream(path, options),
+            CsvSource::ZipMember { .. } => {
+                let name = self.to_string();
+                RecordStream::new(io::Cursor::new(self.read_bytes()?), &options.for_name(&name)).map_err(|e| e.in_file(name))
+            }
+        }
+    }
+
+    /// Reads the raw, unparsed bytes of the source.
+    pub fn read_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        match self {
+            CsvSource::File(path) => {
+                bytes = fs::read(path).map_err(|e| CsvPeekError::from(e).in_file(path.display()))?;
+            }
+            CsvSource::ZipMember { archive, member } => {
+                let mut zip = open_zip_archive(archive)?;
+                let mut entry = zip.by_name(member).map_err(|e| CsvPeekError::Archive {
+                    path: archive.to_path_buf(),
+                    message: format!("could not open member '{}': {}", member, e),
+                })?;
+                entry.read_to_end(&mut bytes).map_err(|e| CsvPeekError::from(e).in_file(self))?;
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+impl fmt::Display for CsvSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvSource::File(path) => write!(f, "{}", display_path(path)),
+            CsvSource::ZipMember { archive, member } => write!(f, "{}::{}", display_path(archive), member),
+        }
+    }
+}
+
+/// Splits a `-f` argument into a zip archive path and an optional member name.
+/// Accepts `archive.zip` (all CSV members) and `archive.zip::inner/file.csv` (one member).
+pub fn split_zip_spec(path: &Path) -> Option<(PathBuf, Option<String>)> {
+    let spec = path.to_string_lossy();
+    if let Some((archive, member)) = spec.split_once("::") {
+        if archive.to_ascii_lowercase().ends_with(".zip") {
+            return Some((PathBuf::from(archive), Some(member.to_string())));
+        }
+    }
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip")) {
+        return Some((path.to_path_buf(), None));
+    }
+    None
+}
+
+fn open_zip_archive(archive_path: &Path) -> Result<zip::ZipArchive<fs::File>> {
+    let file = fs::File::open(archive_path).map_err(|e| CsvPeekError::from(e).in_file(archive_path.display()))?;
+    zip::ZipArchive::new(file).map_err(|e| CsvPeekError::Archive { path: archive_path.to_path_buf(), message: e.to_string() })
+}
+
+pub fn load_data_from_zip_member(archive_path: &Path, member: &str, options: &ReadOptions, load_records: bool) -> Result<CsvData> {
+    let mut archive = open_zip_archive(archive_path)?;
+    let entry = archive.by_name(member).map_err(|e| CsvPeekError::Archive {
+        path: archive_path.to_path_buf(),
+        message: format!("could not open member '{}': {}", member, e),
+    })?;
+    let name = format!("{}::{}", archive_path.display(), member);
+    parse_csv_from_reader(entry, &options.for_name(&name), load_records).map_err(|e| e.in_file(name))
+}
+
+/// Lists the CSV members of a zip archive, sorted by name.
+pub fn zip_csv_members(archive_path: &Path) -> Result<Vec<CsvSource>> {
+    let archive = open_zip_archive(archive_path)?;
+    let mut members: Vec<String> = archive
+        .file_names()
+        .filter(|name| !name.ends_with('/') && name.to_ascii_lowercase().ends_with(".csv"))
+        .map(String::from)
+        .collect();
+    members.sort();
+    Ok(members
+        .into_iter()
+        .map(|member| CsvSource::ZipMember { archive: archive_path.to_path_buf(), member })
+        .collect())
+}
+
+pub fn load_data_from_zip(
+    archive_path: &Path,
+    read: &ReadOptions,
+    merge: &MergeOptions,
+    be_quiet: bool,
+    load_records: bool,
+) -> Result<CsvData> {
+    let sources = zip_csv_members(archive_path)?;
+    let origin = format!("zip archive '{}'", display_path(archive_path));
+    if sources.is_empty() {
+        return Err(CsvPeekError::NoSources { origin });
+    }
+    merge_csv_sources(&sources, None, &origin, read, merge, be_quiet, load_records)
+}
+
+/// Describes a list of `-f` inputs for messages, e.g. `files 'a.csv', 'b.csv'`.
+pub fn describe_files(paths: &[PathBuf]) -> String {
+    let names: Vec<String> = paths.iter().map(|p| format!("'{}'", display_path(p))).collect();
+    format!("files {}", names.join(", "))
+}
+
+/// Merges several `-f` inputs like a directory. Zip archives contribute all of their CSV members;
+/// URLs and stdin cannot take part in a merge.
+pub fn load_data_from_files(
+    paths: &[PathBuf],
+    read: &ReadOptions,
+    merge: &MergeOptions,
+    be_quiet: bool,
+    load_records: bool,
+) -> Result<CsvData> {
+    let sources = file_sources(paths)?;
+    merge_csv_sources(&sources, None, &describe_files(paths), read, merge, be_quiet, load_records)
+}
+
+/// Expands several `-f` inputs into merge sources. Zip archives contribute all of their CSV
+/// members; URLs and stdin are rejected because they cannot take part in a merge.
+pub fn file_sources(paths: &[PathBuf]) -> Result<Vec<CsvSource>> {
+    let mut sources = Vec::new();
+    for path in paths {
+        let spec = path.to_string_lossy();
+        if spec == "-" || is_url(&spec) {
+            return Err(CsvPeekError::Usage(format!("'{}' cannot be merged with other files; only local files and zip archives can be combined.", spec)));
+        }
+        match split_zip_spec(path) {
+            Some((archive, Some(member))) => sources.push(CsvSource::ZipMember { archive, member }),
+            Some((archive, None)) => sources.extend(zip_csv_members(&archive)?),
+            None => sources.push(CsvSource::File(path.clone())),
+        }
+    }
+    Ok(sources)
+}
+
+/// How a directory given with `-d` is scanned for CSV files.
+#[derive(Debug, Clone, Default)]
+pub struct DirectoryOptions {
+    /// File (relative to the directory) whose headers define the main headers.
+    pub main_header_file: Option<String>,
+    /// Descend into subdirectories.
+    pub recursive: bool,
+    /// With `recursive`, how many levels of subdirectories to descend; `None` means unlimited.
+    pub max_depth: Option<usize>,
+    /// File extensions (without the dot, case-insensitive) that are merged; empty means just `csv`.
+    pub extensions: Vec<String>,
+    /// Glob patterns a file must match (any of them) to be merged; empty means all files.
+    /// Patterns containing `/` match the path relative to the directory, others the file name.
+    pub include: Vec<String>,
+    /// Glob patterns excluding files from the merge, matched like `include`.
+    pub exclude: Vec<String>,
+}
+
+/// Compiled `--include`/`--exclude` patterns, split by what they are matched against.
+struct FilePatterns {
+    by_name: GlobSet,
+    by_path: GlobSet,
+}
+
+impl FilePatterns {
+    fn new(patterns: &[String]) -> Result<Self> {
+        let mut by_name = GlobSetBuilder::new();
+        let mut by_path = GlobSetBuilder::new();
+        for pattern in patterns {
+            let glob = GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()
+                .map_err(|e| CsvPeekError::Usage(format!("Invalid file pattern '{}': {}", pattern, e)))?;
+            if pattern.contains('/') { by_path.add(glob); } else { by_name.add(glob); }
+        }
+        let build = |builder: GlobSetBuilder| builder.build().map_err(|e| CsvPeekError::Usage(e.to_string()));
+        Ok(FilePatterns { by_name: build(by_name)?, by_path: build(by_path)? })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.by_name.is_empty() && self.by_path.is_empty()
+    }
+
+    fn matches(&self, relative_path: &Path) -> bool {
+        let name_matches = relative_path.file_name().is_some_and(|name| self.by_name.is_match(name));
+        let slash_path = relative_path.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+        name_matches || self.by_path.is_match(slash_path)
+    }
+}
+
+fn has_merged_extension(extension: &std::ffi::OsStr, extensions: &[String]) -> bool {
+    let extension = extension.to_string_lossy();
+    if extensions.is_empty() {
+        return extension.eq_ignore_ascii_case("csv");
+    }
+    extensions.iter().any(|wanted| wanted.trim_start_matches('.').eq_ignore_ascii_case(&extension))
+}
+
+/// Collects the CSV files below `dir_path`, honouring the recursion settings.
+/// Symlinked directories are not followed, so link cycles cannot cause endless recursion.
+fn collect_csv_files(dir_path: &Path, options: &DirectoryOptions, depth: usize, found: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = fs::read_dir(dir_path).map_err(|e| CsvPeekError::from(e).in_file(display_path(dir_path)))?;
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+        if is_dir {
+            if options.recursive && options.max_depth.is_none_or(|max| depth < max) {
+                collect_csv_files(&path, options, depth + 1, found)?;
+            }
+        } else if path.is_file() && path.extension().is_some_and(|ext| has_merged_extension(ext, &options.extensions)) {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+pub fn load_data_from_directory(
+    dir_path: &Path,
+    options: &DirectoryOptions,
+    read: &ReadOptions,
+    merge: &MergeOptions,
+    be_quiet: bool,
+    load_records: bool,
+) -> Result<CsvData> {
+    let origin = format!("directory '{}'", display_path(dir_path));
+    let sources = directory_sources(dir_path, options)?;
+    if sources.is_empty() {
+        return Err(CsvPeekError::NoSources { origin });
+    }
+
+    let main_header_source = if let Some(filename_str) = &options.main_header_file {
+        let main_header_source = CsvSource::File(dir_path.join(filename_str));
+        if !sources.contains(&main_header_source) {
+             return Err(CsvPeekError::Usage(format!("Specified main header file '{}' not found or does not have a merged extension in directory '{}'.", filename_str, dir_path.display())));
+        }
+        Some(main_header_source)
+    } else {
+        None
+    };
+
+    merge_csv_sources(&sources, main_header_source.as_ref(), &origin, read, merge, be_quiet, load_records)
+}
+
+/// Lists the files of a directory that take part in a merge, honouring recursion,
+/// extensions and include/exclude patterns, sorted by path.
+pub fn directory_sources(dir_path: &Path, options: &DirectoryOptions) -> Result<Vec<CsvSource>> {
+    let mut csv_file_paths: Vec<PathBuf> = Vec::new();
+    collect_csv_files(dir_path, options, 0, &mut csv_file_paths)?;
+    let include = FilePatterns::new(&options.include)?;
+    let exclude = FilePatterns::new(&options.exclude)?;
+    csv_file_paths.retain(|path| {
+        let relative = path.strip_prefix(dir_path).unwrap_or(path);
+        (include.is_empty() || include.matches(relative)) && !exclude.matches(relative)
+    });
+    csv_file_paths.sort();
+    Ok(csv_file_paths.into_iter().map(CsvSource::File).collect())
+}
+
+/// What `--trim` strips surrounding whitespace from.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum TrimMode {
+    /// Header names and field values.
+    All,
+    /// Field values only.
+    Fields,
+    /// Header names only.
+    Headers,
+}
+
+impl From<TrimMode> for csv::Trim {
+    fn from(mode: TrimMode) -> Self {
+        match mode {
+            TrimMode::All => csv::Trim::All,
+            TrimMode::Fields => csv::Trim::Fields,
+            TrimMode::Headers => csv::Trim::Headers,
+        }
+    }
+}
+
+/// Files at least this large are memory-mapped with `MmapMode::Auto`.
+pub const MMAP_AUTO_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// When `--mmap` memory-maps local files instead of reading them.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum MmapMode {
+    /// Map regular files of 64 MiB or more.
+    #[default]
+    Auto,
+    /// Map every regular file.
+    Always,
+    /// Always read files.
+    Never,
+}
+
+impl MmapMode {
+    /// Whether `file` should be mapped. Pipes and other special files are always read.
+    pub fn applies_to(self, file: &fs::File) -> bool {
+        let Ok(metadata) = file.metadata() else {
+            return false;
+        };
+        match self {
+            MmapMode::Auto => metadata.is_file() && metadata.len() >= MMAP_AUTO_THRESHOLD,
+            MmapMode::Always => metadata.is_file() && metadata.len() > 0,
+            MmapMode::Never => false,
+        }
+    }
+}
+
+/// How sources with differing headers are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum MergeStrategy {
+    /// Only merge sources whose headers match the main headers; skip the others with a warning.
+    #[default]
+    Match,
+    /// Merge every source into the union of all columns, leaving cells a source lacks empty.
+    Union,
+}
+
+/// Which source's record `--dedupe-on` keeps when several share a key.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum KeepPolicy {
+    /// The source that sorts last by name, e.g. the newest of date-stamped daily files.
+    #[default]
+    LatestFile,
+    /// The most recently modified file.
+    LatestMtime,
+    /// The source that sorts first by name.
+    FirstFile,
+}
+
+/// How the sources of a directory, zip archive or multi-file input are combined.
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptions {
+    pub strategy: MergeStrategy,
+    /// Accept sources whose headers differ from the main headers only in their labels
+    /// (same number of columns), aligning their columns by index.
+    pub by_position: bool,
+    /// Name of a column to append that holds the source each record was read from.
+    pub source_column: Option<String>,
+    /// Header renames applied to every source before headers are compared, as
+    /// `(name in the file, main header)` pairs; names match case-insensitively.
+    pub header_map: Vec<(String, String)>,
+    /// Abort on the first unreadable source, parse error or header mismatch instead of
+    /// warning and skipping the source.
+    pub strict: bool,
+    /// Key columns: keep only one merged record per key, from the source `keep` prefers.
+    pub dedupe_on: Vec<String>,
+    pub keep: KeepPolicy,
+}
+
+impl MergeOptions {
+    pub(crate) fn rename_headers(&self, headers: Vec<String>) -> Vec<String> {
+        headers.into_iter()
+            .map(|header| match self.header_map.iter().find(|(from, _)| eq_ignore_case(from, &header)) {
+                Some((_, to)) => to.clone(),
+                None => header,
+            })
+            .collect()
+    }
+}
+
+/// Reads a `--header-map` TOML file of `"name in file" = "main header"` entries.
+pub fn load_header_map(path: &Path) -> Result<Vec<(String, String)>> {
+    let text = fs::read_to_string(path).map_err(|e| CsvPeekError::from(e).in_file(path.display()))?;
+    let table: toml::Table = text.parse().map_err(|e: toml::de::Error| {
+        CsvPeekError::Usage(format!("Invalid header map '{}': {}", path.display(), e.message()))
+    })?;
+    table.into_iter()
+        .map(|(from, to)| match to {
+            toml::Value::String(to) => Ok((from, to)),
+            other => Err(CsvPeekError::Usage(format!(
+                "Invalid header map '{}': '{}' must map to a column name, found {}.", path.display(), from, other.type_str()
+            ))),
+        })
+        .collect()
+}
+
+/// Merges the records of several CSV sources.
+/// The main headers come from `main_header_source` if given, otherwise from the first readable source.
+/// With `MergeStrategy::Match` sources whose headers differ are skipped with a warning; with
+/// `MergeStrategy::Union` their new columns are appended to the merged headers instead.
+/// Headers are renamed through `MergeOptions::header_map` before any comparison.
+/// With `MergeOptions::strict`, problems that would otherwise skip a source are returned as errors.
+/// `MergeOptions::by_position` takes precedence for sources with the same number of columns.
+pub fn merge_csv_sources(
+    sources: &[CsvSource],
+    main_header_source: Option<&CsvSource>,
+    origin: &str,
+    read: &ReadOptions,
+    merge: &MergeOptions,
+    be_quiet: bool,
+    load_records: bool,
+) -> Result<CsvData> {
+    let load = |source: &CsvSource, load_records: bool| {
+        source.load(read, load_records).map(|(headers, records)| (merge.rename_headers(headers), records))
+    };
+    let mut main_headers_option: Option<Vec<String>> = None;
+
+    if let Some(source) = main_header_source {
+        if !be_quiet { crate::outln!("Attempting to set main headers from specified file: {}", source); }
+        match load(source, false) {
+            Ok((headers_from_file, _)) => {
+                main_headers_option = Some(headers_from_file);
+            }
+            Err(e) => {
+                return Err(CsvPeekError::Usage(format!("Failed to load headers from specified main header file '{}': {}", source, e)));
+            }
+        }
+    } else {
+        for source in sources {
+            if !be_quiet { crate::outln!("Attempting to determine main headers from: {}", source); }
+            match load(source, false) {
+                Ok((headers_from_file, _)) => {
+                    main_headers_option = Some(headers_from_file);
+                    break;
+                }
+                Err(e) if merge.strict => return Err(strict_load_error(e, source)),
+                Err(CsvPeekError::MissingHeaders) => {
+                    crate::log_warn!("File '{}' has no headers. Trying next file for main headers.", source);
+                }
+                Err(e) => {
+                    crate::log_warn!("Could not read file '{}' to determine main headers: {}. Trying next.", source, e);
+                }
+            }
+        }
+    }
+
+    let final_main_headers = main_headers_option.ok_or_else(|| CsvPeekError::SchemaMismatch { origin: origin.to_string(), main_headers: None })?;
+
+    let mut merged_headers = final_main_headers;
+    let mut combined_records: Vec<StringRecord> = Vec::new();
+    // The index in `sources` of each combined record's source.
+    let mut record_sources: Vec<usize> = Vec::new();
+    let mut files_contributed_records = 0;
+    let report = load_records && !be_quiet;
+
+    for (source_idx, source) in sources.iter().enumerate() {
+        if report { crate::outln!("Processing file for data: {}", source); }
+        let (current_headers, records_chunk) = match load(source, load_records) {
+            Ok(data) => data,
+            Err(e) if merge.strict => return Err(strict_load_error(e, source)),
+            Err(e) if read.problems.is_some() && load_records => {
+                if let Some(problems) = &read.problems {
+                    problems.push(strict_load_error(e, source));
+                }
+                continue;
+            }
+            Err(e) => {
+                if load_records {
+                    crate::log_warn!("Could not read or parse CSV file '{}' for records: {}. Skipping.", source, e);
+                }
+                continue;
+            }
+        };
+
+        if current_headers == merged_headers {
+            combined_records.extend(records_chunk);
+        } else if merge.by_position && current_headers.len() == merged_headers.len() {
+            if report {
+                crate::outln!("Mapping columns by position for file with renamed headers: {}", source);
+            }
+            combined_records.extend(records_chunk);
+        } else if merge.strategy == MergeStrategy::Union {
+            let added = extend_union(&mut merged_headers, &current_headers);
+            if report && !added.is_empty() {
+                crate::outln!("Adding column(s) {:?} from file: {}", added, source);
+            }
+            let positions: Vec<Option<usize>> = merged_headers.iter()
+                .map(|h| current_headers.iter().position(|c| c == h))
+                .collect();
+            combined_records.extend(records_chunk.iter().map(|record| {
+                positions.iter().map(|pos| pos.and_then(|i| record.get(i)).unwrap_or("")).collect::<StringRecord>()
+            }));
+        } else if merge.strict {
+            return Err(CsvPeekError::HeaderMismatch { file: source.to_string(), expected: merged_headers, found: current_headers });
+        } else {
+            if load_records {
+                crate::log_warn!("Headers in file '{}' do not match main headers. Skipping records from this file.", source);
+            }
+            continue;
+        }
+        record_sources.resize(combined_records.len(), source_idx);
+        files_contributed_records += 1;
+    }
+
+    if files_contributed_records == 0 {
+        return Err(CsvPeekError::SchemaMismatch { origin: origin.to_string(), main_headers: Some(merged_headers) });
+    }
+
+    // Records merged before a later file added columns are padded with empty cells.
+    for record in &mut combined_records {
+        while record.len() < merged_headers.len() {
+            record.push_field("");
+        }
+    }
+
+    if !merge.dedupe_on.is_empty() {
+        let keys = merge.dedupe_on.iter()
+            .map(|column| find_column(&merged_headers, column, "Dedupe"))
+            .collect::<Result<Vec<usize>>>()?;
+        let before = combined_records.len();
+        (combined_records, record_sources) = dedupe_records(combined_records, record_sources, &keys, &source_ranks(sources, merge.keep), merge.keep);
+        if report {
+            crate::outln!("Dropped {} duplicate record(s) on {}.", before - combined_records.len(), merge.dedupe_on.join(", "));
+        }
+    }
+
+    if let Some(name) = &merge.source_column {
+        if merged_headers.iter().any(|h| eq_ignore_case(h, name)) {
+            return Err(CsvPeekError::Usage(format!("Cannot add source column '{}': the CSV data already has a column with that name.", name)));
+        }
+        merged_headers.push(name.clone());
+        let names: Vec<String> = sources.iter().map(CsvSource::to_string).collect();
+        for (record, &source_idx) in combined_records.iter_mut().zip(&record_sources) {
+            record.push_field(&names[source_idx]);
+        }
+    }
+
+    Ok((merged_headers, combined_records))
+}
+
+/// How strongly `keep` prefers each source: the record of the highest-ranked source wins.
+/// Sources without a modification time (zip members) rank by name under `LatestMtime`.
+fn source_ranks(sources: &[CsvSource], keep: KeepPolicy) -> Vec<(Option<SystemTime>, usize)> {
+    sources.iter().enumerate()
+        .map(|(idx, source)| match (keep, source) {
+            (KeepPolicy::LatestFile, _) => (None, idx),
+            (KeepPolicy::FirstFile, _) => (None, sources.len() - idx),
+            (KeepPolicy::LatestMtime, CsvSource::File(path)) => (fs::metadata(path).and_then(|m| m.modified()).ok(), idx),
+            (KeepPolicy::LatestMtime, CsvSource::ZipMember { .. }) => (None, idx),
+        })
+        .collect()
+}
+
+/// Keeps one record per value of the `keys` columns (compared trimmed): the one from the
+/// highest-ranked source, and within that source the last one, or the first with
+/// `KeepPolicy::FirstFile`. The kept records stay in merge order.
+fn dedupe_records(
+    records: Vec<StringRecord>,
+    record_sources: Vec<usize>,
+    keys: &[usize],
+    ranks: &[(Option<SystemTime>, usize)],
+    keep: KeepPolicy,
+) -> (Vec<StringRecord>, Vec<usize>) {
+    let mut winners: HashMap<Vec<&str>, usize> = HashMap::new();
+    for (idx, record) in records.iter().enumerate() {
+        let key: Vec<&str> = keys.iter().map(|&k| record.get(k).unwrap_or("").trim()).collect();
+        let rank = &ranks[record_sources[idx]];
+        winners.entry(key)
+            .and_modify(|winner| {
+                let best = &ranks[record_sources[*winner]];
+                if rank > best || (rank == best && keep != KeepPolicy::FirstFile) {
+                    *winner = idx;
+                }
+            })
+            .or_insert(idx);
+    }
+    let mut kept: Vec<bool> = vec![false; records.len()];
+    for &idx in winners.values() {
+        kept[idx] = true;
+    }
+    records.into_iter().zip(record_sources).zip(kept)
+        .filter_map(|(pair, kept)| kept.then_some(pair))
+        .unzip()
+}
+
+/// Names the source in a load error that aborts a `--strict` merge or is logged as a problem.
+fn strict_load_error(error: CsvPeekError, source: &CsvSource) -> CsvPeekError {
+    match error {
+        CsvPeekError::MissingHeaders => CsvPeekError::Parse { file: Some(source.to_string()), line: None, message: "missing header row".to_string() },
+        other => other.in_file(source),
+    }
+}
+
+/// Appends the columns of `current` that `merged` does not have yet, returning the added names.
+pub(crate) fn extend_union(merged: &mut Vec<String>, current: &[String]) -> Vec<String> {
+    let added: Vec<String> = current.iter().filter(|h| !merged.contains(h)).cloned().collect();
+    merged.extend(added.iter().cloned());
+    added
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_progress_tracks_bytes_and_files() {
+        let progress = ReadProgress::with_target(9, 2, ProgressDrawTarget::hidden());
+        let mut text = String::new();
+        progress.track("a.csv", "id\n1\n".as_bytes()).read_to_string(&mut text).unwrap();
+        assert_eq!(progress.bar.position(), 5);
+        assert_eq!(progress.bar.message(), "file 1/2: a.csv");
+
+        progress.clone().track("b.csv", "id\n".as_bytes()).read_to_string(&mut text).unwrap();
+        assert_eq!(text, "id\n1\nid\n");
+        assert_eq!(progress.bar.position(), 8);
+        assert_eq!(progress.bar.message(), "file 2/2: b.csv");
+
+        let single = ReadProgress::with_target(3, 1, ProgressDrawTarget::hidden());
+        single.track("c.csv", "id\n".as_bytes()).read_to_string(&mut text).unwrap();
+        assert_eq!(single.bar.message(), "c.csv");
+    }
+}
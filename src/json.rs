@@ -0,0 +1,98 @@
+//! JSON input: an array of objects (`.json`) or one object per line (`.ndjson`, `.jsonl`) is
+//! handed to the CSV parser as CSV text. Every object is a row and the headers are the union
+//! of the keys, in order of first appearance.
+
+use crate::{CsvPeekError, Result};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+
+/// True when a path names a JSON or JSON Lines file by its extension.
+pub fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ["json", "ndjson", "jsonl"].iter().any(|json| ext.eq_ignore_ascii_case(json)))
+}
+
+/// Reads the objects of the JSON file at `path` and renders them as CSV text. Missing keys and
+/// nulls are empty cells, and nested arrays and objects are kept as compact JSON.
+pub fn json_as_csv(path: &Path) -> Result<Vec<u8>> {
+    let text = fs::read_to_string(path).map_err(|e| CsvPeekError::from(e).in_file(path.display()))?;
+    let lines = path.extension().is_some_and(|ext| !ext.eq_ignore_ascii_case("json"));
+    let objects = if lines { parse_lines(&text)? } else { parse_document(&text)? };
+    objects_as_csv(&objects)
+}
+
+fn parse_error(line: Option<u64>, message: String) -> CsvPeekError {
+    CsvPeekError::Parse { file: None, line, message }
+}
+
+/// The objects of a JSON document: an array of objects, or a single object.
+fn parse_document(text: &str) -> Result<Vec<Map<String, Value>>> {
+    let document: Value = serde_json::from_str(text).map_err(|e| parse_error(Some(e.line() as u64), format!("invalid JSON: {}", e)))?;
+    let values = match document {
+        Value::Array(values) => values,
+        object @ Value::Object(_) => vec![object],
+        _ => return Err(parse_error(None, "expected an array of objects".to_string())),
+    };
+    values.into_iter()
+        .enumerate()
+        .map(|(index, value)| match value {
+            Value::Object(object) => Ok(object),
+            _ => Err(parse_error(None, format!("element {} of the array is not an object", index + 1))),
+        })
+        .collect()
+}
+
+/// The objects of a JSON Lines file, one per non-blank line.
+fn parse_lines(text: &str) -> Result<Vec<Map<String, Value>>> {
+    (1..).zip(text.lines())
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| match serde_json::from_str(line) {
+            Ok(Value::Object(object)) => Ok(object),
+            Ok(_) => Err(parse_error(Some(number), "expected a JSON object".to_string())),
+            Err(e) => Err(parse_error(Some(number), format!("invalid JSON: {}", e))),
+        })
+        .collect()
+}
+
+fn objects_as_csv(objects: &[Map<String, Value>]) -> Result<Vec<u8>> {
+    let mut headers: Vec<&str> = Vec::new();
+    for key in objects.iter().flat_map(|object| object.keys()) {
+        if !headers.contains(&key.as_str()) {
+            headers.push(key);
+        }
+    }
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    if !headers.is_empty() {
+        writer.write_record(&headers)?;
+    }
+    for object in objects {
+        writer.write_record(headers.iter().map(|key| object.get(*key).map_or(String::new(), cell_text)))?;
+    }
+    writer.into_inner().map_err(|e| CsvPeekError::from(e.into_error()))
+}
+
+fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_objects_become_rows() {
+        let objects = parse_lines("{\"level\":\"info\",\"ms\":12}\n\n{\"level\":\"error\",\"tags\":[\"db\"],\"ok\":false,\"ms\":null}\n").unwrap();
+        let csv = String::from_utf8(objects_as_csv(&objects).unwrap()).unwrap();
+        assert_eq!(csv, "level,ms,tags,ok\ninfo,12,,\nerror,,\"[\"\"db\"\"]\",false\n");
+
+        let err = parse_lines("{\"a\":1}\n[1]\n").unwrap_err();
+        assert_eq!(err.to_string(), "CSV parse error at line 2: expected a JSON object");
+        assert_eq!(parse_document("[{\"a\":1},{\"b\":2}]").unwrap().len(), 2);
+        assert!(parse_document("[1, 2]").is_err());
+    }
+}
@@ -0,0 +1,65 @@
+//! Library side of csvpeek-rs: loading and merging CSV sources and evaluating filters.
+//! The `csvpeek-rs` binary is a thin command-line front end over these modules.
+
+pub mod config;
+pub mod drift;
+pub mod error;
+pub mod filter;
+pub mod index;
+pub mod input;
+pub mod json;
+pub mod log;
+pub mod output;
+pub mod params;
+pub mod schema;
+pub mod spill;
+pub mod sqlite;
+pub mod stats;
+pub mod transform;
+pub mod validate;
+pub mod workbook;
+
+pub use error::{CsvPeekError, Result};
+
+use caseless::Caseless;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ASCII_CASE: AtomicBool = AtomicBool::new(false);
+
+/// Makes case-insensitive comparisons ignore the case of ASCII letters only (see `--ascii-case`).
+pub fn set_ascii_case(enabled: bool) {
+    ASCII_CASE.store(enabled, Ordering::Relaxed);
+}
+
+/// Applies full Unicode case folding to `text`, as [`eq_ignore_case`] does: "Straße" becomes
+/// "strasse" and a final "ς" becomes "σ".
+pub fn fold_case(text: &str) -> String {
+    caseless::default_case_fold_str(text)
+}
+
+/// Compares two strings ignoring case, so that "GÖTEBORG" equals "Göteborg" and "STRASSE"
+/// equals "Straße". With `--ascii-case` only the case of ASCII letters is ignored.
+pub fn eq_ignore_case(a: &str, b: &str) -> bool {
+    if ASCII_CASE.load(Ordering::Relaxed) || (a.is_ascii() && b.is_ascii()) {
+        return a.eq_ignore_ascii_case(b);
+    }
+    a.chars().default_case_fold().eq(b.chars().default_case_fold())
+}
+
+/// True when `text` contains `term`, ignoring case the same way as [`eq_ignore_case`].
+pub fn contains_ignore_case(text: &str, term: &str) -> bool {
+    if ASCII_CASE.load(Ordering::Relaxed) || (text.is_ascii() && term.is_ascii()) {
+        return text.to_ascii_lowercase().contains(&term.to_ascii_lowercase());
+    }
+    fold_case(text).contains(&fold_case(term))
+}
+
+/// Finds the index of a column by case-insensitive name. `role` describes what the column
+/// is used for (e.g. "Filter") and ends up in the error message when it is missing.
+pub fn find_column(headers: &[String], name: &str, role: &str) -> Result<usize> {
+    headers.iter().position(|h| eq_ignore_case(h, name)).ok_or_else(|| CsvPeekError::ColumnNotFound {
+        role: role.to_string(),
+        column: name.to_string(),
+        headers: headers.to_vec(),
+    })
+}
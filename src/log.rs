@@ -0,0 +1,128 @@
+//! Diagnostics on stderr, for `--log-level` and `--log-format`. Warnings, errors and `--verbose`
+//! reports go through here so that stdout only carries the output that was asked for, and a job
+//! runner can collect the diagnostics as JSON lines.
+
+use chrono::{DateTime, SecondsFormat};
+use clap::ValueEnum;
+use serde_json::json;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How much is logged; each level includes the ones above it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, ValueEnum)]
+pub enum LogLevel {
+    /// Only errors that stop the command.
+    Error,
+    /// Also warnings, e.g. skipped files or rows.
+    #[default]
+    Warn,
+    /// Also timings and which index was used.
+    Info,
+    /// Also how much was read from each input.
+    Debug,
+}
+
+impl LogLevel {
+    fn name(self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+        }
+    }
+
+    /// Prefix of a text log line, e.g. "Warning: ".
+    fn prefix(self) -> &'static str {
+        match self {
+            LogLevel::Error => "Error: ",
+            LogLevel::Warn => "Warning: ",
+            LogLevel::Info | LogLevel::Debug => "Verbose: ",
+        }
+    }
+}
+
+/// How log lines are written.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum LogFormat {
+    /// "Warning: ..." lines for people.
+    #[default]
+    Text,
+    /// One JSON object per line with "time", "level" and "message".
+    Json,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Warn as u8);
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Sets the level and format for the rest of the process.
+pub fn init(level: LogLevel, format: LogFormat) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+    JSON.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+/// True when messages at `level` are written.
+pub fn enabled(level: LogLevel) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Writes a message at `level` to stderr, if that level is enabled. Use the `log_error!`,
+/// `log_warn!`, `log_info!` and `log_debug!` macros rather than calling this directly.
+pub fn log(level: LogLevel, message: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    if !JSON.load(Ordering::Relaxed) {
+        eprintln!("{}{}", level.prefix(), message);
+        return;
+    }
+    write_json(level, message);
+}
+
+/// Writes a result that is kept off stdout, such as the `--footer` summary of raw output, to
+/// stderr. It is shown as is unless the level is "error", and under `--log-format json` as a
+/// record with level "info" so that the stream stays JSON lines.
+pub fn note(message: fmt::Arguments) {
+    if !enabled(LogLevel::Warn) {
+        return;
+    }
+    if !JSON.load(Ordering::Relaxed) {
+        eprintln!("{}", message);
+        return;
+    }
+    write_json(LogLevel::Info, message);
+}
+
+fn write_json(level: LogLevel, message: fmt::Arguments) {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .and_then(|elapsed| DateTime::from_timestamp(elapsed.as_secs() as i64, elapsed.subsec_nanos()))
+        .map(|now| now.to_rfc3339_opts(SecondsFormat::Millis, true));
+    eprintln!("{}", json!({ "time": time, "level": level.name(), "message": message.to_string() }));
+}
+
+/// Logs an error; see [`log::log`](crate::log::log).
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => { $crate::log::log($crate::log::LogLevel::Error, format_args!($($arg)*)) };
+}
+
+/// Logs a warning; see [`log::log`](crate::log::log).
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => { $crate::log::log($crate::log::LogLevel::Warn, format_args!($($arg)*)) };
+}
+
+/// Logs an informational message; see [`log::log`](crate::log::log).
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => { $crate::log::log($crate::log::LogLevel::Info, format_args!($($arg)*)) };
+}
+
+/// Logs a debugging message; see [`log::log`](crate::log::log).
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => { $crate::log::log($crate::log::LogLevel::Debug, format_args!($($arg)*)) };
+}
@@ -1,62 +1,50 @@
-use clap::{CommandFactory, Parser};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use csvpeek_rs::config::{default_config_path, load_config};
+use csvpeek_rs::drift::{compare_headers, schema_report, ColumnChanges, MergeFate};
+use csvpeek_rs::filter::{self, mixed_numeric_cells, parse_date, parse_date_bound, parse_number, parse_filter_arg, parse_where_hash_arg, DateRange, HashSample, Operator, ResolvedFilter};
+use csvpeek_rs::index::{build_index, index_path, load_index};
+use csvpeek_rs::input::{
+    describe_files, directory_sources, display_path, file_sources, is_converted, is_url, load_data_from_csv, load_data_from_directory, load_data_from_files,
+    load_data_from_stdin, load_data_from_url, load_data_from_zip, load_data_from_zip_member, load_header_map, open_csv_stream, open_stdin_stream,
+    open_url, open_url_stream,
+    parse_comment_char_arg, parse_delimiter_arg, parse_encoding_arg, set_fixture_paths, sniff_header_delimiter, split_zip_spec, CsvData, DirectoryOptions, MergeOptions,
+    CsvSource, KeepPolicy, MergeStrategy, MmapMode, ProblemLog, ReadOptions, ReadProgress, RecordStream, TrimMode,
+};
+use csvpeek_rs::validate::{validate_csv, Finding, ValidateOptions};
+use csvpeek_rs::output::{
+    self, escape_cell, format_decimal, parse_max_col_width_arg, parse_output_delimiter_arg, parse_quote_char_arg, parse_round_arg, ColorChoice, CsvStyle, EscapeChoice, Layout,
+    MaxColWidth, OutputFormat, Paint, QuoteStyle,
+};
+use csvpeek_rs::params::{parse_param_arg, substitute_params};
+use csvpeek_rs::schema::{cast_record, duplicate_keys, infer_schema, orphaned_keys, parse_cast_arg, parse_foreign_key_arg, profile_columns, ColumnType, ForeignKey};
+use csvpeek_rs::spill::{format_size, parse_size_arg, SpillBuffer};
+use csvpeek_rs::sqlite::{write_inserts, write_table};
+use csvpeek_rs::stats::{
+    correlate, crosstab, group_records, parse_aggregate, parse_aggregate_fn, parse_assertion, parse_corr_arg, parse_crosstab_arg, parse_freq_arg,
+    parse_group_by_arg, histogram, percent, share_of_total, sparkline, timeline, value_counts, Aggregate, AggregateFn, Assertion, CorrColumns, Correlation, CorrelationMethod, FreqTable, GroupBy,
+    ResolvedAggregate, Timeline, TopN, HISTOGRAM_BINS, MAX_TIMELINE_PERIODS,
+};
+use csvpeek_rs::workbook::write_xlsx;
+use csvpeek_rs::transform::{
+    concat_columns, cumulative_sum, explode, fill_record, hash_rows, join, melt, parse_concat_arg, parse_cumulative_arg, parse_fill_arg, parse_join_key,
+    parse_replace_arg, parse_rolling_arg, parse_split_on_arg, rolling_aggregates, Concat, Cumulative, Replacement, Rolling,
+};
+use csvpeek_rs::log::{self, LogFormat, LogLevel};
+use csvpeek_rs::{contains_ignore_case, eq_ignore_case, find_column, log_error, log_info, log_warn, outln, CsvPeekError, Result};
+use encoding_rs::Encoding;
+use rand::rngs::StdRng;
 use rand::seq::IndexedRandom;
-use std::error::Error;
-use std::fs;
-use std::io::{self, IsTerminal, Read};
-use std::path::PathBuf;
-use std::fmt;
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum Operator {
-    Eq, NotEq, Lt, Gt, LtEq, GtEq,
-}
-
-impl fmt::Display for Operator {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Operator::Eq => write!(f, "="),
-            Operator::NotEq => write!(f, "!="),
-            Operator::Lt => write!(f, "<"),
-            Operator::Gt => write!(f, ">"),
-            Operator::LtEq => write!(f, "<="),
-            Operator::GtEq => write!(f, ">="),
-        }
-    }
-}
-
-fn parse_filter_arg(s: &str) -> Result<(String, Operator, String), String> {
-    let (key_str_full, op, val_str_full) = if let Some((k, v)) = s.split_once("!=") {
-        (k, Operator::NotEq, v)
-    } else if let Some((k, v)) = s.split_once(">=") {
-        (k, Operator::GtEq, v)
-    } else if let Some((k, v)) = s.split_once("<=") {
-        (k, Operator::LtEq, v)
-    } else if let Some((k, v)) = s.split_once('=') {
-        (k, Operator::Eq, v)
-    } else if let Some((k, v)) = s.split_once('>') {
-        (k, Operator::Gt, v)
-    } else if let Some((k, v)) = s.split_once('<') {
-        (k, Operator::Lt, v)
-    } else {
-        return Err(format!(
-            "Invalid filter format: Operator (e.g., =, !=, >, <, >=, <=) missing or unrecognized in '{}'. Expected COLUMN<OP>VALUE.", s
-        ));
-    };
-
-    let key = key_str_full.trim();
-
-    if key.is_empty() {
-        return Err(format!("Invalid filter format: Column name cannot be empty in '{}'. Expected COLUMN<OP>VALUE.", s));
-    }
-
-    if key.chars().any(|c| "<>=!".contains(c)) {
-        return Err(format!(
-            "Invalid filter format: Column name '{}' is malformed (contains operator characters) in filter string '{}'.", key, s
-        ));
-    }
-    
-    Ok((key.to_string(), op, val_str_full.trim().to_string()))
-}
+use rand::SeedableRng;
+use rayon::prelude::*;
+use std::borrow::Cow;
+use std::num::NonZeroUsize;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::OnceLock;
+use std::time::Instant;
 
 const LONG_ABOUT: &str = "csvpeek-rs: Quickly Inspect and Process Your CSV Data from the Command Line
 
@@ -75,17 +63,111 @@ Core Functionalities:
     * Aggregate data from all .csv files within a specified directory 
         using the -d <directory> flag. `csvpeek-rs` intelligently handles 
         header matching, merging data from files with identical headers 
-        and warning about those that differ.
+        and warning about those that differ. Add --recursive (optionally 
+        with --max-depth N) to include CSV files in subdirectories, and 
+        pick files with --include \"sales_*.csv\" / --exclude \"*_backup.csv\". 
+        Merge other exports too with --extensions csv,tsv,txt. With 
+        --merge-strategy union, files with differing headers are merged 
+        into the union of all columns instead of being skipped; with 
+        --map-by-position, files that only rename the headers of the same 
+        layout are aligned by column index, and --header-map map.toml 
+        renames columns (\"Kundnr\" = \"CustomerID\") before headers are 
+        compared. Use --strict to fail instead of skipping mismatched or 
+        unreadable files. --add-source-column [NAME] 
+        appends a column with the file each record came from, and 
+        --with-row-number [NAME] prepends each record's number in its file.
+        When daily files overlap, --dedupe-on ID --keep latest-file keeps 
+        each key once, from the newest file by name (or latest-mtime).
+    * Check how the files of a directory drift apart with -d exports 
+        --schema-report [json]: which file has which columns, what was 
+        added, removed or renamed from file to file, and which files the 
+        merge would skip.
+    * Check referential integrity with --check-fk \"orders.csv:CustomerID 
+        -> customers.csv:ID\": orphaned keys are listed with counts and 
+        examples, and the exit status is 2 if there are any.
+    * Check that one export can be appended to another with -f jan.csv 
+        --compare-headers feb.csv: lists missing, extra, reordered and 
+        case-differing columns and exits with status 1 if there are any.
+    * Find which files of a directory contain matching rows with -d exports 
+        --files-with-matches --filter \"OrderID=1042\", like grep -l.
+    * Read tab- or semicolon-separated data with --delimiter (.tsv files 
+        are read as tab-separated automatically), and legacy exports with 
+        --encoding latin1|windows-1252|utf-16le|... Skip comment and 
+        metadata lines with --comment-char '#', and banners above the 
+        header row with --skip-rows N. Rows with too few or too many fields 
+        can be repaired with --flexible or dropped with --skip-bad-rows, 
+        and --report-errors [json] lists every malformed record with its 
+        file and line number. --validate [json] lints the raw files (RFC 4180 
+        quoting, field counts, duplicate headers, BOMs, mixed line endings) 
+        and exits non-zero when it finds problems, e.g. in CI. 
+        --trim [fields|headers] strips whitespace around values and header 
+        names so padded cells such as \" London \" match filters. 
+        --fill \"Country=Unknown\" (or --fill-all VALUE) replaces empty cells 
+        with a default before filtering, so blanks don't splinter results. 
+        --cast \"Age:int,Price:float,Active:bool\" converts columns on load so 
+        filters compare typed values; cells that cannot be converted are 
+        errors (or drop their row with --skip-bad-rows). 
+        --infer-schema [json] reports each column's type, nullability and 
+        example values (json emits a Frictionless Table Schema); --sample N 
+        limits it to the first N records.
+    * Merge specific files with -f a.csv -f b.csv, using the same 
+        header-matching rules as directory mode.
+    * Read CSV files packed in a zip archive with -f archive.zip (all 
+        CSV members are merged like a directory) or pick a single member 
+        with -f archive.zip::inner/file.csv.
+    * Read Excel and OpenDocument workbooks (.xlsx, .xls, .ods, ...) with 
+        -f report.xlsx; the first sheet is used unless --sheet NAME picks 
+        another.
+    * Read SQLite databases with -f data.db --table orders, or the result 
+        of a query with --sqlite-query \"SELECT ...\".
+    * Read JSON arrays of objects (.json) and JSON Lines logs (.ndjson, 
+        .jsonl): each object is a row and the keys of all objects are the 
+        headers.
+    * Stream CSV data straight from an http(s) URL with -f <url>, adding 
+        request headers such as auth tokens with --header \"NAME: VALUE\".
+    * Local files of 64 MiB or more are memory-mapped rather than read; 
+        control this with --mmap auto|always|never. Use --mmap never for 
+        files that may be truncated while they are read: a mapped file 
+        that shrinks ends the run with a bus error (SIGBUS).
+    * Reading 16 MiB or more of local files shows a progress bar (bytes 
+        read, current file) on stderr when it is a terminal; it is left 
+        out with --raw, --quiet and machine-readable formats.
+    * Cap the memory a listing uses with --max-memory 512M: matching 
+        rows past the budget spill to a temporary file and are streamed 
+        out with --raw or --format csv.
     * If no input is specified and stdin is a terminal, `csvpeek-rs` 
         provides helpful usage instructions and exits.
 
 * Flexible Data Display & Extraction:
-    * List Mode (--list): Display rows from your CSV data. By default, 
-        it shows the first column, but you can specify any column(s) using 
-        --columns \"Column Name\" (or -c \"Col1,Col2\").
-    * Random Row Selection: If no mode (like --list) is specified, 
+    * List Mode (csvpeek-rs list): Display rows from your CSV data. By 
+        default, it shows the first column, but you can specify any 
+        column(s) using --columns \"Column Name\" (or -c \"Col1,Col2\").
+    * Random Row Selection (csvpeek-rs random): If no subcommand is given, 
         `csvpeek-rs` will pick and display a single random row (from the 
         chosen display column(s)), perfect for sampling data.
+    * Headers (csvpeek-rs headers): Print the column names, one per line; 
+        --detailed adds each column's position, type, non-empty and 
+        distinct counts and example values, and --format csv|json prints 
+        either listing for scripts.
+    * Profile (--profile [text|json]): count the distinct values and the 
+        empty cells (and their share) of every column, and flag constant 
+        columns and candidate keys. A sparkline shows the shape of each 
+        numeric column, and with --date-column OrderDate another one shows 
+        the rows per day, week, month or year.
+    * Summaries (csvpeek-rs stats): print one of --profile, --freq, 
+        --crosstab, --group-by or --corr instead of the rows, e.g. 
+        `csvpeek-rs stats --freq Region --filter \"Year>=2020\"`. The same 
+        options still work with list.
+    * Joins (csvpeek-rs join --with customers.csv --on CustomerID=ID): 
+        list the input's rows followed by the other file's columns from 
+        the rows with the same key. Rows without a match are left out, or 
+        kept with empty cells with --left. The list options apply to the 
+        joined rows.
+    * Uniqueness Gate (--assert-unique OrderID): exit with an error and a 
+        sample of the duplicated keys unless the column(s) are unique 
+        across the whole (merged) input.
+    * The --list and --headers flags still work as deprecated aliases of 
+        the list and headers subcommands for one more release.
     * Customizable Display Column(s) (--columns): Choose exactly 
         which column's data you want to see for both listing and random selection.
 
@@ -93,16 +175,159 @@ Core Functionalities:
     * Precisely filter rows using the --filter \"COLUMN<OP>VALUE\" syntax 
         (e.g., \"Age>=30\", \"City!=London\"). OP can be =, !=, >, <, >=, <=. 
         This can be repeated for multiple AND-conditions.
-    * Comparisons are case-insensitive for = and !=. For ordering operators, 
+    * Comparisons are case-insensitive for = and !=, for all scripts (use 
+        --ascii-case to fold ASCII letters only). For ordering operators, 
         numeric comparison is attempted first; if that fails, a lexicographical 
-        string comparison is performed.
+        string comparison is performed. Numbers may use scientific notation 
+        (1.2e9), and a warning names columns where some cells fall back to 
+        text against a numeric filter. Add --natural-sort to compare text 
+        the way humans expect, e.g. item2 < item10 and 1.9.0 < 1.10.0.
+    * European exports: --decimal-comma reads 3,14 and 1.234,56 as numbers 
+        in filters, sorting and aggregates (pair it with --delimiter ';'). 
+        Computed results such as sums are printed with a decimal point.
+    * Take reproducible samples with --where-hash \"id%100<5\": rows are 
+        selected by a stable hash of a column, so everyone running the 
+        command on the same file gets the same subset.
+    * Grep the rows with --search TERM: a row matches when any of its 
+        cells contains the term, ignoring case. Quoted fields are respected 
+        and --columns still picks what is shown.
+    * Keep the rows in a date range with --date-column OrderDate --since 
+        2024-01-01 --until 2024-03-31, or relative to today with --since 
+        \"30 days ago\".
+    * Parameterize queries with --param region=EU --filter \"Region=${region}\". 
+        Undefined parameters fall back to environment variables. References 
+        also work in the columns, path and null_value of the config file.
+    * Stop after the first match with --first, or after N matches with 
+        --max-results N; a single file, URL or stdin is only read that far.
+    * Show the N rows with the largest or smallest values of a column with 
+        --top 10 --by Price or --bottom 10 --by Price, without sorting the 
+        whole input.
+    * Index a column of a big file once with csvpeek-rs index build -f 
+        big.csv --on CustomerID; equality filters on it then read only the 
+        matching rows until the file changes.
     * Allows you to quickly drill down to the data you need.
 
+    * Layouts (--layout auto|table|vertical): rows that are too wide for 
+        the terminal are automatically shown as vertical Header: value blocks.
+        --vertical always shows one block per record, like MySQL's \\G.
+        Line breaks and tabs inside cells are shown as \\n and \\t; add 
+        --escape to do the same in --raw output, or --escape never to keep them.
+
+* Quick Summaries:
+    * Add --footer \"min(Price),max(Price),count()\" in list mode to print 
+        a summary line (count, null_count, min, max, first, last, sum, avg) 
+        below the rows.
+    * Gate CI jobs with checks on the matching rows such as --assert 
+        \"count()>0\", --assert \"max(Price)<=10000\" or --assert 
+        \"null_count(Email)=0\": a failed check exits with status 2.
+    * Cross-tabulate two columns with --crosstab Region,Status: one row per 
+        region, one column per status, counting the matching rows (or 
+        aggregating --value Total --agg sum).
+    * Count values with --freq Status, or two columns at once with 
+        --freq Region,Status: each cell shows its count with the share of 
+        its row and of its column, next to row and column totals.
+    * Group the matching rows with --group-by Region, or by period with 
+        --group-by \"date_trunc(month, OrderDate)\" (day, week, month or 
+        year), counting each group and aggregating --value Total --agg sum.
+    * Show each row's (or --group-by group's) share of a column's total 
+        over the matching rows with --percent-of-total Revenue, rounded to 
+        --percent-precision digits (default 1); with --top 10 --by Revenue 
+        it shows how much the top rows make up.
+    * Correlate numeric columns over the matching rows with --corr 
+        Price,Rating (or a matrix of every numeric column with --corr all), 
+        using --corr-method pearson|spearman.
+    * Build composite keys with --concat \"FullName=First+' '+Last\" and 
+        use the new column in --columns and --filter.
+    * Fingerprint rows with --hash-rows (or --hash-rows ID,Email): a 
+        \"row_hash\" column that is the same for identical rows in any 
+        file or run, for dedup and change detection.
+    * Add a running total with --cumulative \"RunningTotal=sum(Amount)\", 
+        restarting for each --partition-by Account and accumulating in 
+        --order-by Date order, to check balances at a glance.
+    * Smooth daily metrics with --rolling \"Avg7=avg(Value,7)\" --order-by 
+        Date: the average (or sum, min or max) of each row and the 6 
+        before it, which can be repeated for several windows.
+    * Split multi-value cells with --explode Tags --split-on \";\": each 
+        row is repeated once per tag, so filters and --crosstab count 
+        the individual values.
+    * Unpivot wide tables with --melt --id-columns ID,Name: every other 
+        column becomes a row with \"variable\" and \"value\" columns that 
+        filters, --columns and --crosstab work on.
+
+* Batch Queries:
+    * csvpeek-rs batch -f big.csv loads the data once and runs one list 
+        query per stdin line (e.g. --filter \"Year>1990\" -c Title --raw), 
+        printing each result between ==> query N and <== query N markers.
+
 * Unix-Friendly Output:
+    * CSV Output (--format csv): write the displayed columns of the matching 
+        rows as proper CSV, quoted per --quote-style always|necessary|never|
+        non-numeric with --quote-char, ready for loaders such as Redshift.
+        --split-rows 50000 --output-prefix part_ writes them to part_0001.csv, 
+        part_0002.csv, ... instead, each with a header row, and 
+        --split-by Country --output-dir out/ writes one file per country.
+    * Excel Output (--format xlsx --output out.xlsx): write the same rows to 
+        a workbook with a bold header row and columns sized to fit.
+    * SQL Output (--format sql --table target): print INSERT statements for 
+        the same rows to seed test databases, --rows-per-insert N rows per 
+        statement.
+    * LaTeX Output (--format latex): print the same rows as a tabular 
+        environment with special characters escaped, ready for papers.
+    * SQLite Export (--to-sqlite results.db --table peek): write the same 
+        rows to a SQLite table with inferred column types, replacing any 
+        table of that name, for an instantly queryable copy.
+    * Quiet Mode (-q/--quiet): Keep the formatted output but drop 
+        informational messages such as \"Reading CSV file: ...\".
+    * Existence Check (--quiet-exists): Print nothing and only set the exit 
+        status, like grep -q; reading stops at the first matching row.
+    * Row Count (--count-rows): Print only the number of data rows, 
+        counted without decoding fields for near `wc -l` speed.
+    * Verbose Mode (-v/--verbose): Report timings of loading, filtering and 
+        rendering plus rows and bytes read per file on stderr.
+    * Logging (--log-level, --log-format json): Choose which warnings and 
+        reports reach stderr, and write them as JSON lines for a job runner.
     * Raw Mode (--raw): Output only the data values, one per line, 
         without any headers, numbering, or informational messages. 
         This makes it ideal for piping the output of `csvpeek-rs` into 
-        other standard Unix tools like grep, sort, awk, or for use in scripts.
+        other standard Unix tools like grep, sort, awk, or for use in scripts. 
+        Columns are tab-separated; pick another separator with 
+        --output-delimiter (e.g. \",\" or \"\\x1f\"), and end records with 
+        NUL instead of a newline with -0/--print0 for xargs -0. --crlf 
+        ends every output line with CRLF for Windows consumers.
+    * Normalise displayed values with --replace \"Phone:/[^0-9]+//\" (regex 
+        find and replace within a column) instead of a sed pass that can 
+        break quoting.
+    * Make amounts readable with --round \"Price:2\" and 
+        --thousands-separator (1,234.50) in the listing; --raw and CSV 
+        output keep the exact values unless --format-numbers-everywhere.
+    * Keep one huge free-text column from wrapping every row: --max-col-width 
+        N truncates long cells with an ellipsis (\"auto\" fits the terminal), 
+        or --wrap soft-wraps them within their column instead.
+    * Drop the row numbers with --no-index, or start them at another value 
+        with --start-index N.
+    * Listings longer than the terminal open in $PAGER (or less -SRX); 
+        use --no-pager to print them directly.
+    * Colour the listing with --color auto|always|never: titles in bold, row 
+        numbers dimmed and the cells that matched --filter highlighted. 
+        NO_COLOR is respected and piped output stays plain.
+    * Show missing and empty cells as a placeholder with --null-value NULL, 
+        and make empty cells match no filter with --empty-as-null.
+
+* Configuration:
+    * Put default settings such as delimiter, null_value or color in 
+        ~/.config/csvpeek/config.toml (or pass --config FILE), plus default 
+        columns per data file in [datasets.NAME] tables. Command-line options 
+        always win.
+    * Set CSVPEEK_DATA_FILE, CSVPEEK_DIRECTORY, CSVPEEK_DELIMITER, 
+        CSVPEEK_ENCODING or CSVPEEK_RAW=1 in the environment to change the 
+        defaults of a shell or container; they take precedence over the 
+        config file.
+
+* Exit Status (like grep):
+    * 0 when at least one row matched, 1 when the query ran but matched 
+        nothing, and 2 for errors such as a missing column or a bad file 
+        (and when --validate, --report-errors or --check-fk find problems, 
+        or an --assert or --assert-unique check fails).
 
 `csvpeek-rs` aims to be a simple yet powerful addition to your command-line 
 data toolkit, combining the performance of Rust with a user-friendly 
@@ -118,427 +343,3019 @@ interface for common CSV operations.";
 )]
 struct Args {
     /// Display the list (first column by default).
+    /// Deprecated alias of the list subcommand; it will be removed in the next release.
     #[clap(short, long, group = "mode")]
     list: bool,
 
+    /// Print nothing and exit with status 0 if any row matches the filters, 1 otherwise
+    /// (like grep -q). Reading stops at the first match.
+    #[clap(long, group = "mode", conflicts_with = "headers")]
+    quiet_exists: bool,
+
+    /// Print only the names of the --directory files with at least one row matching the
+    /// filters, like grep -l, and exit with status 1 if there are none. Each file is read up to
+    /// its first match; files without a filtered column do not match.
+    #[clap(long, group = "mode", requires = "directory", conflicts_with = "headers")]
+    files_with_matches: bool,
+
+    /// Fail with an error listing a sample of the duplicates unless the values of COLUMNS
+    /// (comma-separated, e.g. "OrderID" or "Region,OrderID") are unique across the whole,
+    /// merged input, before any filter. Cells are compared trimmed; empty ones count as values.
+    #[clap(long, value_name = "COLUMNS", value_delimiter = ',', global = true)]
+    assert_unique: Vec<String>,
+
+    /// Print the number of data rows and exit. Rows are counted without decoding their fields,
+    /// so even huge exports are counted at close to `wc -l` speed. Takes no filters.
+    #[clap(long, group = "mode", conflicts_with_all = ["headers", "filter", "where_hash", "search", "date_column", "columns"])]
+    count_rows: bool,
+
     /// Filter the list based on COLUMN<OP>VALUE (e.g., "Age>=30", "City!=London").
     /// OP can be =, !=, >, <, >=, <=. Can be repeated for multiple AND conditions.
-    /// Used with --list.
-    #[clap(long, value_parser = parse_filter_arg, requires = "list", num_args = 0..)]
+    /// Used with list or --quiet-exists.
+    #[clap(long, value_parser = parse_filter_arg, num_args = 0.., global = true)]
     filter: Option<Vec<(String, Operator, String)>>,
 
-    /// Path to a single CSV data file. Use "-" to read from stdin.
+    /// Keep a reproducible subset of rows by hashing a column: COLUMN%MODULUS<OP>VALUE
+    /// (e.g. "id%100<5" keeps roughly 5% of ids, the same ones on every run and machine).
+    /// Can be repeated; combines with --filter using AND. Used with list or --quiet-exists.
+    #[clap(long = "where-hash", value_name = "COLUMN%MOD<OP>N", value_parser = parse_where_hash_arg, global = true)]
+    where_hash: Vec<(String, HashSample)>,
+
+    /// Keep only rows where any cell contains TERM, ignoring case, like grep but field by field.
+    /// Can be repeated; every term has to be found. Combines with --filter using AND. Used with
+    /// list or --quiet-exists.
+    #[clap(long, value_name = "TERM", global = true)]
+    search: Vec<String>,
+
+    /// Order text naturally in the ordering filter operators (<, >, <=, >=) and in --top and
+    /// --bottom: runs of digits compare by value, so "item2" comes before "item10" and "1.9.0"
+    /// before "1.10.0". Text is compared lexicographically otherwise.
+    #[clap(long, global = true)]
+    natural_sort: bool,
+
+    /// Ignore only the case of ASCII letters when matching = and != filters and column names,
+    /// instead of the full Unicode case folding that makes "GÖTEBORG" equal "Göteborg".
+    #[clap(long, global = true)]
+    ascii_case: bool,
+
+    /// Read numbers written with a decimal comma and dots between thousands ("3,14",
+    /// "1.234,56") in filters, sorting and aggregates. Numbers with a decimal point are then text.
+    #[clap(long, global = true)]
+    decimal_comma: bool,
+
+    /// Keep only rows whose --date-column is on or after this date: YYYY-MM-DD, "today",
+    /// "yesterday" or e.g. "30 days ago" (also weeks, months and years, counted in UTC).
+    #[clap(long, value_name = "DATE", value_parser = parse_date_bound, requires = "date_column", global = true)]
+    since: Option<chrono::NaiveDate>,
+
+    /// Keep only rows whose --date-column is on or before this date; takes the same forms as
+    /// --since.
+    #[clap(long, value_name = "DATE", value_parser = parse_date_bound, requires = "date_column", global = true)]
+    until: Option<chrono::NaiveDate>,
+
+    /// Column holding the dates that --since and --until compare, read as ISO 8601 dates or
+    /// timestamps or with --date-format. Rows whose cell is not a date are left out. Combines
+    /// with --filter using AND. Used with list or --quiet-exists, or with --profile to show the
+    /// rows per period of the column.
+    #[clap(long, value_name = "COLUMN", global = true)]
+    date_column: Option<String>,
+
+    /// Print a summary line after the listing, e.g. "min(Price),max(Price),count()".
+    /// Functions: count, null_count, min, max, first, last, sum, avg. count() counts rows;
+    /// null_count counts empty cells and the others ignore them. Printed to stderr with --raw. Used with list.
+    #[clap(long, value_name = "FUNC(COLUMN),...", value_delimiter = ',', value_parser = parse_aggregate, global = true)]
+    footer: Vec<Aggregate>,
+
+    /// Check an aggregate of the filtered rows, e.g. "count()>0", "max(Price)<=10000" or
+    /// "null_count(Email)=0", using the --footer functions and the --filter operators. If any
+    /// check fails, nothing is listed and csvpeek-rs exits with status 2, naming the failed
+    /// checks and their values. Can be repeated. Used with list.
+    #[clap(long = "assert", value_name = "FUNC(COLUMN)<OP>VALUE", value_parser = parse_assertion, conflicts_with = "max_memory", global = true)]
+    assertions: Vec<Assertion>,
+
+    /// Show only the first matching row; shorthand for --max-results 1. Used with list.
+    #[clap(long, conflicts_with = "max_results", global = true)]
+    first: bool,
+
+    /// Stop after N matching rows. A single file, URL or stdin is only read until N rows have
+    /// matched, so lookups in huge files return quickly. Used with list.
+    #[clap(long, value_name = "N", global = true)]
+    max_results: Option<usize>,
+
+    /// Show only the N matching rows with the largest values in the --by column, largest first.
+    /// The rows are picked with a bounded heap instead of a full sort, so a single file, URL or
+    /// stdin is streamed in memory proportional to N. Used with list.
+    #[clap(long, value_name = "N", requires = "by", conflicts_with_all = ["bottom", "first", "max_results"], global = true)]
+    top: Option<usize>,
+
+    /// Like --top, but the N rows with the smallest values, smallest first.
+    #[clap(long, value_name = "N", requires = "by", conflicts_with_all = ["first", "max_results"], global = true)]
+    bottom: Option<usize>,
+
+    /// Column that --top and --bottom rank rows by. Numbers compare numerically and rank below
+    /// text; rows with an empty cell are left out.
+    #[clap(long, value_name = "COLUMN", global = true)]
+    by: Option<String>,
+
+    /// Print a contingency table instead of the rows: one row per value of ROWCOL and one
+    /// column per value of COLCOL, counting the matching rows in each cell. Used with stats or list.
+    #[clap(long, value_name = "ROWCOL,COLCOL", value_parser = parse_crosstab_arg, conflicts_with_all = ["columns", "footer"], global = true)]
+    crosstab: Option<(String, String)>,
+
+    /// Print how often each value of COLUMN occurs among the matching rows instead of the rows,
+    /// most frequent first, with its share of the rows. With two columns, print their
+    /// cross-tabulation with each cell's count, its share of its row and of its column, and
+    /// row and column totals. Used with stats or list.
+    #[clap(long, value_name = "COLUMN[,COLUMN]", value_parser = parse_freq_arg, conflicts_with_all = ["columns", "footer", "crosstab", "group_by", "corr"], global = true)]
+    freq: Option<(String, Option<String>)>,
+
+    /// Print one row per group of matching rows instead of the rows, with the number of rows in
+    /// each: groups of equal cells of a column, or with "date_trunc(UNIT, COLUMN)" the day,
+    /// week (from Monday), month or year that a date falls in, e.g.
+    /// "date_trunc(month, OrderDate)". Rows whose cell is not a date are left out. Used with stats
+    /// or list.
+    #[clap(long, value_name = "COLUMN|date_trunc(UNIT, COLUMN)", value_parser = parse_group_by_arg, conflicts_with_all = ["columns", "footer", "crosstab", "corr"], global = true)]
+    group_by: Option<GroupBy>,
+
+    /// Format of the dates read by --date-column and --group-by date_trunc(...), as a strftime
+    /// pattern such as "%d/%m/%Y" or "%m/%d/%Y %H:%M". Defaults to ISO 8601 dates, optionally
+    /// with a time.
+    #[clap(long, value_name = "FORMAT", global = true)]
+    date_format: Option<String>,
+
+    /// Aggregate this column in each --crosstab cell instead of counting rows, or in each
+    /// --group-by group next to the count.
+    #[clap(long, value_name = "COLUMN", global = true)]
+    value: Option<String>,
+
+    /// Function applied to --value: count, min, max, first, last, sum (default) or avg.
+    #[clap(long, value_name = "FUNC", value_parser = parse_aggregate_fn, requires = "value", global = true)]
+    agg: Option<AggregateFn>,
+
+    /// Print the correlation of two or more numeric columns over the matching rows instead of
+    /// the rows, e.g. "Price,Rating", or of every numeric column with "all". Rows where either
+    /// cell of a pair is empty or not a number are left out of that pair. Used with stats or list.
+    #[clap(long, value_name = "COLUMNS", value_parser = parse_corr_arg, conflicts_with_all = ["columns", "footer", "crosstab"], global = true)]
+    corr: Option<CorrColumns>,
+
+    /// Add a column with each listed row's share of the total of COLUMN over all matching rows,
+    /// in percent, or with --group-by each group's share. Rows whose cell is not a number get
+    /// an empty cell. Used with list.
+    #[clap(long, value_name = "COLUMN", conflicts_with_all = ["crosstab", "freq", "corr", "max_memory"], global = true)]
+    percent_of_total: Option<String>,
+
+    /// Number of decimals of the --percent-of-total shares.
+    #[clap(long, value_name = "DIGITS", default_value_t = 1, requires = "percent_of_total", global = true)]
+    percent_precision: usize,
+
+    /// Coefficient computed by --corr: "pearson" (default) or "spearman" (rank correlation).
+    #[clap(long, value_enum, value_name = "METHOD", default_value_t = CorrelationMethod::Pearson, requires = "corr", global = true)]
+    corr_method: CorrelationMethod,
+
+    /// Define a query parameter, e.g. --param region=EU together with --filter "Region=${region}".
+    /// ${NAME} references in filters, columns, footers and file paths, and in the columns, path
+    /// and null_value settings of the config file, are replaced by the parameter value, or by
+    /// the environment variable NAME if no --param defines it.
+    /// Write $$ for a literal $. Can be repeated.
+    #[clap(long, value_name = "NAME=VALUE", value_parser = parse_param_arg, global = true)]
+    param: Vec<(String, String)>,
+
+    /// Read default settings from this TOML file instead of ~/.config/csvpeek/config.toml
+    /// (or $XDG_CONFIG_HOME/csvpeek/config.toml). It can set delimiter, encoding, null_value,
+    /// color, layout and no_pager, plus per-file defaults in [datasets.NAME] tables with a
+    /// path and its columns or delimiter. Options on the command line override the file.
+    #[clap(long, value_name = "FILE", global = true)]
+    config: Option<PathBuf>,
+
+    /// Path to a CSV data file. Use "-" to read from stdin.
+    /// A .zip archive merges all CSV members; use "archive.zip::inner/file.csv" to read a single member.
+    /// An http:// or https:// URL is streamed over the network.
+    /// Excel and OpenDocument workbooks (.xlsx, .xlsm, .xlsb, .xls, .ods) are read one sheet at a time; see --sheet.
+    /// SQLite databases (.db, .sqlite, .sqlite3) are read one table or query at a time; see --table and --sqlite-query.
+    /// JSON arrays of objects (.json) and JSON Lines (.ndjson, .jsonl) are read with one row per object.
+    /// Repeat the flag to merge several files using the same header-matching rules as --directory.
     /// If neither -f nor -d is given, an attempt to read from stdin (if piped) or show help.
-    #[clap(long, short = 'f')]
-    data_file: Option<PathBuf>,
+    #[clap(long, short = 'f', global = true, env = "CSVPEEK_DATA_FILE")]
+    data_file: Vec<PathBuf>,
+
+    /// Extra HTTP request header for URL input, as "NAME: VALUE" (e.g. "Authorization: Bearer TOKEN").
+    /// Can be repeated.
+    #[clap(long, value_name = "NAME: VALUE", global = true)]
+    header: Vec<String>,
 
     /// Path to a directory containing CSV files to merge.
     /// Takes precedence over --data-file if --main-header-file is not also used to clarify source.
-    #[clap(long, short = 'd')]
+    #[clap(long, short = 'd', global = true, env = "CSVPEEK_DIRECTORY")]
     directory: Option<PathBuf>,
 
+    /// Also merge CSV files in subdirectories of --directory (e.g. date-partitioned data/2024/05/*.csv).
+    #[clap(long, requires = "directory", global = true)]
+    recursive: bool,
+
+    /// With --recursive, descend at most N levels below the directory (0 = top level only).
+    #[clap(long, value_name = "N", requires = "recursive", global = true)]
+    max_depth: Option<usize>,
+
+    /// Only merge directory files matching this glob (e.g. "sales_*.csv"). Can be repeated.
+    /// Patterns containing "/" match the path relative to the directory (e.g. "2024/**/*.csv"),
+    /// others match the file name.
+    #[clap(long, value_name = "GLOB", requires = "directory", global = true)]
+    include: Vec<String>,
+
+    /// Skip directory files matching this glob (e.g. "*_backup.csv"). Can be repeated.
+    #[clap(long, value_name = "GLOB", requires = "directory", global = true)]
+    exclude: Vec<String>,
+
+    /// File extensions merged in directory mode, comma-separated (default: csv),
+    /// e.g. "csv,tsv,txt". Without --delimiter, .tsv files are read as tab-separated
+    /// and the delimiter of other non-.csv files is guessed from their header line.
+    #[clap(long, value_name = "EXT,...", value_delimiter = ',', requires = "directory", global = true)]
+    extensions: Vec<String>,
+
+    /// Field delimiter of the input: a single character, or "\t" for tabs. Defaults to ","
+    /// for .csv files and stdin and to tab for .tsv files; for other files it is guessed.
+    #[clap(long, value_name = "CHAR", value_parser = parse_delimiter_arg, global = true, env = "CSVPEEK_DELIMITER")]
+    delimiter: Option<u8>,
+
+    /// Character encoding of the input (e.g. latin1, windows-1252, utf-16le); it is transcoded
+    /// to UTF-8 before parsing. Defaults to UTF-8.
+    #[clap(long, value_name = "LABEL", value_parser = parse_encoding_arg, global = true, env = "CSVPEEK_ENCODING")]
+    encoding: Option<&'static Encoding>,
+
+    /// Sheet of an Excel or OpenDocument workbook to read, by name. Defaults to the first sheet.
+    #[clap(long, value_name = "NAME", global = true, env = "CSVPEEK_SHEET")]
+    sheet: Option<String>,
+
+    /// SQL query whose result is read from a SQLite database given with -f, instead of a whole
+    /// --table, e.g. "SELECT * FROM orders WHERE total > 100".
+    #[clap(long, value_name = "SQL", global = true)]
+    sqlite_query: Option<String>,
+
+    /// Memory-map local files instead of reading them, which speeds up repeated peeks at
+    /// multi-GB exports. "auto" (default) maps files of 64 MiB or more. If a mapped file is
+    /// truncated while it is read, e.g. by a log rotation or an export being rewritten,
+    /// csvpeek is killed by a bus error (SIGBUS) instead of reporting an error; use "never"
+    /// for files that may change while they are read.
+    #[clap(long, value_enum, value_name = "WHEN", default_value_t = MmapMode::Auto, global = true)]
+    mmap: MmapMode,
+
+    /// Memory budget for the rows a listing keeps, e.g. 512M or 2G. A single file, URL or stdin
+    /// is filtered while it is read, and matching rows past the budget are spilled to a temporary
+    /// file and streamed out with --raw or --format csv. Inputs that have to be merged, and text
+    /// listings that outgrow the budget, fail instead of exceeding it. Used with list.
+    #[clap(long, value_name = "SIZE", value_parser = parse_size_arg, global = true)]
+    max_memory: Option<u64>,
+
+    /// Skip lines starting with this character (e.g. '#'), such as comment or metadata lines
+    /// above the header row of scientific exports.
+    #[clap(long, value_name = "CHAR", value_parser = parse_comment_char_arg, global = true)]
+    comment_char: Option<u8>,
+
+    /// Discard the first N lines of each input before reading the header row, e.g. a
+    /// title or export-date banner above the real header.
+    #[clap(long, value_name = "N", default_value_t = 0, global = true)]
+    skip_rows: usize,
+
+    /// Tolerate ragged rows: pad records with too few fields with empty cells and truncate
+    /// those with too many. The number of adjusted rows is reported on stderr.
+    #[clap(long, conflicts_with = "skip_bad_rows", global = true)]
+    flexible: bool,
+
+    /// Drop records whose number of fields differs from the header, or with values that
+    /// --cast cannot convert, instead of failing. The number of dropped rows is reported on stderr.
+    #[clap(long, global = true)]
+    skip_bad_rows: bool,
+
+    /// Strip leading and trailing whitespace from header names and values ("all", the default),
+    /// or only from "fields" or "headers", so padded cells like " London " match filters.
+    #[clap(long, value_enum, value_name = "WHAT", num_args = 0..=1, default_missing_value = "all", global = true)]
+    trim: Option<TrimMode>,
+
+    /// Replace empty cells of a column with a default before filtering and output, as
+    /// COLUMN=VALUE (e.g. "Country=Unknown"). Can be repeated.
+    #[clap(long, value_name = "COLUMN=VALUE", value_parser = parse_fill_arg, global = true)]
+    fill: Vec<(String, String)>,
+
+    /// Replace empty cells of every column not named by --fill with VALUE.
+    #[clap(long, value_name = "VALUE", global = true)]
+    fill_all: Option<String>,
+
+    /// Convert columns to a type when loading, e.g. "Age:int,Price:float,Active:bool" (types:
+    /// int, float, bool, date, string). Values are normalised (007 becomes 7, yes becomes true),
+    /// filter values on these columns must be valid values of the type, and a cell that cannot
+    /// be converted is an error, or drops its row with --skip-bad-rows. Empty cells stay empty.
+    #[clap(long, value_name = "COLUMN:TYPE,...", value_delimiter = ',', value_parser = parse_cast_arg, global = true)]
+    cast: Vec<(String, ColumnType)>,
+
+    /// Add a column joining columns and quoted text, e.g. "FullName=First+' '+Last". The new
+    /// column can be used in --columns, --filter and later --concat rules. Can be repeated.
+    /// Used with list.
+    #[clap(long, value_name = "NAME=PART+...", value_parser = parse_concat_arg, global = true)]
+    concat: Vec<Concat>,
+
+    /// Append a "row_hash" column with a stable 64-bit hash of each row, to find duplicates or
+    /// changed rows across files and runs by comparing hashes. Hashes COLUMNS (comma-separated)
+    /// if given, otherwise every column except those added by --add-source-column and
+    /// --with-row-number. Used with list.
+    #[clap(long, value_name = "COLUMNS", num_args = 0..=1, value_delimiter = ',', global = true)]
+    hash_rows: Option<Vec<String>>,
+
+    /// Add a column with the running total of a numeric column, e.g.
+    /// "RunningTotal=sum(Amount)". Cells that are not numbers add nothing. Rows keep their
+    /// order; the new column can be used in --columns and --filter. Used with list.
+    #[clap(long, value_name = "NAME=sum(COLUMN)", value_parser = parse_cumulative_arg, global = true)]
+    cumulative: Option<Cumulative>,
+
+    /// Add a column with an aggregate over a moving window of rows, e.g. "Avg7=avg(Value,7)"
+    /// for the average of each row's Value and those of the 6 rows before it. FUNC is avg, sum,
+    /// min or max. The cell is empty until a full window of rows is available. Can be
+    /// repeated. Used with list.
+    #[clap(long, value_name = "NAME=FUNC(COLUMN,N)", value_parser = parse_rolling_arg, global = true)]
+    rolling: Vec<Rolling>,
+
+    /// Keep a separate --cumulative total and --rolling windows for each value of this column.
+    #[clap(long, value_name = "COLUMN", global = true)]
+    partition_by: Option<String>,
+
+    /// Accumulate --cumulative and move --rolling windows in the order of this column (numbers
+    /// numerically, other values such as ISO dates as text) instead of the order of the rows.
+    #[clap(long, value_name = "COLUMN", global = true)]
+    order_by: Option<String>,
+
+    /// Duplicate each row once per element of the multi-value cell in COLUMN (split on
+    /// --split-on), so filters and counts see the individual values. Used with list.
+    #[clap(long, value_name = "COLUMN", global = true)]
+    explode: Option<String>,
+
+    /// Separator between the elements of an --explode cell.
+    #[clap(long, value_name = "TEXT", default_value = ";", value_parser = parse_split_on_arg, requires = "explode", global = true)]
+    split_on: String,
+
+    /// Unpivot wide data before querying it: each column not named by --id-columns becomes a
+    /// row of the id columns plus "variable" (the column name) and "value". Used with list.
+    #[clap(long, global = true)]
+    melt: bool,
+
+    /// Columns --melt keeps on every row, e.g. "ID,Name".
+    #[clap(long, value_name = "COLUMN,...", value_delimiter = ',', requires = "melt", global = true)]
+    id_columns: Vec<String>,
+
+    /// How files with differing headers are merged (directory, zip or multiple -f inputs):
+    /// "match" skips files whose headers differ from the main headers, "union" merges all
+    /// files into the union of their columns and leaves missing cells empty.
+    #[clap(long, value_enum, default_value_t = MergeStrategy::Match, global = true)]
+    merge_strategy: MergeStrategy,
+
+    /// When merging, align the columns of files whose headers differ only in their labels
+    /// (same number of columns) by position instead of skipping them. The main headers
+    /// supply the column names.
+    #[clap(long, global = true)]
+    map_by_position: bool,
+
+    /// When merging, rename columns of non-conforming files through a TOML file of
+    /// "name in file" = "main header" entries (e.g. "Kundnr" = "CustomerID"), so files with
+    /// renamed columns are merged instead of skipped.
+    #[clap(long, value_name = "FILE", global = true)]
+    header_map: Option<PathBuf>,
+
+    /// When merging, abort with an error on header mismatches, unreadable files or parse
+    /// errors instead of warning and skipping the file.
+    #[clap(long, global = true)]
+    strict: bool,
+
+    /// When merging overlapping files (directory, zip or multiple -f inputs), keep only one
+    /// record per value of these key columns (comma-separated, e.g. "ID"), from the file
+    /// --keep prefers. Cells are compared trimmed.
+    #[clap(long, value_name = "COLUMNS", value_delimiter = ',', global = true)]
+    dedupe_on: Vec<String>,
+
+    /// Which file's record --dedupe-on keeps: latest-file (last in name order, e.g. the newest
+    /// daily export), latest-mtime (most recently modified) or first-file. Within that file
+    /// the last record with the key wins, or the first with first-file.
+    #[clap(long, value_enum, value_name = "POLICY", default_value_t = KeepPolicy::LatestFile, requires = "dedupe_on", global = true)]
+    keep: KeepPolicy,
+
+    /// When merging, append a column (named NAME, default "source") holding the file each
+    /// record came from, so results can be traced back to their file.
+    #[clap(long, value_name = "NAME", num_args = 0..=1, default_missing_value = "source", global = true)]
+    add_source_column: Option<String>,
+
+    /// Prepend a column (named NAME, default "row") holding the 1-based number of each record
+    /// in its file, counted before filtering, so matches can be found again in an editor. The
+    /// column is listed first. Used with list.
+    #[clap(long, value_name = "NAME", num_args = 0..=1, default_missing_value = "row", global = true)]
+    with_row_number: Option<String>,
+
     /// Specify a file within the input directory (used with -d/--directory)
     /// to define the main headers against which other files will be compared.
-    #[clap(long = "main-header-file", short = 'm', value_name = "FILENAME", requires = "directory")]
+    #[clap(long = "main-header-file", short = 'm', value_name = "FILENAME", requires = "directory", global = true)]
     main_header_file: Option<String>,
 
     /// Specify column(s) to display. Use comma-separated values or repeat the flag.
     /// Defaults to the first column if not specified.
-    #[clap(long = "columns", short = 'c', value_delimiter = ',')]
+    #[clap(long = "columns", short = 'c', value_delimiter = ',', global = true)]
     columns: Option<Vec<String>>,
 
+    /// Layout of list-mode rows: "table" prints numbered tab-separated rows, "vertical" prints
+    /// one "Header: value" block per record, and "auto" switches to vertical when rows are
+    /// wider than the terminal.
+    #[clap(long, value_enum, default_value_t = Layout::Auto, global = true)]
+    layout: Layout,
+
+    /// Print each row as a block of "Header: value" lines under a rule, like MySQL's \G;
+    /// shorthand for --layout vertical.
+    #[clap(long, conflicts_with = "layout", global = true)]
+    vertical: bool,
+
+    /// Rewrite displayed values of a column with a regular expression, sed-style:
+    /// COLUMN:/PATTERN/REPLACEMENT/ replaces every match (e.g. "Phone:/[^0-9]+//" strips
+    /// non-digits; $1 refers to a capture group). Filters still see the original values.
+    /// Can be repeated; rules for the same column apply in order.
+    #[clap(long, value_name = "COLUMN:/PATTERN/REPLACEMENT/", value_parser = parse_replace_arg, global = true)]
+    replace: Vec<Replacement>,
+
+    /// Round the displayed numbers of a column to DIGITS decimals, e.g. "Price:2" (halves away
+    /// from zero). Comma-separated or repeated. Only changes the text listing unless
+    /// --format-numbers-everywhere is given; filters still see the original values.
+    #[clap(long, value_name = "COLUMN:DIGITS", value_delimiter = ',', value_parser = parse_round_arg, global = true)]
+    round: Vec<(String, usize)>,
+
+    /// Group the integer digits of displayed numbers in thousands with SEP (default ","), e.g.
+    /// 1,234,567.50. Applies to every cell holding a plain decimal number, so leave ID or year
+    /// columns out of --columns or expect 2,024. Only changes the text listing unless
+    /// --format-numbers-everywhere is given.
+    #[clap(long, value_name = "SEP", num_args = 0..=1, default_missing_value = ",", global = true)]
+    thousands_separator: Option<String>,
+
+    /// Apply --round and --thousands-separator to --raw output, to --format csv, xlsx, sql and
+    /// latex, and to the files --to-sqlite, --split-rows and --split-by write as well.
+    #[clap(long, global = true)]
+    format_numbers_everywhere: bool,
+
+    /// Text shown for missing and empty cells in every output (e.g. "NULL" or "-"). By default
+    /// missing cells show as [N/A] (nothing with --raw) and empty cells as nothing.
+    #[clap(long, value_name = "STR", global = true)]
+    null_value: Option<String>,
+
+    /// Treat empty cells as nulls: like missing cells they match no --filter condition,
+    /// including != conditions. Footer aggregates always skip empty cells.
+    #[clap(long, global = true)]
+    empty_as_null: bool,
+
+    /// Colour the text listing: titles in bold, row numbers dimmed and the cells that matched
+    /// a --filter highlighted. "auto" (default) colours only when stdout is a terminal and the
+    /// NO_COLOR environment variable is not set.
+    #[clap(long, value_enum, value_name = "WHEN", default_value_t = ColorChoice::Auto, global = true)]
+    color: ColorChoice,
+
+    /// Show line breaks and tabs inside cells as \n, \r and \t. "auto" (default) escapes them in
+    /// the text listing, where they would break the alignment, but not in --raw output;
+    /// "always" (or --escape alone) escapes them there too, doubling backslashes so that every
+    /// record stays on one unambiguous line; "never" prints cells as they are.
+    #[clap(long, value_enum, value_name = "WHEN", num_args = 0..=1, default_missing_value = "always", default_value_t = EscapeChoice::Auto, global = true)]
+    escape: EscapeChoice,
+
+    /// Output format of list mode: "text" (default) for the human-readable listing, "csv"
+    /// for the displayed columns of the matching rows as CSV with a header row, ready for
+    /// other tools, "xlsx" for the same rows in an Excel workbook written to --output, "sql"
+    /// for INSERT statements into --table, or "latex" for a LaTeX tabular. Messages are
+    /// suppressed and a --footer goes to stderr. The headers subcommand takes "text", "csv"
+    /// or "json" to print the column list (or --detailed profile) for scripts.
+    #[clap(long, value_enum, default_value_t = OutputFormat::Text, conflicts_with = "raw", global = true)]
+    format: OutputFormat,
+
+    /// File that --format xlsx writes the workbook to; an existing file is replaced.
+    #[clap(long, value_name = "PATH", required_if_eq("format", "xlsx"), global = true)]
+    output: Option<PathBuf>,
+
+    /// Write the matching rows to a table of this SQLite database (created if missing) instead
+    /// of printing them. Column types (INTEGER, REAL or TEXT) are inferred from the values and
+    /// an existing table of the same name is replaced. Used with list.
+    #[clap(long, value_name = "PATH", conflicts_with_all = ["raw", "format", "crosstab", "freq", "corr", "group_by", "split_rows", "split_by"], global = true)]
+    to_sqlite: Option<PathBuf>,
+
+    /// Table read from a SQLite database given with -f (needed when it has more than one), and
+    /// the name of the table --to-sqlite and --format sql write (default: peek).
+    #[clap(long, value_name = "NAME", global = true)]
+    table: Option<String>,
+
+    /// Number of rows in each INSERT statement of --format sql (default: 1).
+    #[clap(long, value_name = "N", default_value = "1", requires = "format", global = true)]
+    rows_per_insert: NonZeroUsize,
+
+    /// Write the matching rows as CSV files of at most N rows each, every one with a header
+    /// row, instead of printing them; for loaders with row-count limits. Used with list.
+    #[clap(long, value_name = "N", conflicts_with_all = ["raw", "crosstab", "freq", "corr", "group_by"], global = true)]
+    split_rows: Option<NonZeroUsize>,
+
+    /// Path prefix of the --split-rows files, which are numbered from 1: part_0001.csv, ...
+    #[clap(long, value_name = "PREFIX", default_value = "part_", requires = "split_rows", global = true)]
+    output_prefix: String,
+
+    /// Write the matching rows as one CSV file per distinct value of COLUMN (e.g. US.csv,
+    /// DE.csv) instead of printing them, reading the input only once. Used with list.
+    #[clap(long, value_name = "COLUMN", conflicts_with_all = ["raw", "crosstab", "freq", "corr", "group_by", "split_rows"], global = true)]
+    split_by: Option<String>,
+
+    /// Directory the --split-by files are written to; created if missing.
+    #[clap(long, value_name = "DIR", default_value = ".", requires = "split_by", global = true)]
+    output_dir: PathBuf,
+
+    /// When fields of --format csv output are quoted: "necessary" (default) quotes fields
+    /// containing the delimiter, quotes or line breaks; "always", "never" and "non-numeric"
+    /// as named, to match what a loader such as Redshift or Excel expects.
+    #[clap(long, value_enum, default_value_t = QuoteStyle::Necessary, requires = "format", global = true)]
+    quote_style: QuoteStyle,
+
+    /// Quote character of --format csv output (default: "). Quotes inside fields are doubled.
+    #[clap(long, value_name = "CHAR", default_value = "\"", value_parser = parse_quote_char_arg, requires = "format", global = true)]
+    quote_char: u8,
+
+    /// Truncate cells longer than N characters with an ellipsis in the text listing, or with
+    /// "auto" just enough for table rows to fit the terminal. Raw and CSV output are never
+    /// truncated.
+    #[clap(long, value_name = "N|auto", value_parser = parse_max_col_width_arg, global = true)]
+    max_col_width: Option<MaxColWidth>,
+
+    /// Soft-wrap long cells onto continuation lines within their column of the table listing
+    /// instead of truncating them. Cells wrap at the --max-col-width limit, by default just
+    /// enough for rows to fit the terminal.
+    #[clap(long, global = true)]
+    wrap: bool,
+
+    /// Print the listing directly instead of showing it in a pager. By default a listing
+    /// longer than the terminal is shown through $PAGER, or "less -SRX" if it is not set.
+    #[clap(long, global = true)]
+    no_pager: bool,
+
+    /// Leave out the "1. " row numbers of the text listing, e.g. for copy and paste.
+    #[clap(long, global = true)]
+    no_index: bool,
+
+    /// Number the rows of the text listing from N instead of 1, e.g. to continue the
+    /// numbering of an earlier chunk.
+    #[clap(long, value_name = "N", default_value_t = 1, conflicts_with = "no_index", global = true)]
+    start_index: usize,
+
+    /// Suppress informational messages such as "Reading CSV file: ..." on stdout while keeping
+    /// the normal formatted output. Warnings still go to stderr.
+    #[clap(short, long, global = true)]
+    quiet: bool,
+
+    /// Report on stderr how long loading, filtering and rendering took, and how many rows and
+    /// bytes were read from each file, e.g. to find out why a directory merge is slow.
+    /// Same as --log-level debug.
+    #[clap(short, long, conflicts_with = "log_level", global = true)]
+    verbose: bool,
+
+    /// Which diagnostics are written to stderr: error, warn (the default), info for timings
+    /// and index use, or debug to also report what was read from each file.
+    #[clap(long, value_enum, value_name = "LEVEL", global = true, env = "CSVPEEK_LOG_LEVEL")]
+    log_level: Option<LogLevel>,
+
+    /// How diagnostics are written to stderr: text ("Warning: ..." lines) or json, one object
+    /// per line with "time", "level" and "message", for collecting them from a job runner.
+    #[clap(long, value_enum, value_name = "FORMAT", default_value_t = LogFormat::Text, global = true, env = "CSVPEEK_LOG_FORMAT")]
+    log_format: LogFormat,
+
     /// Output raw data values only, one per line (for piping).
-    #[clap(long)]
+    /// Setting the environment variable CSVPEEK_RAW=1 turns it on unless --headers or
+    /// --format pick another output.
+    #[clap(long, global = true)]
     raw: bool,
 
+    /// Separator between columns in --raw output (default: tab), e.g. ",", "|" or "\x1f".
+    /// The escapes \t, \n, \0, \\ and \xHH are expanded.
+    #[clap(long, value_name = "STR", requires = "raw", default_value = "\t", hide_default_value = true, value_parser = parse_output_delimiter_arg, global = true)]
+    output_delimiter: String,
+
+    /// End each --raw record with a NUL byte instead of a newline, for xargs -0 and other
+    /// tools that must cope with values containing newlines.
+    #[clap(short = '0', long, requires = "raw", global = true)]
+    print0: bool,
+
+    /// End every line written to stdout with CRLF instead of LF, in all output modes
+    /// (e.g. for Windows tools that require CRLF-terminated CSV files).
+    #[clap(long, global = true)]
+    crlf: bool,
+
     /// Display only the header row from the CSV data and exit.
     /// Cannot be used with --list, --filter, --columns, or --raw.
+    /// Deprecated alias of the headers subcommand; it will be removed in the next release.
     #[clap(long, conflicts_with_all = ["list", "filter", "columns", "raw"])]
     headers: bool,
-}
 
-fn parse_csv_from_reader<R: Read>(
-    reader_source: R,
-    load_records: bool,
-) -> Result<(Vec<String>, Vec<csv::StringRecord>), Box<dyn Error>> {
-    let mut reader = csv::Reader::from_reader(reader_source);
-    let headers = reader.headers()?.iter().map(String::from).collect::<Vec<String>>();
-    if headers.is_empty() {
-        return Err("CSV data is missing headers or is empty.".into());
-    }
+    /// With headers: also show each column's position, inferred type, number of non-empty
+    /// cells, number of distinct values and a few example values. Reads every row.
+    #[clap(long, global = true)]
+    detailed: bool,
 
-    if !load_records {
-        return Ok((headers, Vec::new()));
-    }
+    /// Deterministic output for golden-file tests: fixed random seed and
+    /// platform-independent, cwd-relative paths in messages.
+    #[clap(long, hide = true, global = true)]
+    render_fixture: bool,
 
-    let mut records_data = Vec::new();
-    for result in reader.records() {
-        let record: csv::StringRecord = result?;
-        records_data.push(record);
-    }
-    Ok((headers, records_data))
+    /// Instead of stopping at the first bad record, read the whole input and report every
+    /// parse problem with its file and line number, as text (default) or JSON.
+    /// Exits with an error status when any problem was found.
+    #[clap(long, value_enum, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "text",
+        conflicts_with_all = ["list", "headers", "profile"])]
+    report_errors: Option<ReportFormat>,
+
+    /// Lint the raw input instead of displaying it: check RFC 4180 conformance, consistent
+    /// field counts, duplicate or empty headers, byte order marks, mixed line endings and
+    /// unbalanced quotes. Prints the findings as text (default) or JSON and exits with an
+    /// error status when there are any.
+    #[clap(long, value_enum, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "text",
+        conflicts_with_all = ["list", "headers", "report_errors", "profile"])]
+    validate: Option<ReportFormat>,
+
+    /// Infer each column's type (integer, float, boolean, date or string), whether it has empty
+    /// cells and a few example values. Prints a table (default) or, with "json", a Frictionless
+    /// Table Schema document.
+    #[clap(long, value_enum, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "text",
+        conflicts_with_all = ["list", "headers", "report_errors", "validate", "profile"])]
+    infer_schema: Option<ReportFormat>,
+
+    /// Profile every column: its number of distinct values, the number and percentage of empty
+    /// cells, and whether it is constant (at most one distinct value) or a candidate key (a
+    /// value in every row, none repeated), with a sparkline of the distribution of numeric
+    /// columns. With --date-column, also shows a sparkline of the rows per period of that
+    /// column. Prints a table (default) or JSON. Used on its own or with stats.
+    #[clap(long, value_enum, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "text", global = true)]
+    profile: Option<ReportFormat>,
+
+    /// Instead of merging the --directory files, report their schema drift: a matrix of which
+    /// file has which columns, the columns added, removed or renamed from one file to the next
+    /// (in name order, so date-stamped exports read chronologically), and which files the
+    /// current merge options would skip. Prints text (default) or JSON.
+    #[clap(long, value_enum, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "text", requires = "directory",
+        conflicts_with_all = ["list", "headers", "report_errors", "validate", "infer_schema", "profile"])]
+    schema_report: Option<ReportFormat>,
+
+    /// Compare only the header row of the input with that of FILE and list the columns FILE is
+    /// missing or adds, the shared columns in a different order and the names that differ only
+    /// in case. Exits with status 1 when the headers differ, e.g. as a check before appending
+    /// FILE to the input.
+    #[clap(long, value_name = "FILE",
+        conflicts_with_all = ["list", "headers", "report_errors", "validate", "infer_schema", "profile", "schema_report"])]
+    compare_headers: Option<PathBuf>,
+
+    /// Check referential integrity between two files, e.g. "orders.csv:CustomerID ->
+    /// customers.csv:ID": report the values of the child column that the parent column lacks,
+    /// with their counts and a few examples. Empty child cells are not checked. Exits with
+    /// status 2 if any key is orphaned. Can be repeated.
+    #[clap(long = "check-fk", value_name = "CHILD:COLUMN -> PARENT:COLUMN", value_parser = parse_foreign_key_arg,
+        conflicts_with_all = ["list", "headers", "report_errors", "validate", "infer_schema", "profile", "schema_report", "compare_headers"])]
+    check_fk: Vec<ForeignKey>,
+
+    /// With --infer-schema, only examine the first N records instead of scanning all of them.
+    #[clap(long, value_name = "N", requires = "infer_schema")]
+    sample: Option<usize>,
+
+    /// Print a completion script for SHELL (bash, zsh, fish, powershell or elvish) and exit,
+    /// e.g. csvpeek-rs --generate-completions bash > /etc/bash_completion.d/csvpeek-rs.
+    #[clap(long, value_name = "SHELL", value_enum, hide = true)]
+    generate_completions: Option<clap_complete::Shell>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Sizes of the local input files, looked up once per run; see `local_input_sizes`.
+    #[clap(skip)]
+    input_sizes: OnceLock<Vec<u64>>,
 }
 
-fn load_data_from_csv(filepath: &PathBuf, load_records: bool) -> Result<(Vec<String>, Vec<csv::StringRecord>), Box<dyn Error>> {
-    let file = fs::File::open(filepath)?;
-    parse_csv_from_reader(file, load_records)
+/// Output format of `--report-errors`, `--validate`, `--infer-schema` and `--profile`.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum ReportFormat {
+    Text,
+    Json,
 }
 
-fn load_data_from_stdin(load_records: bool) -> Result<(Vec<String>, Vec<csv::StringRecord>), Box<dyn Error>> {
-    let stdin = io::stdin();
-    parse_csv_from_reader(stdin.lock(), load_records)
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List the matching rows (the first column by default). Takes the list options such as
+    /// --filter, --columns, --footer and --format.
+    List,
+    /// Show a single random row (the default when no subcommand is given).
+    Random,
+    /// Display only the header row from the CSV data and exit.
+    Headers,
+    /// Summarize the matching rows instead of listing them. Takes one of --profile, --freq,
+    /// --crosstab, --group-by or --corr, e.g. "stats --freq Region", with list options such
+    /// as --filter or --format where that summary supports them.
+    Stats,
+    /// List the input's rows joined with another CSV file on equal key cells: each row is
+    /// followed by the other file's columns, once per matching row. Takes the list options,
+    /// which see the joined columns.
+    Join {
+        /// The CSV file to join with.
+        #[clap(long, value_name = "FILE")]
+        with: PathBuf,
+        /// Key column: "COLUMN" when both files name it the same, or "LEFT=RIGHT", e.g.
+        /// "CustomerID=ID". Cells are compared trimmed; empty keys never match.
+        #[clap(long, value_name = "COLUMN|LEFT=RIGHT", value_parser = parse_join_key)]
+        on: (String, String),
+        /// Keep the input rows without a match, with empty cells for the other file's columns.
+        #[clap(long)]
+        left: bool,
+    },
+    /// Manage sidecar indexes that let equality filters on large files skip the full scan.
+    Index {
+        #[clap(subcommand)]
+        action: IndexAction,
+    },
+    /// Load the input once, then run one list query per line read from stdin.
+    /// Each line holds list options such as --filter, --where-hash, --columns, --footer,
+    /// --layout or --raw (quoted like a shell command line; --list is implied). Every result
+    /// is framed by "==> query N: LINE" and "<== query N: ok" (or "<== query N: error: ...")
+    /// lines, where N is the line number. Empty lines and lines starting with # are ignored. Input must come from -f or -d.
+    Batch,
 }
 
-fn load_data_from_directory(
-    dir_path: &PathBuf,
-    be_quiet: bool,
-    load_records: bool,
-    specified_main_header_filename: &Option<String>,
-) -> Result<(Vec<String>, Vec<csv::StringRecord>), Box<dyn Error>> {
-    
-    let mut csv_file_paths: Vec<PathBuf> = fs::read_dir(dir_path)?
-        .filter_map(Result::ok)
-        .map(|entry| entry.path())
-        .filter(|path| path.is_file() && path.extension().map_or(false, |ext| ext == "csv"))
-        .collect();
-    csv_file_paths.sort();
+#[derive(Subcommand, Debug)]
+enum IndexAction {
+    /// Index the given columns of the file named by -f, writing FILE.csvpeek-index next to it.
+    /// While the file is unchanged, --filter "COLUMN=VALUE" on an indexed column then reads
+    /// only the matching rows. Run it again after the file changes.
+    Build {
+        /// Column(s) to index; comma-separated or repeated.
+        #[clap(long, value_name = "COLUMN", value_delimiter = ',', required = true)]
+        on: Vec<String>,
+    },
+}
 
-    if csv_file_paths.is_empty() {
-        return Err(format!("No CSV files found in directory '{}'.", dir_path.display()).into());
-    }
+/// Query options, which batch queries read from stdin rather than from the command line.
+const QUERY_OPTIONS: [&str; 64] = [
+    "filter", "where_hash", "search", "natural_sort", "since", "until", "date_column", "footer", "assertions", "crosstab", "freq", "group_by", "date_format", "value", "agg", "corr", "corr_method", "percent_of_total", "percent_precision", "concat", "hash_rows", "cumulative", "rolling", "partition_by", "order_by", "explode", "split_on", "melt", "id_columns", "first", "max_results", "top", "bottom", "by", "max_memory", "columns", "layout", "vertical", "replace", "round", "thousands_separator", "format_numbers_everywhere", "null_value", "empty_as_null", "color",
+    "format", "output", "to_sqlite", "rows_per_insert", "split_rows", "output_prefix", "split_by", "output_dir", "quote_style", "quote_char", "max_col_width", "wrap", "escape", "no_pager", "no_index", "start_index", "raw", "output_delimiter", "print0",
+];
 
-    let mut main_headers_option: Option<Vec<String>> = None;
+/// Options that print a summary of the rows, one of which the stats subcommand takes.
+const STATS_OPTIONS: [&str; 5] = ["profile", "freq", "crosstab", "group_by", "corr"];
 
-    if let Some(filename_str) = specified_main_header_filename {
-        let main_header_path = dir_path.join(filename_str);
-        if !csv_file_paths.iter().any(|p| p == &main_header_path) {
-             return Err(format!("Specified main header file '{}' not found or is not a .csv file in directory '{}'.", filename_str, dir_path.display()).into());
+/// Seed used for random selection under `--render-fixture`.
+const FIXTURE_SEED: u64 = 0x5eed;
+
+/// Human-readable name of the input source, used in list titles and random-entry messages.
+fn describe_source(args: &Args) -> String {
+    match &args.command {
+        Some(Command::Join { with, .. }) => format!("{} joined with '{}'", describe_input(args), display_path(with)),
+        _ => describe_input(args),
+    }
+}
+
+/// Human-readable name of the input files, directory or stdin.
+fn describe_input(args: &Args) -> String {
+    if let Some(dir_path) = &args.directory {
+        return format!("directory '{}'", display_path(dir_path));
+    }
+    match args.data_file.as_slice() {
+        [] => "stdin".to_string(),
+        [file_path] => {
+            if is_url(&file_path.to_string_lossy()) {
+                return format!("URL '{}'", file_path.to_string_lossy());
+            }
+            match split_zip_spec(file_path) {
+                Some((archive_path, None)) => format!("zip archive '{}'", display_path(&archive_path)),
+                _ if file_path.to_string_lossy() == "-" => "stdin".to_string(),
+                _ => format!("file '{}'", display_path(file_path)),
+            }
         }
-        if !be_quiet { println!("Attempting to set main headers from specified file: {}", main_header_path.display()); }
-        match load_data_from_csv(&main_header_path, false) { 
-            Ok((headers_from_file, _)) => {
-                if headers_from_file.is_empty() {
-                    return Err(format!("Specified main header file '{}' is empty or has no headers.", main_header_path.display()).into());
-                }
-                main_headers_option = Some(headers_from_file);
+        file_paths => describe_files(file_paths),
+    }
+}
+
+/// Whether informational messages such as "Reading CSV file: ..." are left out of stdout.
+fn be_quiet(args: &Args) -> bool {
+    args.quiet || args.quiet_exists || args.files_with_matches || args.count_rows || args.raw || args.format != OutputFormat::Text || args.headers || matches!(args.command, Some(Command::Batch))
+        || args.report_errors.is_some() || args.infer_schema.is_some() || args.profile.is_some() || args.compare_headers.is_some()
+}
+
+/// Loads headers (and records, if requested) from whichever input source the arguments select.
+/// With `problems`, bad records are logged there and skipped instead of failing the load.
+fn load_input(args: &Args, should_load_records: bool, problems: Option<&ProblemLog>) -> Result<CsvData> {
+    let be_quiet = be_quiet(args);
+    let read = read_options(args, problems);
+    let merge = merge_options(args)?;
+    if let Some(dir_path) = &args.directory {
+        return load_data_from_directory(dir_path, &directory_options(args), &read, &merge, be_quiet, should_load_records);
+    }
+    match args.data_file.as_slice() {
+        [] => {
+            if std::io::stdin().is_terminal() {
+                Args::command().print_help()?;
+                return Err(CsvPeekError::Usage("No input source specified. Please use -f <file>, -d <directory>, or pipe data to stdin.".to_string()));
             }
-            Err(e) => {
-                return Err(format!("Failed to load headers from specified main header file '{}': {}", main_header_path.display(), e).into());
+            if !be_quiet {
+                outln!("No input file specified, reading CSV data from piped stdin...");
             }
+            load_data_from_stdin(&read, should_load_records)
         }
-    } else {
-        for path in &csv_file_paths {
-            if !be_quiet { println!("Attempting to determine main headers from: {}", path.display()); }
-            match load_data_from_csv(path, false) { 
-                Ok((headers_from_file, _)) => {
-                    if !headers_from_file.is_empty() {
-                        main_headers_option = Some(headers_from_file);
-                        break; 
-                    } else if !be_quiet {
-                        eprintln!("Warning: File '{}' has no headers. Trying next file for main headers.", path.display());
-                    }
+        [file_path] => {
+            if is_url(&file_path.to_string_lossy()) {
+                let url = file_path.to_string_lossy();
+                if !be_quiet {
+                    outln!("Reading CSV data from URL: {}", url);
                 }
-                Err(e) => {
+                load_data_from_url(&url, &args.header, &read, should_load_records)
+            } else if let Some((archive_path, member)) = split_zip_spec(file_path) {
+                if let Some(member) = member {
                     if !be_quiet {
-                        eprintln!("Warning: Could not read file '{}' to determine main headers: {}. Trying next.", path.display(), e);
+                        outln!("Reading CSV file: {}", display_path(file_path));
                     }
+                    load_data_from_zip_member(&archive_path, &member, &read, should_load_records)
+                } else {
+                    load_data_from_zip(&archive_path, &read, &merge, be_quiet, should_load_records)
                 }
+            } else if file_path.to_string_lossy() == "-" {
+                if !be_quiet && std::io::stdin().is_terminal() {
+                    outln!("Reading CSV data from stdin (specified by '-f -')...");
+                }
+                load_data_from_stdin(&read, should_load_records)
+            } else {
+                if !be_quiet {
+                    outln!("Reading CSV file: {}", display_path(file_path));
+                }
+                load_data_from_csv(file_path, &read, should_load_records)
             }
         }
+        file_paths => load_data_from_files(file_paths, &read, &merge, be_quiet, should_load_records),
+    }
+}
+
+/// Applies the load-time transformations to the records: `--fill` and `--fill-all` defaults,
+/// then `--cast` conversions. Records with a cell that cannot be cast fail the load, are dropped
+/// with `--skip-bad-rows`, or are logged in `problems` and dropped.
+fn prepare_records(args: &Args, headers: &[String], records: Vec<csv::StringRecord>, problems: Option<&ProblemLog>) -> Result<Vec<csv::StringRecord>> {
+    let preparation = Preparation::resolve(args, headers)?;
+    if preparation.is_noop() {
+        return Ok(records);
+    }
+    let mut prepared = Vec::with_capacity(records.len());
+    let mut skipped = 0;
+    for record in &records {
+        match preparation.prepare(record) {
+            Ok(record) => prepared.push(record),
+            Err(e) => match problems {
+                Some(log) => log.push(e),
+                None if args.skip_bad_rows => skipped += 1,
+                None => return Err(e),
+            },
+        }
     }
+    if skipped > 0 {
+        log_warn!("Skipped {} row(s) with values that could not be cast.", skipped);
+    }
+    Ok(prepared)
+}
 
-    let final_main_headers = main_headers_option.ok_or_else(|| format!("Could not determine main headers from any suitable file in directory '{}'.", dir_path.display()))?;
-    
-    let mut combined_records: Vec<csv::StringRecord> = Vec::new();
-    let mut files_contributed_records = 0;
-
-    if load_records {
-        for path in &csv_file_paths {
-            if !be_quiet { println!("Processing file for data: {}", path.display()); }
-            match load_data_from_csv(path, true) { 
-                Ok((current_headers, records_chunk)) => {
-                    if current_headers == final_main_headers {
-                        combined_records.extend(records_chunk);
-                        files_contributed_records += 1;
-                    } else if !be_quiet {
-                        eprintln!("Warning: Headers in file '{}' do not match main headers. Skipping records from this file.", path.display());
-                    }
-                }
-                Err(e) => {
-                    if !be_quiet { 
-                        eprintln!("Warning: Could not read or parse CSV file '{}' for records: {}. Skipping.", path.display(), e); 
-                    }
-                }
-            }
+/// Whether --concat, --hash-rows, --cumulative, --rolling, --explode or --melt changes the rows
+/// that the query sees.
+fn reshapes(args: &Args) -> bool {
+    matches!(args.command, Some(Command::Join { .. })) || !args.concat.is_empty() || args.hash_rows.is_some() || args.cumulative.is_some() || !args.rolling.is_empty() || args.explode.is_some()
+        || args.melt
+}
+
+/// Applies the join subcommand, --concat, --hash-rows, --cumulative, --rolling, --explode and
+/// then --melt to the loaded records.
+fn reshape(args: &Args, headers: Vec<String>, records: Vec<csv::StringRecord>) -> Result<CsvData> {
+    let (headers, records) = match &args.command {
+        Some(Command::Join { with, on: (left_key, right_key), left }) => {
+            let (right_headers, right_records) = load_data_from_csv(with, &read_options(args, None), true)?;
+            let left_column = find_column(&headers, left_key, "Join")?;
+            let right_column = find_column(&right_headers, right_key, "Join")?;
+            let right_name = with.file_stem().map_or_else(|| display_path(with), |stem| stem.to_string_lossy().into_owned());
+            join((headers, records), (right_headers, right_records), left_column, right_column, *left, &right_name)
         }
-    } else {
-        for path in &csv_file_paths {
-            if let Ok((current_headers, _)) = load_data_from_csv(path, false) {
-                if current_headers == final_main_headers {
-                    files_contributed_records += 1;
-                }
-            }
+        _ => (headers, records),
+    };
+    let (headers, records) = concat_columns(headers, records, &args.concat)?;
+    let (headers, records) = match &args.hash_rows {
+        Some(columns) => {
+            let added: Vec<&str> = args.add_source_column.iter().chain(&args.with_row_number).map(String::as_str).collect();
+            hash_rows(headers, records, columns, &added)?
         }
+        None => (headers, records),
+    };
+    let (headers, records) = match &args.cumulative {
+        Some(rule) => cumulative_sum(headers, records, rule, args.partition_by.as_deref(), args.order_by.as_deref())?,
+        None => (headers, records),
+    };
+    let (headers, records) = match args.rolling.as_slice() {
+        [] => (headers, records),
+        rules => rolling_aggregates(headers, records, rules, args.partition_by.as_deref(), args.order_by.as_deref())?,
+    };
+    let records = match &args.explode {
+        Some(column) => explode(records, find_column(&headers, column, "Explode")?, &args.split_on),
+        None => records,
+    };
+    if !args.melt {
+        return Ok((headers, records));
     }
-    
-    if files_contributed_records == 0 {
-        let for_what_msg = if load_records { " with records" } else { " (for header consistency check)" };
-        return Err(format!("No CSV files{} matching main headers ({:?}) found/processed in directory '{}'.", for_what_msg, final_main_headers, dir_path.display()).into());
+    let id_columns = args.id_columns.iter()
+        .map(|column| find_column(&headers, column, "Id"))
+        .collect::<Result<Vec<usize>>>()?;
+    Ok(melt(&headers, &records, &id_columns))
+}
+
+/// The `--fill`, `--fill-all` and `--cast` settings resolved against the headers.
+struct Preparation<'a> {
+    headers: &'a [String],
+    fills: Vec<(usize, String)>,
+    fill_all: Option<&'a str>,
+    casts: Vec<(usize, ColumnType)>,
+}
+
+impl<'a> Preparation<'a> {
+    fn resolve(args: &'a Args, headers: &'a [String]) -> Result<Self> {
+        let fills = args.fill.iter()
+            .map(|(column, value)| Ok((find_column(headers, column, "Fill")?, value.clone())))
+            .collect::<Result<_>>()?;
+        let casts = args.cast.iter()
+            .map(|(column, column_type)| Ok((find_column(headers, column, "Cast")?, *column_type)))
+            .collect::<Result<_>>()?;
+        Ok(Preparation { headers, fills, fill_all: args.fill_all.as_deref(), casts })
+    }
+
+    fn is_noop(&self) -> bool {
+        self.fills.is_empty() && self.fill_all.is_none() && self.casts.is_empty()
+    }
+
+    /// Fills in defaults, then converts the cast columns. Fails if a cell cannot be converted.
+    fn prepare(&self, record: &csv::StringRecord) -> Result<csv::StringRecord> {
+        let filled = fill_record(record, &self.fills, self.fill_all);
+        cast_record(&filled, self.headers, &self.casts)
+    }
+}
+
+fn read_options(args: &Args, problems: Option<&ProblemLog>) -> ReadOptions {
+    ReadOptions {
+        delimiter: args.delimiter,
+        encoding: args.encoding,
+        comment: args.comment_char,
+        skip_rows: args.skip_rows,
+        flexible: args.flexible,
+        skip_bad_rows: args.skip_bad_rows,
+        trim: args.trim.map_or(csv::Trim::None, csv::Trim::from),
+        verbose: log::enabled(LogLevel::Debug),
+        mmap: args.mmap,
+        columns: projected_columns(args),
+        row_number: args.with_row_number.clone(),
+        sheet: args.sheet.clone(),
+        table: args.table.clone(),
+        sqlite_query: args.sqlite_query.clone(),
+        problems: problems.cloned(),
+        progress: read_progress(args),
+        source: None,
+    }
+}
+
+/// Inputs at least this large (in total) get a progress bar.
+const PROGRESS_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// A progress bar for reading the local files of a large load, shown only when stderr is a
+/// terminal and the output is meant for a person.
+fn read_progress(args: &Args) -> Option<ReadProgress> {
+    if !std::io::stderr().is_terminal() || be_quiet(args) {
+        return None;
     }
+    let sizes = local_input_sizes(args);
+    let total_bytes = sizes.iter().sum();
+    (total_bytes >= PROGRESS_THRESHOLD).then(|| ReadProgress::new(total_bytes, sizes.len()))
+}
+
+/// Sizes of the local files the arguments select, directly or through a directory. The
+/// directory is walked on the first call only, as every `read_options` call needs the sizes.
+fn local_input_sizes(args: &Args) -> &[u64] {
+    args.input_sizes.get_or_init(|| find_local_input_sizes(args))
+}
 
-    Ok((final_main_headers, combined_records))
+fn find_local_input_sizes(args: &Args) -> Vec<u64> {
+    let paths: Vec<PathBuf> = match &args.directory {
+        Some(dir_path) => directory_sources(dir_path, &directory_options(args)).unwrap_or_default().into_iter()
+            .filter_map(|source| match source {
+                CsvSource::File(path) => Some(path),
+                _ => None,
+            })
+            .collect(),
+        None => args.data_file.iter().filter(|path| split_zip_spec(path).is_none()).cloned().collect(),
+    };
+    paths.iter()
+        .filter(|path| !is_converted(path))
+        .filter_map(|path| std::fs::metadata(path).ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .collect()
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
+/// Whether the input is a merge of several sources: a directory, a whole zip archive or
+/// several -f files.
+fn is_merge(args: &Args) -> bool {
+    args.directory.is_some() || args.data_file.len() > 1
+        || args.data_file.first().and_then(|path| split_zip_spec(path)).is_some_and(|(_, member)| member.is_none())
+}
 
-    let should_load_records = !args.headers;
+/// Columns the query reads when it selects its display columns from a single input: the
+/// displayed, filtered, cast and aggregated ones. Other fields are not copied out while parsing.
+/// `None` (read every column) for modes that look at whole rows and for merged inputs, whose
+/// columns may be renamed or reordered before they are matched.
+fn projected_columns(args: &Args) -> Option<Vec<String>> {
+    let display = args.columns.as_ref()?;
+    let whole_rows = args.headers || matches!(args.command, Some(Command::Index { .. } | Command::Batch))
+        || args.report_errors.is_some() || args.validate.is_some() || args.infer_schema.is_some() || args.profile.is_some()
+        || !args.search.is_empty();
+    if whole_rows || is_merge(args) || reshapes(args) {
+        return None;
+    }
+    let mut columns = display.clone();
+    columns.extend(args.filter.iter().flatten().map(|(column, _, _)| column.clone()));
+    columns.extend(args.where_hash.iter().map(|(column, _)| column.clone()));
+    columns.extend(args.date_column.iter().cloned());
+    columns.extend(args.assert_unique.iter().cloned());
+    columns.extend(args.cast.iter().map(|(column, _)| column.clone()));
+    columns.extend(args.footer.iter().filter_map(|aggregate| aggregate.column.clone()));
+    columns.extend(args.assertions.iter().filter_map(|assertion| assertion.aggregate.column.clone()));
+    columns.extend(args.split_by.iter().cloned());
+    columns.extend(args.crosstab.iter().flat_map(|(row, column)| [row.clone(), column.clone()]));
+    columns.extend(args.freq.iter().flat_map(|(row, column)| std::iter::once(row.clone()).chain(column.clone())));
+    columns.extend(args.group_by.iter().map(|group_by| group_by.column().to_string()));
+    columns.extend(args.value.iter().cloned());
+    columns.extend(args.percent_of_total.iter().cloned());
+    columns.extend(args.by.iter().cloned());
+    Some(columns)
+}
 
-    let (headers, records): (Vec<String>, Vec<csv::StringRecord>) = {
-        if let Some(dir_path) = &args.directory {
-            load_data_from_directory(dir_path, args.raw || args.headers, should_load_records, &args.main_header_file)?
-        } else if let Some(file_path) = &args.data_file {
-            if file_path.to_string_lossy() == "-" {
-                if !args.raw && !args.headers && std::io::stdin().is_terminal() {
-                    println!("Reading CSV data from stdin (specified by '-f -')...");
-                }
-                load_data_from_stdin(should_load_records)?
-            } else {
-                if !args.raw && !args.headers {
-                    println!("Reading CSV file: {}", file_path.display());
-                }
-                load_data_from_csv(file_path, should_load_records)?
+fn merge_options(args: &Args) -> Result<MergeOptions> {
+    Ok(MergeOptions {
+        strategy: args.merge_strategy,
+        by_position: args.map_by_position,
+        source_column: args.add_source_column.clone(),
+        header_map: match &args.header_map {
+            Some(path) => load_header_map(path)?,
+            None => Vec::new(),
+        },
+        strict: args.strict,
+        dedupe_on: args.dedupe_on.clone(),
+        keep: args.keep,
+    })
+}
+
+fn directory_options(args: &Args) -> DirectoryOptions {
+    DirectoryOptions {
+        main_header_file: args.main_header_file.clone(),
+        recursive: args.recursive,
+        max_depth: args.max_depth,
+        include: args.include.clone(),
+        exclude: args.exclude.clone(),
+        extensions: args.extensions.clone(),
+    }
+}
+
+/// Exit status when the query ran but no row matched, as with grep.
+const EXIT_NO_MATCH: u8 = 1;
+/// Exit status for errors such as an unknown column or an unreadable file.
+const EXIT_ERROR: u8 = 2;
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(e) => {
+            log_error!("{}", e);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+/// Exit status of a check such as --validate: success if it passed, an error otherwise.
+fn error_status(ok: bool) -> ExitCode {
+    if ok { ExitCode::SUCCESS } else { ExitCode::from(EXIT_ERROR) }
+}
+
+/// Exit status of a query: success if any row matched.
+fn match_status(matched: bool) -> ExitCode {
+    if matched { ExitCode::SUCCESS } else { ExitCode::from(EXIT_NO_MATCH) }
+}
+
+impl Args {
+    /// Fills in options that were not given on the command line from the configuration file:
+    /// the one named by --config, or the default one if it exists. `${NAME}` references in
+    /// the settings are replaced as in [`Args::apply_params`].
+    fn apply_config(&mut self, matches: &ArgMatches) -> Result<()> {
+        let mut config = match &self.config {
+            Some(path) => load_config(path)?,
+            None => match default_config_path().filter(|path| path.is_file()) {
+                Some(path) => load_config(&path)?,
+                None => return Ok(()),
+            },
+        };
+        let unset = |id: &str| matches.value_source(id).is_none_or(|source| source == ValueSource::DefaultValue);
+        for dataset in &mut config.datasets {
+            // A dataset whose path names an undefined parameter cannot be the one being read.
+            if let Ok(path) = substitute_params(&dataset.path.to_string_lossy(), &self.param) {
+                dataset.path = PathBuf::from(path);
             }
-        } else {
-            if std::io::stdin().is_terminal() {
-                Args::command().print_help()?;
-                eprintln!("\nError: No input source specified. Please use -f <file>, -d <directory>, or pipe data to stdin.");
-                std::process::exit(1);
-            } else {
-                if !args.raw && !args.headers {
-                    println!("No input file specified, reading CSV data from piped stdin...");
+        }
+        if let [file_path] = self.data_file.as_slice() {
+            if let Some(dataset) = config.dataset_for(file_path) {
+                if unset("columns") && !dataset.columns.is_empty() {
+                    self.columns = Some(dataset.columns.iter().map(|column| substitute_params(column, &self.param)).collect::<Result<_>>()?);
+                }
+                if unset("delimiter") && dataset.delimiter.is_some() {
+                    self.delimiter = dataset.delimiter;
                 }
-                load_data_from_stdin(should_load_records)?
             }
         }
-    };
-    
-    if args.headers {
-        if headers.is_empty() {
-            eprintln!("No headers found or could be determined from the input source.");
-        } else {
-            for header_name in &headers {
-                println!("{}", header_name);
-            }
+        if unset("delimiter") && self.delimiter.is_none() {
+            self.delimiter = config.delimiter;
         }
-        return Ok(()); 
-    }
-
-    if records.is_empty() { 
-        if !args.raw {
-            println!("No data rows found.");
+        if unset("encoding") {
+            self.encoding = config.encoding;
         }
-        return Ok(());
+        if unset("null_value") {
+            self.null_value = config.null_value.map(|value| substitute_params(&value, &self.param)).transpose()?;
+        }
+        if let Some(color) = config.color.filter(|_| unset("color")) {
+            self.color = color;
+        }
+        if let Some(layout) = config.layout.filter(|_| unset("layout") && !self.vertical) {
+            self.layout = layout;
+        }
+        if let Some(no_pager) = config.no_pager.filter(|_| unset("no_pager")) {
+            self.no_pager = no_pager;
+        }
+        Ok(())
     }
 
-    let columns_to_display_names: Vec<String> = if let Some(ref specified_cols_args) = args.columns {
-        let mut valid_cols = Vec::new();
-        for col_name_arg in specified_cols_args {
-            if let Some(found_header) = headers.iter().find(|h| h.eq_ignore_ascii_case(col_name_arg)) {
-                valid_cols.push(found_header.clone());
-            } else {
-                if !args.raw {
-                    eprintln!("Error: Specified display column '{}' not found in CSV headers: {:?}", col_name_arg, headers);
+    /// Selects the mode of the list, random, headers or stats subcommand, which the --list and
+    /// --headers flags still select too. The query options are global so that they can follow
+    /// a subcommand, so the options that only make sense in some modes are checked here.
+    fn apply_command(&mut self, matches: &ArgMatches) -> std::result::Result<(), clap::Error> {
+        let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+        let fail = |kind, message: String| Err(Args::command().error(kind, message));
+        let conflict = |option: &str, other: &str| fail(ErrorKind::ArgumentConflict, format!("the argument '{}' cannot be used with '{}'", option, other));
+        let flag = |id: &str| format!("--{}", id.replace('_', "-"));
+        let mode_flag = [("--list", self.list), ("--headers", self.headers), ("--quiet-exists", self.quiet_exists), ("--files-with-matches", self.files_with_matches), ("--count-rows", self.count_rows)]
+            .into_iter()
+            .find_map(|(name, set)| set.then_some(name));
+        if given("detailed") && !self.headers && !matches!(self.command, Some(Command::Headers)) {
+            return fail(ErrorKind::MissingRequiredArgument, "the argument '--detailed' can only be used with the headers subcommand".to_string());
+        }
+        if given("profile") {
+            let other = match self.command {
+                Some(Command::Stats) => None,
+                Some(_) => matches.subcommand_name(),
+                None => mode_flag.filter(|name| ["--list", "--headers"].contains(name)),
+            };
+            if let Some(other) = other {
+                return conflict("--profile", other);
+            }
+        }
+        match (&self.command, mode_flag) {
+            (Some(Command::List), Some("--list")) | (Some(Command::Headers), Some("--headers")) => {}
+            (Some(Command::List | Command::Random | Command::Headers | Command::Stats | Command::Join { .. }), Some(other)) => {
+                return conflict(matches.subcommand_name().unwrap_or_default(), other);
+            }
+            (Some(Command::List), None) => self.list = true,
+            (Some(Command::Headers), None) => self.headers = true,
+            (Some(Command::Join { .. }), None) => self.list = true,
+            (Some(Command::Stats), None) => {
+                match STATS_OPTIONS.into_iter().filter(|id| given(id)).collect::<Vec<_>>().as_slice() {
+                    [] => return fail(ErrorKind::MissingRequiredArgument,
+                        "the stats subcommand needs one of '--profile', '--freq', '--crosstab', '--group-by' or '--corr'".to_string()),
+                    [_] => {}
+                    [first, second, ..] => return conflict(&flag(first), &flag(second)),
                 }
-                std::process::exit(1); 
+                // The other summaries are computed by the list query.
+                self.list = self.profile.is_none();
+            }
+            (Some(Command::Batch | Command::Index { .. }), _) => {
+                // Batch queries take their list options from stdin, and indexes have none.
+                return match QUERY_OPTIONS.iter().find(|id| given(id)) {
+                    Some(id) => conflict(&flag(id), matches.subcommand_name().unwrap_or_default()),
+                    None => Ok(()),
+                };
             }
+            _ => {}
         }
-        if valid_cols.is_empty() { 
-             if !args.raw {
-                eprintln!("Error: No valid display columns were specified (or provided list was empty).");
-             }
-             std::process::exit(1);
+        if self.headers {
+            if let Some(id) = ["filter", "columns", "raw"].into_iter().find(|id| given(id)) {
+                return conflict(&flag(id), "headers");
+            }
         }
-        valid_cols
+        if let Some(id) = ["footer", "assertions", "crosstab", "freq", "group_by", "corr", "percent_of_total", "split_rows", "split_by", "with_row_number", "concat", "hash_rows", "cumulative", "rolling", "explode", "melt", "first", "max_results", "top", "bottom", "by", "max_memory", "output", "to_sqlite"].into_iter().find(|id| given(id) && !self.list) {
+            return fail(ErrorKind::MissingRequiredArgument, format!("the argument '{}' can only be used with the list subcommand", flag(id)));
+        }
+        let profiles_dates = |id: &str| id == "date_column" && self.profile.is_some();
+        if let Some(id) = ["filter", "where_hash", "search", "date_column"].into_iter()
+            .find(|id| given(id) && !self.list && !self.quiet_exists && !self.files_with_matches && !profiles_dates(id))
+        {
+            return fail(ErrorKind::MissingRequiredArgument,
+                format!("the argument '{}' can only be used with the list subcommand, --quiet-exists or --files-with-matches", flag(id)));
+        }
+        if given("format") {
+            let supported = if self.headers {
+                [OutputFormat::Text, OutputFormat::Csv, OutputFormat::Json].contains(&self.format)
+            } else {
+                self.list && self.format != OutputFormat::Json
+            };
+            if !supported {
+                return fail(ErrorKind::InvalidValue, format!(
+                    "'--format {}' can only be used with {}",
+                    self.format.to_possible_value().map_or_else(String::new, |value| value.get_name().to_string()),
+                    if self.format == OutputFormat::Json { "the headers subcommand" } else { "the list subcommand" }
+                ));
+            }
+        }
+        if given("value") && self.crosstab.is_none() && self.group_by.is_none() {
+            return fail(ErrorKind::MissingRequiredArgument, "the argument '--value <COLUMN>' can only be used with '--crosstab' or '--group-by'".to_string());
+        }
+        if given("date_format") && self.date_column.is_none() && !matches!(self.group_by, Some(GroupBy::DateTrunc(..))) {
+            return fail(ErrorKind::MissingRequiredArgument,
+                "the argument '--date-format <FORMAT>' can only be used with '--date-column' or '--group-by date_trunc(...)'".to_string());
+        }
+        if let Some(id) = ["since", "until"].into_iter().find(|id| given(id) && self.profile.is_some()) {
+            return fail(ErrorKind::ArgumentConflict, format!("the argument '{}' cannot be used with '--profile'", flag(id)));
+        }
+        if given("date_column") && self.since.is_none() && self.until.is_none() && self.profile.is_none() {
+            return fail(ErrorKind::MissingRequiredArgument, "the argument '--date-column <COLUMN>' can only be used with '--since' or '--until'".to_string());
+        }
+        if given("format_numbers_everywhere") && self.round.is_empty() && self.thousands_separator.is_none() {
+            return fail(ErrorKind::MissingRequiredArgument,
+                "the argument '--format-numbers-everywhere' can only be used with '--round' or '--thousands-separator'".to_string());
+        }
+        if let Some(id) = ["partition_by", "order_by"].into_iter().find(|id| given(id) && self.cumulative.is_none() && self.rolling.is_empty()) {
+            return fail(ErrorKind::MissingRequiredArgument, format!("the argument '{}' can only be used with '--cumulative' or '--rolling'", flag(id)));
+        }
+        if given("by") && self.top.is_none() && self.bottom.is_none() {
+            return fail(ErrorKind::MissingRequiredArgument, "the argument '--by <COLUMN>' can only be used with '--top' or '--bottom'".to_string());
+        }
+        if given("dedupe_on") && !is_merge(self) {
+            return fail(ErrorKind::MissingRequiredArgument,
+                "the argument '--dedupe-on <COLUMNS>' can only be used when merging a --directory, a zip archive or several -f files".to_string());
+        }
+        if given("assert_unique") {
+            if let Some(id) = ["quiet_exists", "files_with_matches", "count_rows", "report_errors", "validate"].into_iter().find(|id| given(id)) {
+                return conflict("--assert-unique <COLUMNS>", &flag(id));
+            }
+        }
+        if given("output") && self.format != OutputFormat::Xlsx {
+            return fail(ErrorKind::MissingRequiredArgument, "the argument '--output <PATH>' can only be used with '--format xlsx'".to_string());
+        }
+        if self.vertical {
+            self.layout = Layout::Vertical;
+        }
+        Ok(())
+    }
+
+    /// Turns on --raw when the CSVPEEK_RAW environment variable is set to a true value, unless
+    /// --headers or --format ask for another output. This is not done through clap so that the
+    /// variable cannot make those options conflict.
+    fn apply_raw_env(&mut self) {
+        let enabled = std::env::var("CSVPEEK_RAW")
+            .is_ok_and(|value| !matches!(value.trim().to_ascii_lowercase().as_str(), "" | "0" | "n" | "no" | "f" | "false" | "off"));
+        if enabled && !self.headers && self.format == OutputFormat::Text {
+            self.raw = true;
+        }
+    }
+
+    /// Replaces `${NAME}` parameter references in the query options and input paths.
+    fn apply_params(&mut self) -> Result<()> {
+        let params = &self.param;
+        let sub = |text: &mut String| -> Result<()> {
+            *text = substitute_params(text, params)?;
+            Ok(())
+        };
+        for (column, _, value) in self.filter.iter_mut().flatten() {
+            sub(column)?;
+            sub(value)?;
+        }
+        for (column, _) in &mut self.where_hash {
+            sub(column)?;
+        }
+        for term in &mut self.search {
+            sub(term)?;
+        }
+        if let Some(column) = &mut self.date_column {
+            sub(column)?;
+        }
+        for column in self.footer.iter_mut().filter_map(|aggregate| aggregate.column.as_mut()) {
+            sub(column)?;
+        }
+        for assertion in &mut self.assertions {
+            if let Some(column) = &mut assertion.aggregate.column {
+                sub(column)?;
+            }
+            sub(&mut assertion.value)?;
+        }
+        for column in self.columns.iter_mut().flatten() {
+            sub(column)?;
+        }
+        for path in &mut self.data_file {
+            *path = PathBuf::from(substitute_params(&path.to_string_lossy(), params)?);
+        }
+        if let Some(dir) = &mut self.directory {
+            *dir = PathBuf::from(substitute_params(&dir.to_string_lossy(), params)?);
+        }
+        Ok(())
+    }
+}
+
+fn run() -> Result<ExitCode> {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Some(shell) = args.generate_completions {
+        let mut command = Args::command();
+        let name = command.get_name().to_string();
+        let mut script = Vec::new();
+        clap_complete::generate(shell, &mut command, name, &mut script);
+        std::io::stdout().write_all(&script)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Err(e) = args.apply_command(&matches) {
+        e.exit();
+    }
+    args.apply_params()?;
+    args.apply_config(&matches)?;
+    args.apply_raw_env();
+    set_fixture_paths(args.render_fixture);
+    output::set_crlf(args.crlf);
+    csvpeek_rs::set_ascii_case(args.ascii_case);
+    csvpeek_rs::filter::set_decimal_comma(args.decimal_comma);
+    let level = if args.verbose { LogLevel::Debug } else { args.log_level.unwrap_or_default() };
+    log::init(level, args.log_format);
+    if let Some(alias) = ["list", "headers"].into_iter().find(|id| matches.value_source(id) == Some(ValueSource::CommandLine)) {
+        log_warn!("--{} is deprecated and will be removed in the next release; use the {} subcommand instead.", alias, alias);
+    }
+
+    let should_load_records = !args.headers || args.detailed || !args.assert_unique.is_empty();
+
+    if let Some(format) = args.report_errors {
+        return report_errors(&args, format).map(error_status);
+    }
+    if let Some(format) = args.validate {
+        return validate_input(&args, format).map(error_status);
+    }
+    if let Some(format) = args.schema_report {
+        return print_schema_report(&args, format).map(|()| ExitCode::SUCCESS);
+    }
+    if let Some(other) = &args.compare_headers {
+        return print_header_diff(&args, other).map(match_status);
+    }
+    if !args.check_fk.is_empty() {
+        return check_foreign_keys(&args).map(error_status);
+    }
+
+    if args.quiet_exists {
+        return any_match(&args).map(match_status);
+    }
+
+    if args.files_with_matches {
+        return files_with_matches(&args).map(match_status);
+    }
+
+    if args.count_rows {
+        return count_rows(&args).map(|()| ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Index { action: IndexAction::Build { on } }) = &args.command {
+        return build_indexes(&args, on).map(|()| ExitCode::SUCCESS);
+    }
+
+    let loading = Instant::now();
+    // An index or a result limit lets a single file be read only in part, unless the query is
+    // about the reshaped rows or needs the total of every matching row.
+    let scannable = should_load_records && !reshapes(&args) && args.assert_unique.is_empty() && args.percent_of_total.is_none();
+    let streamed = match (result_limit(&args), ranking(&args), args.max_memory) {
+        (Some(limit), _, _) if scannable => scan_matches(&args, limit)?,
+        (None, Some(ranking), _) if scannable && args.list => scan_ranked(&args, ranking)?,
+        (None, None, Some(budget)) if scannable && args.list => match scan_within_budget(&args, budget)? {
+            Some((headers, matches)) if matches.is_spilled() => {
+                report_time(&format!("Loading {} record(s)", matches.len()), loading);
+                return write_spilled(&args, &headers, matches, budget).map(match_status);
+            }
+            Some((headers, matches)) => matches.into_records().map(|records| (headers, records)),
+            None => {
+                check_merge_budget(&args, budget)?;
+                None
+            }
+        },
+        _ => None,
+    };
+    let indexed = match &streamed {
+        None if scannable => load_indexed(&args)?,
+        _ => None,
+    };
+    let is_streamed = streamed.is_some() || indexed.is_some();
+    let (headers, records) = match streamed {
+        Some(data) => data,
+        None => {
+            let (headers, records) = match indexed {
+                Some(data) => data,
+                None => load_input(&args, should_load_records, None)?,
+            };
+            let records = prepare_records(&args, &headers, records, None)?;
+            reshape(&args, headers, records)?
+        }
+    };
+    report_time(&format!("Loading {} record(s)", records.len()), loading);
+    if !args.assert_unique.is_empty() {
+        assert_unique(&args.assert_unique, &headers, &records)?;
+    }
+    
+    if args.headers {
+        return print_headers(&args, &headers, &records).map(|()| ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Batch) = args.command {
+        return run_batch(&args, &headers, &records).map(|()| ExitCode::SUCCESS);
+    }
+
+    if let Some(format) = args.infer_schema {
+        return print_schema(&args, format, &headers, &records).map(|()| ExitCode::SUCCESS);
+    }
+
+    if let Some(format) = args.profile {
+        return print_profile(&args, format, &headers, &records).map(|()| ExitCode::SUCCESS);
+    }
+
+    if records.is_empty() && args.format == OutputFormat::Text && !is_streamed { 
+        check_assertions(&args.assertions, &headers, &[])?;
+        if !args.raw {
+            outln!("No data rows found.");
+        }
+        return Ok(match_status(false));
+    }
+
+    run_query(&args, &describe_source(&args), &headers, &records).map(match_status)
+}
+
+/// Fails with `CsvPeekError::NotUnique` for --assert-unique if two records share the values of
+/// `columns`.
+fn assert_unique(columns: &[String], headers: &[String], records: &[csv::StringRecord]) -> Result<()> {
+    let indices = columns.iter()
+        .map(|column| find_column(headers, column, "Unique"))
+        .collect::<Result<Vec<usize>>>()?;
+    let duplicates = duplicate_keys(records, &indices);
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+    Err(CsvPeekError::NotUnique { columns: indices.iter().map(|&idx| headers[idx].clone()).collect(), duplicates })
+}
+
+/// Fails with `CsvPeekError::AssertionFailed` for the --assert checks that the filtered
+/// `records` do not pass.
+fn check_assertions(assertions: &[Assertion], headers: &[String], records: &[&csv::StringRecord]) -> Result<()> {
+    let mut failures = Vec::new();
+    for assertion in assertions {
+        let result = ResolvedAggregate::resolve(&assertion.aggregate, headers)?.evaluate(records);
+        if !assertion.holds(&result) {
+            failures.push(format!("{} (got {})", assertion, result));
+        }
+    }
+    if failures.is_empty() {
+        return Ok(());
+    }
+    Err(CsvPeekError::AssertionFailed { failures })
+}
+
+/// The `--filter`, `--where-hash`, `--search` and `--since`/`--until` conditions resolved
+/// against the headers.
+struct RowFilter {
+    filters: Vec<ResolvedFilter>,
+    hash_samples: Vec<(usize, HashSample)>,
+    search: Vec<String>,
+    /// The --date-column and its range, read with --date-format.
+    dates: Option<(usize, DateRange, Option<String>)>,
+    empty_as_null: bool,
+    /// One description per condition, for the list title.
+    descriptions: Vec<String>,
+}
+
+impl RowFilter {
+    /// Resolves the filter columns. Values compared against `--cast` columns are cast the same
+    /// way as the cells, so that e.g. "Active=yes" matches a cell cast to "true".
+    fn resolve(args: &Args, headers: &[String]) -> Result<Self> {
+        let mut filters = Vec::new();
+        let mut descriptions = Vec::new();
+        for (user_col_name, op, val_str) in args.filter.as_deref().unwrap_or_default() {
+            let idx = find_column(headers, user_col_name, "Filter")?;
+            let value = match args.cast.iter().find(|(column, _)| eq_ignore_case(column, &headers[idx])) {
+                Some((_, column_type)) => column_type.cast(val_str).ok_or_else(|| CsvPeekError::Usage(format!(
+                    "Filter value '{}' for column '{}' is not a valid {}.", val_str, user_col_name, column_type
+                )))?,
+                None => val_str.clone(),
+            };
+            filters.push(ResolvedFilter { column: idx, op: *op, value, natural: args.natural_sort });
+            descriptions.push(format!("{} {} '{}'", user_col_name, op, val_str));
+        }
+        let mut hash_samples = Vec::new();
+        for (user_col_name, sample) in &args.where_hash {
+            hash_samples.push((find_column(headers, user_col_name, "Hash sample")?, *sample));
+            descriptions.push(format!("hash({}) {}", user_col_name, sample));
+        }
+        descriptions.extend(args.search.iter().map(|term| format!("any column contains '{}'", term)));
+        let dates = match &args.date_column {
+            Some(column) => {
+                let range = DateRange { since: args.since, until: args.until };
+                descriptions.push(format!("{} {}", column, range));
+                Some((find_column(headers, column, "Date")?, range, args.date_format.clone()))
+            }
+            None => None,
+        };
+        Ok(RowFilter { filters, hash_samples, search: args.search.clone(), dates, empty_as_null: args.empty_as_null, descriptions })
+    }
+
+    fn matches(&self, record: &csv::StringRecord) -> bool {
+        filter::record_matches(record, &self.filters)
+            && (!self.empty_as_null || filter::filtered_cells_present(record, &self.filters))
+            && self.hash_samples.iter().all(|(idx, sample)| record.get(*idx).is_some_and(|cell| sample.matches(cell)))
+            && self.search.iter().all(|term| record.iter().any(|cell| contains_ignore_case(cell, term)))
+            && self.dates.as_ref().is_none_or(|(idx, range, format)| record.get(*idx).is_some_and(|cell| range.matches(cell, format.as_deref())))
+    }
+}
+
+/// Opens the input as a record stream when it is a single file, URL or piped stdin, so that a
+/// query can stop reading early. Returns `None` for inputs that have to be merged first.
+fn open_stream(args: &Args, read: &ReadOptions) -> Result<Option<RecordStream<'static>>> {
+    if args.directory.is_some() {
+        return Ok(None);
+    }
+    let be_quiet = be_quiet(args);
+    match args.data_file.as_slice() {
+        [] if !std::io::stdin().is_terminal() => {
+            if !be_quiet {
+                outln!("No input file specified, reading CSV data from piped stdin...");
+            }
+            open_stdin_stream(read).map(Some)
+        }
+        [file_path] => {
+            let name = file_path.to_string_lossy();
+            if is_url(&name) {
+                if !be_quiet {
+                    outln!("Reading CSV data from URL: {}", name);
+                }
+                open_url_stream(&name, &args.header, read).map(Some)
+            } else if name == "-" {
+                if !be_quiet && std::io::stdin().is_terminal() {
+                    outln!("Reading CSV data from stdin (specified by '-f -')...");
+                }
+                open_stdin_stream(read).map(Some)
+            } else if split_zip_spec(file_path).is_some() {
+                Ok(None)
+            } else {
+                if !be_quiet {
+                    outln!("Reading CSV file: {}", display_path(file_path));
+                }
+                open_csv_stream(file_path, read).map(Some)
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+/// The `--max-results` (or `--first`) limit on matching rows, if any.
+fn result_limit(args: &Args) -> Option<usize> {
+    if args.first { Some(1) } else { args.max_results }
+}
+
+/// The `--top` or `--bottom` row count, and whether the largest values are wanted.
+fn ranking(args: &Args) -> Option<(usize, bool)> {
+    args.top.map(|n| (n, true)).or(args.bottom.map(|n| (n, false)))
+}
+
+/// Streams the input and passes the prepared records that pass the filters to `keep`, along
+/// with the headers, until it returns false. Returns the headers, or `None` for inputs that
+/// have to be merged first.
+fn scan_input(args: &Args, mut keep: impl FnMut(&[String], csv::StringRecord) -> Result<bool>) -> Result<Option<Vec<String>>> {
+    let Some(mut stream) = open_stream(args, &read_options(args, None))? else {
+        return Ok(None);
+    };
+    let headers = stream.headers().to_vec();
+    let preparation = Preparation::resolve(args, &headers)?;
+    let row_filter = RowFilter::resolve(args, &headers)?;
+    let mut skipped = 0;
+    for record in stream.by_ref() {
+        let record = match preparation.prepare(&record?) {
+            Ok(record) => record,
+            Err(_) if args.skip_bad_rows => {
+                skipped += 1;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        if row_filter.matches(&record) && !keep(&headers, record)? {
+            break;
+        }
+    }
+    if skipped > 0 {
+        log_warn!("Skipped {} row(s) with values that could not be cast.", skipped);
+    }
+    Ok(Some(headers))
+}
+
+/// Streams the input and keeps the prepared records that pass the filters, stopping as soon as
+/// `limit` of them have been found. Returns `None` for inputs that have to be merged first.
+fn scan_matches(args: &Args, limit: usize) -> Result<Option<CsvData>> {
+    let mut matches = Vec::new();
+    if limit == 0 {
+        return Ok(open_stream(args, &read_options(args, None))?.map(|stream| (stream.headers().to_vec(), matches)));
+    }
+    let headers = scan_input(args, |_, record| {
+        matches.push(record);
+        Ok(matches.len() < limit)
+    })?;
+    Ok(headers.map(|headers| (headers, matches)))
+}
+
+/// Streams the input and keeps only the --top or --bottom matches. Returns `None` for inputs
+/// that have to be merged first.
+fn scan_ranked(args: &Args, (limit, largest): (usize, bool)) -> Result<Option<CsvData>> {
+    let by = args.by.as_deref().unwrap_or_default();
+    let mut ranked = TopN::new(limit, largest).natural(args.natural_sort);
+    let mut column = None;
+    let headers = scan_input(args, |headers, record| {
+        let idx = match column {
+            Some(idx) => idx,
+            None => *column.insert(find_column(headers, by, "Ranking")?),
+        };
+        ranked.push(record, |record| record.get(idx).unwrap_or_default());
+        Ok(true)
+    })?;
+    Ok(headers.map(|headers| (headers, ranked.into_sorted())))
+}
+
+/// Streams the input and keeps the records that pass the filters within --max-memory, spilling
+/// the rest to a temporary file. Returns `None` for inputs that have to be merged first.
+fn scan_within_budget(args: &Args, budget: u64) -> Result<Option<(Vec<String>, SpillBuffer)>> {
+    let mut matches = SpillBuffer::new(budget);
+    let headers = scan_input(args, |_, record| matches.push(record).map(|()| true))?;
+    Ok(headers.map(|headers| (headers, matches)))
+}
+
+/// Fails before loading inputs that have to be merged in memory when their files alone take up
+/// more than --max-memory.
+fn check_merge_budget(args: &Args, budget: u64) -> Result<()> {
+    let total_bytes: u64 = local_input_sizes(args).iter().sum();
+    if total_bytes > budget {
+        return Err(CsvPeekError::Usage(format!(
+            "Merging {} needs more than --max-memory {} ({} of files); raise the budget or query the files one at a time.",
+            describe_source(args), format_size(budget), format_size(total_bytes)
+        )));
+    }
+    Ok(())
+}
+
+/// Writes the rows of a listing whose matches spilled past --max-memory, one chunk at a time.
+/// Only raw and CSV output can be written this way, as text layouts and --footer need every row.
+fn write_spilled(args: &Args, headers: &[String], matches: SpillBuffer, budget: u64) -> Result<bool> {
+    if !(args.raw || args.format == OutputFormat::Csv) || !args.footer.is_empty() {
+        return Err(CsvPeekError::Usage(format!(
+            "The {} matching rows need more than --max-memory {}; use --raw or --format csv (without --footer) to stream them, or narrow the filter.",
+            matches.len(), format_size(budget)
+        )));
+    }
+    let (names, indices) = display_columns(args, headers)?;
+    let replacements = resolve_replacements(args, headers)?;
+    let style = CsvStyle { quote_style: args.quote_style, quote: args.quote_char };
+    let mut csv_out = (args.format == OutputFormat::Csv).then(|| output::csv_writer(std::io::stdout().lock(), &style));
+    if let Some(writer) = &mut csv_out {
+        writer.write_record(&names)?;
+    }
+    for chunk in matches.into_chunks()? {
+        let chunk = chunk?;
+        let refs: Vec<&csv::StringRecord> = chunk.iter().collect();
+        for row in project_rows(args, &replacements, &refs, &indices) {
+            match &mut csv_out {
+                Some(writer) => writer.write_record(&row)?,
+                None => print!("{}{}", row.join(&args.output_delimiter), record_terminator(args)),
+            }
+        }
+    }
+    if let Some(writer) = &mut csv_out {
+        writer.flush()?;
+    }
+    Ok(true)
+}
+
+/// Prints the number of data rows. A single file, URL or stdin is scanned without decoding its
+/// fields; inputs that have to be merged are loaded first.
+fn count_rows(args: &Args) -> Result<()> {
+    let counting = Instant::now();
+    let rows = match open_stream(args, &read_options(args, None))? {
+        Some(stream) => stream.count_records()?,
+        None => load_input(args, true, None)?.1.len(),
+    };
+    report_time(&format!("Counting {} row(s)", rows), counting);
+    outln!("{}", rows);
+    Ok(())
+}
+
+/// Builds the sidecar index of the single local file given with -f.
+fn build_indexes(args: &Args, columns: &[String]) -> Result<()> {
+    let file_path = match args.data_file.as_slice() {
+        [file_path] if args.directory.is_none() && !is_url(&file_path.to_string_lossy()) && file_path.to_string_lossy() != "-"
+            && split_zip_spec(file_path).is_none() && !is_converted(file_path) => file_path,
+        _ => return Err(CsvPeekError::Usage("Indexes can only be built for a single local CSV file given with -f.".to_string())),
+    };
+    let started = Instant::now();
+    let (index, rows) = build_index(file_path, columns, &read_options(args, None))?;
+    report_time(&format!("Indexing {} record(s)", rows), started);
+    if !args.quiet {
+        for column in columns {
+            let values = index.column(column).map_or(0, |values| values.len());
+            outln!("Indexed column '{}' of {}: {} distinct value(s) in {} row(s).", column, display_path(file_path), values, rows);
+        }
+        outln!("Wrote index {}", display_path(&index_path(file_path)));
+    }
+    Ok(())
+}
+
+/// Reads only the rows an equality filter can match when the input is a single local file with
+/// a current sidecar index on the filtered column. Returns `None` when no index applies.
+/// Columns changed by --fill or --cast on load are not looked up, as their indexed values differ.
+fn load_indexed(args: &Args) -> Result<Option<CsvData>> {
+    let [file_path] = args.data_file.as_slice() else {
+        return Ok(None);
+    };
+    let name = file_path.to_string_lossy();
+    if !args.list || args.directory.is_some() || args.fill_all.is_some() || args.with_row_number.is_some() || is_url(&name) || name == "-" || split_zip_spec(file_path).is_some() || is_converted(file_path) {
+        return Ok(None);
+    }
+    let path = index_path(file_path);
+    let Some(index) = load_index(&path)? else {
+        return Ok(None);
+    };
+    let read = read_options(args, None);
+    if !index.is_current(file_path, &read)? {
+        log_warn!("Ignoring out-of-date index '{}'; rebuild it with csvpeek-rs index build.", display_path(&path));
+        return Ok(None);
+    }
+    let loaded_as_is = |column: &str| {
+        !args.fill.iter().any(|(fill, _)| eq_ignore_case(fill, column)) && !args.cast.iter().any(|(cast, _)| eq_ignore_case(cast, column))
+    };
+    let Some(starts) = args.filter.iter().flatten()
+        .filter(|(column, op, _)| *op == Operator::Eq && loaded_as_is(column))
+        .find_map(|(column, _, value)| index.lookup(column, value))
+    else {
+        return Ok(None);
+    };
+    if !be_quiet(args) {
+        outln!("Reading CSV file: {}", display_path(file_path));
+    }
+    log_info!("Using index '{}' to read {} candidate row(s).", display_path(&path), starts.len());
+    let records = index.read_records(file_path, starts, &read)?;
+    Ok(Some((index.headers.clone(), records)))
+}
+
+/// Checks for `--quiet-exists` whether any row matches, reading only up to the first match
+/// when the input can be streamed.
+fn any_match(args: &Args) -> Result<bool> {
+    if let Some((_, matches)) = scan_matches(args, 1)? {
+        return Ok(!matches.is_empty());
+    }
+    let (headers, records) = load_input(args, true, None)?;
+    let records = prepare_records(args, &headers, records, None)?;
+    run_query(args, &describe_source(args), &headers, &records)
+}
+
+/// Prints the --directory files with at least one row that passes the filters, for
+/// --files-with-matches. Returns whether any file matched.
+fn files_with_matches(args: &Args) -> Result<bool> {
+    let Some(dir_path) = &args.directory else {
+        return Ok(false);
+    };
+    let read = read_options(args, None);
+    let mut matched = false;
+    for source in directory_sources(dir_path, &directory_options(args))? {
+        let mut stream = source.open_stream(&read)?;
+        let headers = stream.headers().to_vec();
+        let resolved = Preparation::resolve(args, &headers).and_then(|preparation| Ok((preparation, RowFilter::resolve(args, &headers)?)));
+        let (preparation, row_filter) = match resolved {
+            Ok(resolved) => resolved,
+            Err(CsvPeekError::ColumnNotFound { .. }) => continue,
+            Err(e) => return Err(e),
+        };
+        let mut skipped = 0;
+        for record in stream.by_ref() {
+            let record = match preparation.prepare(&record?) {
+                Ok(record) => record,
+                Err(_) if args.skip_bad_rows => {
+                    skipped += 1;
+                    continue;
+                }
+                Err(e) => return Err(e.in_file(&source)),
+            };
+            if row_filter.matches(&record) {
+                outln!("{}", source);
+                matched = true;
+                break;
+            }
+        }
+        if skipped > 0 {
+            log_warn!("Skipped {} row(s) of {} with values that could not be cast.", skipped, source);
+        }
+    }
+    Ok(matched)
+}
+
+/// Writes `rows` as numbered CSV files of at most `split_rows` rows for --split-rows.
+fn write_split_files(args: &Args, headers: &[String], rows: &[Vec<String>], split_rows: NonZeroUsize) -> Result<()> {
+    let style = CsvStyle { quote_style: args.quote_style, quote: args.quote_char };
+    for (index, chunk) in rows.chunks(split_rows.get()).enumerate() {
+        let path = PathBuf::from(format!("{}{:04}.csv", args.output_prefix, index + 1));
+        let file = std::fs::File::create(&path).map_err(|e| CsvPeekError::from(e).in_file(path.display()))?;
+        output::write_csv(std::io::BufWriter::new(file), headers, chunk, &style).map_err(|e| CsvPeekError::from(e).in_file(path.display()))?;
+        if !be_quiet(args) {
+            outln!("Wrote {} row(s) to {}", chunk.len(), path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Writes `rows` to one CSV file per distinct value in `values` (one per row) for --split-by.
+/// Files are named after the value, with characters unsafe in file names replaced by `_`.
+fn write_files_by_value(args: &Args, headers: &[String], rows: &[Vec<String>], values: &[&str]) -> Result<()> {
+    let mut groups: Vec<(String, Vec<Vec<String>>)> = Vec::new();
+    for (row, value) in rows.iter().zip(values) {
+        let name: String = value.trim().chars().map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' }).collect();
+        let name = if name.is_empty() || name.chars().all(|c| c == '.') { "empty".to_string() } else { name };
+        match groups.iter_mut().find(|(group, _)| *group == name) {
+            Some((_, group_rows)) => group_rows.push(row.clone()),
+            None => groups.push((name, vec![row.clone()])),
+        }
+    }
+    std::fs::create_dir_all(&args.output_dir).map_err(|e| CsvPeekError::from(e).in_file(args.output_dir.display()))?;
+    let style = CsvStyle { quote_style: args.quote_style, quote: args.quote_char };
+    for (name, group_rows) in &groups {
+        let path = args.output_dir.join(format!("{}.csv", name));
+        let file = std::fs::File::create(&path).map_err(|e| CsvPeekError::from(e).in_file(path.display()))?;
+        output::write_csv(std::io::BufWriter::new(file), headers, group_rows, &style).map_err(|e| CsvPeekError::from(e).in_file(path.display()))?;
+        if !be_quiet(args) {
+            outln!("Wrote {} row(s) to {}", group_rows.len(), path.display());
+        }
+    }
+    Ok(())
+}
+
+/// The table that --to-sqlite and --format sql write to.
+fn sql_table(args: &Args) -> &str {
+    args.table.as_deref().unwrap_or("peek")
+}
+
+/// Writes `rows` to the --output workbook of --format xlsx.
+fn write_workbook(args: &Args, headers: &[String], rows: &[Vec<String>]) -> Result<()> {
+    let path = args.output.as_ref().ok_or_else(|| CsvPeekError::Usage("--format xlsx needs --output <PATH>.".to_string()))?;
+    write_xlsx(path, headers, rows)?;
+    if !args.quiet {
+        outln!("Wrote {} row(s) to {}", rows.len(), display_path(path));
+    }
+    Ok(())
+}
+
+/// Writes a derived table such as a --crosstab in the --format or --raw output asked for.
+/// Returns false, writing nothing, for the text listing, which each table lays out itself.
+fn write_machine_rows(args: &Args, headers: &[String], rows: &[Vec<String>]) -> Result<bool> {
+    match args.format {
+        OutputFormat::Csv => {
+            let style = CsvStyle { quote_style: args.quote_style, quote: args.quote_char };
+            output::write_csv(std::io::stdout().lock(), headers, rows, &style)?;
+        }
+        OutputFormat::Xlsx => write_workbook(args, headers, rows)?,
+        OutputFormat::Sql => write_inserts(std::io::stdout().lock(), sql_table(args), headers, rows, args.rows_per_insert.get())?,
+        OutputFormat::Latex => output::write_latex(std::io::stdout().lock(), headers, rows)?,
+        _ if args.raw => {
+            for row in std::iter::once(headers).chain(rows.iter().map(Vec::as_slice)) {
+                print!("{}{}", row.join(&args.output_delimiter), record_terminator(args));
+            }
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Prints the --corr coefficients of the matching records: a sentence for two columns or a
+/// matrix for more as text, and one `x,y,coefficient,rows` row per pair otherwise.
+fn write_correlations(args: &Args, source: &str, headers: &[String], columns: &CorrColumns, records: &[&csv::StringRecord]) -> Result<()> {
+    let indices: Vec<usize> = match columns {
+        CorrColumns::Columns(names) => names.iter().map(|name| find_column(headers, name, "Correlation")).collect::<Result<_>>()?,
+        CorrColumns::All => {
+            let owned: Vec<csv::StringRecord> = records.iter().map(|record| (*record).clone()).collect();
+            let numeric: Vec<usize> = infer_schema(headers, &owned).iter().enumerate()
+                .filter(|(_, column)| matches!(column.column_type, ColumnType::Integer | ColumnType::Float))
+                .map(|(index, _)| index)
+                .collect();
+            if numeric.len() < 2 {
+                return Err(CsvPeekError::Usage("--corr all needs at least two numeric columns in the matching rows.".to_string()));
+            }
+            numeric
+        }
+    };
+    let method = args.corr_method;
+    let coefficient = |correlation: &Correlation| correlation.coefficient.map_or("-".to_string(), |c| format!("{:.4}", c));
+    let pairs: Vec<(usize, usize, Correlation)> = indices.iter().enumerate()
+        .flat_map(|(i, &x)| indices[i + 1..].iter().map(move |&y| (x, y)))
+        .map(|(x, y)| (x, y, correlate(records, x, y, method)))
+        .collect();
+    let rows: Vec<Vec<String>> = pairs.iter()
+        .map(|(x, y, correlation)| vec![headers[*x].clone(), headers[*y].clone(), coefficient(correlation), correlation.pairs.to_string()])
+        .collect();
+    if write_machine_rows(args, &["x", "y", "coefficient", "rows"].map(String::from), &rows)? {
+        return Ok(());
+    }
+    if let [(x, y, correlation)] = pairs.as_slice() {
+        let value = correlation.coefficient.map_or("undefined".to_string(), |c| format!("{:.4}", c));
+        outln!("{} correlation of {} and {} in {}: {} ({} row(s))", method, headers[*x], headers[*y], source, value, correlation.pairs);
+        if correlation.skipped > 0 {
+            outln!("Skipped {} row(s) where either cell is empty or not a number.", correlation.skipped);
+        }
+        return Ok(());
+    }
+    outln!("{} correlation matrix of {}:", method, source);
+    let names: Vec<&str> = indices.iter().map(|&index| headers[index].as_str()).collect();
+    let cell = |x: usize, y: usize| match pairs.iter().find(|(a, b, _)| (*a, *b) == (x, y) || (*a, *b) == (y, x)) {
+        Some((_, _, correlation)) => coefficient(correlation),
+        None => "1.0000".to_string(),
+    };
+    let table: Vec<Vec<String>> = std::iter::once(std::iter::once(String::new()).chain(names.iter().map(|name| name.to_string())).collect())
+        .chain(indices.iter().map(|&x| std::iter::once(headers[x].clone()).chain(indices.iter().map(|&y| cell(x, y))).collect()))
+        .collect();
+    let widths: Vec<usize> = (0..table[0].len()).map(|i| table.iter().map(|row| row[i].chars().count()).max().unwrap_or(0)).collect();
+    for row in &table {
+        let line: Vec<String> = row.iter().zip(&widths).enumerate()
+            .map(|(i, (cell, &width))| if i == 0 { format!("{:<width$}", cell) } else { format!("{:>width$}", cell) })
+            .collect();
+        outln!("{}", line.join("  ").trim_end());
+    }
+    Ok(())
+}
+
+/// The --value aggregate (--agg, summing by default), resolved against the headers.
+fn value_aggregate(args: &Args, headers: &[String]) -> Result<Option<ResolvedAggregate>> {
+    match &args.value {
+        Some(value) => {
+            let aggregate = Aggregate { func: args.agg.unwrap_or(AggregateFn::Sum), column: Some(value.clone()) };
+            Ok(Some(ResolvedAggregate::resolve(&aggregate, headers)?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// The --percent-of-total column as a sum to evaluate over records.
+fn total_aggregate(args: &Args, headers: &[String]) -> Result<Option<ResolvedAggregate>> {
+    match &args.percent_of_total {
+        Some(column) => {
+            let aggregate = Aggregate { func: AggregateFn::Sum, column: Some(column.clone()) };
+            Ok(Some(ResolvedAggregate::resolve(&aggregate, headers)?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Header of the --percent-of-total column.
+fn share_header(args: &Args, headers: &[String], total: &ResolvedAggregate) -> String {
+    let column = total.column.map_or_else(|| args.percent_of_total.clone().unwrap_or_default(), |idx| headers[idx].clone());
+    format!("% of {}", column)
+}
+
+/// `value` as a --percent-of-total share with --percent-precision decimals; empty without a
+/// value or total.
+fn format_share(args: &Args, value: Option<f64>, total: f64) -> String {
+    value.and_then(|value| share_of_total(value, total))
+        .map_or_else(String::new, |share| format!("{:.*}", args.percent_precision, share))
+}
+
+/// Prints an aligned table under a title, the first column to the left and the others to the
+/// right.
+fn write_aligned(args: &Args, title: &str, rows: &[Vec<String>]) {
+    let paint = Paint { enabled: args.color.enabled(std::io::stdout().is_terminal()), highlight: Vec::new() };
+    outln!("{}", paint.title(title));
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|i| rows.iter().map(|row| row[i].chars().count()).max().unwrap_or(0))
+        .collect();
+    for row in rows {
+        let line: Vec<String> = row.iter().zip(&widths).enumerate()
+            .map(|(i, (cell, &width))| if i == 0 { format!("{:<width$}", cell) } else { format!("{:>width$}", cell) })
+            .collect();
+        outln!("{}", line.join("  ").trim_end());
+    }
+}
+
+/// Prints the --group-by groups of the matching records with their counts and --value
+/// aggregates: aligned under a title as text, or in the --format or --raw output asked for.
+fn write_groups(args: &Args, source: &str, headers: &[String], group_by: &GroupBy, row_filter: &RowFilter, records: &[&csv::StringRecord]) -> Result<()> {
+    let column = find_column(headers, group_by.column(), "Group")?;
+    let unit = match group_by {
+        GroupBy::Column(_) => None,
+        GroupBy::DateTrunc(unit, _) => Some(*unit),
+    };
+    let value = value_aggregate(args, headers)?;
+    let groups = group_records(records, column, unit, args.date_format.as_deref(), value.as_ref());
+    let label = match unit {
+        Some(unit) => format!("{}({})", unit, headers[column]),
+        None => headers[column].clone(),
+    };
+    let total = total_aggregate(args, headers)?;
+    let shares = total.as_ref().map(|total| {
+        let sums = group_records(records, column, unit, args.date_format.as_deref(), Some(total)).values.unwrap_or_default();
+        let grand_total: f64 = sums.iter().filter_map(|sum| sum.as_number()).sum();
+        sums.iter().map(|sum| format_share(args, sum.as_number(), grand_total)).collect::<Vec<_>>()
+    });
+    let header: Vec<String> = [label, "count()".to_string()].into_iter()
+        .chain(value.iter().map(|value| value.aggregate.to_string()))
+        .chain(total.iter().map(|total| share_header(args, headers, total)))
+        .collect();
+    let rows: Vec<Vec<String>> = groups.keys.iter().zip(&groups.counts).enumerate()
+        .map(|(i, (key, count))| {
+            let aggregate = groups.values.as_ref().map(|values| values[i].to_string());
+            let share = shares.as_ref().map(|shares| shares[i].clone());
+            [key.clone(), count.to_string()].into_iter().chain(aggregate).chain(share).collect()
+        })
+        .collect();
+    if groups.skipped > 0 {
+        log_warn!("Skipped {} row(s) where {} is not a date.", groups.skipped, headers[column]);
+    }
+    if !write_machine_rows(args, &header, &rows)? {
+        let mut title = format!("Groups of {} by {}", source, group_by);
+        if !row_filter.descriptions.is_empty() {
+            title = format!("{} filtered where {}", title, row_filter.descriptions.join(" AND "));
+        }
+        write_aligned(args, &title, &std::iter::once(header).chain(rows).collect::<Vec<_>>());
+    }
+    Ok(())
+}
+
+/// Prints the --crosstab table of the matching records: aligned under a title as text, or as
+/// CSV or delimited raw lines with the column values as the first row.
+fn write_crosstab(args: &Args, source: &str, headers: &[String], (row_column, column_column): (&str, &str), row_filter: &RowFilter, records: &[&csv::StringRecord]) -> Result<()> {
+    let row = find_column(headers, row_column, "Crosstab")?;
+    let column = find_column(headers, column_column, "Crosstab")?;
+    let value = value_aggregate(args, headers)?;
+    let table = crosstab(records, row, column, value.as_ref());
+    let rows: Vec<Vec<String>> = std::iter::once(std::iter::once(headers[row].clone()).chain(table.column_values.iter().cloned()).collect())
+        .chain(table.row_values.iter().zip(&table.cells).map(|(value, cells)| {
+            std::iter::once(value.clone()).chain(cells.iter().map(|cell| cell.to_string())).collect()
+        }))
+        .collect();
+    if !write_machine_rows(args, &rows[0], &rows[1..])? {
+        let cell = value.as_ref().map_or("count()".to_string(), |value| value.aggregate.to_string());
+        let mut title = format!("Crosstab of {} by {} ({}) from {}", headers[row], headers[column], cell, source);
+        if !row_filter.descriptions.is_empty() {
+            title = format!("{} filtered where {}", title, row_filter.descriptions.join(" AND "));
+        }
+        write_aligned(args, &title, &rows);
+    }
+    Ok(())
+}
+
+/// Prints the --freq table of the matching records. For one column: each value with its count
+/// and percentage. For two: aligned as text, each cell as "count (row % / column %)" with
+/// totals, and otherwise one row per pair of values with its count and both percentages.
+fn write_freq(args: &Args, source: &str, headers: &[String], (row_column, column_column): (&str, Option<&str>), row_filter: &RowFilter, records: &[&csv::StringRecord]) -> Result<()> {
+    let row = find_column(headers, row_column, "Frequency")?;
+    let (title, rows) = match column_column {
+        None => {
+            let header = vec![headers[row].clone(), "count".to_string(), "percent".to_string()];
+            let rows: Vec<Vec<String>> = value_counts(records, row).into_iter()
+                .map(|(value, count)| vec![value, count.to_string(), format!("{:.1}", percent(count, records.len()))])
+                .collect();
+            if write_machine_rows(args, &header, &rows)? {
+                return Ok(());
+            }
+            let title = format!("Frequencies of {} from {}", headers[row], source);
+            (title, std::iter::once(header).chain(rows).collect::<Vec<_>>())
+        }
+        Some(column_column) => {
+            let column = find_column(headers, column_column, "Frequency")?;
+            let table = FreqTable::new(records, row, column);
+            let header = vec![
+                headers[row].clone(), headers[column].clone(),
+                "count".to_string(), "row_percent".to_string(), "column_percent".to_string(),
+            ];
+            let pairs: Vec<Vec<String>> = table.row_values.iter().enumerate()
+                .flat_map(|(r, row_value)| table.column_values.iter().enumerate().map(move |(c, column_value)| (r, row_value, c, column_value)))
+                .map(|(r, row_value, c, column_value)| vec![
+                    row_value.clone(), column_value.clone(), table.counts[r][c].to_string(),
+                    format!("{:.1}", table.row_percent(r, c)), format!("{:.1}", table.column_percent(r, c)),
+                ])
+                .collect();
+            if write_machine_rows(args, &header, &pairs)? {
+                return Ok(());
+            }
+            let total = table.total();
+            let rows: Vec<Vec<String>> = std::iter::once(
+                std::iter::once(headers[row].clone()).chain(table.column_values.iter().cloned()).chain(["Total".to_string()]).collect(),
+            )
+                .chain(table.row_values.iter().enumerate().map(|(r, value)| {
+                    std::iter::once(value.clone())
+                        .chain((0..table.column_values.len()).map(|c| {
+                            format!("{} ({:.1}% / {:.1}%)", table.counts[r][c], table.row_percent(r, c), table.column_percent(r, c))
+                        }))
+                        .chain([format!("{} ({:.1}%)", table.row_total(r), percent(table.row_total(r), total))])
+                        .collect()
+                }))
+                .chain([std::iter::once("Total".to_string())
+                    .chain((0..table.column_values.len()).map(|c| format!("{} ({:.1}%)", table.column_total(c), percent(table.column_total(c), total))))
+                    .chain([total.to_string()])
+                    .collect()])
+                .collect();
+            let title = format!("Frequencies of {} by {} (count, row % / column %) from {}", headers[row], headers[column], source);
+            (title, rows)
+        }
+    };
+    let title = if row_filter.descriptions.is_empty() {
+        title
     } else {
-        vec![headers.first().ok_or_else(|| Box::<dyn Error>::from("No headers found in data (cannot determine default display column)."))?.clone()]
+        format!("{} filtered where {}", title, row_filter.descriptions.join(" AND "))
     };
+    write_aligned(args, &title, &rows);
+    Ok(())
+}
+
+/// The displayed columns (--columns, or the first one) as named in the headers, with their indices.
+fn display_columns(args: &Args, headers: &[String]) -> Result<(Vec<String>, Vec<usize>)> {
+    let mut columns_to_display_names: Vec<String> = if let Some(ref specified_cols_args) = args.columns {
+        let mut valid_cols = Vec::new();
+        for col_name_arg in specified_cols_args {
+            if let Some(found_header) = headers.iter().find(|h| eq_ignore_case(h, col_name_arg)) {
+                valid_cols.push(found_header.clone());
+            } else {
+                return Err(CsvPeekError::ColumnNotFound { role: "Display".to_string(), column: col_name_arg.clone(), headers: headers.to_vec() });
+            }
+        }
+        if valid_cols.is_empty() { 
+            return Err(CsvPeekError::Usage("No valid display columns were specified (or provided list was empty).".to_string()));
+        }
+        valid_cols
+    } else {
+        // The first column of the data, after any row number column.
+        let first = usize::from(args.with_row_number.is_some());
+        vec![headers.get(first).or(headers.first()).ok_or(CsvPeekError::MissingHeaders)?.clone()]
+    };
+    if let Some(row_number) = &args.with_row_number {
+        if !columns_to_display_names.iter().any(|name| eq_ignore_case(name, row_number)) {
+            columns_to_display_names.insert(0, row_number.clone());
+        }
+    }
 
     let display_column_indices: Vec<usize> = columns_to_display_names.iter()
         .map(|name| headers.iter().position(|h| h == name).expect("Internal error: Validated display column name not found in headers during index lookup."))
         .collect();
+    Ok((columns_to_display_names, display_column_indices))
+}
+
+/// The --round digits of each displayed column, `None` for columns without a rule. Fails for
+/// rules naming a column the input lacks.
+fn resolve_rounding(args: &Args, headers: &[String], display: &[String]) -> Result<Vec<Option<usize>>> {
+    let rules = args.round.iter()
+        .map(|(column, digits)| Ok((find_column(headers, column, "Round")?, *digits)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(display.iter()
+        .map(|name| rules.iter().rev().find(|(idx, _)| headers[*idx] == *name).map(|(_, digits)| *digits))
+        .collect())
+}
+
+/// Applies --round and --thousands-separator to displayed rows, whose columns have the given
+/// `rounding`. Output meant for other programs is left alone without
+/// --format-numbers-everywhere; `human` tells whether these rows are for reading.
+fn format_numbers(args: &Args, rows: &mut [Vec<String>], rounding: &[Option<usize>], human: bool) {
+    if !human && !args.format_numbers_everywhere {
+        return;
+    }
+    let separator = args.thousands_separator.as_deref();
+    for row in rows {
+        for (cell, decimals) in row.iter_mut().zip(rounding) {
+            if decimals.is_none() && separator.is_none() {
+                continue;
+            }
+            if let Some(formatted) = format_decimal(cell, *decimals, separator) {
+                *cell = formatted;
+            }
+        }
+    }
+}
+
+/// The --replace rules with the index of the column each applies to.
+fn resolve_replacements<'a>(args: &'a Args, headers: &[String]) -> Result<Vec<(usize, &'a Replacement)>> {
+    args.replace.iter()
+        .map(|replacement| Ok((find_column(headers, &replacement.column, "Replace")?, replacement)))
+        .collect()
+}
+
+/// Runs the list or random-pick query described by `args` over already loaded data.
+/// `source` describes the input in titles and messages. Returns whether any row matched.
+fn run_query(args: &Args, source: &str, headers: &[String], records: &[csv::StringRecord]) -> Result<bool> {
+    if args.quiet_exists {
+        let row_filter = RowFilter::resolve(args, headers)?;
+        return Ok(records.iter().any(|record| row_filter.matches(record)));
+    }
+    let (mut columns_to_display_names, display_column_indices) = display_columns(args, headers)?;
+    let replacements = resolve_replacements(args, headers)?;
 
     if args.list {
+        let total = total_aggregate(args, headers)?;
+        columns_to_display_names.extend(total.iter().map(|total| share_header(args, headers, total)));
         let mut list_title = String::new();
         if !args.raw {
             let display_cols_str = columns_to_display_names.join(", ");
-            let source_name_str = if let Some(dir_path) = &args.directory {
-                format!("directory '{}'", dir_path.display())
-            } else if let Some(file_path) = &args.data_file {
-                 if file_path.to_string_lossy() == "-" { "stdin".to_string() }
-                 else { format!("file '{}'", file_path.display()) }
-            } else { 
-                "stdin".to_string() 
-            };
-            list_title = format!("List from {} (displaying column(s): {})", source_name_str, display_cols_str);
+            list_title = format!("List from {} (displaying column(s): {})", source, display_cols_str);
         }
 
-        let records_to_process_refs: Vec<&csv::StringRecord> = if let Some(raw_filters) = &args.filter {
-            let mut validated_filters: Vec<(usize, Operator, String)> = Vec::new();
-            for (user_col_name, op, val_str) in raw_filters {
-                if let Some(idx) = headers.iter().position(|h| h.eq_ignore_ascii_case(user_col_name)) {
-                    validated_filters.push((idx, *op, val_str.clone()));
-                } else {
-                    if !args.raw {
-                       eprintln!("Error: Filter column '{}' not found in CSV file headers: {:?}", user_col_name, headers);
-                    }
-                    std::process::exit(1);
-                }
+        let row_filter = RowFilter::resolve(args, headers)?;
+        let is_filtered = !row_filter.descriptions.is_empty();
+        warn_mixed_numbers(headers, records, &row_filter);
+
+        if !args.raw && is_filtered {
+            list_title = format!("{} filtered where {}", list_title, row_filter.descriptions.join(" AND "));
+        }
+        if let (false, Some((limit, largest)), Some(by)) = (args.raw, ranking(args), &args.by) {
+            list_title = format!("{}, {} {} by {}", list_title, if largest { "top" } else { "bottom" }, limit, by);
+        }
+
+        let footer_aggregates: Vec<ResolvedAggregate> = args.footer.iter()
+            .map(|aggregate| ResolvedAggregate::resolve(aggregate, headers))
+            .collect::<Result<_>>()?;
+
+        let filtering = Instant::now();
+        let mut records_to_process_refs = filter_records(records, &row_filter, result_limit(args));
+        // Shares are of the total over every matching row, before --top or --first narrow them.
+        let share_total = total.map(|total| {
+            let sum = match result_limit(args) {
+                Some(_) => total.evaluate(&filter_records(records, &row_filter, None)),
+                None => total.evaluate(&records_to_process_refs),
+            };
+            (total, sum.as_number().unwrap_or(0.0))
+        });
+        if let Some((limit, largest)) = ranking(args) {
+            let idx = find_column(headers, args.by.as_deref().unwrap_or_default(), "Ranking")?;
+            let mut ranked = TopN::new(limit, largest).natural(args.natural_sort);
+            for record in records_to_process_refs {
+                ranked.push(record, |record| record.get(idx).unwrap_or_default());
             }
-            
-            if !args.raw && !validated_filters.is_empty() {
-                let filter_descriptions: Vec<String> = raw_filters.iter() 
-                    .map(|(col, op, val)| format!("{} {} '{}'", col, op, val)) 
-                    .collect();
-                list_title = format!("{} filtered where {}", list_title, filter_descriptions.join(" AND "));
-            }
-            
-            records.iter().filter(|record| {
-                validated_filters.iter().all(|(col_idx, operator, filter_value_str)| {
-                    if let Some(value_in_record_str) = record.get(*col_idx) {
-                        match operator {
-                            Operator::Eq => value_in_record_str.eq_ignore_ascii_case(filter_value_str),
-                            Operator::NotEq => !value_in_record_str.eq_ignore_ascii_case(filter_value_str),
-                            Operator::Lt | Operator::Gt | Operator::LtEq | Operator::GtEq => {
-                                let record_num_res = value_in_record_str.trim().parse::<f64>();
-                                let filter_num_res = filter_value_str.trim().parse::<f64>();
-                                if let (Ok(record_num), Ok(filter_num)) = (record_num_res, filter_num_res) {
-                                    match operator {
-                                        Operator::Lt => record_num < filter_num,
-                                        Operator::Gt => record_num > filter_num,
-                                        Operator::LtEq => record_num <= filter_num,
-                                        Operator::GtEq => record_num >= filter_num,
-                                        _ => false, 
-                                    }
-                                } else { 
-                                    match operator {
-                                        Operator::Lt => value_in_record_str < filter_value_str,
-                                        Operator::Gt => value_in_record_str > filter_value_str,
-                                        Operator::LtEq => value_in_record_str <= filter_value_str,
-                                        Operator::GtEq => value_in_record_str >= filter_value_str,
-                                        _ => false, 
-                                    }
-                                }
-                            }
-                        }
-                    } else { false } 
-                })
-            }).collect()
-        } else {
-            records.iter().collect()
-        };
+            records_to_process_refs = ranked.into_sorted();
+        }
+        report_time(&format!("Filtering to {} record(s)", records_to_process_refs.len()), filtering);
+        check_assertions(&args.assertions, headers, &records_to_process_refs)?;
 
-        if !args.raw { 
+        if let Some(columns) = &args.corr {
+            write_correlations(args, source, headers, columns, &records_to_process_refs)?;
+            return Ok(!records_to_process_refs.is_empty());
+        }
+
+        if let Some(group_by) = &args.group_by {
+            write_groups(args, source, headers, group_by, &row_filter, &records_to_process_refs)?;
+            return Ok(!records_to_process_refs.is_empty());
+        }
+
+        if let Some((row_column, column_column)) = &args.crosstab {
+            write_crosstab(args, source, headers, (row_column, column_column), &row_filter, &records_to_process_refs)?;
+            return Ok(!records_to_process_refs.is_empty());
+        }
+
+        if let Some((row_column, column_column)) = &args.freq {
+            write_freq(args, source, headers, (row_column, column_column.as_deref()), &row_filter, &records_to_process_refs)?;
+            return Ok(!records_to_process_refs.is_empty());
+        }
+
+        let rendering = Instant::now();
+        // The text listing is collected first so that long output can be shown in a pager.
+        let mut listing: Vec<u8> = Vec::new();
+        let mut rows = project_rows(args, &replacements, &records_to_process_refs, &display_column_indices);
+        if let Some((total, sum)) = &share_total {
+            let idx = total.column.unwrap_or_default();
+            for (row, record) in rows.iter_mut().zip(&records_to_process_refs) {
+                row.push(format_share(args, record.get(idx).and_then(parse_number), *sum));
+            }
+        }
+        let human = args.format == OutputFormat::Text && !args.raw && args.to_sqlite.is_none() && args.split_rows.is_none() && args.split_by.is_none();
+        format_numbers(args, &mut rows, &resolve_rounding(args, headers, &columns_to_display_names)?, human);
+        if let Some(path) = &args.to_sqlite {
+            let table = sql_table(args);
+            write_table(path, table, &columns_to_display_names, &rows)?;
+            if !be_quiet(args) {
+                outln!("Wrote {} row(s) to table '{}' in {}", rows.len(), table, display_path(path));
+            }
+        } else if let Some(split_rows) = args.split_rows {
+            write_split_files(args, &columns_to_display_names, &rows, split_rows)?;
+        } else if let Some(column) = &args.split_by {
+            let idx = find_column(headers, column, "Split")?;
+            let values: Vec<&str> = records_to_process_refs.iter().map(|record| record.get(idx).unwrap_or_default()).collect();
+            write_files_by_value(args, &columns_to_display_names, &rows, &values)?;
+        } else if args.format == OutputFormat::Csv {
+            let style = CsvStyle { quote_style: args.quote_style, quote: args.quote_char };
+            output::write_csv(std::io::stdout().lock(), &columns_to_display_names, &rows, &style)?;
+        } else if args.format == OutputFormat::Xlsx {
+            write_workbook(args, &columns_to_display_names, &rows)?;
+        } else if args.format == OutputFormat::Sql {
+            write_inserts(std::io::stdout().lock(), sql_table(args), &columns_to_display_names, &rows, args.rows_per_insert.get())?;
+        } else if args.format == OutputFormat::Latex {
+            output::write_latex(std::io::stdout().lock(), &columns_to_display_names, &rows)?;
+        } else if !args.raw { 
+            let mut rows = rows;
+            escape_rows(args, &mut rows, false);
             if records_to_process_refs.is_empty() {
-                if args.filter.is_some() { println!("No entries matched your filter."); }
+                if is_filtered { output::write_line(&mut listing, format_args!("No entries matched your filter."))?; }
             } else {
-                println!("{}", list_title);
-                let mut lines_buffer: Vec<String> = Vec::new();
-                for record_ref in &records_to_process_refs {
-                    let mut current_line_values = Vec::new();
-                    for &idx in &display_column_indices {
-                        let value = record_ref.get(idx).unwrap_or("[N/A]");
-                        current_line_values.push(value.to_string());
-                    }
-                    lines_buffer.push(current_line_values.join("\t"));
+                let highlight = display_column_indices.iter().enumerate()
+                    .filter(|(_, idx)| row_filter.filters.iter().any(|filter| filter.column == **idx))
+                    .map(|(position, _)| position)
+                    .collect();
+                let paint = Paint { enabled: args.color.enabled(std::io::stdout().is_terminal()), highlight };
+                output::write_line(&mut listing, format_args!("{}", paint.title(&list_title)))?;
+                output::write_line(&mut listing, format_args!("Number of entries: {}", rows.len()))?;
+                let width = if std::io::stdout().is_terminal() { output::terminal_width() } else { None };
+                let cell_limit = match (args.max_col_width, args.wrap) {
+                    (Some(MaxColWidth::Chars(max)), _) => Some(max),
+                    (Some(MaxColWidth::Auto), _) | (None, true) => width.and_then(|width| output::fit_col_width(&rows, width)),
+                    (None, false) => None,
+                };
+                let fitted_rows = match cell_limit {
+                    Some(max) => output::truncate_rows(&rows, max),
+                    None => rows.clone(),
+                };
+                let layout = output::resolve_layout(args.layout, &fitted_rows, width);
+                if layout == Layout::Vertical && args.layout == Layout::Auto && !args.quiet {
+                    output::write_line(&mut listing, format_args!("(Rows are wider than the terminal; showing records vertically. Use --layout table to keep one row per line.)"))?;
                 }
-                println!("Number of entries: {}", lines_buffer.len());
-                for (index, line_str) in lines_buffer.iter().enumerate() {
-                    println!("{}. {}", index + 1, line_str);
+                let first_number = (!args.no_index).then_some(args.start_index);
+                let out = &mut listing;
+                match (layout, cell_limit) {
+                    (Layout::Vertical, _) if args.wrap => output::write_vertical(out, &columns_to_display_names, &rows, first_number, &paint)?,
+                    (Layout::Vertical, _) => output::write_vertical(out, &columns_to_display_names, &fitted_rows, first_number, &paint)?,
+                    (_, Some(max)) if args.wrap => output::write_wrapped_table(out, &rows, max, first_number, &paint)?,
+                    _ => output::write_table(out, &fitted_rows, first_number, &paint)?,
                 }
             }
         } else { 
-            for record_ref in &records_to_process_refs {
-                let mut current_line_values = Vec::new();
-                for &idx in &display_column_indices {
-                    let value = record_ref.get(idx).unwrap_or(""); 
-                    current_line_values.push(value.to_string());
-                }
-                println!("{}", current_line_values.join("\t"));
+            let mut rows = rows;
+            escape_rows(args, &mut rows, true);
+            for row in &rows {
+                print!("{}{}", row.join(&args.output_delimiter), record_terminator(args));
             }
         }
+
+        if !footer_aggregates.is_empty() {
+            let summary: Vec<String> = footer_aggregates.iter()
+                .map(|aggregate| format!("{} = {}", aggregate.aggregate, aggregate.evaluate(&records_to_process_refs)))
+                .collect();
+            // Keep stdout pipeable in raw and CSV mode: the summary goes to stderr there.
+            if args.raw || args.format != OutputFormat::Text {
+                log::note(format_args!("Summary: {}", summary.join(" | ")));
+            } else {
+                output::write_line(&mut listing, format_args!("Summary: {}", summary.join(" | ")))?;
+            }
+        }
+        let use_pager = !args.no_pager && std::io::stdout().is_terminal();
+        report_time("Rendering", rendering);
+        output::page(&listing, use_pager)?;
+        Ok(!records_to_process_refs.is_empty())
     } else {
-        let mut rng = rand::rng();
-        if let Some(random_record) = records.choose(&mut rng) {
+        let picked = if args.render_fixture {
+            records.choose(&mut StdRng::seed_from_u64(FIXTURE_SEED))
+        } else {
+            records.choose(&mut rand::rng())
+        };
+        if let Some(random_record) = picked {
             let mut values_to_print = Vec::new();
             for &idx in &display_column_indices {
-                 let value = random_record.get(idx).unwrap_or_else(|| {
-                    if !args.raw { "[N/A]" } else { "" }
-                });
-                values_to_print.push(value.to_string());
+                values_to_print.push(display_cell(args, &replacements, random_record, idx));
             }
+            let rounding = resolve_rounding(args, headers, &columns_to_display_names)?;
+            format_numbers(args, std::slice::from_mut(&mut values_to_print), &rounding, !args.raw);
+            escape_rows(args, std::slice::from_mut(&mut values_to_print), args.raw);
 
             if !args.raw {
                 let display_cols_str = columns_to_display_names.join(", ");
-                let source_name = if let Some(dir_path) = &args.directory {
-                    format!("directory '{}'", dir_path.display())
-                } else if let Some(file_path) = &args.data_file {
-                    if file_path.to_string_lossy() == "-" { "stdin".to_string() }
-                    else { format!("file '{}'", file_path.display()) }
-                } else { 
-                    "stdin".to_string()
-                };
-                println!("Random entry (from column(s) '{}' in {}): {}", display_cols_str, source_name, values_to_print.join("\t"));
+                outln!("Random entry (from column(s) '{}' in {}): {}", display_cols_str, source, values_to_print.join("\t"));
             } else {
-                println!("{}", values_to_print.join("\t"));
+                print!("{}{}", values_to_print.join(&args.output_delimiter), record_terminator(args));
             }
         } else if !args.raw && !records.is_empty() {
-             println!("Could not select a random entry (unexpected).");
+             outln!("Could not select a random entry (unexpected).");
+        }
+        Ok(picked.is_some())
+    }
+}
+
+/// Number of records from which filtering and projection are spread over all cores.
+const PARALLEL_THRESHOLD: usize = 10_000;
+
+/// Warns about ordering filters against a number on columns that also hold text, whose text
+/// cells are compared as text and so may match or not regardless of their size.
+fn warn_mixed_numbers(headers: &[String], records: &[csv::StringRecord], row_filter: &RowFilter) {
+    for filter in &row_filter.filters {
+        if let Some((count, example)) = mixed_numeric_cells(records, filter) {
+            let column = &headers[filter.column];
+            log_warn!("Column '{}' mixes numbers and text: {} cell(s) such as '{}' are compared as text by the filter {} {} '{}'.",
+                column, count, example, column, filter.op, filter.value);
+        }
+    }
+}
+
+/// Keeps the records that pass `row_filter` in their original order, stopping after `limit`
+/// matches. Large inputs without a limit are filtered in parallel.
+fn filter_records<'a>(records: &'a [csv::StringRecord], row_filter: &RowFilter, limit: Option<usize>) -> Vec<&'a csv::StringRecord> {
+    match limit {
+        None if records.len() >= PARALLEL_THRESHOLD => records.par_iter().filter(|record| row_filter.matches(record)).collect(),
+        _ => records.iter().filter(|record| row_filter.matches(record)).take(limit.unwrap_or(usize::MAX)).collect(),
+    }
+}
+
+/// The displayed cells of each record, in parallel for large inputs.
+fn project_rows(args: &Args, replacements: &[(usize, &Replacement)], records: &[&csv::StringRecord], indices: &[usize]) -> Vec<Vec<String>> {
+    let project = |record: &&csv::StringRecord| indices.iter().map(|&idx| display_cell(args, replacements, record, idx)).collect();
+    if records.len() >= PARALLEL_THRESHOLD {
+        records.par_iter().map(project).collect()
+    } else {
+        records.iter().map(project).collect()
+    }
+}
+
+/// Escapes the line breaks and tabs in displayed cells as --escape asks for; `raw` tells
+/// whether the rows are printed with --raw, where backslashes are escaped too.
+fn escape_rows(args: &Args, rows: &mut [Vec<String>], raw: bool) {
+    let escape = match args.escape {
+        EscapeChoice::Auto => !raw,
+        EscapeChoice::Always => true,
+        EscapeChoice::Never => false,
+    };
+    if !escape {
+        return;
+    }
+    for cell in rows.iter_mut().flatten() {
+        if let Cow::Owned(escaped) = escape_cell(cell, raw) {
+            *cell = escaped;
+        }
+    }
+}
+
+/// At --log-level info, reports on stderr how long a phase that began at `started` took.
+fn report_time(phase: &str, started: Instant) {
+    log_info!("{} took {:.2?}.", phase, started.elapsed());
+}
+
+/// What ends each record in raw output.
+fn record_terminator(args: &Args) -> &'static str {
+    if args.print0 { "\0" } else { output::line_ending() }
+}
+
+/// Text shown for the cell in column `idx`: the value after any `--replace` rules for the column,
+/// or the `--null-value` placeholder for missing and empty cells if one is set. Without one,
+/// missing cells are shown as "[N/A]" (or nothing in raw mode).
+fn display_cell(args: &Args, replacements: &[(usize, &Replacement)], record: &csv::StringRecord, idx: usize) -> String {
+    let cell = record.get(idx).map(|value| {
+        replacements.iter()
+            .filter(|(column, _)| *column == idx)
+            .fold(value.to_string(), |value, (_, replacement)| replacement.apply(&value))
+    });
+    match (cell, &args.null_value) {
+        (Some(value), Some(null_value)) if value.is_empty() => null_value.clone(),
+        (None, Some(null_value)) => null_value.clone(),
+        (Some(value), _) => value,
+        (None, None) if args.raw || args.format != OutputFormat::Text => String::new(),
+        (None, None) => "[N/A]".to_string(),
+    }
+}
+
+/// Reads list queries from stdin, one per line, and runs each over the loaded data.
+/// Failing queries are reported in their result block; the batch fails if any query did.
+fn run_batch(args: &Args, headers: &[String], records: &[csv::StringRecord]) -> Result<()> {
+    if args.directory.is_none() && (args.data_file.is_empty() || args.data_file.iter().any(|p| p.to_string_lossy() == "-")) {
+        return Err(CsvPeekError::Usage("Batch mode reads queries from stdin; specify the data with -f <file> or -d <directory>.".to_string()));
+    }
+    let source = describe_source(args);
+    let mut failed = 0;
+    for (number, line) in std::io::stdin().lines().enumerate() {
+        let line = line?;
+        let query_line = line.trim();
+        if query_line.is_empty() || query_line.starts_with('#') {
+            continue;
+        }
+        outln!("==> query {}: {}", number + 1, query_line);
+        match parse_batch_query(args, query_line).and_then(|query| run_query(&query, &source, headers, records)) {
+            Ok(_) => outln!("<== query {}: ok", number + 1),
+            Err(e) => {
+                failed += 1;
+                outln!("<== query {}: error: {}", number + 1, e);
+            }
+        }
+        std::io::stdout().flush()?;
+    }
+    if failed > 0 {
+        return Err(CsvPeekError::Usage(format!("{} batch quer{} failed.", failed, if failed == 1 { "y" } else { "ies" })));
+    }
+    Ok(())
+}
+
+/// Parses one batch line into list-mode arguments, inheriting the parameters of the batch itself.
+fn parse_batch_query(args: &Args, line: &str) -> Result<Args> {
+    let tokens = shlex::split(line).ok_or_else(|| CsvPeekError::Usage("Unbalanced quotes in query.".to_string()))?;
+    let implied_list = !tokens.iter().any(|t| t == "--list" || t == "-l");
+    let argv = std::iter::once("csvpeek-rs".to_string())
+        .chain(implied_list.then(|| "--list".to_string()))
+        .chain(tokens);
+    let usage = |e: clap::Error| {
+        let message = e.to_string();
+        let first_line = message.lines().next().unwrap_or_default();
+        CsvPeekError::Usage(first_line.trim_start_matches("error: ").to_string())
+    };
+    let matches = Args::command().try_get_matches_from(argv).map_err(usage)?;
+    let mut query = Args::from_arg_matches(&matches).map_err(usage)?;
+    // Inputs named by CSVPEEK_DATA_FILE or CSVPEEK_DIRECTORY are the batch input itself.
+    let on_line = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    if on_line("data_file") || on_line("directory") || query.command.is_some() || query.headers || !query.cast.is_empty() || reshapes(&query) {
+        return Err(CsvPeekError::Usage("Batch queries cannot change the input or mode; use list options only.".to_string()));
+    }
+    if query.format == OutputFormat::Json {
+        return Err(CsvPeekError::Usage("'--format json' can only be used with the headers subcommand".to_string()));
+    }
+    query.param.splice(0..0, args.param.iter().cloned());
+    query.apply_params()?;
+    query.apply_raw_env();
+    if query.vertical {
+        query.layout = Layout::Vertical;
+    }
+    query.render_fixture = args.render_fixture;
+    query.no_pager = true;
+    query.cast = args.cast.clone();
+    Ok(query)
+}
+
+/// Loads the whole input while logging bad records, then prints every problem found. Returns
+/// whether the input had none.
+fn report_errors(args: &Args, format: ReportFormat) -> Result<bool> {
+    let log = ProblemLog::default();
+    let loaded = load_input(args, true, Some(&log))
+        .and_then(|(headers, records)| prepare_records(args, &headers, records, Some(&log)));
+    if let Err(e) = loaded {
+        log.push(e);
+    }
+    let problems = log.take();
+    let source = describe_source(args);
+    match format {
+        ReportFormat::Text if problems.is_empty() => outln!("No problems found in {}.", source),
+        ReportFormat::Text => {
+            outln!("Found {} problem(s) in {}:", problems.len(), source);
+            for problem in &problems {
+                match problem {
+                    CsvPeekError::Parse { file, line, message } => outln!(
+                        "  {}:{}: {}",
+                        file.as_deref().unwrap_or("<input>"),
+                        line.map_or("?".to_string(), |l| l.to_string()),
+                        message
+                    ),
+                    other => outln!("  {}", other),
+                }
+            }
+        }
+        ReportFormat::Json => {
+            let entries: Vec<serde_json::Value> = problems.iter()
+                .map(|problem| match problem {
+                    CsvPeekError::Parse { file, line, message } => serde_json::json!({ "file": file, "line": line, "message": message }),
+                    other => serde_json::json!({ "file": null, "line": null, "message": other.to_string() }),
+                })
+                .collect();
+            let report = serde_json::json!({ "source": source, "problem_count": problems.len(), "problems": entries });
+            outln!("{}", serde_json::to_string_pretty(&report).map_err(|e| CsvPeekError::Usage(e.to_string()))?);
+        }
+    }
+    Ok(problems.is_empty())
+}
+
+/// Reads the raw bytes of every input, lints them and prints the findings per input. Returns
+/// whether there were none.
+fn validate_input(args: &Args, format: ReportFormat) -> Result<bool> {
+    let read = read_options(args, None);
+    let read_all = |mut reader: Box<dyn std::io::Read>| -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    };
+    let stdin_input = || -> Result<(String, Vec<u8>, ReadOptions)> {
+        let mut options = read.clone();
+        options.delimiter.get_or_insert(b',');
+        Ok(("stdin".to_string(), read_all(Box::new(std::io::stdin()))?, options))
+    };
+    // Each input with the read options resolved for it, as the loaders would.
+    let mut inputs: Vec<(String, Vec<u8>, ReadOptions)> = Vec::new();
+    let sources = match (&args.directory, args.data_file.as_slice()) {
+        (Some(dir_path), _) => directory_sources(dir_path, &directory_options(args))?,
+        (None, []) => {
+            inputs.push(stdin_input()?);
+            Vec::new()
+        }
+        (None, [path]) if path.to_string_lossy() == "-" => {
+            inputs.push(stdin_input()?);
+            Vec::new()
+        }
+        (None, [path]) if is_url(&path.to_string_lossy()) => {
+            let url = path.to_string_lossy().to_string();
+            let bytes = read_all(Box::new(open_url(&url, &args.header)?))?;
+            let options = read.for_name(url.split(['?', '#']).next().unwrap_or(&url));
+            inputs.push((url, bytes, options));
+            Vec::new()
+        }
+        (None, paths) => file_sources(paths)?,
+    };
+    for source in sources {
+        let name = source.to_string();
+        let options = read.for_name(&name);
+        inputs.push((name, source.read_bytes()?, options));
+    }
+    if inputs.is_empty() {
+        return Err(CsvPeekError::NoSources { origin: describe_source(args) });
+    }
+
+    let mut results: Vec<(String, Vec<Finding>)> = Vec::new();
+    for (name, bytes, options) in inputs {
+        let (bytes, check_utf8) = match options.encoding {
+            Some(encoding) => (encoding.decode(&bytes).0.into_owned().into_bytes(), false),
+            None => (bytes, true),
+        };
+        // Skipped banner lines are not checked, but line numbers still count them.
+        let mut start = 0;
+        for _ in 0..options.skip_rows {
+            start = bytes[start..].iter().position(|&b| b == b'\n').map_or(bytes.len(), |pos| start + pos + 1);
+        }
+        let data = &bytes[start..];
+        let validate_options = ValidateOptions {
+            delimiter: options.delimiter.unwrap_or_else(|| sniff_header_delimiter(data, options.comment)),
+            comment: options.comment,
+            check_utf8,
+        };
+        let mut findings = validate_csv(data, &validate_options);
+        for finding in &mut findings {
+            finding.line = finding.line.map(|line| line + options.skip_rows as u64);
+        }
+        results.push((name, findings));
+    }
+
+    let total: usize = results.iter().map(|(_, findings)| findings.len()).sum();
+    match format {
+        ReportFormat::Text => {
+            for (name, findings) in &results {
+                if findings.is_empty() {
+                    outln!("{}: OK", name);
+                } else {
+                    outln!("{}: {} finding(s)", name, findings.len());
+                    for finding in findings {
+                        outln!("  {}", finding);
+                    }
+                }
+            }
+            let failing = results.iter().filter(|(_, findings)| !findings.is_empty()).count();
+            outln!("{} finding(s) in {} of {} input(s).", total, failing, results.len());
+        }
+        ReportFormat::Json => {
+            let inputs: Vec<serde_json::Value> = results.iter()
+                .map(|(name, findings)| serde_json::json!({
+                    "input": name,
+                    "findings": findings.iter()
+                        .map(|finding| serde_json::json!({ "line": finding.line, "message": finding.message }))
+                        .collect::<Vec<_>>(),
+                }))
+                .collect();
+            let report = serde_json::json!({ "finding_count": total, "inputs": inputs });
+            outln!("{}", serde_json::to_string_pretty(&report).map_err(|e| CsvPeekError::Usage(e.to_string()))?);
+        }
+    }
+    Ok(total == 0)
+}
+
+/// Prints the header names one per line, or with --detailed a table profiling each column.
+/// With --format csv or json, the same information is printed as CSV rows or a JSON array.
+fn print_headers(args: &Args, headers: &[String], records: &[csv::StringRecord]) -> Result<()> {
+    if headers.is_empty() {
+        log_warn!("No headers found or could be determined from the input source.");
+        return Ok(());
+    }
+    let style = CsvStyle { quote_style: args.quote_style, quote: args.quote_char };
+    if !args.detailed {
+        match args.format {
+            OutputFormat::Csv => {
+                let rows: Vec<Vec<String>> = headers.iter().map(|name| vec![name.clone()]).collect();
+                output::write_csv(std::io::stdout().lock(), &["name".to_string()], &rows, &style)?;
+            }
+            OutputFormat::Json => outln!("{}", serde_json::to_string_pretty(headers).map_err(|e| CsvPeekError::Usage(e.to_string()))?),
+            _ => {
+                for header_name in headers {
+                    outln!("{}", header_name);
+                }
+            }
+        }
+        return Ok(());
+    }
+    let profiles = profile_columns(headers, records);
+    if args.format == OutputFormat::Json {
+        let columns: Vec<serde_json::Value> = profiles.iter().enumerate()
+            .map(|(index, profile)| serde_json::json!({
+                "index": index + 1,
+                "name": profile.schema.name,
+                "type": profile.schema.column_type.to_string(),
+                "non_empty": profile.non_empty,
+                "distinct": profile.distinct,
+                "examples": profile.schema.examples,
+            }))
+            .collect();
+        outln!("{}", serde_json::to_string_pretty(&columns).map_err(|e| CsvPeekError::Usage(e.to_string()))?);
+        return Ok(());
+    }
+    let rows: Vec<[String; 6]> = profiles.into_iter()
+        .enumerate()
+        .map(|(index, profile)| [
+            (index + 1).to_string(),
+            profile.schema.name,
+            profile.schema.column_type.to_string(),
+            profile.non_empty.to_string(),
+            profile.distinct.to_string(),
+            profile.schema.examples.join(if args.format == OutputFormat::Csv { "|" } else { ", " }),
+        ])
+        .collect();
+    if args.format == OutputFormat::Csv {
+        let titles = ["index", "name", "type", "non_empty", "distinct", "examples"].map(String::from);
+        let rows: Vec<Vec<String>> = rows.into_iter().map(Vec::from).collect();
+        output::write_csv(std::io::stdout().lock(), &titles, &rows, &style)?;
+        return Ok(());
+    }
+    let titles = ["#", "Column", "Type", "Non-empty", "Distinct", "Examples"];
+    let widths: Vec<usize> = (0..5)
+        .map(|i| rows.iter().map(|row| row[i].chars().count()).chain([titles[i].len()]).max().unwrap_or(0))
+        .collect();
+    for row in std::iter::once(titles.map(String::from)).chain(rows) {
+        outln!("{:>w0$}  {:<w1$}  {:<w2$}  {:>w3$}  {:>w4$}  {}", row[0], row[1], row[2], row[3], row[4], row[5],
+            w0 = widths[0], w1 = widths[1], w2 = widths[2], w3 = widths[3], w4 = widths[4]);
+    }
+    Ok(())
+}
+
+/// The rows per period of the --date-column for --profile, with the column's name, warning
+/// about cells that are not dates.
+fn profile_timeline(args: &Args, headers: &[String], records: &[csv::StringRecord]) -> Result<Option<(String, Timeline)>> {
+    let Some(column) = &args.date_column else {
+        return Ok(None);
+    };
+    let idx = find_column(headers, column, "Date")?;
+    let cells = records.iter().map(|record| record.get(idx).unwrap_or_default().trim()).filter(|cell| !cell.is_empty());
+    let dates: Vec<chrono::NaiveDate> = cells.clone().filter_map(|cell| parse_date(cell, args.date_format.as_deref())).collect();
+    let skipped = cells.count() - dates.len();
+    if skipped > 0 {
+        log_warn!("Skipped {} row(s) where {} is not a date.", skipped, headers[idx]);
+    }
+    Ok(timeline(&dates, MAX_TIMELINE_PERIODS).map(|timeline| (headers[idx].clone(), timeline)))
+}
+
+/// Prints the --profile report: distinct and empty counts, the constant and candidate key
+/// flags and, for numeric columns, a histogram of every column, and the --date-column timeline.
+fn print_profile(args: &Args, format: ReportFormat, headers: &[String], records: &[csv::StringRecord]) -> Result<()> {
+    let profiles = profile_columns(headers, records);
+    let histograms: Vec<Option<Vec<usize>>> = profiles.iter().enumerate()
+        .map(|(index, profile)| {
+            matches!(profile.schema.column_type, ColumnType::Integer | ColumnType::Float).then(|| {
+                let values: Vec<f64> = records.iter().filter_map(|record| parse_number(record.get(index).unwrap_or_default().trim())).collect();
+                histogram(&values, HISTOGRAM_BINS)
+            })
+        })
+        .collect();
+    let timeline = profile_timeline(args, headers, records)?;
+    match format {
+        ReportFormat::Text => {
+            outln!("Profile of {} ({} records):", describe_source(args), records.len());
+            let rows: Vec<[String; 6]> = profiles.iter().zip(&histograms)
+                .map(|(profile, histogram)| {
+                    let flags: Vec<&str> = [(profile.is_constant(), "constant"), (profile.is_candidate_key(), "candidate key")]
+                        .into_iter()
+                        .filter_map(|(set, flag)| set.then_some(flag))
+                        .collect();
+                    [
+                        profile.schema.name.clone(),
+                        profile.distinct.to_string(),
+                        profile.empty.to_string(),
+                        format!("{:.1}%", profile.empty_percent()),
+                        histogram.as_deref().map(sparkline).unwrap_or_default(),
+                        flags.join(", "),
+                    ]
+                })
+                .collect();
+            let titles = ["Column", "Distinct", "Empty", "Empty %", "Shape", "Flags"];
+            let widths: Vec<usize> = (0..5)
+                .map(|i| rows.iter().map(|row| row[i].chars().count()).chain([titles[i].len()]).max().unwrap_or(0))
+                .collect();
+            for row in std::iter::once(titles.map(String::from)).chain(rows) {
+                let line = format!("{:<w0$}  {:>w1$}  {:>w2$}  {:>w3$}  {:<w4$}  {}", row[0], row[1], row[2], row[3], row[4], row[5],
+                    w0 = widths[0], w1 = widths[1], w2 = widths[2], w3 = widths[3], w4 = widths[4]);
+                outln!("{}", line.trim_end());
+            }
+            if let Some((column, timeline)) = &timeline {
+                outln!("Rows per {} of {} from {} to {}: {}", timeline.unit, column, timeline.start, timeline.end(), sparkline(&timeline.counts));
+            }
+        }
+        ReportFormat::Json => {
+            let columns: Vec<serde_json::Value> = profiles.iter().zip(&histograms)
+                .map(|(profile, histogram)| serde_json::json!({
+                    "name": profile.schema.name,
+                    "distinct": profile.distinct,
+                    "empty": profile.empty,
+                    "empty_percent": profile.empty_percent(),
+                    "constant": profile.is_constant(),
+                    "candidate_key": profile.is_candidate_key(),
+                    "histogram": histogram,
+                    "sparkline": histogram.as_deref().map(sparkline),
+                }))
+                .collect();
+            let mut report = serde_json::json!({ "source": describe_source(args), "records": records.len(), "columns": columns });
+            if let Some((column, timeline)) = &timeline {
+                report["timeline"] = serde_json::json!({
+                    "column": column,
+                    "unit": timeline.unit.to_string(),
+                    "start": timeline.start.to_string(),
+                    "counts": timeline.counts,
+                    "sparkline": sparkline(&timeline.counts),
+                });
+            }
+            outln!("{}", serde_json::to_string_pretty(&report).map_err(|e| CsvPeekError::Usage(e.to_string()))?);
+        }
+    }
+    Ok(())
+}
+
+/// How many orphaned keys --check-fk shows per relation.
+const MAX_ORPHAN_EXAMPLES: usize = 5;
+
+/// Prints the orphaned keys of each --check-fk relation. Returns whether there were none.
+fn check_foreign_keys(args: &Args) -> Result<bool> {
+    let read = read_options(args, None);
+    let mut ok = true;
+    for fk in &args.check_fk {
+        let (child_headers, children) = load_data_from_csv(&fk.child, &read, true)?;
+        let (parent_headers, parents) = load_data_from_csv(&fk.parent, &read, true)?;
+        let child_column = find_column(&child_headers, &fk.child_column, "Foreign key")?;
+        let parent_column = find_column(&parent_headers, &fk.parent_column, "Referenced")?;
+        let orphans = orphaned_keys(&children, child_column, &parents, parent_column);
+        if orphans.is_empty() {
+            outln!("{}: OK", fk);
+            continue;
+        }
+        ok = false;
+        let rows: usize = orphans.iter().map(|(_, rows)| rows).sum();
+        let examples: Vec<String> = orphans.iter().take(MAX_ORPHAN_EXAMPLES)
+            .map(|(key, rows)| format!("'{}' ({} row(s))", key, rows))
+            .collect();
+        let more = orphans.len().saturating_sub(MAX_ORPHAN_EXAMPLES);
+        outln!("{}: {} orphaned row(s) with {} key(s) missing from {}: {}{}", fk, rows, orphans.len(), display_path(&fk.parent),
+            examples.join(", "), if more > 0 { format!(" and {} more", more) } else { String::new() });
+    }
+    Ok(ok)
+}
+
+/// Prints how the headers of the --compare-headers file differ from the input's. Returns
+/// whether they are the same.
+fn print_header_diff(args: &Args, other: &Path) -> Result<bool> {
+    let (headers, _) = load_input(args, false, None)?;
+    let (other_headers, _) = load_data_from_csv(other, &read_options(args, None), false)?;
+    let diff = compare_headers(&headers, &other_headers);
+    let source = describe_source(args);
+    let other_name = display_path(other);
+    if diff.is_empty() {
+        outln!("Headers of {} and '{}' match ({} column(s)).", source, other_name, headers.len());
+        return Ok(true);
+    }
+    outln!("Headers of {} and '{}' differ:", source, other_name);
+    if !diff.missing.is_empty() {
+        outln!("  missing from '{}': {}", other_name, diff.missing.join(", "));
+    }
+    if !diff.extra.is_empty() {
+        outln!("  extra in '{}': {}", other_name, diff.extra.join(", "));
+    }
+    if !diff.reordered.is_empty() {
+        let moves: Vec<String> = diff.reordered.iter().map(|(name, from, to)| format!("{} ({} -> {})", name, from, to)).collect();
+        outln!("  reordered: {}", moves.join(", "));
+    }
+    if !diff.case_differs.is_empty() {
+        let names: Vec<String> = diff.case_differs.iter().map(|(name, other)| format!("{} / {}", name, other)).collect();
+        outln!("  case differs: {}", names.join(", "));
+    }
+    Ok(false)
+}
+
+/// Prints the --schema-report of the --directory files: a column matrix with each file's fate
+/// in the merge, the column changes between files and the skipped files, or the same as JSON.
+fn print_schema_report(args: &Args, format: ReportFormat) -> Result<()> {
+    let dir_path = args.directory.as_deref().ok_or_else(|| CsvPeekError::Usage("--schema-report needs a --directory.".to_string()))?;
+    let options = directory_options(args);
+    let sources = directory_sources(dir_path, &options)?;
+    if sources.is_empty() {
+        return Err(CsvPeekError::NoSources { origin: describe_source(args) });
+    }
+    let main_header_source = options.main_header_file.as_ref().map(|name| CsvSource::File(dir_path.join(name)));
+    let report = schema_report(&sources, main_header_source.as_ref(), &read_options(args, None), &merge_options(args)?);
+    match format {
+        ReportFormat::Text => {
+            outln!("Schema of {} file(s) in {}:", report.sources.len(), describe_source(args));
+            let titles = std::iter::once("File").chain(report.columns.iter().map(String::as_str)).chain(["Merge"]);
+            let mut rows: Vec<Vec<String>> = vec![titles.map(String::from).collect()];
+            for source in &report.sources {
+                let marks = report.columns.iter().map(|column| match &source.headers {
+                    Some(headers) if headers.contains(column) => "x".to_string(),
+                    Some(_) => "-".to_string(),
+                    None => "?".to_string(),
+                });
+                rows.push(std::iter::once(source.source.clone()).chain(marks).chain([source.fate.to_string()]).collect());
+            }
+            let widths: Vec<usize> = (0..rows[0].len())
+                .map(|i| rows.iter().map(|row| row[i].chars().count()).max().unwrap_or(0))
+                .collect();
+            for row in &rows {
+                let line: Vec<String> = row.iter().zip(&widths).map(|(cell, &width)| format!("{:<width$}", cell)).collect();
+                outln!("{}", line.join("  ").trim_end());
+            }
+            let changes: Vec<(&str, &ColumnChanges)> = report.sources.iter()
+                .filter_map(|source| source.changes.as_ref().filter(|changes| !changes.is_empty()).map(|changes| (source.source.as_str(), changes)))
+                .collect();
+            if changes.is_empty() {
+                outln!("\nEvery file has the same columns.");
+            } else {
+                outln!("\nColumn changes:");
+                for (source, changes) in changes {
+                    outln!("  {}: {}", source, changes);
+                }
+            }
+            let skipped: Vec<&str> = report.sources.iter()
+                .filter(|source| !source.fate.is_merged())
+                .map(|source| source.source.as_str())
+                .collect();
+            if skipped.is_empty() {
+                outln!("Every file would be merged.");
+            } else {
+                outln!("{} file(s) would not be merged: {}", skipped.len(), skipped.join(", "));
+            }
+        }
+        ReportFormat::Json => {
+            let files: Vec<serde_json::Value> = report.sources.iter()
+                .map(|source| {
+                    let (merge, detail) = match &source.fate {
+                        MergeFate::Main => ("main", serde_json::Value::Null),
+                        MergeFate::Merged => ("merged", serde_json::Value::Null),
+                        MergeFate::ByPosition => ("by_position", serde_json::Value::Null),
+                        MergeFate::Union(added) => ("union", serde_json::json!(added)),
+                        MergeFate::Skipped(reason) => ("skipped", serde_json::json!(reason)),
+                        MergeFate::Aborts(reason) => ("fails_strict", serde_json::json!(reason)),
+                    };
+                    let changes = source.changes.as_ref().map(|changes| serde_json::json!({
+                        "added": changes.added,
+                        "removed": changes.removed,
+                        "renamed": changes.renamed.iter().map(|(from, to)| serde_json::json!({ "from": from, "to": to })).collect::<Vec<_>>(),
+                    }));
+                    serde_json::json!({ "file": source.source, "columns": source.headers, "merge": merge, "detail": detail, "changes": changes })
+                })
+                .collect();
+            let report = serde_json::json!({ "source": describe_source(args), "columns": report.columns, "files": files });
+            outln!("{}", serde_json::to_string_pretty(&report).map_err(|e| CsvPeekError::Usage(e.to_string()))?);
         }
     }
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_parse_filter_arg_valid_ops() {
-        assert_eq!(parse_filter_arg("Col=Val"), Ok(("Col".to_string(), Operator::Eq, "Val".to_string())));
-        assert_eq!(parse_filter_arg("Col!=Val"), Ok(("Col".to_string(), Operator::NotEq, "Val".to_string())));
-        assert_eq!(parse_filter_arg("Col>Val"), Ok(("Col".to_string(), Operator::Gt, "Val".to_string())));
-        assert_eq!(parse_filter_arg("Col<Val"), Ok(("Col".to_string(), Operator::Lt, "Val".to_string())));
-        assert_eq!(parse_filter_arg("Col>=Val"), Ok(("Col".to_string(), Operator::GtEq, "Val".to_string())));
-        assert_eq!(parse_filter_arg("Col<=Val"), Ok(("Col".to_string(), Operator::LtEq, "Val".to_string())));
-        assert_eq!(parse_filter_arg("  Col  >=  Val  "), Ok(("Col".to_string(), Operator::GtEq, "Val".to_string())));
-    }
-
-    #[test]
-    fn test_parse_filter_arg_invalid_ops_or_format() {
-        assert!(parse_filter_arg("ColVal").is_err()); 
-        assert!(parse_filter_arg("Col<>Val").is_err());
-        assert_eq!(parse_filter_arg("Col><Val"), Ok(("Col".to_string(), Operator::Gt, "<Val".to_string())));
-    }
-
-     #[test]
-     fn test_parse_filter_arg_empty_key_error() {
-         let result = parse_filter_arg("=Value");
-         assert!(result.is_err());
-         if let Err(e) = result {
-             assert!(e.contains("Column name cannot be empty"));
-         }
-
-         let result_op = parse_filter_arg(">=Value"); 
-         assert!(result_op.is_err());
-         if let Err(e) = result_op {
-             assert!(e.contains("Column name cannot be empty"));
-         }
-     }
-
-    #[test]
-    fn test_parse_filter_arg_empty_value_is_ok() {
-         assert_eq!(parse_filter_arg("Col="), Ok(("Col".to_string(), Operator::Eq, "".to_string())));
-         assert_eq!(parse_filter_arg("Col>="), Ok(("Col".to_string(), Operator::GtEq, "".to_string())));
+/// Prints the inferred schema of the loaded data as an aligned table or Frictionless Table Schema JSON.
+fn print_schema(args: &Args, format: ReportFormat, headers: &[String], records: &[csv::StringRecord]) -> Result<()> {
+    let examined = &records[..args.sample.unwrap_or(records.len()).min(records.len())];
+    let schema = infer_schema(headers, examined);
+    match format {
+        ReportFormat::Text => {
+            outln!("Schema of {} ({} of {} records examined):", describe_source(args), examined.len(), records.len());
+            let rows: Vec<[String; 4]> = schema.iter()
+                .map(|column| [
+                    column.name.clone(),
+                    column.column_type.to_string(),
+                    if column.nullable { "yes" } else { "no" }.to_string(),
+                    column.examples.join(", "),
+                ])
+                .collect();
+            let titles = ["Column", "Type", "Nullable", "Examples"];
+            let widths: Vec<usize> = (0..3)
+                .map(|i| rows.iter().map(|row| row[i].chars().count()).chain([titles[i].len()]).max().unwrap_or(0))
+                .collect();
+            for row in std::iter::once(titles.map(String::from)).chain(rows) {
+                outln!("{:<w0$}  {:<w1$}  {:<w2$}  {}", row[0], row[1], row[2], row[3], w0 = widths[0], w1 = widths[1], w2 = widths[2]);
+            }
+        }
+        ReportFormat::Json => {
+            let fields: Vec<serde_json::Value> = schema.iter()
+                .map(|column| {
+                    let mut field = serde_json::json!({
+                        "name": column.name,
+                        "type": column.column_type.frictionless_name(),
+                        "constraints": { "required": !column.nullable },
+                    });
+                    if let Some(example) = column.examples.first() {
+                        field["example"] = serde_json::json!(example);
+                    }
+                    field
+                })
+                .collect();
+            let table_schema = serde_json::json!({ "fields": fields, "missingValues": [""] });
+            outln!("{}", serde_json::to_string_pretty(&table_schema).map_err(|e| CsvPeekError::Usage(e.to_string()))?);
+        }
     }
+    Ok(())
 }
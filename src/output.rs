@@ -0,0 +1,727 @@
+use crate::schema::{infer_schema, ColumnType};
+use clap::ValueEnum;
+use csv::StringRecord;
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static CRLF: AtomicBool = AtomicBool::new(false);
+
+/// Switches every line written to stdout to CRLF endings (see `--crlf`).
+pub fn set_crlf(enabled: bool) {
+    CRLF.store(enabled, Ordering::Relaxed);
+}
+
+/// The line ending of stdout output: `\r\n` with `--crlf`, `\n` otherwise.
+pub fn line_ending() -> &'static str {
+    if CRLF.load(Ordering::Relaxed) { "\r\n" } else { "\n" }
+}
+
+/// Writes one line with the configured line ending; line breaks inside the text (e.g. in
+/// pretty-printed JSON) are converted too.
+pub fn write_line<W: Write>(out: &mut W, text: fmt::Arguments) -> io::Result<()> {
+    let text = text.to_string();
+    if CRLF.load(Ordering::Relaxed) {
+        write!(out, "{}\r\n", text.replace("\r\n", "\n").replace('\n', "\r\n"))
+    } else {
+        writeln!(out, "{}", text)
+    }
+}
+
+/// Prints one line to stdout like `write_line`. Used through `outln!`.
+pub fn print_line(text: fmt::Arguments) {
+    if let Err(e) = write_line(&mut io::stdout().lock(), text) {
+        panic!("failed printing to stdout: {}", e);
+    }
+}
+
+/// Pager command line: `$PAGER` if set and not empty, otherwise `less -SRX` (no line wrapping,
+/// colours kept, screen not cleared on exit).
+fn pager_command() -> Vec<String> {
+    parse_pager(std::env::var("PAGER").ok().as_deref())
+}
+
+/// Splits a `$PAGER` value into the pager's command line, like a shell would.
+fn parse_pager(pager: Option<&str>) -> Vec<String> {
+    pager.and_then(shlex::split)
+        .filter(|words| !words.is_empty())
+        .unwrap_or_else(|| vec!["less".to_string(), "-SRX".to_string()])
+}
+
+/// Writes `text` to stdout, through the pager when `use_pager` is set and the text has more
+/// lines than the terminal. If the pager cannot be started the text is printed directly.
+pub fn page(text: &[u8], use_pager: bool) -> io::Result<()> {
+    let height = terminal_size::terminal_size().map(|(_, terminal_size::Height(h))| h as usize);
+    let too_long = height.is_some_and(|height| text.iter().filter(|&&b| b == b'\n').count() >= height);
+    if use_pager && too_long {
+        let command = pager_command();
+        let child = std::process::Command::new(&command[0]).args(&command[1..]).stdin(std::process::Stdio::piped()).spawn();
+        if let Ok(mut child) = child {
+            if let Some(mut stdin) = child.stdin.take() {
+                match stdin.write_all(text) {
+                    // The user quit the pager before reading everything.
+                    Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+                    other => other?,
+                }
+            }
+            child.wait()?;
+            return Ok(());
+        }
+    }
+    let mut out = io::stdout().lock();
+    out.write_all(text)?;
+    out.flush()
+}
+
+/// Like `println!`, but honours `--crlf`.
+#[macro_export]
+macro_rules! outln {
+    () => { $crate::output::print_line(format_args!("")) };
+    ($($arg:tt)*) => { $crate::output::print_line(format_args!($($arg)*)) };
+}
+
+/// How list-mode rows are laid out in the (non-raw) display.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum Layout {
+    /// Numbered, tab-separated rows; switch to vertical when they don't fit the terminal.
+    #[default]
+    Auto,
+    /// Always numbered, tab-separated rows.
+    Table,
+    /// One `Header: value` block per record.
+    Vertical,
+}
+
+/// What list mode prints: the human-readable listing or machine-readable data.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Title, entry count and numbered rows (see `Layout`).
+    #[default]
+    Text,
+    /// RFC 4180 CSV with a header row, and nothing else on stdout.
+    Csv,
+    /// An Excel workbook with a header row, written to the `--output` file.
+    Xlsx,
+    /// `INSERT` statements for the `--table` table, and nothing else on stdout.
+    Sql,
+    /// A LaTeX `tabular` environment, and nothing else on stdout.
+    Latex,
+    /// JSON; only the headers subcommand writes it.
+    Json,
+}
+
+/// When fields of CSV output are quoted.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum QuoteStyle {
+    /// Quote every field.
+    Always,
+    /// Quote fields containing the delimiter, a quote or a line break.
+    #[default]
+    Necessary,
+    /// Never quote, even if that makes the output ambiguous.
+    Never,
+    /// Quote every field that is not a number.
+    NonNumeric,
+}
+
+impl From<QuoteStyle> for csv::QuoteStyle {
+    fn from(style: QuoteStyle) -> Self {
+        match style {
+            QuoteStyle::Always => csv::QuoteStyle::Always,
+            QuoteStyle::Necessary => csv::QuoteStyle::Necessary,
+            QuoteStyle::Never => csv::QuoteStyle::Never,
+            QuoteStyle::NonNumeric => csv::QuoteStyle::NonNumeric,
+        }
+    }
+}
+
+/// How `write_csv` quotes fields.
+#[derive(Debug, Clone, Copy)]
+pub struct CsvStyle {
+    pub quote_style: QuoteStyle,
+    pub quote: u8,
+}
+
+impl Default for CsvStyle {
+    fn default() -> Self {
+        CsvStyle { quote_style: QuoteStyle::Necessary, quote: b'"' }
+    }
+}
+
+/// Parses a `--quote-char` value: a single ASCII character.
+pub fn parse_quote_char_arg(s: &str) -> Result<u8, String> {
+    match s.as_bytes() {
+        [byte] if byte.is_ascii() => Ok(*byte),
+        _ => Err(format!("Invalid quote character '{}'. Expected a single ASCII character.", s)),
+    }
+}
+
+/// A CSV writer in the given style. Quotes inside fields are escaped by doubling them.
+pub fn csv_writer<W: Write>(out: W, style: &CsvStyle) -> csv::Writer<W> {
+    csv::WriterBuilder::new()
+        .quote_style(style.quote_style.into())
+        .quote(style.quote)
+        .terminator(if line_ending() == "\r\n" { csv::Terminator::CRLF } else { csv::Terminator::Any(b'\n') })
+        .from_writer(out)
+}
+
+/// Writes the header row and the rows as CSV.
+pub fn write_csv<W: Write>(out: W, headers: &[String], rows: &[Vec<String>], style: &CsvStyle) -> csv::Result<()> {
+    let mut writer = csv_writer(out, style);
+    writer.write_record(headers)?;
+    for row in rows {
+        writer.write_record(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Escapes the characters LaTeX treats specially so that `text` is typeset as is.
+pub fn latex_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' | '\r' => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes the header row and the rows as a LaTeX `tabular` environment with rules around the
+/// header. Columns whose values are all numbers are right-aligned.
+pub fn write_latex<W: Write>(mut out: W, headers: &[String], rows: &[Vec<String>]) -> io::Result<()> {
+    let records: Vec<StringRecord> = rows.iter().map(|row| StringRecord::from(row.as_slice())).collect();
+    let alignment: String = infer_schema(headers, &records).iter()
+        .map(|column| if matches!(column.column_type, ColumnType::Integer | ColumnType::Float) { 'r' } else { 'l' })
+        .collect();
+    let line = |cells: &[String]| format!("{} \\\\", cells.iter().map(|cell| latex_escape(cell)).collect::<Vec<_>>().join(" & "));
+    write_line(&mut out, format_args!("\\begin{{tabular}}{{{}}}", alignment))?;
+    write_line(&mut out, format_args!("\\hline"))?;
+    write_line(&mut out, format_args!("{}", line(headers)))?;
+    write_line(&mut out, format_args!("\\hline"))?;
+    for row in rows {
+        write_line(&mut out, format_args!("{}", line(row)))?;
+    }
+    write_line(&mut out, format_args!("\\hline"))?;
+    write_line(&mut out, format_args!("\\end{{tabular}}"))?;
+    out.flush()
+}
+
+/// When list output is coloured.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum ColorChoice {
+    /// Colour when stdout is a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Resolves the choice for stdout; `NO_COLOR` (https://no-color.org) only affects `Auto`.
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => is_terminal && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()),
+        }
+    }
+}
+
+/// When newlines, carriage returns and tabs inside cells are shown as `\n`, `\r` and `\t`.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum EscapeChoice {
+    /// Escape them in the text listing, which they would otherwise misalign, but not in --raw
+    /// output.
+    #[default]
+    Auto,
+    /// Also escape them, and backslashes, in --raw output, so every record is one line.
+    Always,
+    /// Print cells as they are.
+    Never,
+}
+
+/// Shows the line breaks and tabs of a cell as `\n`, `\r` and `\t`. With `backslashes`,
+/// backslashes are doubled as well so that the escaping can be undone.
+pub fn escape_cell(cell: &str, backslashes: bool) -> Cow<'_, str> {
+    let special = |c: char| matches!(c, '\n' | '\r' | '\t') || (backslashes && c == '\\');
+    if !cell.contains(special) {
+        return Cow::Borrowed(cell);
+    }
+    let mut escaped = String::with_capacity(cell.len() + 2);
+    for c in cell.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\\' if backslashes => escaped.push_str("\\\\"),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// ANSI styling of the text listing: titles and labels in bold cyan, row numbers dimmed and the
+/// cells a filter matched in bold yellow. Does nothing unless `enabled`.
+#[derive(Debug, Clone, Default)]
+pub struct Paint {
+    pub enabled: bool,
+    /// Positions within a displayed row of the cells to highlight.
+    pub highlight: Vec<usize>,
+}
+
+impl Paint {
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    pub fn title(&self, text: &str) -> String {
+        self.paint("1;36", text)
+    }
+
+    pub fn dim(&self, text: &str) -> String {
+        self.paint("2", text)
+    }
+
+    /// Styles the cell at `position` in a displayed row.
+    pub fn cell(&self, position: usize, text: &str) -> String {
+        if self.highlight.contains(&position) {
+            self.paint("1;33", text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Width of the terminal attached to stdout, if any.
+pub fn terminal_width() -> Option<usize> {
+    terminal_size::terminal_size().map(|(terminal_size::Width(w), _)| w as usize)
+}
+
+/// Display width of a line as a terminal shows it: characters, with tabs expanded to 8-column stops.
+pub fn display_width(line: &str) -> usize {
+    line.chars().fold(0, |width, c| if c == '\t' { (width / 8 + 1) * 8 } else { width + 1 })
+}
+
+/// Formats a row the way the table layout prints it, e.g. `3. Hey Jude\t1968`, or without
+/// the number prefix when `number` is `None`.
+pub fn table_line(number: Option<usize>, row: &[String]) -> String {
+    match number {
+        Some(number) => format!("{}. {}", number, row.join("\t")),
+        None => row.join("\t"),
+    }
+}
+
+/// Number of the row at `index` when numbering starts at `first_number` (`None`: unnumbered).
+fn row_number(first_number: Option<usize>, index: usize) -> Option<usize> {
+    first_number.map(|first| first + index)
+}
+
+/// True when every table-layout line fits within `width` columns.
+pub fn rows_fit(rows: &[Vec<String>], width: usize) -> bool {
+    rows.iter().enumerate().all(|(index, row)| display_width(&table_line(Some(index + 1), row)) <= width)
+}
+
+/// Longest cell value in the text listing, from `--max-col-width`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MaxColWidth {
+    /// Truncate just enough for table rows to fit the terminal.
+    Auto,
+    Chars(usize),
+}
+
+/// Parses `--max-col-width`: a number of characters (at least 2) or "auto".
+pub fn parse_max_col_width_arg(s: &str) -> Result<MaxColWidth, String> {
+    if s.eq_ignore_ascii_case("auto") {
+        return Ok(MaxColWidth::Auto);
+    }
+    match s.parse::<usize>() {
+        Ok(n) if n >= 2 => Ok(MaxColWidth::Chars(n)),
+        _ => Err(format!("Invalid column width '{}'. Expected a number of characters (at least 2) or \"auto\".", s)),
+    }
+}
+
+/// Parses a `--round COLUMN:DIGITS` rule, e.g. `Price:2`.
+pub fn parse_round_arg(s: &str) -> Result<(String, usize), String> {
+    match s.rsplit_once(':') {
+        Some((column, digits)) if !column.trim().is_empty() => match digits.trim().parse::<usize>() {
+            Ok(digits) => Ok((column.trim().to_string(), digits)),
+            Err(_) => Err(format!("Invalid round '{}'. Expected COLUMN:DIGITS, e.g. \"Price:2\".", s)),
+        },
+        _ => Err(format!("Invalid round '{}'. Expected COLUMN:DIGITS, e.g. \"Price:2\".", s)),
+    }
+}
+
+/// Formats a plain decimal number such as "-1234.5" for display: rounded half away from zero
+/// to `decimals` digits after the point when given, and with `separator` between groups of
+/// three integer digits. Rounding works on the digits, so 2.675 becomes 2.68 as written rather
+/// than as the nearest float. `None` for cells that are not plain decimals (e.g. "1e5" or
+/// "n/a"), which are shown as they are.
+pub fn format_decimal(cell: &str, decimals: Option<usize>, separator: Option<&str>) -> Option<String> {
+    let trimmed = cell.trim();
+    let (sign, unsigned) = match trimmed.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", trimmed.strip_prefix('+').unwrap_or(trimmed)),
+    };
+    let (int, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if (int.is_empty() && fraction.is_empty()) || !is_digits(int) || !is_digits(fraction) {
+        return None;
+    }
+    let mut int: Vec<u8> = if int.is_empty() { b"0".to_vec() } else { int.as_bytes().to_vec() };
+    let mut fraction = fraction.as_bytes().to_vec();
+    if let Some(decimals) = decimals {
+        let round_up = fraction.get(decimals).is_some_and(|&digit| digit >= b'5');
+        fraction.resize(decimals, b'0');
+        if round_up {
+            let carry = int.iter_mut().chain(fraction.iter_mut()).rev().all(|digit| {
+                let overflow = *digit == b'9';
+                *digit = if overflow { b'0' } else { *digit + 1 };
+                overflow
+            });
+            if carry {
+                int.insert(0, b'1');
+            }
+        }
+    }
+    let zero = int.iter().chain(&fraction).all(|&digit| digit == b'0');
+    let mut formatted = String::from(if zero { "" } else { sign });
+    for (i, &digit) in int.iter().enumerate() {
+        if let Some(separator) = separator.filter(|_| i > 0 && (int.len() - i).is_multiple_of(3)) {
+            formatted.push_str(separator);
+        }
+        formatted.push(char::from(digit));
+    }
+    if !fraction.is_empty() {
+        formatted.push('.');
+        formatted.extend(fraction.iter().map(|&digit| char::from(digit)));
+    }
+    Some(formatted)
+}
+
+/// Cuts a cell to at most `max` characters, ending truncated values with an ellipsis.
+pub fn truncate_cell(cell: &str, max: usize) -> String {
+    if cell.chars().count() <= max {
+        return cell.to_string();
+    }
+    let mut truncated: String = cell.chars().take(max.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+pub fn truncate_rows(rows: &[Vec<String>], max: usize) -> Vec<Vec<String>> {
+    rows.iter().map(|row| row.iter().map(|cell| truncate_cell(cell, max)).collect()).collect()
+}
+
+/// Narrowest cells `MaxColWidth::Auto` truncates to, even if rows still don't fit.
+const MIN_AUTO_COL_WIDTH: usize = 8;
+
+/// The widest cell limit at which every table row fits `width` columns, or `None` when the
+/// rows already fit untruncated.
+pub fn fit_col_width(rows: &[Vec<String>], width: usize) -> Option<usize> {
+    if rows_fit(rows, width) {
+        return None;
+    }
+    let longest = rows.iter().flatten().map(|cell| cell.chars().count()).max().unwrap_or(0);
+    let (mut low, mut high) = (MIN_AUTO_COL_WIDTH, longest.max(MIN_AUTO_COL_WIDTH));
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        if rows_fit(&truncate_rows(rows, mid), width) {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    Some(low)
+}
+
+/// Resolves `Layout::Auto` against the terminal width: vertical when rows would wrap.
+/// Without a terminal (e.g. when piping) the table layout is kept.
+pub fn resolve_layout(layout: Layout, rows: &[Vec<String>], width: Option<usize>) -> Layout {
+    match (layout, width) {
+        (Layout::Auto, Some(width)) if !rows_fit(rows, width) => Layout::Vertical,
+        (Layout::Auto, _) => Layout::Table,
+        (other, _) => other,
+    }
+}
+
+/// Parses `--output-delimiter`, expanding the escapes `\t`, `\n`, `\0`, `\\` and `\xHH`
+/// (e.g. `\x1f`, the ASCII unit separator).
+pub fn parse_output_delimiter_arg(s: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('0') => out.push('\0'),
+            Some('\\') => out.push('\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&hex, 16).ok().filter(|b| b.is_ascii() && hex.len() == 2)
+                    .ok_or_else(|| format!("Invalid escape '\\x{}' in output delimiter '{}'. Expected \\x00 to \\x7f.", hex, s))?;
+                out.push(char::from(byte));
+            }
+            other => return Err(format!("Invalid escape '\\{}' in output delimiter '{}'. Use \\t, \\n, \\0, \\\\ or \\xHH.", other.map(String::from).unwrap_or_default(), s)),
+        }
+    }
+    Ok(out)
+}
+
+/// Writes one tab-separated line per row, numbered from `first_number` unless it is `None`.
+pub fn write_table<W: Write>(out: &mut W, rows: &[Vec<String>], first_number: Option<usize>, paint: &Paint) -> io::Result<()> {
+    for (index, row) in rows.iter().enumerate() {
+        let number = row_number(first_number, index);
+        if !paint.enabled {
+            write!(out, "{}{}", table_line(number, row), line_ending())?;
+            continue;
+        }
+        let cells: Vec<String> = row.iter().enumerate().map(|(position, cell)| paint.cell(position, cell)).collect();
+        let prefix = number.map(|number| format!("{} ", paint.dim(&format!("{}.", number)))).unwrap_or_default();
+        write!(out, "{}{}{}", prefix, cells.join("\t"), line_ending())?;
+    }
+    Ok(())
+}
+
+/// Splits a cell into lines of at most `width` characters, breaking at spaces where possible
+/// and inside words longer than `width` otherwise.
+pub fn wrap_cell(cell: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in cell.split(' ') {
+        let mut word: Vec<char> = word.chars().collect();
+        let line_len = line.chars().count();
+        if line_len > 0 && line_len + 1 + word.len() <= width {
+            line.push(' ');
+            line.extend(&word);
+            continue;
+        }
+        if line_len > 0 {
+            lines.push(std::mem::take(&mut line));
+        }
+        while word.len() > width {
+            lines.push(word.drain(..width).collect());
+        }
+        line.extend(&word);
+    }
+    lines.push(line);
+    lines
+}
+
+/// Writes rows (numbered as in `write_table`) as a table with space-aligned columns, wrapping
+/// cells longer than `width` characters onto continuation lines within their column.
+pub fn write_wrapped_table<W: Write>(out: &mut W, rows: &[Vec<String>], width: usize, first_number: Option<usize>, paint: &Paint) -> io::Result<()> {
+    let wrapped: Vec<Vec<Vec<String>>> = rows.iter().map(|row| row.iter().map(|cell| wrap_cell(cell, width)).collect()).collect();
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let column_widths: Vec<usize> = (0..columns)
+        .map(|column| wrapped.iter().filter_map(|row| row.get(column)).flatten().map(|line| line.chars().count()).max().unwrap_or(0))
+        .collect();
+    let label = |index: usize| row_number(first_number, index).map(|number| format!("{}.", number));
+    let label_width = label(rows.len().saturating_sub(1)).map_or(0, |label| label.len() + 1);
+    for (index, row) in wrapped.iter().enumerate() {
+        let height = row.iter().map(Vec::len).max().unwrap_or(1);
+        for line_index in 0..height {
+            let line = match label(index).filter(|_| line_index == 0) {
+                Some(label) => format!("{}{}", paint.dim(&label), " ".repeat(label_width - label.len())),
+                None => " ".repeat(label_width),
+            };
+            let cells: Vec<String> = row.iter().enumerate().map(|(position, lines)| {
+                let text = lines.get(line_index).map(String::as_str).unwrap_or("");
+                let padding = " ".repeat(column_widths[position] - text.chars().count());
+                format!("{}{}", paint.cell(position, text), padding)
+            }).collect();
+            write!(out, "{}{}{}", line, cells.join("  ").trim_end(), line_ending())?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes each row as a block of right-aligned `Header: value` lines under a rule numbered as
+/// in `write_table`, in the style of MySQL's `\G`.
+pub fn write_vertical<W: Write>(out: &mut W, headers: &[String], rows: &[Vec<String>], first_number: Option<usize>, paint: &Paint) -> io::Result<()> {
+    let label_width = headers.iter().map(|h| h.chars().count()).max().unwrap_or(0);
+    for (index, row) in rows.iter().enumerate() {
+        let rule = match row_number(first_number, index) {
+            Some(number) => format!("*************************** {}. row ***************************", number),
+            None => "*************************************************************".to_string(),
+        };
+        write!(out, "{}{}", paint.dim(&rule), line_ending())?;
+        for (position, (header, value)) in headers.iter().zip(row).enumerate() {
+            let label = format!("{:>width$}:", header, width = label_width);
+            write!(out, "{} {}{}", paint.title(&label), paint.cell(position, value), line_ending())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(values: &[&[&str]]) -> Vec<Vec<String>> {
+        values.iter().map(|r| r.iter().map(|v| v.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn test_escape_cell() {
+        assert_eq!(escape_cell("plain", true), Cow::Borrowed("plain"));
+        assert_eq!(escape_cell("a\nb\tc\\d", false), "a\\nb\\tc\\d");
+        assert_eq!(escape_cell("a\r\nb\\n", true), "a\\r\\nb\\\\n");
+    }
+
+    #[test]
+    fn test_write_latex() {
+        assert_eq!(latex_escape("50% of R&D_1 {x} ~ ^ \\"), "50\\% of R\\&D\\_1 \\{x\\} \\textasciitilde{} \\textasciicircum{} \\textbackslash{}");
+        let headers = vec!["Item".to_string(), "Cost $".to_string()];
+        let mut out = Vec::new();
+        write_latex(&mut out, &headers, &rows(&[&["Tea #1", "2.5"], &["Cake", "4"]])).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+            "\\begin{tabular}{lr}\n\\hline\nItem & Cost \\$ \\\\\n\\hline\nTea \\#1 & 2.5 \\\\\nCake & 4 \\\\\n\\hline\n\\end{tabular}\n");
+    }
+
+    #[test]
+    fn test_display_width_expands_tabs() {
+        assert_eq!(display_width("abc"), 3);
+        assert_eq!(display_width("1. a\tb"), 9);
+        assert_eq!(display_width("åäö"), 3);
+    }
+
+    #[test]
+    fn test_resolve_layout_auto() {
+        let data = rows(&[&["short", "row"], &["a much longer value that will not fit", "x"]]);
+        assert_eq!(resolve_layout(Layout::Auto, &data, Some(200)), Layout::Table);
+        assert_eq!(resolve_layout(Layout::Auto, &data, Some(20)), Layout::Vertical);
+        assert_eq!(resolve_layout(Layout::Auto, &data, None), Layout::Table);
+        assert_eq!(resolve_layout(Layout::Table, &data, Some(20)), Layout::Table);
+    }
+
+    #[test]
+    fn test_parse_output_delimiter_arg() {
+        assert_eq!(parse_output_delimiter_arg(","), Ok(",".to_string()));
+        assert_eq!(parse_output_delimiter_arg(" | "), Ok(" | ".to_string()));
+        assert_eq!(parse_output_delimiter_arg("\\x1f"), Ok("\u{1f}".to_string()));
+        assert_eq!(parse_output_delimiter_arg("\\t\\\\"), Ok("\t\\".to_string()));
+        assert!(parse_output_delimiter_arg("\\q").is_err());
+        assert!(parse_output_delimiter_arg("\\x1").is_err());
+        assert!(parse_output_delimiter_arg("\\xff").is_err());
+    }
+
+    #[test]
+    fn test_write_csv_quote_styles() {
+        let headers = vec!["Title".to_string(), "Year".to_string()];
+        let data = rows(&[&["Say \"Hi\", Jude", "1968"]]);
+        let write = |style: CsvStyle| {
+            let mut out = Vec::new();
+            write_csv(&mut out, &headers, &data, &style).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(write(CsvStyle::default()), "Title,Year\n\"Say \"\"Hi\"\", Jude\",1968\n");
+        assert_eq!(write(CsvStyle { quote_style: QuoteStyle::NonNumeric, quote: b'\'' }), "'Title','Year'\n'Say \"Hi\", Jude',1968\n");
+        assert_eq!(write(CsvStyle { quote_style: QuoteStyle::Always, quote: b'"' }), "\"Title\",\"Year\"\n\"Say \"\"Hi\"\", Jude\",\"1968\"\n");
+    }
+
+    #[test]
+    fn test_write_table_with_colors() {
+        let mut out = Vec::new();
+        let paint = Paint { enabled: true, highlight: vec![1] };
+        write_table(&mut out, &rows(&[&["Hey Jude", "1968"]]), Some(1), &paint).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\x1b[2m1.\x1b[0m Hey Jude\t\x1b[1;33m1968\x1b[0m\n");
+        assert_eq!(Paint::default().title("List"), "List");
+
+        let mut plain = Vec::new();
+        write_table(&mut plain, &rows(&[&["a"], &["b"]]), Some(10), &Paint::default()).unwrap();
+        write_table(&mut plain, &rows(&[&["c"]]), None, &Paint::default()).unwrap();
+        assert_eq!(String::from_utf8(plain).unwrap(), "10. a\n11. b\nc\n");
+    }
+
+    #[test]
+    fn test_format_decimal() {
+        assert_eq!(parse_round_arg("Unit Price:2"), Ok(("Unit Price".to_string(), 2)));
+        assert!(parse_round_arg("Price").is_err());
+        assert!(parse_round_arg("Price:-1").is_err());
+
+        assert_eq!(format_decimal("2.675", Some(2), None).as_deref(), Some("2.68"));
+        assert_eq!(format_decimal("-9.995", Some(2), Some(",")).as_deref(), Some("-10.00"));
+        assert_eq!(format_decimal("999.5", Some(0), Some(",")).as_deref(), Some("1,000"));
+        assert_eq!(format_decimal("1234567.1", Some(3), Some(" ")).as_deref(), Some("1 234 567.100"));
+        assert_eq!(format_decimal("+123456", None, Some(",")).as_deref(), Some("123,456"));
+        assert_eq!(format_decimal(".5", Some(0), None).as_deref(), Some("1"));
+        assert_eq!(format_decimal("-0.001", Some(2), None).as_deref(), Some("0.00"));
+        assert_eq!(format_decimal("1e5", Some(2), None), None);
+        assert_eq!(format_decimal("n/a", Some(2), None), None);
+        assert_eq!(format_decimal("-", Some(2), None), None);
+    }
+
+    #[test]
+    fn test_truncation() {
+        assert_eq!(parse_max_col_width_arg("auto"), Ok(MaxColWidth::Auto));
+        assert_eq!(parse_max_col_width_arg("12"), Ok(MaxColWidth::Chars(12)));
+        assert!(parse_max_col_width_arg("1").is_err());
+        assert_eq!(truncate_cell("Bohemian Rhapsody", 8), "Bohemia…");
+        assert_eq!(truncate_cell("Hey Jude", 8), "Hey Jude");
+
+        let data = rows(&[&["short", "row"], &["a much longer value that will not fit", "x"]]);
+        assert_eq!(fit_col_width(&data, 200), None);
+        let limit = fit_col_width(&data, 30).unwrap();
+        assert!(rows_fit(&truncate_rows(&data, limit), 30));
+        assert!(!rows_fit(&truncate_rows(&data, limit + 1), 30));
+    }
+
+    #[test]
+    fn test_wrap_cell() {
+        assert_eq!(wrap_cell("a long comment here", 9), vec!["a long", "comment", "here"]);
+        assert_eq!(wrap_cell("supercalifragilistic", 8), vec!["supercal", "ifragili", "stic"]);
+        assert_eq!(wrap_cell("", 8), vec![""]);
+    }
+
+    #[test]
+    fn test_write_wrapped_table() {
+        let mut out = Vec::new();
+        let data = rows(&[&["Hey Jude", "a long comment here"], &["Yesterday", "short"]]);
+        write_wrapped_table(&mut out, &data, 9, Some(1), &Paint::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "1. Hey Jude   a long\n              comment\n              here\n2. Yesterday  short\n"
+        );
+    }
+
+    #[test]
+    fn test_write_vertical() {
+        let mut out = Vec::new();
+        let headers = vec!["Title".to_string(), "Year".to_string()];
+        write_vertical(&mut out, &headers, &rows(&[&["Hey Jude", "1968"]]), Some(1), &Paint::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "*************************** 1. row ***************************\nTitle: Hey Jude\n Year: 1968\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_pager() {
+        assert_eq!(parse_pager(Some("most")), ["most"]);
+        assert_eq!(parse_pager(Some("less -R --quit-if-one-screen")), ["less", "-R", "--quit-if-one-screen"]);
+        assert_eq!(parse_pager(Some("'/opt/my pager/bin/pg' -x")), ["/opt/my pager/bin/pg", "-x"]);
+        assert_eq!(parse_pager(Some("")), ["less", "-SRX"]);
+        assert_eq!(parse_pager(Some("  ")), ["less", "-SRX"]);
+        assert_eq!(parse_pager(Some("less 'unterminated")), ["less", "-SRX"]);
+        assert_eq!(parse_pager(None), ["less", "-SRX"]);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::error::{CsvPeekError, Result};
+
+/// Parses a `--param NAME=VALUE` argument.
+pub fn parse_param_arg(s: &str) -> std::result::Result<(String, String), String> {
+    let (name, value) = s.split_once('=').ok_or_else(|| format!("Invalid parameter '{}'. Expected NAME=VALUE.", s))?;
+    let name = name.trim();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("Invalid parameter name '{}'. Use letters, digits and underscores.", name));
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Replaces every `${NAME}` in `text` with the value of the `NAME` parameter, falling back to the
+/// environment variable `NAME` when no `--param` defines it. `$$` stands for a literal `$`.
+pub fn substitute_params(text: &str, params: &[(String, String)]) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        if let Some(tail) = after.strip_prefix('$') {
+            out.push('$');
+            rest = tail;
+        } else if let Some(body) = after.strip_prefix('{') {
+            let end = body.find('}').ok_or_else(|| CsvPeekError::Usage(format!("Unterminated parameter reference in '{}'.", text)))?;
+            let name = &body[..end];
+            let value = params.iter().rev().find(|(n, _)| n == name).map(|(_, v)| v.clone())
+                .or_else(|| std::env::var(name).ok())
+                .ok_or_else(|| CsvPeekError::Usage(format!("Parameter '{}' used in '{}' is not defined. Pass --param {}=VALUE or set the environment variable.", name, text, name)))?;
+            out.push_str(&value);
+            rest = &body[end + 1..];
+        } else {
+            out.push('$');
+            rest = after;
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_param_arg() {
+        assert_eq!(parse_param_arg("region=EU"), Ok(("region".to_string(), "EU".to_string())));
+        assert_eq!(parse_param_arg("q=a=b"), Ok(("q".to_string(), "a=b".to_string())));
+        assert!(parse_param_arg("region").is_err());
+        assert!(parse_param_arg("my region=EU").is_err());
+    }
+
+    #[test]
+    fn test_substitute_params() {
+        let params = vec![("region".to_string(), "EU".to_string()), ("region".to_string(), "US".to_string())];
+        assert_eq!(substitute_params("Region=${region}", &params).unwrap(), "Region=US");
+        assert_eq!(substitute_params("Price=$$5 or $x", &params).unwrap(), "Price=$5 or $x");
+        assert!(substitute_params("${undefined_csvpeek_param}", &params).is_err());
+        assert!(substitute_params("${region", &params).is_err());
+    }
+}
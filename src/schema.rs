@@ -0,0 +1,413 @@
+use crate::error::{CsvPeekError, Result};
+use crate::filter::parse_number;
+use csv::StringRecord;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+
+/// Inferred type of a column, from the most to the least specific.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnType {
+    Integer,
+    Float,
+    Boolean,
+    Date,
+    String,
+}
+
+impl ColumnType {
+    /// The type name used by Frictionless Table Schema.
+    pub fn frictionless_name(self) -> &'static str {
+        match self {
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "number",
+            ColumnType::Boolean => "boolean",
+            ColumnType::Date => "date",
+            ColumnType::String => "string",
+        }
+    }
+
+    /// Converts a cell to this type's canonical text (`007` as an integer is `7`, `yes` as a
+    /// boolean is `true`), or `None` if it is not a valid value of the type. Empty cells are
+    /// nulls and stay empty.
+    pub fn cast(self, value: &str) -> Option<String> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Some(String::new());
+        }
+        match self {
+            ColumnType::Integer => value.parse::<i64>().ok().map(|n| n.to_string()),
+            ColumnType::Float => parse_number(value).map(|n| n.to_string()),
+            ColumnType::Boolean if is_boolean(value) => {
+                Some((value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("yes")).to_string())
+            }
+            ColumnType::Date if is_date(value) => Some(value.to_string()),
+            ColumnType::String => Some(value.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Parses one `COLUMN:TYPE` entry of `--cast`. Types: int, float, bool, date and string
+/// (integer, number and boolean are accepted too).
+pub fn parse_cast_arg(s: &str) -> std::result::Result<(String, ColumnType), String> {
+    let (column, type_name) = s.rsplit_once(':').ok_or_else(|| format!("Invalid cast '{}'. Expected COLUMN:TYPE, e.g. \"Age:int\".", s))?;
+    let column_type = match type_name.trim().to_ascii_lowercase().as_str() {
+        "int" | "integer" => ColumnType::Integer,
+        "float" | "number" => ColumnType::Float,
+        "bool" | "boolean" => ColumnType::Boolean,
+        "date" => ColumnType::Date,
+        "string" => ColumnType::String,
+        other => return Err(format!("Unknown type '{}' in cast '{}'. Use int, float, bool, date or string.", other, s)),
+    };
+    let column = column.trim();
+    if column.is_empty() {
+        return Err(format!("Invalid cast '{}': column name cannot be empty.", s));
+    }
+    Ok((column.to_string(), column_type))
+}
+
+/// Casts the cells of `record` listed in `casts` (column index and type). Fails on the first
+/// cell that is not a valid value of its type, with the record's line number when known.
+pub fn cast_record(record: &StringRecord, headers: &[String], casts: &[(usize, ColumnType)]) -> Result<StringRecord> {
+    let mut cells: Vec<String> = record.iter().map(str::to_string).collect();
+    for &(index, column_type) in casts {
+        let Some(cell) = cells.get_mut(index) else { continue };
+        *cell = column_type.cast(cell).ok_or_else(|| CsvPeekError::Parse {
+            file: None,
+            line: record.position().map(|pos| pos.line()),
+            message: format!("cannot cast '{}' in column '{}' to {}", cell, headers[index], column_type),
+        })?;
+    }
+    let mut cast = StringRecord::from(cells);
+    cast.set_position(record.position().cloned());
+    Ok(cast)
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Boolean => "boolean",
+            ColumnType::Date => "date",
+            ColumnType::String => "string",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What `infer_schema` found out about one column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnSchema {
+    pub name: String,
+    pub column_type: ColumnType,
+    /// Whether any examined cell was empty.
+    pub nullable: bool,
+    /// Up to `MAX_EXAMPLES` distinct non-empty values, in order of appearance.
+    pub examples: Vec<String>,
+}
+
+pub const MAX_EXAMPLES: usize = 3;
+
+pub fn is_integer(value: &str) -> bool {
+    let digits = value.strip_prefix(['-', '+']).unwrap_or(value);
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+pub fn is_boolean(value: &str) -> bool {
+    ["true", "false", "yes", "no"].iter().any(|b| value.eq_ignore_ascii_case(b))
+}
+
+/// ISO 8601 calendar date, `YYYY-MM-DD`.
+pub fn is_date(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('-').collect();
+    let [year, month, day] = parts.as_slice() else { return false };
+    let all_digits = |s: &str, len: usize| s.len() == len && s.bytes().all(|b| b.is_ascii_digit());
+    if !(all_digits(year, 4) && all_digits(month, 2) && all_digits(day, 2)) {
+        return false;
+    }
+    let (month, day): (u32, u32) = (month.parse().unwrap_or(0), day.parse().unwrap_or(0));
+    (1..=12).contains(&month) && (1..=31).contains(&day)
+}
+
+fn value_type(value: &str) -> ColumnType {
+    if is_integer(value) {
+        ColumnType::Integer
+    } else if parse_number(value).is_some() {
+        ColumnType::Float
+    } else if is_boolean(value) {
+        ColumnType::Boolean
+    } else if is_date(value) {
+        ColumnType::Date
+    } else {
+        ColumnType::String
+    }
+}
+
+/// The narrowest type that covers both: integers widen to floats, anything else mixed is a string.
+fn widen(a: ColumnType, b: ColumnType) -> ColumnType {
+    match (a, b) {
+        (a, b) if a == b => a,
+        (ColumnType::Integer, ColumnType::Float) | (ColumnType::Float, ColumnType::Integer) => ColumnType::Float,
+        _ => ColumnType::String,
+    }
+}
+
+/// Infers the type, nullability and example values of every column from the given records.
+/// Cells that are empty (after trimming) count as nulls and do not affect the type; a column
+/// without any values is reported as a string.
+pub fn infer_schema<'a>(headers: &[String], records: impl IntoIterator<Item = &'a StringRecord>) -> Vec<ColumnSchema> {
+    let mut types: Vec<Option<ColumnType>> = vec![None; headers.len()];
+    let mut columns: Vec<ColumnSchema> = headers.iter()
+        .map(|name| ColumnSchema { name: name.clone(), column_type: ColumnType::String, nullable: false, examples: Vec::new() })
+        .collect();
+    for record in records {
+        for (index, column) in columns.iter_mut().enumerate() {
+            let value = record.get(index).unwrap_or("").trim();
+            if value.is_empty() {
+                column.nullable = true;
+                continue;
+            }
+            let found = value_type(value);
+            types[index] = Some(types[index].map_or(found, |known| widen(known, found)));
+            if column.examples.len() < MAX_EXAMPLES && !column.examples.iter().any(|e| e == value) {
+                column.examples.push(value.to_string());
+            }
+        }
+    }
+    for (column, inferred) in columns.iter_mut().zip(types) {
+        column.column_type = inferred.unwrap_or(ColumnType::String);
+    }
+    columns
+}
+
+/// What `--headers --detailed` and `--profile` show about one column: its inferred schema and
+/// how many of its cells are empty, non-empty and distinct.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnProfile {
+    pub schema: ColumnSchema,
+    pub non_empty: usize,
+    pub empty: usize,
+    /// Number of distinct non-empty values.
+    pub distinct: usize,
+}
+
+impl ColumnProfile {
+    /// Whether the column holds at most one distinct value, not counting empty cells.
+    pub fn is_constant(&self) -> bool {
+        self.distinct <= 1
+    }
+
+    /// Whether every cell holds a value no other row has, so the column could identify rows.
+    pub fn is_candidate_key(&self) -> bool {
+        self.non_empty > 0 && self.empty == 0 && self.distinct == self.non_empty
+    }
+
+    /// Share of the cells that are empty, in percent.
+    pub fn empty_percent(&self) -> f64 {
+        match self.non_empty + self.empty {
+            0 => 0.0,
+            cells => self.empty as f64 * 100.0 / cells as f64,
+        }
+    }
+}
+
+/// Profiles every column of the given records. Values are compared after trimming, like
+/// `infer_schema` does.
+pub fn profile_columns(headers: &[String], records: &[StringRecord]) -> Vec<ColumnProfile> {
+    infer_schema(headers, records).into_iter()
+        .enumerate()
+        .map(|(index, schema)| {
+            let values = records.iter().map(|record| record.get(index).unwrap_or("").trim()).filter(|value| !value.is_empty());
+            let non_empty = values.clone().count();
+            let distinct = values.collect::<HashSet<&str>>().len();
+            ColumnProfile { schema, non_empty, empty: records.len() - non_empty, distinct }
+        })
+        .collect()
+}
+
+/// The values of the `columns` key that more than one record holds, with the number of records
+/// holding each, in order of first appearance. Cells are compared after trimming, like
+/// `profile_columns` does; for `--assert-unique`.
+pub fn duplicate_keys(records: &[StringRecord], columns: &[usize]) -> Vec<(Vec<String>, usize)> {
+    let mut counts: HashMap<Vec<&str>, usize> = HashMap::new();
+    let mut order = Vec::new();
+    for record in records {
+        let key: Vec<&str> = columns.iter().map(|&idx| record.get(idx).unwrap_or("").trim()).collect();
+        let count = counts.entry(key.clone()).or_insert(0);
+        *count += 1;
+        if *count == 2 {
+            order.push(key);
+        }
+    }
+    order.into_iter()
+        .map(|key| {
+            let rows = counts[&key];
+            (key.into_iter().map(String::from).collect(), rows)
+        })
+        .collect()
+}
+
+/// A `--check-fk` relation: every value of `child_column` in `child` should occur in
+/// `parent_column` of `parent`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForeignKey {
+    pub child: PathBuf,
+    pub child_column: String,
+    pub parent: PathBuf,
+    pub parent_column: String,
+}
+
+impl fmt::Display for ForeignKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{} -> {}:{}", self.child.display(), self.child_column, self.parent.display(), self.parent_column)
+    }
+}
+
+/// Parses `CHILD_FILE:COLUMN -> PARENT_FILE:COLUMN`. Each side is split at its last colon, so
+/// paths may contain colons.
+pub fn parse_foreign_key_arg(s: &str) -> std::result::Result<ForeignKey, String> {
+    let invalid = || format!("Invalid foreign key '{}'. Expected CHILD_FILE:COLUMN -> PARENT_FILE:COLUMN, e.g. \"orders.csv:CustomerID -> customers.csv:ID\".", s);
+    let side = |part: &str| -> std::result::Result<(PathBuf, String), String> {
+        let (path, column) = part.trim().rsplit_once(':').ok_or_else(invalid)?;
+        if path.trim().is_empty() || column.trim().is_empty() {
+            return Err(invalid());
+        }
+        Ok((PathBuf::from(path.trim()), column.trim().to_string()))
+    };
+    let (child, parent) = s.split_once("->").ok_or_else(invalid)?;
+    let (child, child_column) = side(child)?;
+    let (parent, parent_column) = side(parent)?;
+    Ok(ForeignKey { child, child_column, parent, parent_column })
+}
+
+/// The values of `child_column` in `children` that no record of `parents` has in
+/// `parent_column`, with the number of child records holding each, in order of first
+/// appearance. Cells are compared trimmed; empty child cells are not references.
+pub fn orphaned_keys(children: &[StringRecord], child_column: usize, parents: &[StringRecord], parent_column: usize) -> Vec<(String, usize)> {
+    let known: HashSet<&str> = parents.iter().map(|record| record.get(parent_column).unwrap_or("").trim()).collect();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    let mut order = Vec::new();
+    for value in children.iter().map(|record| record.get(child_column).unwrap_or("").trim()) {
+        if value.is_empty() || known.contains(value) {
+            continue;
+        }
+        let count = counts.entry(value).or_insert(0);
+        if *count == 0 {
+            order.push(value);
+        }
+        *count += 1;
+    }
+    order.into_iter().map(|value| (value.to_string(), counts[value])).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_schema() {
+        let headers: Vec<String> = ["Id", "Price", "Active", "Day", "Note", "Empty"].iter().map(|h| h.to_string()).collect();
+        let records = vec![
+            StringRecord::from(vec!["1", "2", "yes", "2024-01-31", "x", ""]),
+            StringRecord::from(vec!["2", "2.5", "FALSE", "2024-02-01", "2024-02-01", ""]),
+            StringRecord::from(vec!["3", "", "true", "2024-13-01", "7", ""]),
+        ];
+        let schema = infer_schema(&headers, &records);
+        let types: Vec<ColumnType> = schema.iter().map(|c| c.column_type).collect();
+        assert_eq!(types, vec![ColumnType::Integer, ColumnType::Float, ColumnType::Boolean, ColumnType::String, ColumnType::String, ColumnType::String]);
+        assert!(!schema[0].nullable);
+        assert!(schema[1].nullable);
+        assert_eq!(schema[0].examples, vec!["1", "2", "3"]);
+        assert!(schema[5].examples.is_empty());
+    }
+
+    #[test]
+    fn test_profile_columns() {
+        let headers: Vec<String> = ["City", "Pop"].iter().map(|h| h.to_string()).collect();
+        let records = vec![
+            StringRecord::from(vec!["Oslo", "700"]),
+            StringRecord::from(vec![" Oslo ", ""]),
+            StringRecord::from(vec!["Bergen", "290"]),
+        ];
+        let profiles = profile_columns(&headers, &records);
+        assert_eq!((profiles[0].non_empty, profiles[0].distinct), (3, 2));
+        assert_eq!((profiles[1].non_empty, profiles[1].distinct), (2, 2));
+        assert_eq!(profiles[1].schema.column_type, ColumnType::Integer);
+        assert_eq!(profiles[1].empty, 1);
+        assert!((profiles[1].empty_percent() - 100.0 / 3.0).abs() < 1e-9);
+        assert!(!profiles[0].is_candidate_key() && !profiles[0].is_constant());
+
+        let ids = profile_columns(&["Id".to_string(), "Kind".to_string()], &[StringRecord::from(vec!["1", "a"]), StringRecord::from(vec!["2", ""])]);
+        assert!(ids[0].is_candidate_key());
+        assert!(ids[1].is_constant() && !ids[1].is_candidate_key());
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        let records = vec![
+            StringRecord::from(vec!["1", "a"]),
+            StringRecord::from(vec!["2", "a"]),
+            StringRecord::from(vec!["2 ", "b"]),
+            StringRecord::from(vec!["1", "a"]),
+            StringRecord::from(vec!["2", "a"]),
+            StringRecord::from(vec!["1", "c"]),
+        ];
+        let by_id = duplicate_keys(&records, &[0]);
+        assert_eq!(by_id, vec![(vec!["2".to_string()], 3), (vec!["1".to_string()], 3)]);
+        let by_pair = duplicate_keys(&records, &[0, 1]);
+        assert_eq!(by_pair, vec![(vec!["1".to_string(), "a".to_string()], 2), (vec!["2".to_string(), "a".to_string()], 2)]);
+
+        let err = CsvPeekError::NotUnique { columns: vec!["Id".to_string(), "Kind".to_string()], duplicates: by_pair };
+        assert_eq!(err.to_string(), "Column(s) Id, Kind are not unique: 2 value(s) occur in more than one row: '1, a' (2 rows); '2, a' (2 rows)");
+    }
+
+    #[test]
+    fn test_foreign_keys() {
+        let fk = parse_foreign_key_arg("orders.csv:CustomerID -> C:\\data\\customers.csv:ID").unwrap();
+        assert_eq!(fk.child, PathBuf::from("orders.csv"));
+        assert_eq!(fk.child_column, "CustomerID");
+        assert_eq!(fk.parent, PathBuf::from("C:\\data\\customers.csv"));
+        assert_eq!(fk.parent_column, "ID");
+        assert!(parse_foreign_key_arg("orders.csv:CustomerID").is_err());
+        assert!(parse_foreign_key_arg("orders.csv -> customers.csv:ID").is_err());
+        assert!(parse_foreign_key_arg("orders.csv: -> customers.csv:ID").is_err());
+
+        let orders = vec![
+            StringRecord::from(vec!["1", "C9"]),
+            StringRecord::from(vec!["2", "C1"]),
+            StringRecord::from(vec!["3", "C7 "]),
+            StringRecord::from(vec!["4", ""]),
+            StringRecord::from(vec!["5", "C9"]),
+        ];
+        let customers = vec![StringRecord::from(vec!["C1"]), StringRecord::from(vec!["C2"])];
+        assert_eq!(orphaned_keys(&orders, 1, &customers, 0), vec![("C9".to_string(), 2), ("C7".to_string(), 1)]);
+        assert!(orphaned_keys(&orders[1..2], 1, &customers, 0).is_empty());
+    }
+
+    #[test]
+    fn test_cast() {
+        assert_eq!(parse_cast_arg("Age:int"), Ok(("Age".to_string(), ColumnType::Integer)));
+        assert_eq!(parse_cast_arg(" Active : Bool "), Ok(("Active".to_string(), ColumnType::Boolean)));
+        assert!(parse_cast_arg("Age").is_err());
+        assert!(parse_cast_arg("Age:money").is_err());
+
+        assert_eq!(ColumnType::Integer.cast(" 007 ").as_deref(), Some("7"));
+        assert_eq!(ColumnType::Integer.cast("7.5"), None);
+        assert_eq!(ColumnType::Float.cast("2.50").as_deref(), Some("2.5"));
+        assert_eq!(ColumnType::Float.cast("inf"), None);
+        assert_eq!(ColumnType::Boolean.cast("YES").as_deref(), Some("true"));
+        assert_eq!(ColumnType::Boolean.cast("1"), None);
+        assert_eq!(ColumnType::Date.cast("2024-02-30").as_deref(), Some("2024-02-30"));
+        assert_eq!(ColumnType::Date.cast("31/01/2024"), None);
+        assert_eq!(ColumnType::Integer.cast("").as_deref(), Some(""));
+
+        let headers = vec!["Name".to_string(), "Age".to_string()];
+        let casts = [(1, ColumnType::Integer)];
+        let cast = cast_record(&StringRecord::from(vec!["Anna", "030"]), &headers, &casts).unwrap();
+        assert_eq!(cast, StringRecord::from(vec!["Anna", "30"]));
+        let err = cast_record(&StringRecord::from(vec!["Bo", "old"]), &headers, &casts).unwrap_err();
+        assert_eq!(err.to_string(), "CSV parse error: cannot cast 'old' in column 'Age' to integer");
+    }
+}
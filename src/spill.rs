@@ -0,0 +1,180 @@
+//! Record buffers with a memory budget, for `--max-memory`. Records are kept in memory until
+//! their estimated size reaches the budget; the rest are written to a temporary file and read
+//! back in budget-sized chunks, so a query never holds much more than the budget at once.
+
+use crate::{CsvPeekError, Result};
+use csv::StringRecord;
+use std::io::{BufWriter, Seek, SeekFrom};
+use std::mem::size_of;
+
+/// Parses a `--max-memory` size: a number of bytes with an optional K, M, G or T suffix
+/// (powers of 1024), e.g. `512M` or `2GiB`.
+pub fn parse_size_arg(s: &str) -> std::result::Result<u64, String> {
+    let invalid = || format!("Invalid size '{}'. Expected a number of bytes with an optional K, M, G or T suffix, e.g. 512M.", s);
+    let text = s.trim().to_ascii_uppercase();
+    let text = text.strip_suffix("IB").or_else(|| text.strip_suffix('B')).unwrap_or(&text);
+    let (number, shift) = match text.char_indices().last() {
+        Some((at, 'K')) => (&text[..at], 10),
+        Some((at, 'M')) => (&text[..at], 20),
+        Some((at, 'G')) => (&text[..at], 30),
+        Some((at, 'T')) => (&text[..at], 40),
+        _ => (text, 0),
+    };
+    let number: u64 = number.trim().parse().map_err(|_| invalid())?;
+    number.checked_mul(1 << shift).filter(|&size| size > 0).ok_or_else(invalid)
+}
+
+/// Renders a byte count the way `--max-memory` takes it, e.g. `512M`.
+pub fn format_size(bytes: u64) -> String {
+    let units = [(40, "T"), (30, "G"), (20, "M"), (10, "K")];
+    match units.iter().find(|(shift, _)| bytes >= 1 << shift && bytes.is_multiple_of(1 << shift)) {
+        Some((shift, unit)) => format!("{}{}", bytes >> shift, unit),
+        None => format!("{} bytes", bytes),
+    }
+}
+
+/// Rough number of heap and inline bytes a record takes up.
+pub fn record_size(record: &StringRecord) -> u64 {
+    (size_of::<StringRecord>() + record.as_byte_record().as_slice().len() + record.len() * size_of::<usize>()) as u64
+}
+
+/// Records kept in order within a memory budget, spilling to a temporary file past it.
+pub struct SpillBuffer {
+    budget: u64,
+    used: u64,
+    records: Vec<StringRecord>,
+    spill: Option<csv::Writer<BufWriter<std::fs::File>>>,
+    spilled: usize,
+}
+
+impl SpillBuffer {
+    pub fn new(budget: u64) -> Self {
+        SpillBuffer { budget, used: 0, records: Vec::new(), spill: None, spilled: 0 }
+    }
+
+    /// Adds a record after the ones pushed so far.
+    pub fn push(&mut self, record: StringRecord) -> Result<()> {
+        if self.spill.is_none() {
+            let size = record_size(&record);
+            if self.used + size <= self.budget {
+                self.used += size;
+                self.records.push(record);
+                return Ok(());
+            }
+            let file = tempfile::tempfile()?;
+            self.spill = Some(csv::WriterBuilder::new().has_headers(false).flexible(true).from_writer(BufWriter::new(file)));
+        }
+        let spill = self.spill.as_mut().expect("spill file was just created");
+        spill.write_record(&record)?;
+        self.spilled += 1;
+        Ok(())
+    }
+
+    /// Number of records pushed.
+    pub fn len(&self) -> usize {
+        self.records.len() + self.spilled
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether records went past the budget into the temporary file.
+    pub fn is_spilled(&self) -> bool {
+        self.spill.is_some()
+    }
+
+    /// The records, if none were spilled.
+    pub fn into_records(self) -> Option<Vec<StringRecord>> {
+        (!self.is_spilled()).then_some(self.records)
+    }
+
+    /// The records in the order they were pushed, in chunks that each fit in the budget.
+    pub fn into_chunks(self) -> Result<SpillChunks> {
+        let reader = match self.spill {
+            Some(writer) => {
+                let mut file = writer.into_inner().map_err(|e| CsvPeekError::from(e.into_error()))?
+                    .into_inner().map_err(|e| CsvPeekError::from(e.into_error()))?;
+                file.seek(SeekFrom::Start(0))?;
+                Some(csv::ReaderBuilder::new().has_headers(false).flexible(true).from_reader(file))
+            }
+            None => None,
+        };
+        Ok(SpillChunks { budget: self.budget, first: Some(self.records), reader })
+    }
+}
+
+/// Chunks of a `SpillBuffer`: the records kept in memory, then the spilled ones.
+pub struct SpillChunks {
+    budget: u64,
+    first: Option<Vec<StringRecord>>,
+    reader: Option<csv::Reader<std::fs::File>>,
+}
+
+impl Iterator for SpillChunks {
+    type Item = Result<Vec<StringRecord>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(records) = self.first.take().filter(|records| !records.is_empty()) {
+            return Some(Ok(records));
+        }
+        let reader = self.reader.as_mut()?;
+        let mut chunk = Vec::new();
+        let mut used = 0;
+        let mut record = StringRecord::new();
+        while used < self.budget {
+            match reader.read_record(&mut record) {
+                Ok(true) => {
+                    used += record_size(&record);
+                    chunk.push(record.clone());
+                }
+                Ok(false) => {
+                    self.reader = None;
+                    break;
+                }
+                Err(e) => {
+                    self.reader = None;
+                    return Some(Err(e.into()));
+                }
+            }
+        }
+        (!chunk.is_empty()).then_some(Ok(chunk))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size_arg() {
+        assert_eq!(parse_size_arg("512M"), Ok(512 << 20));
+        assert_eq!(parse_size_arg("2GiB"), Ok(2 << 30));
+        assert_eq!(parse_size_arg("64kb"), Ok(64 << 10));
+        assert_eq!(parse_size_arg("1000"), Ok(1000));
+        assert!(parse_size_arg("0").is_err());
+        assert!(parse_size_arg("lots").is_err());
+        assert_eq!(format_size(512 << 20), "512M");
+        assert_eq!(format_size(1000), "1000 bytes");
+    }
+
+    #[test]
+    fn test_spill_keeps_order_within_budget() {
+        let records: Vec<StringRecord> = (0..100).map(|i| StringRecord::from(vec![i.to_string(), "x".repeat(i)])).collect();
+        let budget = records.iter().take(10).map(record_size).sum();
+        let mut buffer = SpillBuffer::new(budget);
+        for record in &records {
+            buffer.push(record.clone()).unwrap();
+        }
+        assert_eq!(buffer.len(), 100);
+        assert!(buffer.is_spilled());
+        let chunks = buffer.into_chunks().unwrap().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(chunks[0].len(), 10);
+        assert!(chunks.len() > 2);
+        assert_eq!(chunks.concat(), records);
+
+        let mut small = SpillBuffer::new(budget);
+        small.push(records[0].clone()).unwrap();
+        assert_eq!(small.into_records(), Some(vec![records[0].clone()]));
+    }
+}
@@ -0,0 +1,228 @@
+//! SQLite databases. As input, a table or the result of a query is handed to the CSV parser as
+//! CSV text, like a workbook sheet. As output, `--to-sqlite` writes the displayed columns of the
+//! matching rows to a table whose column types are inferred like `--infer-schema` does, and
+//! `--format sql` prints them as `INSERT` statements instead.
+
+use crate::input::display_path;
+use crate::output::write_line;
+use crate::schema::{infer_schema, ColumnType};
+use crate::{CsvPeekError, Result};
+use csv::StringRecord;
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{params_from_iter, Connection, OpenFlags};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// File extensions read as SQLite databases rather than delimited text.
+pub const DATABASE_EXTENSIONS: [&str; 3] = ["db", "sqlite", "sqlite3"];
+
+/// True when a path names a SQLite database by its extension.
+pub fn is_database(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| DATABASE_EXTENSIONS.iter().any(|database| ext.eq_ignore_ascii_case(database)))
+}
+
+/// Renders the rows of `query`, or else of `table`, from the SQLite database at `path` as CSV
+/// text with the column names as the header row. Without either, the database must hold
+/// exactly one table. The database is opened read-only.
+pub fn query_as_csv(path: &Path, table: Option<&str>, query: Option<&str>) -> Result<Vec<u8>> {
+    let sqlite_error = |error: rusqlite::Error| CsvPeekError::Sqlite { path: path.to_path_buf(), message: error.to_string() };
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+        .map_err(sqlite_error)?;
+    let sql = match (query, table) {
+        (Some(query), _) => query.to_string(),
+        (None, Some(table)) => format!("SELECT * FROM {}", quote_identifier(table)),
+        (None, None) => {
+            let tables: Vec<String> = connection.prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name")
+                .and_then(|mut statement| statement.query_map([], |row| row.get(0))?.collect())
+                .map_err(sqlite_error)?;
+            match tables.as_slice() {
+                [table] => format!("SELECT * FROM {}", quote_identifier(table)),
+                [] => return Err(CsvPeekError::MissingHeaders),
+                _ => return Err(CsvPeekError::Usage(format!(
+                    "'{}' has several tables; pick one with --table or use --sqlite-query. Tables: {}",
+                    display_path(path),
+                    tables.join(", ")
+                ))),
+            }
+        }
+    };
+    let mut statement = connection.prepare(&sql).map_err(sqlite_error)?;
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(statement.column_names())?;
+    let width = statement.column_count();
+    let mut rows = statement.query([]).map_err(sqlite_error)?;
+    while let Some(row) = rows.next().map_err(sqlite_error)? {
+        let mut cells = Vec::with_capacity(width);
+        for index in 0..width {
+            cells.push(cell_text(row.get_ref(index).map_err(sqlite_error)?));
+        }
+        writer.write_record(&cells)?;
+    }
+    writer.into_inner().map_err(|e| CsvPeekError::from(e.into_error()))
+}
+
+/// The text of a value: NULL is an empty cell, and blobs are read as (lossy) UTF-8.
+fn cell_text(value: ValueRef) -> String {
+    match value {
+        ValueRef::Null => String::new(),
+        ValueRef::Integer(number) => number.to_string(),
+        ValueRef::Real(number) => number.to_string(),
+        ValueRef::Text(text) | ValueRef::Blob(text) => String::from_utf8_lossy(text).into_owned(),
+    }
+}
+
+/// Quotes a table or column name for use in SQL.
+pub fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// The SQLite column type that holds values of an inferred type. Booleans are stored as 0 and
+/// 1, and dates as ISO 8601 text.
+fn sql_type(column_type: ColumnType) -> &'static str {
+    match column_type {
+        ColumnType::Integer | ColumnType::Boolean => "INTEGER",
+        ColumnType::Float => "REAL",
+        ColumnType::Date | ColumnType::String => "TEXT",
+    }
+}
+
+/// Converts a cell to the SQLite value of its column's type; empty cells become NULL.
+fn sql_value(column_type: ColumnType, cell: &str) -> Value {
+    let Some(value) = column_type.cast(cell).filter(|value| !value.is_empty()) else {
+        return Value::Null;
+    };
+    match column_type {
+        ColumnType::Integer => value.parse().map_or(Value::Text(value), Value::Integer),
+        ColumnType::Float => value.parse().map_or(Value::Text(value), Value::Real),
+        ColumnType::Boolean => Value::Integer(i64::from(value == "true")),
+        ColumnType::Date | ColumnType::String => Value::Text(value),
+    }
+}
+
+/// Creates `table` in the SQLite database at `path` (creating the file if needed) and fills it
+/// with `rows`. An existing table of that name is replaced. Everything happens in one
+/// transaction, so a failed export leaves the database as it was.
+pub fn write_table(path: &Path, table: &str, headers: &[String], rows: &[Vec<String>]) -> Result<()> {
+    let sqlite_error = |error: rusqlite::Error| CsvPeekError::Sqlite { path: path.to_path_buf(), message: error.to_string() };
+    let records: Vec<StringRecord> = rows.iter().map(|row| StringRecord::from(row.as_slice())).collect();
+    let types: Vec<ColumnType> = infer_schema(headers, &records).into_iter().map(|column| column.column_type).collect();
+    let mut connection = Connection::open(path).map_err(sqlite_error)?;
+    let transaction = connection.transaction().map_err(sqlite_error)?;
+    let columns: Vec<String> = headers.iter().zip(&types)
+        .map(|(name, &column_type)| format!("{} {}", quote_identifier(name), sql_type(column_type)))
+        .collect();
+    transaction.execute_batch(&format!(
+        "DROP TABLE IF EXISTS {table}; CREATE TABLE {table} ({});",
+        columns.join(", "),
+        table = quote_identifier(table)
+    )).map_err(sqlite_error)?;
+    {
+        let placeholders = vec!["?"; headers.len()].join(", ");
+        let mut insert = transaction.prepare(&format!("INSERT INTO {} VALUES ({})", quote_identifier(table), placeholders)).map_err(sqlite_error)?;
+        for row in rows {
+            let values = types.iter().enumerate().map(|(index, &column_type)| sql_value(column_type, row.get(index).map_or("", String::as_str)));
+            insert.execute(params_from_iter(values)).map_err(sqlite_error)?;
+        }
+    }
+    transaction.commit().map_err(sqlite_error)
+}
+
+/// Writes `rows` as `INSERT INTO table (...) VALUES (...);` statements of up to `batch` rows
+/// each. Columns inferred as integers or floats get number literals, empty cells are NULL and
+/// everything else is a string literal with its single quotes doubled.
+pub fn write_inserts<W: Write>(mut out: W, table: &str, headers: &[String], rows: &[Vec<String>], batch: usize) -> io::Result<()> {
+    let records: Vec<StringRecord> = rows.iter().map(|row| StringRecord::from(row.as_slice())).collect();
+    let types: Vec<ColumnType> = infer_schema(headers, &records).into_iter().map(|column| column.column_type).collect();
+    let columns: Vec<String> = headers.iter().map(|name| quote_identifier(name)).collect();
+    let insert = format!("INSERT INTO {} ({}) VALUES", quote_identifier(table), columns.join(", "));
+    let tuple = |row: &Vec<String>| {
+        let values: Vec<String> = types.iter().enumerate()
+            .map(|(index, &column_type)| sql_literal(column_type, row.get(index).map_or("", String::as_str)))
+            .collect();
+        format!("({})", values.join(", "))
+    };
+    for chunk in rows.chunks(batch.max(1)) {
+        if let [row] = chunk {
+            write_line(&mut out, format_args!("{} {};", insert, tuple(row)))?;
+            continue;
+        }
+        write_line(&mut out, format_args!("{}", insert))?;
+        for (index, row) in chunk.iter().enumerate() {
+            let end = if index + 1 == chunk.len() { ";" } else { "," };
+            write_line(&mut out, format_args!("  {}{}", tuple(row), end))?;
+        }
+    }
+    out.flush()
+}
+
+/// A cell as a SQL literal of its column's type.
+fn sql_literal(column_type: ColumnType, cell: &str) -> String {
+    match sql_value(column_type, cell) {
+        Value::Null => "NULL".to_string(),
+        Value::Integer(number) if column_type == ColumnType::Integer => number.to_string(),
+        Value::Real(number) => number.to_string(),
+        _ => format!("'{}'", cell.replace('\'', "''")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_as_csv() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shop.db");
+        let connection = Connection::open(&path).unwrap();
+        connection.execute_batch(
+            "CREATE TABLE orders (id INTEGER, total REAL, note TEXT);
+             INSERT INTO orders VALUES (1, 9.5, 'gift, wrapped'), (2, 20, NULL);",
+        ).unwrap();
+        let text = |csv: Vec<u8>| String::from_utf8(csv).unwrap();
+        assert_eq!(text(query_as_csv(&path, None, None).unwrap()), "id,total,note\n1,9.5,\"gift, wrapped\"\n2,20,\n");
+        assert_eq!(text(query_as_csv(&path, Some("orders"), Some("SELECT id FROM orders WHERE total > 10")).unwrap()), "id\n2\n");
+
+        connection.execute_batch("CREATE TABLE customers (name TEXT);").unwrap();
+        let err = query_as_csv(&path, None, None).unwrap_err();
+        assert!(err.to_string().ends_with("Tables: customers, orders"));
+        assert_eq!(text(query_as_csv(&path, Some("customers"), None).unwrap()), "name\n");
+    }
+
+    #[test]
+    fn test_write_inserts() {
+        let headers: Vec<String> = ["Name", "Age", "Active"].iter().map(|h| h.to_string()).collect();
+        let rows: Vec<Vec<String>> = [["O'Brien", "040", "yes"], ["Ada", "", "no"], ["Bo", "7", "true"]].iter()
+            .map(|row| row.iter().map(|c| c.to_string()).collect())
+            .collect();
+        let mut single = Vec::new();
+        write_inserts(&mut single, "people", &headers, &rows[..2], 1).unwrap();
+        assert_eq!(String::from_utf8(single).unwrap(),
+            "INSERT INTO \"people\" (\"Name\", \"Age\", \"Active\") VALUES ('O''Brien', 40, 'yes');\n\
+             INSERT INTO \"people\" (\"Name\", \"Age\", \"Active\") VALUES ('Ada', NULL, 'no');\n");
+        let mut batched = Vec::new();
+        write_inserts(&mut batched, "people", &headers, &rows, 2).unwrap();
+        assert_eq!(String::from_utf8(batched).unwrap(),
+            "INSERT INTO \"people\" (\"Name\", \"Age\", \"Active\") VALUES\n  ('O''Brien', 40, 'yes'),\n  ('Ada', NULL, 'no');\n\
+             INSERT INTO \"people\" (\"Name\", \"Age\", \"Active\") VALUES ('Bo', 7, 'true');\n");
+    }
+
+    #[test]
+    fn test_write_table_infers_types_and_replaces() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.db");
+        let headers: Vec<String> = ["Name", "Age", "Score", "Active"].iter().map(|h| h.to_string()).collect();
+        let row = |cells: [&str; 4]| cells.iter().map(|c| c.to_string()).collect::<Vec<String>>();
+        write_table(&path, "peek", &headers, &[row(["Old", "1", "1", "no"])]).unwrap();
+        write_table(&path, "peek", &headers, &[row(["Ada", "36", "9.5", "yes"]), row(["Bo \"B\"", "", "7", "no"])]).unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        let declared: Vec<String> = connection.prepare("SELECT type FROM pragma_table_info('peek')").unwrap()
+            .query_map([], |row| row.get(0)).unwrap().collect::<rusqlite::Result<_>>().unwrap();
+        assert_eq!(declared, vec!["TEXT", "INTEGER", "REAL", "INTEGER"]);
+        let rows: Vec<(String, Option<i64>, f64, i64)> = connection.prepare("SELECT * FROM peek ORDER BY Name").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))).unwrap()
+            .collect::<rusqlite::Result<_>>().unwrap();
+        assert_eq!(rows, vec![("Ada".to_string(), Some(36), 9.5, 1), ("Bo \"B\"".to_string(), None, 7.0, 0)]);
+    }
+}
@@ -0,0 +1,983 @@
+use crate::error::Result;
+use crate::filter::{natural_cmp, parse_date, parse_filter_arg, parse_number, value_matches, Operator};
+use crate::find_column;
+use chrono::{Datelike, Days, Months, NaiveDate};
+use clap::ValueEnum;
+use csv::StringRecord;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::fmt;
+
+/// Aggregate functions available in `--footer`, `--agg` and `--assert`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFn {
+    Count,
+    /// Number of empty cells.
+    NullCount,
+    Min,
+    Max,
+    First,
+    Last,
+    Sum,
+    Avg,
+}
+
+impl AggregateFn {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "count" => Some(AggregateFn::Count),
+            "null_count" => Some(AggregateFn::NullCount),
+            "min" => Some(AggregateFn::Min),
+            "max" => Some(AggregateFn::Max),
+            "first" => Some(AggregateFn::First),
+            "last" => Some(AggregateFn::Last),
+            "sum" => Some(AggregateFn::Sum),
+            "avg" => Some(AggregateFn::Avg),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AggregateFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AggregateFn::Count => "count",
+            AggregateFn::NullCount => "null_count",
+            AggregateFn::Min => "min",
+            AggregateFn::Max => "max",
+            AggregateFn::First => "first",
+            AggregateFn::Last => "last",
+            AggregateFn::Sum => "sum",
+            AggregateFn::Avg => "avg",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// An aggregate expression such as `max(Price)` or `count()`.
+/// `column` is `None` only for `count()`, which counts rows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    pub func: AggregateFn,
+    pub column: Option<String>,
+}
+
+impl fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({})", self.func, self.column.as_deref().unwrap_or(""))
+    }
+}
+
+/// Parses an `--agg` function name such as `sum`.
+pub fn parse_aggregate_fn(s: &str) -> std::result::Result<AggregateFn, String> {
+    AggregateFn::from_name(s.trim()).ok_or_else(|| format!("Invalid aggregate function '{}'. Expected one of count, null_count, min, max, first, last, sum, avg.", s))
+}
+
+/// Parses `FUNC(COLUMN)` or `count()`.
+pub fn parse_aggregate(s: &str) -> std::result::Result<Aggregate, String> {
+    let s = s.trim();
+    let invalid = || format!("Invalid aggregate '{}'. Expected FUNC(COLUMN) with FUNC one of count, null_count, min, max, first, last, sum, avg.", s);
+    let (name, rest) = s.split_once('(').ok_or_else(invalid)?;
+    let column = rest.strip_suffix(')').ok_or_else(invalid)?.trim();
+    let func = AggregateFn::from_name(name.trim()).ok_or_else(invalid)?;
+    if column.is_empty() {
+        if func != AggregateFn::Count {
+            return Err(format!("Aggregate '{}' needs a column, e.g. {}(Price).", s, func));
+        }
+        return Ok(Aggregate { func, column: None });
+    }
+    Ok(Aggregate { func, column: Some(column.to_string()) })
+}
+
+/// Result of evaluating an aggregate.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AggregateValue {
+    Number(f64),
+    Text(String),
+    /// No (usable) values to aggregate.
+    Empty,
+}
+
+impl AggregateValue {
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            AggregateValue::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AggregateValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AggregateValue::Number(n) => write!(f, "{}", format_number(*n)),
+            AggregateValue::Text(text) => write!(f, "{}", text),
+            AggregateValue::Empty => write!(f, "-"),
+        }
+    }
+}
+
+/// Formats a number without a trailing `.0` for whole values.
+pub fn format_number(n: f64) -> String {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        format!("{:.0}", n)
+    } else {
+        format!("{}", n)
+    }
+}
+
+/// An aggregate whose column has been resolved against the headers.
+#[derive(Debug, Clone)]
+pub struct ResolvedAggregate {
+    pub aggregate: Aggregate,
+    pub column: Option<usize>,
+}
+
+impl ResolvedAggregate {
+    pub fn resolve(aggregate: &Aggregate, headers: &[String]) -> Result<Self> {
+        let column = match &aggregate.column {
+            Some(name) => Some(find_column(headers, name, "Aggregate")?),
+            None => None,
+        };
+        Ok(ResolvedAggregate { aggregate: aggregate.clone(), column })
+    }
+
+    /// Evaluates the aggregate over the given records. Empty cells are ignored;
+    /// `min`/`max` compare numerically when every value is a number and as text otherwise,
+    /// while `sum`/`avg` only consider numeric cells.
+    pub fn evaluate(&self, records: &[&StringRecord]) -> AggregateValue {
+        let Some(idx) = self.column else {
+            return AggregateValue::Number(records.len() as f64);
+        };
+        let mut values = records.iter().filter_map(|r| r.get(idx)).filter(|v| !v.trim().is_empty());
+        match self.aggregate.func {
+            AggregateFn::Count => AggregateValue::Number(values.count() as f64),
+            AggregateFn::NullCount => AggregateValue::Number((records.len() - values.count()) as f64),
+            AggregateFn::First => values.next().map_or(AggregateValue::Empty, |v| AggregateValue::Text(v.to_string())),
+            AggregateFn::Last => values.next_back().map_or(AggregateValue::Empty, |v| AggregateValue::Text(v.to_string())),
+            AggregateFn::Sum | AggregateFn::Avg => {
+                let numbers: Vec<f64> = values.filter_map(parse_number).collect();
+                if numbers.is_empty() {
+                    return AggregateValue::Empty;
+                }
+                let sum: f64 = numbers.iter().sum();
+                if self.aggregate.func == AggregateFn::Sum {
+                    AggregateValue::Number(sum)
+                } else {
+                    AggregateValue::Number(sum / numbers.len() as f64)
+                }
+            }
+            AggregateFn::Min | AggregateFn::Max => {
+                let values: Vec<&str> = values.collect();
+                if values.is_empty() {
+                    return AggregateValue::Empty;
+                }
+                let want_max = self.aggregate.func == AggregateFn::Max;
+                let numbers: Option<Vec<f64>> = values.iter().map(|v| parse_number(v)).collect();
+                if let Some(numbers) = numbers {
+                    let pick = if want_max { f64::max } else { f64::min };
+                    return AggregateValue::Number(numbers.into_iter().reduce(pick).unwrap_or_default());
+                }
+                let text = if want_max { values.iter().max() } else { values.iter().min() };
+                text.map_or(AggregateValue::Empty, |v| AggregateValue::Text(v.to_string()))
+            }
+        }
+    }
+}
+
+/// A `--assert` check such as `max(Price)<=10000`: an aggregate compared with a value using a
+/// filter operator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assertion {
+    pub aggregate: Aggregate,
+    pub op: Operator,
+    pub value: String,
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.aggregate, self.op, self.value)
+    }
+}
+
+/// Parses `FUNC(COLUMN)<OP>VALUE`, e.g. `count()>0` or `null_count(Email)=0`.
+pub fn parse_assertion(s: &str) -> std::result::Result<Assertion, String> {
+    let invalid = |reason: String| format!("Invalid assertion '{}': {} Expected FUNC(COLUMN)<OP>VALUE, e.g. \"max(Price)<=10000\".", s, reason);
+    let (aggregate, op, value) = parse_filter_arg(s).map_err(|_| invalid("operator missing.".to_string()))?;
+    let aggregate = parse_aggregate(&aggregate).map_err(invalid)?;
+    if value.is_empty() {
+        return Err(invalid("value missing.".to_string()));
+    }
+    Ok(Assertion { aggregate, op, value })
+}
+
+impl Assertion {
+    /// Whether the aggregate's `result` satisfies the assertion. Numbers are compared
+    /// numerically and text as filters compare it; an empty result never does.
+    pub fn holds(&self, result: &AggregateValue) -> bool {
+        // A numeric result is compared as is: printing it would use a decimal point, which
+        // does not read back as the same number under `--decimal-comma`.
+        match (result, parse_number(&self.value)) {
+            (AggregateValue::Empty, _) => false,
+            (AggregateValue::Number(n), Some(expected)) => match self.op {
+                Operator::Eq => *n == expected,
+                Operator::NotEq => *n != expected,
+                Operator::Lt => *n < expected,
+                Operator::Gt => *n > expected,
+                Operator::LtEq => *n <= expected,
+                Operator::GtEq => *n >= expected,
+                Operator::Custom(_) => value_matches(&result.to_string(), self.op, &self.value),
+            },
+            (result, _) => value_matches(&result.to_string(), self.op, &self.value),
+        }
+    }
+}
+
+/// Parses a `--crosstab` value: the row and column columns, `ROWCOL,COLCOL`.
+pub fn parse_crosstab_arg(s: &str) -> std::result::Result<(String, String), String> {
+    match s.split_once(',') {
+        Some((row, column)) if !row.trim().is_empty() && !column.trim().is_empty() && !column.contains(',') => {
+            Ok((row.trim().to_string(), column.trim().to_string()))
+        }
+        _ => Err(format!("Invalid crosstab '{}'. Expected two columns, ROWCOL,COLCOL.", s)),
+    }
+}
+
+/// A contingency table: one row per distinct value of one column and one column per distinct
+/// value of another, with a count or aggregate of the records in each cell.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Crosstab {
+    pub row_values: Vec<String>,
+    pub column_values: Vec<String>,
+    /// `cells[r][c]` covers the records with `row_values[r]` and `column_values[c]`.
+    pub cells: Vec<Vec<AggregateValue>>,
+}
+
+/// Cross-tabulates `records` by the `row` and `column` columns. Cells count their records, or
+/// evaluate `value` over them; combinations without records count 0 or are empty. Values are
+/// sorted numerically when they all are numbers and as text otherwise.
+pub fn crosstab(records: &[&StringRecord], row: usize, column: usize, value: Option<&ResolvedAggregate>) -> Crosstab {
+    let mut groups: BTreeMap<(&str, &str), Vec<&StringRecord>> = BTreeMap::new();
+    for record in records {
+        let key = (record.get(row).unwrap_or_default(), record.get(column).unwrap_or_default());
+        groups.entry(key).or_default().push(record);
+    }
+    let row_values = sorted_values(groups.keys().map(|(r, _)| *r));
+    let column_values = sorted_values(groups.keys().map(|(_, c)| *c));
+    let cells = row_values.iter()
+        .map(|r| column_values.iter()
+            .map(|c| match (groups.get(&(r.as_str(), c.as_str())), value) {
+                (Some(group), Some(value)) => value.evaluate(group),
+                (Some(group), None) => AggregateValue::Number(group.len() as f64),
+                (None, Some(_)) => AggregateValue::Empty,
+                (None, None) => AggregateValue::Number(0.0),
+            })
+            .collect())
+        .collect();
+    Crosstab { row_values, column_values, cells }
+}
+
+/// Parses a `--freq` value: one column, or two as `ROWCOL,COLCOL`.
+pub fn parse_freq_arg(s: &str) -> std::result::Result<(String, Option<String>), String> {
+    let columns: Vec<String> = s.split(',').map(|column| column.trim().to_string()).collect();
+    match columns.as_slice() {
+        [column] if !column.is_empty() => Ok((column.clone(), None)),
+        [row, column] if !row.is_empty() && !column.is_empty() => Ok((row.clone(), Some(column.clone()))),
+        _ => Err(format!("Invalid freq '{}'. Expected a column, or two as ROWCOL,COLCOL.", s)),
+    }
+}
+
+/// The distinct values of `column` with the number of records holding each, most frequent
+/// first; equally frequent values are sorted like crosstab values.
+pub fn value_counts(records: &[&StringRecord], column: usize) -> Vec<(String, usize)> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for record in records {
+        *counts.entry(record.get(column).unwrap_or_default()).or_default() += 1;
+    }
+    let mut values: Vec<(String, usize)> = sorted_values(counts.keys().copied()).into_iter()
+        .map(|value| {
+            let count = counts[value.as_str()];
+            (value, count)
+        })
+        .collect();
+    values.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    values
+}
+
+/// A two-column `--freq` table: the record counts of a crosstab with their row and column
+/// totals, from which each cell's share of its row and of its column follow.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FreqTable {
+    pub row_values: Vec<String>,
+    pub column_values: Vec<String>,
+    /// `counts[r][c]` records have `row_values[r]` and `column_values[c]`.
+    pub counts: Vec<Vec<usize>>,
+}
+
+impl FreqTable {
+    pub fn new(records: &[&StringRecord], row: usize, column: usize) -> Self {
+        let table = crosstab(records, row, column, None);
+        let counts = table.cells.iter()
+            .map(|cells| cells.iter().map(|cell| match cell {
+                AggregateValue::Number(n) => *n as usize,
+                _ => 0,
+            }).collect())
+            .collect();
+        FreqTable { row_values: table.row_values, column_values: table.column_values, counts }
+    }
+
+    pub fn row_total(&self, r: usize) -> usize {
+        self.counts[r].iter().sum()
+    }
+
+    pub fn column_total(&self, c: usize) -> usize {
+        self.counts.iter().map(|cells| cells[c]).sum()
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().flatten().sum()
+    }
+
+    /// Share of row `r` that falls in column `c`, in percent.
+    pub fn row_percent(&self, r: usize, c: usize) -> f64 {
+        percent(self.counts[r][c], self.row_total(r))
+    }
+
+    /// Share of column `c` that falls in row `r`, in percent.
+    pub fn column_percent(&self, r: usize, c: usize) -> f64 {
+        percent(self.counts[r][c], self.column_total(c))
+    }
+}
+
+/// `value` as a percentage of `total` for `--percent-of-total`; `None` when `total` is zero.
+pub fn share_of_total(value: f64, total: f64) -> Option<f64> {
+    (total != 0.0).then(|| value * 100.0 / total)
+}
+
+/// `part` of `whole` in percent; 0 for an empty whole.
+pub fn percent(part: usize, whole: usize) -> f64 {
+    if whole == 0 { 0.0 } else { part as f64 * 100.0 / whole as f64 }
+}
+
+fn sorted_values<'a>(values: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut values: Vec<&str> = values.collect();
+    values.sort_unstable();
+    values.dedup();
+    if let Some(mut numbers) = values.iter().map(|v| parse_number(v).map(|n| (n, *v))).collect::<Option<Vec<_>>>() {
+        numbers.sort_by(|a, b| a.0.total_cmp(&b.0));
+        return numbers.into_iter().map(|(_, v)| v.to_string()).collect();
+    }
+    values.into_iter().map(String::from).collect()
+}
+
+/// Period that `date_trunc` in `--group-by` truncates dates to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DateUnit {
+    Day,
+    /// Weeks start on Monday.
+    Week,
+    Month,
+    Year,
+}
+
+impl fmt::Display for DateUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DateUnit::Day => "day",
+            DateUnit::Week => "week",
+            DateUnit::Month => "month",
+            DateUnit::Year => "year",
+        })
+    }
+}
+
+/// What `--group-by` groups rows by: the cells of a column, or the period their date falls in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GroupBy {
+    Column(String),
+    DateTrunc(DateUnit, String),
+}
+
+impl GroupBy {
+    pub fn column(&self) -> &str {
+        match self {
+            GroupBy::Column(column) | GroupBy::DateTrunc(_, column) => column,
+        }
+    }
+}
+
+impl fmt::Display for GroupBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GroupBy::Column(column) => write!(f, "{}", column),
+            GroupBy::DateTrunc(unit, column) => write!(f, "date_trunc({}, {})", unit, column),
+        }
+    }
+}
+
+/// Parses a `--group-by` value: `COLUMN` or `date_trunc(UNIT, COLUMN)` with UNIT one of day,
+/// week, month or year.
+pub fn parse_group_by_arg(s: &str) -> std::result::Result<GroupBy, String> {
+    let trimmed = s.trim();
+    let Some(inner) = trimmed.strip_suffix(')').and_then(|rest| {
+        let (name, args) = rest.split_once('(')?;
+        name.trim().eq_ignore_ascii_case("date_trunc").then_some(args)
+    }) else {
+        return match trimmed {
+            "" => Err("Invalid group-by ''. Expected COLUMN or date_trunc(UNIT, COLUMN).".to_string()),
+            column => Ok(GroupBy::Column(column.to_string())),
+        };
+    };
+    let invalid = || format!("Invalid group-by '{}'. Expected date_trunc(UNIT, COLUMN) with UNIT one of day, week, month or year.", s);
+    let (unit, column) = inner.split_once(',').ok_or_else(invalid)?;
+    let unit = match unit.trim().to_ascii_lowercase().as_str() {
+        "day" => DateUnit::Day,
+        "week" => DateUnit::Week,
+        "month" => DateUnit::Month,
+        "year" => DateUnit::Year,
+        _ => return Err(invalid()),
+    };
+    match column.trim() {
+        "" => Err(invalid()),
+        column => Ok(GroupBy::DateTrunc(unit, column.to_string())),
+    }
+}
+
+/// The first day of the `unit` that `date` falls in.
+pub fn truncate_date(date: NaiveDate, unit: DateUnit) -> NaiveDate {
+    match unit {
+        DateUnit::Day => date,
+        DateUnit::Week => date - Days::new(u64::from(date.weekday().num_days_from_monday())),
+        DateUnit::Month => date.with_day(1).unwrap_or(date),
+        DateUnit::Year => date.with_ordinal(1).unwrap_or(date),
+    }
+}
+
+/// The `--group-by` groups of some records, in ascending order of their key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Groups {
+    pub keys: Vec<String>,
+    pub counts: Vec<usize>,
+    /// The `--value` aggregate of each group, when one was given.
+    pub values: Option<Vec<AggregateValue>>,
+    /// Number of records left out because their cell is not a date.
+    pub skipped: usize,
+}
+
+/// Groups `records` by the `column` cells, truncated to the start of their `unit` period as
+/// YYYY-MM-DD when one is given, and counts each group or evaluates `value` over it. Keys are
+/// sorted numerically when they all are numbers and as text otherwise, so periods are in
+/// chronological order.
+pub fn group_records(
+    records: &[&StringRecord],
+    column: usize,
+    unit: Option<DateUnit>,
+    date_format: Option<&str>,
+    value: Option<&ResolvedAggregate>,
+) -> Groups {
+    let mut groups: BTreeMap<String, Vec<&StringRecord>> = BTreeMap::new();
+    let mut skipped = 0;
+    for record in records {
+        let cell = record.get(column).unwrap_or_default();
+        let key = match unit {
+            None => cell.to_string(),
+            Some(unit) => match parse_date(cell, date_format) {
+                Some(date) => truncate_date(date, unit).to_string(),
+                None => {
+                    skipped += 1;
+                    continue;
+                }
+            },
+        };
+        groups.entry(key).or_default().push(record);
+    }
+    let keys = sorted_values(groups.keys().map(String::as_str));
+    let counts = keys.iter().map(|key| groups[key].len()).collect();
+    let values = value.map(|value| keys.iter().map(|key| value.evaluate(&groups[key])).collect());
+    Groups { keys, counts, values, skipped }
+}
+
+/// Number of bins in the value distributions that `--profile` draws as sparklines.
+pub const HISTOGRAM_BINS: usize = 8;
+
+/// Most periods a `--profile` timeline has; longer spans use a coarser unit.
+pub const MAX_TIMELINE_PERIODS: usize = 48;
+
+/// Bars of a sparkline, from the smallest count to the largest.
+const SPARK_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Counts `values` into `bins` equal-width bins spanning the smallest to the largest value.
+/// Equal values make a single bin, and no values make none.
+pub fn histogram(values: &[f64], bins: usize) -> Vec<usize> {
+    let (min, max) = values.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &n| (min.min(n), max.max(n)));
+    if values.is_empty() || bins == 0 {
+        return Vec::new();
+    }
+    if min == max {
+        return vec![values.len()];
+    }
+    let mut counts = vec![0; bins];
+    for &n in values {
+        let bin = ((n - min) / (max - min) * bins as f64) as usize;
+        counts[bin.min(bins - 1)] += 1;
+    }
+    counts
+}
+
+/// Draws `counts` as one block character each, the largest as a full block and any other
+/// non-zero count as at least the lowest bar; a zero count is a space.
+pub fn sparkline(counts: &[usize]) -> String {
+    let max = counts.iter().copied().max().unwrap_or(0);
+    counts.iter()
+        .map(|&count| match count {
+            0 => ' ',
+            count => SPARK_BARS[(count * SPARK_BARS.len()).div_ceil(max) - 1],
+        })
+        .collect()
+}
+
+/// The number of dates in each consecutive period from the earliest date to the latest,
+/// including periods without any.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeline {
+    pub unit: DateUnit,
+    /// First day of the first period.
+    pub start: NaiveDate,
+    pub counts: Vec<usize>,
+}
+
+impl Timeline {
+    /// First day of the last period.
+    pub fn end(&self) -> NaiveDate {
+        (1..self.counts.len()).fold(self.start, |date, _| next_period(date, self.unit))
+    }
+}
+
+/// Counts `dates` per day, week, month or year, using the finest unit that spans them in at
+/// most `max_periods` periods (or years, however many). `None` without dates.
+pub fn timeline(dates: &[NaiveDate], max_periods: usize) -> Option<Timeline> {
+    let first = *dates.iter().min()?;
+    let last = *dates.iter().max()?;
+    let unit = [DateUnit::Day, DateUnit::Week, DateUnit::Month]
+        .into_iter()
+        .find(|&unit| {
+            let end = truncate_date(last, unit);
+            std::iter::successors(Some(truncate_date(first, unit)), |&period| Some(next_period(period, unit)))
+                .take(max_periods)
+                .any(|period| period == end)
+        })
+        .unwrap_or(DateUnit::Year);
+    let mut per_period: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+    for &date in dates {
+        *per_period.entry(truncate_date(date, unit)).or_default() += 1;
+    }
+    let start = truncate_date(first, unit);
+    let end = truncate_date(last, unit);
+    let mut counts = Vec::new();
+    let mut period = start;
+    while period <= end {
+        counts.push(per_period.get(&period).copied().unwrap_or(0));
+        period = next_period(period, unit);
+    }
+    Some(Timeline { unit, start, counts })
+}
+
+/// The first day of the `unit` after the one starting on `period`.
+fn next_period(period: NaiveDate, unit: DateUnit) -> NaiveDate {
+    match unit {
+        DateUnit::Day => period + Days::new(1),
+        DateUnit::Week => period + Days::new(7),
+        DateUnit::Month => period + Months::new(1),
+        DateUnit::Year => period + Months::new(12),
+    }
+}
+
+/// The columns named by `--corr`: every numeric column, or the listed ones.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CorrColumns {
+    All,
+    Columns(Vec<String>),
+}
+
+/// Parses a `--corr` value: `all`, or two or more comma-separated columns.
+pub fn parse_corr_arg(s: &str) -> std::result::Result<CorrColumns, String> {
+    if s.trim().eq_ignore_ascii_case("all") {
+        return Ok(CorrColumns::All);
+    }
+    let columns: Vec<String> = s.split(',').map(|column| column.trim().to_string()).collect();
+    if columns.len() < 2 || columns.iter().any(String::is_empty) {
+        return Err(format!("Invalid correlation '{}'. Expected \"all\" or two or more columns, e.g. \"Price,Rating\".", s));
+    }
+    Ok(CorrColumns::Columns(columns))
+}
+
+/// Correlation coefficient computed by `--corr`.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum CorrelationMethod {
+    /// Linear correlation of the values.
+    #[default]
+    Pearson,
+    /// Correlation of the ranks of the values, for monotonic relationships.
+    Spearman,
+}
+
+impl fmt::Display for CorrelationMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CorrelationMethod::Pearson => "Pearson",
+            CorrelationMethod::Spearman => "Spearman",
+        })
+    }
+}
+
+/// The correlation of two columns over the records where both cells are numbers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Correlation {
+    /// `None` with fewer than two pairs, or when either column has the same value throughout.
+    pub coefficient: Option<f64>,
+    /// Number of records used.
+    pub pairs: usize,
+    /// Number of records skipped because either cell was empty or not a number.
+    pub skipped: usize,
+}
+
+/// Correlates columns `x` and `y` of `records`, skipping records where either is not a number.
+pub fn correlate(records: &[&StringRecord], x: usize, y: usize, method: CorrelationMethod) -> Correlation {
+    let (xs, ys): (Vec<f64>, Vec<f64>) = records.iter()
+        .filter_map(|record| Some((parse_number(record.get(x)?)?, parse_number(record.get(y)?)?)))
+        .unzip();
+    let pairs = xs.len();
+    let coefficient = match method {
+        CorrelationMethod::Pearson => pearson(&xs, &ys),
+        CorrelationMethod::Spearman => pearson(&ranks(&xs), &ranks(&ys)),
+    };
+    Correlation { coefficient, pairs, skipped: records.len() - pairs }
+}
+
+fn pearson(xs: &[f64], ys: &[f64]) -> Option<f64> {
+    if xs.len() < 2 {
+        return None;
+    }
+    let n = xs.len() as f64;
+    let (mean_x, mean_y) = (xs.iter().sum::<f64>() / n, ys.iter().sum::<f64>() / n);
+    let (mut covariance, mut variance_x, mut variance_y) = (0.0, 0.0, 0.0);
+    for (x, y) in xs.iter().zip(ys) {
+        covariance += (x - mean_x) * (y - mean_y);
+        variance_x += (x - mean_x).powi(2);
+        variance_y += (y - mean_y).powi(2);
+    }
+    let denominator = (variance_x * variance_y).sqrt();
+    (denominator > 0.0).then(|| (covariance / denominator).clamp(-1.0, 1.0))
+}
+
+/// The 1-based ranks of `values`, with tied values sharing the average of their ranks.
+fn ranks(values: &[f64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+    let mut ranks = vec![0.0; values.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let end = (start..order.len()).find(|&i| values[order[i]] != values[order[start]]).unwrap_or(order.len());
+        let rank = (start + end + 1) as f64 / 2.0;
+        for &index in &order[start..end] {
+            ranks[index] = rank;
+        }
+        start = end;
+    }
+    ranks
+}
+
+/// Value of a `--by` cell. Numbers compare numerically and rank below text.
+#[derive(Debug, Clone)]
+enum RankKey {
+    Number(f64),
+    Text(String),
+}
+
+impl RankKey {
+    fn new(cell: &str) -> Self {
+        parse_number(cell).map_or_else(|| RankKey::Text(cell.to_string()), RankKey::Number)
+    }
+
+    /// Orders text lexicographically, or with [`natural_cmp`] when `natural` is set.
+    fn compare(&self, other: &Self, natural: bool) -> Ordering {
+        match (self, other) {
+            (RankKey::Number(a), RankKey::Number(b)) => a.total_cmp(b),
+            (RankKey::Text(a), RankKey::Text(b)) if natural => natural_cmp(a, b),
+            (RankKey::Text(a), RankKey::Text(b)) => a.cmp(b),
+            (RankKey::Number(_), RankKey::Text(_)) => Ordering::Less,
+            (RankKey::Text(_), RankKey::Number(_)) => Ordering::Greater,
+        }
+    }
+}
+
+/// An item kept by [`TopN`]. Better-ranked items compare as less, so the heap's greatest item
+/// is the first to be dropped; ties go to the item offered first.
+struct Ranked<T> {
+    key: RankKey,
+    position: usize,
+    largest: bool,
+    natural: bool,
+    item: T,
+}
+
+impl<T> Ord for Ranked<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let by_value = self.key.compare(&other.key, self.natural);
+        let by_value = if self.largest { by_value.reverse() } else { by_value };
+        by_value.then(self.position.cmp(&other.position))
+    }
+}
+
+impl<T> PartialOrd for Ranked<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> PartialEq for Ranked<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Ranked<T> {}
+
+/// Keeps the `limit` items with the largest (or smallest) values seen so far for `--top` and
+/// `--bottom`, in a bounded heap rather than sorting every item.
+pub struct TopN<T> {
+    heap: BinaryHeap<Ranked<T>>,
+    limit: usize,
+    largest: bool,
+    natural: bool,
+    offered: usize,
+}
+
+impl<T> TopN<T> {
+    pub fn new(limit: usize, largest: bool) -> Self {
+        TopN { heap: BinaryHeap::new(), limit, largest, natural: false, offered: 0 }
+    }
+
+    /// Ranks text cells in natural order, e.g. "v1.10" above "v1.9".
+    pub fn natural(mut self, natural: bool) -> Self {
+        self.natural = natural;
+        self
+    }
+
+    /// Offers `item`, ranked by the cell that `cell` picks from it. Items with an empty cell are
+    /// never kept.
+    pub fn push(&mut self, item: T, cell: impl FnOnce(&T) -> &str) {
+        let position = self.offered;
+        self.offered += 1;
+        let value = cell(&item);
+        if self.limit == 0 || value.trim().is_empty() {
+            return;
+        }
+        let ranked = Ranked { key: RankKey::new(value), position, largest: self.largest, natural: self.natural, item };
+        if self.heap.len() < self.limit {
+            self.heap.push(ranked);
+        } else if self.heap.peek().is_some_and(|worst| ranked < *worst) {
+            self.heap.pop();
+            self.heap.push(ranked);
+        }
+    }
+
+    /// The kept items, best first.
+    pub fn into_sorted(self) -> Vec<T> {
+        self.heap.into_sorted_vec().into_iter().map(|ranked| ranked.item).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(values: &[&str]) -> Vec<StringRecord> {
+        values.iter().map(|v| StringRecord::from(vec![*v])).collect()
+    }
+
+    #[test]
+    fn test_correlate() {
+        let data: Vec<StringRecord> = [["1", "2"], ["2", "4"], ["3", "7"], ["4", "n/a"], ["", "9"], ["5", "100"]].iter()
+            .map(|row| StringRecord::from(row.to_vec()))
+            .collect();
+        let refs: Vec<&StringRecord> = data.iter().collect();
+        let pearson = correlate(&refs, 0, 1, CorrelationMethod::Pearson);
+        assert_eq!((pearson.pairs, pearson.skipped), (4, 2));
+        assert!(pearson.coefficient.unwrap() < 0.9);
+        let spearman = correlate(&refs, 0, 1, CorrelationMethod::Spearman);
+        assert_eq!(spearman.coefficient, Some(1.0));
+        assert_eq!(correlate(&refs[..1], 0, 1, CorrelationMethod::Pearson).coefficient, None);
+
+        assert_eq!(ranks(&[10.0, 20.0, 10.0, 5.0]), vec![2.5, 4.0, 2.5, 1.0]);
+        assert_eq!(parse_corr_arg("ALL"), Ok(CorrColumns::All));
+        assert_eq!(parse_corr_arg("a, b"), Ok(CorrColumns::Columns(vec!["a".to_string(), "b".to_string()])));
+        assert!(parse_corr_arg("a").is_err());
+    }
+
+    #[test]
+    fn test_parse_aggregate() {
+        assert_eq!(parse_aggregate("count()"), Ok(Aggregate { func: AggregateFn::Count, column: None }));
+        assert_eq!(parse_aggregate(" MAX( Price ) "), Ok(Aggregate { func: AggregateFn::Max, column: Some("Price".to_string()) }));
+        assert!(parse_aggregate("min()").is_err());
+        assert!(parse_aggregate("median(Price)").is_err());
+        assert!(parse_aggregate("Price").is_err());
+    }
+
+    #[test]
+    fn test_evaluate_numeric_and_text() {
+        let headers = vec!["Price".to_string()];
+        let data = records(&["10", "", "2.5", "7"]);
+        let refs: Vec<&StringRecord> = data.iter().collect();
+        let eval = |expr: &str| ResolvedAggregate::resolve(&parse_aggregate(expr).unwrap(), &headers).unwrap().evaluate(&refs);
+        assert_eq!(eval("min(Price)"), AggregateValue::Number(2.5));
+        assert_eq!(eval("max(Price)"), AggregateValue::Number(10.0));
+        assert_eq!(eval("sum(Price)").to_string(), "19.5");
+        assert_eq!(eval("count(Price)"), AggregateValue::Number(3.0));
+        assert_eq!(eval("count()"), AggregateValue::Number(4.0));
+        assert_eq!(eval("first(Price)"), AggregateValue::Text("10".to_string()));
+        assert_eq!(eval("last(Price)"), AggregateValue::Text("7".to_string()));
+
+        assert_eq!(eval("null_count(Price)"), AggregateValue::Number(1.0));
+
+        let text = records(&["pear", "apple", "10"]);
+        let refs: Vec<&StringRecord> = text.iter().collect();
+        let max = ResolvedAggregate::resolve(&parse_aggregate("max(Price)").unwrap(), &headers).unwrap().evaluate(&refs);
+        assert_eq!(max, AggregateValue::Text("pear".to_string()));
+    }
+
+    #[test]
+    fn test_assertion() {
+        let assertion = parse_assertion(" max(Price) <= 10000").unwrap();
+        assert_eq!(assertion.aggregate, Aggregate { func: AggregateFn::Max, column: Some("Price".to_string()) });
+        assert_eq!(assertion.to_string(), "max(Price) <= 10000");
+        assert!(assertion.holds(&AggregateValue::Number(9999.5)));
+        assert!(!assertion.holds(&AggregateValue::Number(12000.0)));
+        assert!(!assertion.holds(&AggregateValue::Empty));
+        assert!(parse_assertion("null_count(Email)=0").unwrap().holds(&AggregateValue::Number(0.0)));
+        assert!(parse_assertion("sum(Price)=2.50").unwrap().holds(&AggregateValue::Number(2.5)));
+        assert!(parse_assertion("first(Status)!=draft").unwrap().holds(&AggregateValue::Text("final".to_string())));
+        assert!(parse_assertion("count()").is_err());
+        assert!(parse_assertion("median(Price)>1").is_err());
+        assert!(parse_assertion("count()>").is_err());
+    }
+
+    #[test]
+    fn test_crosstab() {
+        let headers: Vec<String> = ["Region", "Status", "Total"].map(String::from).to_vec();
+        let data: Vec<StringRecord> = [["EU", "open", "5"], ["US", "paid", "7"], ["EU", "paid", "1"], ["EU", "open", "2"]]
+            .into_iter()
+            .map(|row| StringRecord::from(row.to_vec()))
+            .collect();
+        let refs: Vec<&StringRecord> = data.iter().collect();
+        let counts = crosstab(&refs, 0, 1, None);
+        assert_eq!(counts.row_values, ["EU", "US"]);
+        assert_eq!(counts.column_values, ["open", "paid"]);
+        let cells = |table: &Crosstab| table.cells.iter().map(|row| row.iter().map(|v| v.to_string()).collect::<Vec<_>>()).collect::<Vec<_>>();
+        assert_eq!(cells(&counts), [["2", "1"], ["0", "1"]]);
+
+        let sum = ResolvedAggregate::resolve(&Aggregate { func: AggregateFn::Sum, column: Some("total".to_string()) }, &headers).unwrap();
+        assert_eq!(cells(&crosstab(&refs, 0, 1, Some(&sum))), [["7", "1"], ["-", "7"]]);
+        assert_eq!(crosstab(&refs, 2, 0, None).row_values, ["1", "2", "5", "7"]);
+        assert_eq!(parse_aggregate_fn("AVG"), Ok(AggregateFn::Avg));
+        assert!(parse_aggregate_fn("median").is_err());
+        assert_eq!(parse_crosstab_arg("Region, Status"), Ok(("Region".to_string(), "Status".to_string())));
+        assert!(parse_crosstab_arg("Region").is_err());
+    }
+
+    #[test]
+    fn test_freq() {
+        assert_eq!(parse_freq_arg("Region"), Ok(("Region".to_string(), None)));
+        assert_eq!(parse_freq_arg("Region, Status"), Ok(("Region".to_string(), Some("Status".to_string()))));
+        assert!(parse_freq_arg("Region,").is_err());
+        assert!(parse_freq_arg("a,b,c").is_err());
+
+        let data: Vec<StringRecord> = [["EU", "open"], ["US", "paid"], ["EU", "paid"], ["EU", "open"], ["NO", "open"]]
+            .into_iter()
+            .map(|row| StringRecord::from(row.to_vec()))
+            .collect();
+        let refs: Vec<&StringRecord> = data.iter().collect();
+        assert_eq!(value_counts(&refs, 0), [("EU".to_string(), 3), ("NO".to_string(), 1), ("US".to_string(), 1)]);
+
+        let table = FreqTable::new(&refs, 0, 1);
+        assert_eq!(table.row_values, ["EU", "NO", "US"]);
+        assert_eq!(table.column_values, ["open", "paid"]);
+        assert_eq!(table.counts, [[2, 1], [1, 0], [0, 1]]);
+        assert_eq!((table.row_total(0), table.column_total(0), table.total()), (3, 3, 5));
+        assert!((table.row_percent(0, 0) - 200.0 / 3.0).abs() < 1e-9);
+        assert!((table.column_percent(0, 1) - 50.0).abs() < 1e-9);
+        assert_eq!(percent(1, 0), 0.0);
+        assert_eq!(share_of_total(5.0, 20.0), Some(25.0));
+        assert_eq!(share_of_total(-5.0, 20.0), Some(-25.0));
+        assert_eq!(share_of_total(5.0, 0.0), None);
+    }
+
+    #[test]
+    fn test_top_n() {
+        let cells = ["5", "", "12", "text", "5", "-3", "7"];
+        let mut top = TopN::new(3, true);
+        let mut bottom = TopN::new(3, false);
+        for (position, cell) in cells.iter().enumerate() {
+            top.push((position, *cell), |(_, cell)| cell);
+            bottom.push((position, *cell), |(_, cell)| cell);
+        }
+        let positions = |kept: Vec<(usize, &str)>| kept.into_iter().map(|(position, _)| position).collect::<Vec<_>>();
+        assert_eq!(positions(top.into_sorted()), vec![3, 2, 6]);
+        assert_eq!(positions(bottom.into_sorted()), vec![5, 0, 4]);
+
+        let mut versions = TopN::new(2, true).natural(true);
+        for version in ["v1.9.0", "v1.10.0", "v1.2.0"] {
+            versions.push(version, |version| version);
+        }
+        assert_eq!(versions.into_sorted(), vec!["v1.10.0", "v1.9.0"]);
+
+        let mut none = TopN::new(0, true);
+        none.push("1", |cell| cell);
+        assert!(none.into_sorted().is_empty());
+    }
+
+    #[test]
+    fn test_group_by_period() {
+        assert_eq!(parse_group_by_arg("Region"), Ok(GroupBy::Column("Region".to_string())));
+        assert_eq!(parse_group_by_arg("DATE_TRUNC( Month , Order Date)"), Ok(GroupBy::DateTrunc(DateUnit::Month, "Order Date".to_string())));
+        assert!(parse_group_by_arg("date_trunc(quarter, OrderDate)").is_err());
+        assert!(parse_group_by_arg("date_trunc(month)").is_err());
+
+        assert_eq!(parse_date("2024-03-15T10:30:00Z", None), NaiveDate::from_ymd_opt(2024, 3, 15));
+        assert_eq!(parse_date("2024-03-15x", None), None);
+        assert_eq!(parse_date("15/03/2024 10:30", Some("%d/%m/%Y %H:%M")), NaiveDate::from_ymd_opt(2024, 3, 15));
+        assert_eq!(parse_date("15/03/2024", Some("%d/%m/%Y")), NaiveDate::from_ymd_opt(2024, 3, 15));
+        let date = NaiveDate::from_ymd_opt(2024, 3, 14).unwrap();
+        assert_eq!(truncate_date(date, DateUnit::Week).to_string(), "2024-03-11");
+        assert_eq!(truncate_date(date, DateUnit::Year).to_string(), "2024-01-01");
+
+        let rows: Vec<StringRecord> = [["2024-02-03", "5"], ["2024-01-31", "2"], ["soon", "9"], ["2024-02-28", "1"]]
+            .iter().map(|row| StringRecord::from(row.to_vec())).collect();
+        let refs: Vec<&StringRecord> = rows.iter().collect();
+        let sum = ResolvedAggregate { aggregate: Aggregate { func: AggregateFn::Sum, column: Some("b".to_string()) }, column: Some(1) };
+        let groups = group_records(&refs, 0, Some(DateUnit::Month), None, Some(&sum));
+        assert_eq!(groups.keys, vec!["2024-01-01", "2024-02-01"]);
+        assert_eq!(groups.counts, vec![1, 2]);
+        assert_eq!(groups.values, Some(vec![AggregateValue::Number(2.0), AggregateValue::Number(6.0)]));
+        assert_eq!(groups.skipped, 1);
+    }
+
+    #[test]
+    fn test_sparklines() {
+        assert_eq!(histogram(&[1.0, 2.0, 2.5, 9.0, 10.0], 3), vec![3, 0, 2]);
+        assert_eq!(histogram(&[4.0, 4.0], 8), vec![2]);
+        assert!(histogram(&[], 8).is_empty());
+        assert_eq!(sparkline(&[3, 0, 2, 1, 8]), "▃ ▂▁█");
+        assert_eq!(sparkline(&[]), "");
+
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let days = timeline(&[date(2024, 3, 4), date(2024, 3, 1), date(2024, 3, 4)], 48).unwrap();
+        assert_eq!((days.unit, days.start, days.counts.as_slice()), (DateUnit::Day, date(2024, 3, 1), &[1, 0, 0, 2][..]));
+        assert_eq!(days.end(), date(2024, 3, 4));
+        let months = timeline(&[date(2023, 11, 20), date(2024, 2, 3)], 8).unwrap();
+        assert_eq!((months.unit, months.start, months.counts.as_slice()), (DateUnit::Month, date(2023, 11, 1), &[1, 0, 0, 1][..]));
+        let years = timeline(&[date(1990, 5, 1), date(2024, 1, 1)], 12).unwrap();
+        assert_eq!((years.unit, years.counts.len()), (DateUnit::Year, 35));
+        assert_eq!(timeline(&[], 48), None);
+    }
+}
@@ -0,0 +1,627 @@
+use crate::filter::{fraction_digits, parse_number, stable_hash};
+use crate::find_column;
+use crate::input::CsvData;
+use csv::StringRecord;
+use regex::Regex;
+use std::collections::HashMap;
+
+/// Parses a `--fill COLUMN=VALUE` argument. The value may be empty or contain `=`.
+pub fn parse_fill_arg(s: &str) -> Result<(String, String), String> {
+    let (column, value) = s.split_once('=').ok_or_else(|| format!("Invalid fill '{}'. Expected COLUMN=VALUE, e.g. \"Country=Unknown\".", s))?;
+    let column = column.trim();
+    if column.is_empty() {
+        return Err(format!("Invalid fill '{}': column name cannot be empty.", s));
+    }
+    Ok((column.to_string(), value.to_string()))
+}
+
+/// Replaces empty (or whitespace-only) cells with a default: the value given for their column
+/// in `fills` (column index and value), otherwise `fill_all` if set. Other cells are kept.
+pub fn fill_record(record: &StringRecord, fills: &[(usize, String)], fill_all: Option<&str>) -> StringRecord {
+    let cells = record.iter().enumerate().map(|(index, cell)| {
+        if !cell.trim().is_empty() {
+            return cell;
+        }
+        fills.iter().rev().find(|(column, _)| *column == index).map(|(_, value)| value.as_str()).or(fill_all).unwrap_or(cell)
+    });
+    let mut filled: StringRecord = cells.collect();
+    filled.set_position(record.position().cloned());
+    filled
+}
+
+/// Unpivots wide records into long ones for `--melt`: every column that is not in `id_columns`
+/// becomes a row of the id columns followed by `variable` (the column name) and `value`.
+pub fn melt(headers: &[String], records: &[StringRecord], id_columns: &[usize]) -> CsvData {
+    let melted_headers = id_columns.iter().map(|&idx| headers[idx].clone())
+        .chain(["variable".to_string(), "value".to_string()])
+        .collect();
+    let variables: Vec<usize> = (0..headers.len()).filter(|idx| !id_columns.contains(idx)).collect();
+    let mut melted = Vec::with_capacity(records.len() * variables.len());
+    for record in records {
+        for &variable in &variables {
+            let mut row: StringRecord = id_columns.iter().map(|&idx| record.get(idx).unwrap_or_default())
+                .chain([headers[variable].as_str(), record.get(variable).unwrap_or_default()])
+                .collect();
+            row.set_position(record.position().cloned());
+            melted.push(row);
+        }
+    }
+    (melted_headers, melted)
+}
+
+/// A `--concat NAME=EXPR` rule: a new column `name` joining the `parts` of each record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Concat {
+    pub name: String,
+    pub parts: Vec<ConcatPart>,
+}
+
+/// A column reference or a quoted literal in a `--concat` expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConcatPart {
+    Column(String),
+    Literal(String),
+}
+
+/// Parses `NAME=PART+PART+...`, where each part is a column name or a text in single or double
+/// quotes, e.g. `FullName=First+' '+Last`.
+pub fn parse_concat_arg(s: &str) -> Result<Concat, String> {
+    let expected = || format!("Invalid concat '{}'. Expected NAME=PART+PART..., e.g. \"FullName=First+' '+Last\".", s);
+    let (name, expression) = s.split_once('=').ok_or_else(expected)?;
+    if name.trim().is_empty() {
+        return Err(expected());
+    }
+    let mut parts = Vec::new();
+    let mut rest = expression.trim_start();
+    loop {
+        let (part, after) = match rest.chars().next() {
+            Some(quote @ ('\'' | '"')) => {
+                let end = rest[1..].find(quote).ok_or_else(expected)? + 1;
+                (ConcatPart::Literal(rest[1..end].to_string()), &rest[end + 1..])
+            }
+            _ => {
+                let end = rest.find('+').unwrap_or(rest.len());
+                let column = rest[..end].trim();
+                if column.is_empty() {
+                    return Err(expected());
+                }
+                (ConcatPart::Column(column.to_string()), &rest[end..])
+            }
+        };
+        parts.push(part);
+        let after = after.trim_start();
+        match after.strip_prefix('+') {
+            Some(next) => rest = next.trim_start(),
+            None if after.is_empty() => break,
+            None => return Err(expected()),
+        }
+    }
+    Ok(Concat { name: name.trim().to_string(), parts })
+}
+
+/// Appends a column per `--concat` rule to the headers and records. Rules may use the columns
+/// added by earlier ones.
+pub fn concat_columns(mut headers: Vec<String>, mut records: Vec<StringRecord>, rules: &[Concat]) -> crate::Result<CsvData> {
+    for rule in rules {
+        let parts = rule.parts.iter()
+            .map(|part| match part {
+                ConcatPart::Column(column) => find_column(&headers, column, "Concat").map(Ok),
+                ConcatPart::Literal(text) => Ok(Err(text.as_str())),
+            })
+            .collect::<crate::Result<Vec<std::result::Result<usize, &str>>>>()?;
+        for record in &mut records {
+            let value: String = parts.iter()
+                .map(|part| match part {
+                    Ok(idx) => record.get(*idx).unwrap_or_default(),
+                    Err(text) => text,
+                })
+                .collect();
+            record.push_field(&value);
+        }
+        headers.push(rule.name.clone());
+    }
+    Ok((headers, records))
+}
+
+/// Name of the column `--hash-rows` appends.
+pub const HASH_COLUMN: &str = "row_hash";
+
+/// Appends a `row_hash` column for `--hash-rows`: the FNV-1a hash, as 16 hex digits, of each
+/// record's cells in `columns` joined by a unit separator. With no columns, every column except
+/// those in `skip` (the added source and row number columns) is hashed, so identical rows in
+/// different files and runs get the same hash.
+pub fn hash_rows(mut headers: Vec<String>, mut records: Vec<StringRecord>, columns: &[String], skip: &[&str]) -> crate::Result<CsvData> {
+    let hashed: Vec<usize> = if columns.is_empty() {
+        (0..headers.len()).filter(|&idx| !skip.contains(&headers[idx].as_str())).collect()
+    } else {
+        columns.iter().map(|column| find_column(&headers, column, "Hash")).collect::<crate::Result<_>>()?
+    };
+    for record in &mut records {
+        let key = hashed.iter().map(|&idx| record.get(idx).unwrap_or_default()).collect::<Vec<_>>().join("\x1f");
+        record.push_field(&format!("{:016x}", stable_hash(&key)));
+    }
+    headers.push(HASH_COLUMN.to_string());
+    Ok((headers, records))
+}
+
+/// A `--cumulative NAME=sum(COLUMN)` rule: a new column `name` with the running total of
+/// `column`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cumulative {
+    pub name: String,
+    pub column: String,
+}
+
+/// Parses `NAME=sum(COLUMN)`, e.g. `RunningTotal=sum(Amount)`.
+pub fn parse_cumulative_arg(s: &str) -> Result<Cumulative, String> {
+    let expected = || format!("Invalid cumulative '{}'. Expected NAME=sum(COLUMN), e.g. \"RunningTotal=sum(Amount)\".", s);
+    let (name, expression) = s.split_once('=').ok_or_else(expected)?;
+    let column = expression.trim()
+        .strip_suffix(')')
+        .and_then(|rest| rest.split_once('('))
+        .filter(|(func, _)| func.trim().eq_ignore_ascii_case("sum"))
+        .map(|(_, column)| column.trim())
+        .ok_or_else(expected)?;
+    if name.trim().is_empty() || column.is_empty() {
+        return Err(expected());
+    }
+    Ok(Cumulative { name: name.trim().to_string(), column: column.to_string() })
+}
+
+/// Appends the `--cumulative` running total of `rule.column` to every record. Records are
+/// summed in the order of their `order_by` cells (numbers numerically, other cells as text,
+/// equal cells in input order) or in input order, separately for each value of `partition_by`;
+/// they keep their position. Cells that are not numbers add nothing. Totals are shown with as
+/// many decimals as the most precise summed cell, so cents do not turn into float noise.
+pub fn cumulative_sum(
+    mut headers: Vec<String>,
+    mut records: Vec<StringRecord>,
+    rule: &Cumulative,
+    partition_by: Option<&str>,
+    order_by: Option<&str>,
+) -> crate::Result<CsvData> {
+    let column = find_column(&headers, &rule.column, "Cumulative")?;
+    let decimals = decimals(&records, column);
+    let mut running = vec![String::new(); records.len()];
+    for positions in ordered_partitions(&headers, &records, partition_by, order_by)? {
+        let mut total = 0.0;
+        for position in positions {
+            total += parse_number(trimmed_cell(&records[position], column)).unwrap_or(0.0);
+            running[position] = format!("{:.*}", decimals, total);
+        }
+    }
+    for (record, total) in records.iter_mut().zip(running) {
+        record.push_field(&total);
+    }
+    headers.push(rule.name.clone());
+    Ok((headers, records))
+}
+
+/// Function of a `--rolling` window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RollingFn {
+    Avg,
+    Sum,
+    Min,
+    Max,
+}
+
+/// A `--rolling NAME=FUNC(COLUMN,N)` rule: a new column `name` with `func` of `column` over
+/// each record and the `window - 1` records before it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rolling {
+    pub name: String,
+    pub func: RollingFn,
+    pub column: String,
+    pub window: usize,
+}
+
+/// Parses `NAME=FUNC(COLUMN,N)` with FUNC one of avg, sum, min or max and a window of N ≥ 1
+/// rows, e.g. `Avg7=avg(Value,7)`.
+pub fn parse_rolling_arg(s: &str) -> Result<Rolling, String> {
+    let expected = || format!("Invalid rolling '{}'. Expected NAME=FUNC(COLUMN,N) with FUNC avg, sum, min or max, e.g. \"Avg7=avg(Value,7)\".", s);
+    let (name, expression) = s.split_once('=').ok_or_else(expected)?;
+    let (func, inner) = expression.trim().strip_suffix(')').and_then(|rest| rest.split_once('(')).ok_or_else(expected)?;
+    let func = match func.trim().to_ascii_lowercase().as_str() {
+        "avg" => RollingFn::Avg,
+        "sum" => RollingFn::Sum,
+        "min" => RollingFn::Min,
+        "max" => RollingFn::Max,
+        _ => return Err(expected()),
+    };
+    let (column, window) = inner.rsplit_once(',').ok_or_else(expected)?;
+    let window = window.trim().parse::<usize>().ok().filter(|&window| window > 0).ok_or_else(expected)?;
+    if name.trim().is_empty() || column.trim().is_empty() {
+        return Err(expected());
+    }
+    Ok(Rolling { name: name.trim().to_string(), func, column: column.trim().to_string(), window })
+}
+
+/// Appends a column per `--rolling` rule, ordering and partitioning records like
+/// [`cumulative_sum`]. A cell stays empty until its partition has a full window of records, or
+/// when no cell of the window is a number; cells that are not numbers are left out of the
+/// window's aggregate. Sums, minimums and maximums have as many decimals as the most precise
+/// cell of the column, averages two more.
+pub fn rolling_aggregates(
+    mut headers: Vec<String>,
+    mut records: Vec<StringRecord>,
+    rules: &[Rolling],
+    partition_by: Option<&str>,
+    order_by: Option<&str>,
+) -> crate::Result<CsvData> {
+    let partitions = ordered_partitions(&headers, &records, partition_by, order_by)?;
+    for rule in rules {
+        let column = find_column(&headers, &rule.column, "Rolling")?;
+        let decimals = decimals(&records, column) + if rule.func == RollingFn::Avg { 2 } else { 0 };
+        let mut rolled = vec![String::new(); records.len()];
+        for positions in &partitions {
+            let values: Vec<Option<f64>> = positions.iter().map(|&position| parse_number(trimmed_cell(&records[position], column))).collect();
+            for (end, &position) in positions.iter().enumerate().skip(rule.window - 1) {
+                let window: Vec<f64> = values[end + 1 - rule.window..=end].iter().flatten().copied().collect();
+                if window.is_empty() {
+                    continue;
+                }
+                let value = match rule.func {
+                    RollingFn::Avg => window.iter().sum::<f64>() / window.len() as f64,
+                    RollingFn::Sum => window.iter().sum(),
+                    RollingFn::Min => window.iter().copied().fold(f64::INFINITY, f64::min),
+                    RollingFn::Max => window.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                };
+                rolled[position] = format!("{:.*}", decimals, value);
+            }
+        }
+        for (record, value) in records.iter_mut().zip(rolled) {
+            record.push_field(&value);
+        }
+        headers.push(rule.name.clone());
+    }
+    Ok((headers, records))
+}
+
+/// The positions of `records` grouped by their `partition_by` cell (one group without it), each
+/// group in the order of the `order_by` cells or else in input order. Numbers compare
+/// numerically and other cells as text; equal cells keep their input order.
+fn ordered_partitions(headers: &[String], records: &[StringRecord], partition_by: Option<&str>, order_by: Option<&str>) -> crate::Result<Vec<Vec<usize>>> {
+    let partition = partition_by.map(|name| find_column(headers, name, "Partition")).transpose()?;
+    let order = order_by.map(|name| find_column(headers, name, "Order")).transpose()?;
+    let mut positions: Vec<usize> = (0..records.len()).collect();
+    if let Some(order) = order {
+        positions.sort_by(|&a, &b| {
+            let (a, b) = (trimmed_cell(&records[a], order), trimmed_cell(&records[b], order));
+            match (parse_number(a), parse_number(b)) {
+                (Some(x), Some(y)) => x.total_cmp(&y),
+                _ => a.cmp(b),
+            }
+        });
+    }
+    let mut groups: HashMap<&str, usize> = HashMap::new();
+    let mut partitions: Vec<Vec<usize>> = Vec::new();
+    for position in positions {
+        let key = partition.map_or("", |idx| trimmed_cell(&records[position], idx));
+        let group = *groups.entry(key).or_insert_with(|| {
+            partitions.push(Vec::new());
+            partitions.len() - 1
+        });
+        partitions[group].push(position);
+    }
+    Ok(partitions)
+}
+
+/// The most decimals of any number in `column`.
+fn decimals(records: &[StringRecord], column: usize) -> usize {
+    records.iter()
+        .map(|record| trimmed_cell(record, column))
+        .filter(|value| parse_number(value).is_some())
+        .map(fraction_digits)
+        .max()
+        .unwrap_or(0)
+}
+
+fn trimmed_cell(record: &StringRecord, idx: usize) -> &str {
+    record.get(idx).unwrap_or_default().trim()
+}
+
+/// Parses a join key: `COLUMN` when both files name it the same, or `LEFT=RIGHT`.
+pub fn parse_join_key(s: &str) -> Result<(String, String), String> {
+    let (left, right) = s.split_once('=').unwrap_or((s, s));
+    match (left.trim(), right.trim()) {
+        ("", _) | (_, "") => Err(format!("Invalid join key '{}'. Expected COLUMN or LEFT=RIGHT, e.g. \"CustomerID=ID\".", s)),
+        (left, right) => Ok((left.to_string(), right.to_string())),
+    }
+}
+
+/// Joins `left` with the `right` table on equal (trimmed) cells of `left_column` and
+/// `right_column`: every left record is followed by the right table's other columns, once per
+/// matching right record. Left records without a match are dropped, or with `keep_unmatched`
+/// kept with empty cells. Empty keys never match. Right columns whose name the left table
+/// already uses are prefixed with `right_name`, e.g. "customers.Name".
+pub fn join(left: CsvData, right: CsvData, left_column: usize, right_column: usize, keep_unmatched: bool, right_name: &str) -> CsvData {
+    let (mut headers, records) = left;
+    let (right_headers, right_records) = right;
+    let added: Vec<usize> = (0..right_headers.len()).filter(|&idx| idx != right_column).collect();
+    let mut matches: HashMap<&str, Vec<&StringRecord>> = HashMap::new();
+    for record in &right_records {
+        let key = trimmed_cell(record, right_column);
+        if !key.is_empty() {
+            matches.entry(key).or_default().push(record);
+        }
+    }
+    let mut joined = Vec::with_capacity(records.len());
+    for record in &records {
+        let mut push = |right: Option<&StringRecord>| {
+            let mut row = record.clone();
+            for &idx in &added {
+                row.push_field(right.and_then(|right| right.get(idx)).unwrap_or_default());
+            }
+            joined.push(row);
+        };
+        match matches.get(trimmed_cell(record, left_column)) {
+            Some(rights) => rights.iter().for_each(|right| push(Some(right))),
+            None if keep_unmatched => push(None),
+            None => {}
+        }
+    }
+    for &idx in &added {
+        let name = &right_headers[idx];
+        let name = if headers.contains(name) { format!("{}.{}", right_name, name) } else { name.clone() };
+        headers.push(name);
+    }
+    (headers, joined)
+}
+
+/// Parses the `--split-on` separator, which cannot be empty.
+pub fn parse_split_on_arg(s: &str) -> Result<String, String> {
+    if s.is_empty() {
+        return Err("The --explode separator cannot be empty.".to_string());
+    }
+    Ok(s.to_string())
+}
+
+/// Duplicates each record once per element of its multi-value cell in `column` for `--explode`.
+/// Elements are split on `separator` and trimmed; a record whose cell is empty, or with an
+/// empty separator every record, is kept as is.
+pub fn explode(records: Vec<StringRecord>, column: usize, separator: &str) -> Vec<StringRecord> {
+    let mut exploded = Vec::with_capacity(records.len());
+    for record in records {
+        let cell = record.get(column).unwrap_or_default();
+        if separator.is_empty() || cell.trim().is_empty() || !cell.contains(separator) {
+            exploded.push(record);
+            continue;
+        }
+        for element in cell.split(separator).map(str::trim) {
+            let mut row: StringRecord = record.iter().enumerate()
+                .map(|(idx, value)| if idx == column { element } else { value })
+                .collect();
+            row.set_position(record.position().cloned());
+            exploded.push(row);
+        }
+    }
+    exploded
+}
+
+/// A `--replace` rule: every match of `pattern` in `column` is replaced by `replacement`,
+/// in which `$1` or `${name}` refer to capture groups.
+#[derive(Debug, Clone)]
+pub struct Replacement {
+    pub column: String,
+    pub pattern: Regex,
+    pub replacement: String,
+}
+
+impl Replacement {
+    pub fn apply(&self, value: &str) -> String {
+        self.pattern.replace_all(value, self.replacement.as_str()).into_owned()
+    }
+}
+
+/// Parses a sed-style `COLUMN:/PATTERN/REPLACEMENT/` rule. The character after the colon is the
+/// separator, so `Path:|/tmp/|/var/|` works too; a backslash escapes the separator.
+pub fn parse_replace_arg(s: &str) -> Result<Replacement, String> {
+    let expected = || format!("Invalid replacement '{}'. Expected COLUMN:/PATTERN/REPLACEMENT/, e.g. \"Phone:/[^0-9]+//\".", s);
+    let (column, rule) = s.split_once(':').ok_or_else(expected)?;
+    let mut chars = rule.chars();
+    let separator = chars.next().ok_or_else(expected)?;
+    if separator.is_alphanumeric() || separator == '\\' {
+        return Err(expected());
+    }
+    let mut parts = vec![String::new()];
+    let mut escaped = false;
+    for c in chars {
+        match (escaped, c) {
+            (false, '\\') => escaped = true,
+            (false, c) if c == separator => parts.push(String::new()),
+            (true, c) => {
+                let part = parts.last_mut().expect("parts is never empty");
+                if c != separator {
+                    part.push('\\');
+                }
+                part.push(c);
+                escaped = false;
+            }
+            (false, c) => parts.last_mut().expect("parts is never empty").push(c),
+        }
+    }
+    let column = column.trim();
+    let [pattern, replacement, rest] = parts.as_slice() else { return Err(expected()) };
+    if column.is_empty() || !rest.is_empty() || escaped {
+        return Err(expected());
+    }
+    let pattern = Regex::new(pattern).map_err(|e| format!("Invalid pattern in replacement '{}': {}", s, e))?;
+    Ok(Replacement { column: column.to_string(), pattern, replacement: replacement.clone() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fill_arg() {
+        assert_eq!(parse_fill_arg("Country=Unknown"), Ok(("Country".to_string(), "Unknown".to_string())));
+        assert_eq!(parse_fill_arg(" Note =a=b"), Ok(("Note".to_string(), "a=b".to_string())));
+        assert!(parse_fill_arg("Country").is_err());
+        assert!(parse_fill_arg("=Unknown").is_err());
+    }
+
+    #[test]
+    fn test_parse_replace_arg() {
+        let strip = parse_replace_arg("Phone:/[^0-9]+//").unwrap();
+        assert_eq!(strip.column, "Phone");
+        assert_eq!(strip.apply("+46 (0)70-123 45"), "4607012345");
+
+        let swap = parse_replace_arg(r"Name:/(\w+) (\w+)/$2, $1/").unwrap();
+        assert_eq!(swap.apply("Anna Berg"), "Berg, Anna");
+
+        let path = parse_replace_arg(r"Path:|/tmp/|/var/\|x/|").unwrap();
+        assert_eq!(path.apply("/tmp/a"), "/var/|x/a");
+        assert_eq!(parse_replace_arg(r"Note:/a\/b/-/").unwrap().apply("a/b"), "-");
+
+        assert!(parse_replace_arg("Phone").is_err());
+        assert!(parse_replace_arg("Phone:/x/").is_err());
+        assert!(parse_replace_arg("Phone:/x/y/z").is_err());
+        assert!(parse_replace_arg("Phone:/(/y/").is_err());
+    }
+
+    #[test]
+    fn test_melt() {
+        let headers: Vec<String> = ["ID", "Jan", "Name", "Feb"].map(String::from).to_vec();
+        let records = vec![StringRecord::from(vec!["1", "10", "Ada", "12"]), StringRecord::from(vec!["2", "", "Bob", "7"])];
+        let (melted_headers, melted) = melt(&headers, &records, &[0, 2]);
+        assert_eq!(melted_headers, ["ID", "Name", "variable", "value"]);
+        assert_eq!(melted, [
+            StringRecord::from(vec!["1", "Ada", "Jan", "10"]),
+            StringRecord::from(vec!["1", "Ada", "Feb", "12"]),
+            StringRecord::from(vec!["2", "Bob", "Jan", ""]),
+            StringRecord::from(vec!["2", "Bob", "Feb", "7"]),
+        ]);
+    }
+
+    #[test]
+    fn test_concat() {
+        let rule = parse_concat_arg("FullName = First + ' ' + Last+\"+\"").unwrap();
+        assert_eq!(rule.name, "FullName");
+        assert_eq!(rule.parts, [
+            ConcatPart::Column("First".to_string()),
+            ConcatPart::Literal(" ".to_string()),
+            ConcatPart::Column("Last".to_string()),
+            ConcatPart::Literal("+".to_string()),
+        ]);
+        assert!(parse_concat_arg("=First").is_err());
+        assert!(parse_concat_arg("Key=First++Last").is_err());
+        assert!(parse_concat_arg("Key=First+'x").is_err());
+
+        let headers: Vec<String> = ["first", "last"].map(String::from).to_vec();
+        let rules = [parse_concat_arg("Name=First+' '+Last").unwrap(), parse_concat_arg("Key=Name+'#'").unwrap()];
+        let (headers, records) = concat_columns(headers, vec![StringRecord::from(vec!["Ada", "Lovelace"])], &rules).unwrap();
+        assert_eq!(headers, ["first", "last", "Name", "Key"]);
+        assert_eq!(records, [StringRecord::from(vec!["Ada", "Lovelace", "Ada Lovelace", "Ada Lovelace#"])]);
+    }
+
+    #[test]
+    fn test_hash_rows() {
+        let headers: Vec<String> = ["source", "id", "name"].map(String::from).to_vec();
+        let records = vec![StringRecord::from(vec!["a.csv", "1", "x"]), StringRecord::from(vec!["b.csv", "1", "x"]), StringRecord::from(vec!["b.csv", "1", "y"])];
+        let (hashed_headers, hashed) = hash_rows(headers.clone(), records.clone(), &[], &["source"]).unwrap();
+        assert_eq!(hashed_headers, ["source", "id", "name", "row_hash"]);
+        assert_eq!(hashed[0][3], format!("{:016x}", stable_hash("1\x1fx")));
+        assert_eq!(hashed[0][3], hashed[1][3]);
+        assert_ne!(hashed[1][3], hashed[2][3]);
+
+        let (_, by_id) = hash_rows(headers.clone(), records.clone(), &["ID".to_string()], &[]).unwrap();
+        assert_eq!(by_id[1][3], by_id[2][3]);
+        assert!(hash_rows(headers, records, &["missing".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn test_cumulative_sum() {
+        let rule = parse_cumulative_arg("Running Total = SUM( Amount )").unwrap();
+        assert_eq!(rule, Cumulative { name: "Running Total".to_string(), column: "Amount".to_string() });
+        assert!(parse_cumulative_arg("Total=avg(Amount)").is_err());
+        assert!(parse_cumulative_arg("Total=sum()").is_err());
+        assert!(parse_cumulative_arg("sum(Amount)").is_err());
+
+        let headers: Vec<String> = ["Region", "Day", "Amount"].map(String::from).to_vec();
+        let records: Vec<StringRecord> = [["EU", "3", "0.1"], ["US", "1", "5"], ["EU", "1", "0.2"], ["EU", "2", "n/a"], ["US", "10", "1.25"]]
+            .into_iter()
+            .map(|row| StringRecord::from(row.to_vec()))
+            .collect();
+        let (summed_headers, summed) = cumulative_sum(headers.clone(), records.clone(), &rule, None, None).unwrap();
+        assert_eq!(summed_headers, ["Region", "Day", "Amount", "Running Total"]);
+        let totals: Vec<&str> = summed.iter().map(|record| &record[3]).collect();
+        assert_eq!(totals, ["0.10", "5.10", "5.30", "5.30", "6.55"]);
+
+        let (_, by_region) = cumulative_sum(headers.clone(), records.clone(), &rule, Some("region"), Some("Day")).unwrap();
+        let totals: Vec<&str> = by_region.iter().map(|record| &record[3]).collect();
+        assert_eq!(totals, ["0.30", "5.00", "0.20", "0.20", "6.25"]);
+        assert!(cumulative_sum(headers, records, &rule, Some("missing"), None).is_err());
+    }
+
+    #[test]
+    fn test_rolling_aggregates() {
+        let avg = parse_rolling_arg("Avg 2 = AVG( Value , 2 )").unwrap();
+        assert_eq!(avg, Rolling { name: "Avg 2".to_string(), func: RollingFn::Avg, column: "Value".to_string(), window: 2 });
+        assert!(parse_rolling_arg("X=median(Value,3)").is_err());
+        assert!(parse_rolling_arg("X=avg(Value,0)").is_err());
+        assert!(parse_rolling_arg("X=avg(Value)").is_err());
+
+        let headers: Vec<String> = ["Day", "Value"].map(String::from).to_vec();
+        let records: Vec<StringRecord> = [["3", "6"], ["1", "1"], ["2", "x"], ["4", "3"], ["5", ""]]
+            .into_iter()
+            .map(|row| StringRecord::from(row.to_vec()))
+            .collect();
+        let rules = [avg, parse_rolling_arg("Max3=max(Value,3)").unwrap()];
+        let (rolled_headers, rolled) = rolling_aggregates(headers.clone(), records.clone(), &rules, None, Some("Day")).unwrap();
+        assert_eq!(rolled_headers, ["Day", "Value", "Avg 2", "Max3"]);
+        let cells: Vec<(&str, &str, &str)> = rolled.iter().map(|record| (&record[0], &record[2], &record[3])).collect();
+        assert_eq!(cells, [("3", "6.00", "6"), ("1", "", ""), ("2", "1.00", ""), ("4", "4.50", "6"), ("5", "3.00", "6")]);
+        assert!(rolling_aggregates(headers, records, &rules, Some("missing"), None).is_err());
+    }
+
+    #[test]
+    fn test_explode() {
+        let records = vec![
+            StringRecord::from(vec!["1", "red; green"]),
+            StringRecord::from(vec!["2", ""]),
+            StringRecord::from(vec!["3", "blue"]),
+        ];
+        assert_eq!(explode(records, 1, ";"), [
+            StringRecord::from(vec!["1", "red"]),
+            StringRecord::from(vec!["1", "green"]),
+            StringRecord::from(vec!["2", ""]),
+            StringRecord::from(vec!["3", "blue"]),
+        ]);
+        assert_eq!(explode(vec![StringRecord::from(vec!["1", "ab"])], 1, ""), [StringRecord::from(vec!["1", "ab"])]);
+        assert!(parse_split_on_arg("").is_err());
+        assert_eq!(parse_split_on_arg(" | "), Ok(" | ".to_string()));
+    }
+
+    #[test]
+    fn test_join() {
+        let table = |headers: &[&str], rows: &[&[&str]]| -> CsvData {
+            (headers.iter().map(|h| h.to_string()).collect(), rows.iter().map(|row| StringRecord::from(row.to_vec())).collect())
+        };
+        let orders = || table(&["Order", "Customer"], &[&["1", "c1"], &["2", "c9"], &["3", " c2"], &["4", ""]]);
+        let customers = || table(&["Id", "Order", "City"], &[&["c1", "x", "Oslo"], &["c2", "y", "Rome"], &["c2", "z", "Pisa"], &["", "w", "Nowhere"]]);
+        let (headers, records) = join(orders(), customers(), 1, 0, false, "customers");
+        assert_eq!(headers, ["Order", "Customer", "customers.Order", "City"]);
+        assert_eq!(records, [
+            StringRecord::from(vec!["1", "c1", "x", "Oslo"]),
+            StringRecord::from(vec!["3", " c2", "y", "Rome"]),
+            StringRecord::from(vec!["3", " c2", "z", "Pisa"]),
+        ]);
+        let (_, records) = join(orders(), customers(), 1, 0, true, "customers");
+        assert_eq!(records.len(), 5);
+        assert_eq!(records[1], StringRecord::from(vec!["2", "c9", "", ""]));
+        assert_eq!(records[4], StringRecord::from(vec!["4", "", "", ""]));
+        assert_eq!(parse_join_key("CustomerID = ID"), Ok(("CustomerID".to_string(), "ID".to_string())));
+        assert_eq!(parse_join_key("Id"), Ok(("Id".to_string(), "Id".to_string())));
+        assert!(parse_join_key("Id=").is_err());
+    }
+
+    #[test]
+    fn test_fill_record() {
+        let record = StringRecord::from(vec!["", "Anna", " ", ""]);
+        let fills = vec![(0, "Unknown".to_string()), (2, "0".to_string())];
+        assert_eq!(fill_record(&record, &fills, None), StringRecord::from(vec!["Unknown", "Anna", "0", ""]));
+        assert_eq!(fill_record(&record, &fills, Some("-")), StringRecord::from(vec!["Unknown", "Anna", "0", "-"]));
+    }
+}
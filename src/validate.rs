@@ -0,0 +1,235 @@
+use crate::eq_ignore_case;
+use std::fmt;
+
+/// A conformance problem found by `validate_csv`. `line` is the 1-based physical line, when
+/// the problem has one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub line: Option<u64>,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// How `validate_csv` reads the data.
+#[derive(Debug, Clone, Copy)]
+pub struct ValidateOptions {
+    pub delimiter: u8,
+    /// Lines starting with this byte are comments and are not checked.
+    pub comment: Option<u8>,
+    /// Whether the bytes must be valid UTF-8 (false when they were transcoded from another encoding).
+    pub check_utf8: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    FieldStart,
+    Unquoted,
+    Quoted,
+    /// Just saw a `"` inside a quoted field: either an escaped quote or the closing quote.
+    QuoteInQuoted,
+}
+
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Checks CSV bytes against RFC 4180 and common export problems: byte order marks, invalid
+/// UTF-8, mixed line endings, stray or unbalanced quotes, duplicate or empty header names,
+/// and records whose field count differs from the header.
+pub fn validate_csv(data: &[u8], options: &ValidateOptions) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut data = data;
+    if let Some(rest) = data.strip_prefix(BOM) {
+        findings.push(Finding { line: Some(1), message: "UTF-8 byte order mark at start of file".to_string() });
+        data = rest;
+    }
+
+    if options.check_utf8 {
+        if let Err(err) = std::str::from_utf8(data) {
+            let line = line_of(data, err.valid_up_to());
+            findings.push(Finding { line: Some(line), message: "invalid UTF-8".to_string() });
+        }
+    }
+    let mut search_from = 0;
+    while let Some(pos) = data[search_from..].windows(BOM.len()).position(|w| w == BOM) {
+        let offset = search_from + pos;
+        findings.push(Finding { line: Some(line_of(data, offset)), message: "stray byte order mark inside the data".to_string() });
+        search_from = offset + BOM.len();
+    }
+
+    let delimiter = options.delimiter;
+    let mut state = State::FieldStart;
+    let mut line: u64 = 1;
+    let mut record_line: u64 = 1;
+    let mut quote_line: u64 = 1;
+    let mut fields = 0usize;
+    let mut record_has_content = false;
+    let mut header_fields: Option<usize> = None;
+    let (mut crlf, mut lf, mut cr) = (0u64, 0u64, 0u64);
+
+    let mut end_record = |fields: usize, record_line: u64, findings: &mut Vec<Finding>| match header_fields {
+        None => header_fields = Some(fields),
+        Some(expected) if expected != fields => findings.push(Finding {
+            line: Some(record_line),
+            message: format!("record has {} field(s), the header has {}", fields, expected),
+        }),
+        Some(_) => {}
+    };
+
+    let mut i = 0;
+    while i < data.len() {
+        let byte = data[i];
+        let newline_len = match byte {
+            b'\r' if data.get(i + 1) == Some(&b'\n') => 2,
+            b'\r' | b'\n' => 1,
+            _ => 0,
+        };
+        if newline_len > 0 {
+            match (byte, newline_len) {
+                (b'\r', 2) => crlf += 1,
+                (b'\r', _) => cr += 1,
+                _ => lf += 1,
+            }
+            line += 1;
+            i += newline_len;
+            if state == State::Quoted {
+                continue;
+            }
+            if record_has_content {
+                end_record(fields + 1, record_line, &mut findings);
+            }
+            state = State::FieldStart;
+            fields = 0;
+            record_has_content = false;
+            record_line = line;
+            continue;
+        }
+
+        if !record_has_content && state == State::FieldStart && fields == 0 && options.comment == Some(byte) {
+            while i < data.len() && data[i] != b'\n' && data[i] != b'\r' {
+                i += 1;
+            }
+            continue;
+        }
+        record_has_content = true;
+        match state {
+            State::Quoted => {
+                if byte == b'"' {
+                    state = State::QuoteInQuoted;
+                }
+            }
+            State::QuoteInQuoted => {
+                if byte == b'"' {
+                    state = State::Quoted;
+                } else if byte == delimiter {
+                    fields += 1;
+                    state = State::FieldStart;
+                } else {
+                    findings.push(Finding { line: Some(line), message: "characters after the closing quote of a field".to_string() });
+                    state = State::Unquoted;
+                }
+            }
+            State::FieldStart | State::Unquoted => {
+                if byte == delimiter {
+                    fields += 1;
+                    state = State::FieldStart;
+                } else if byte == b'"' {
+                    if state == State::FieldStart {
+                        state = State::Quoted;
+                        quote_line = line;
+                    } else {
+                        findings.push(Finding { line: Some(line), message: "quote inside an unquoted field".to_string() });
+                    }
+                } else {
+                    state = State::Unquoted;
+                }
+            }
+        }
+        i += 1;
+    }
+    if state == State::Quoted {
+        findings.push(Finding { line: Some(quote_line), message: "unbalanced quote: quoted field is never closed".to_string() });
+    } else if record_has_content {
+        end_record(fields + 1, record_line, &mut findings);
+    }
+
+    let kinds = [(crlf, "CRLF"), (lf, "LF"), (cr, "CR")];
+    if kinds.iter().filter(|(count, _)| *count > 0).count() > 1 {
+        let counts: Vec<String> = kinds.iter().filter(|(count, _)| *count > 0).map(|(count, name)| format!("{} {}", count, name)).collect();
+        findings.push(Finding { line: None, message: format!("mixed line endings: {}", counts.join(", ")) });
+    }
+
+    findings.extend(header_findings(data, options));
+    findings.sort_by_key(|finding| finding.line.unwrap_or(u64::MAX));
+    findings.dedup();
+    findings
+}
+
+/// Duplicate (case-insensitively, as csvpeek matches column names) and empty header names.
+fn header_findings(data: &[u8], options: &ValidateOptions) -> Vec<Finding> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .comment(options.comment)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(data);
+    let mut header = csv::ByteRecord::new();
+    if !reader.read_byte_record(&mut header).unwrap_or(false) {
+        return vec![Finding { line: None, message: "no header row".to_string() }];
+    }
+    let line = header.position().map(|pos| pos.line());
+    let names: Vec<String> = header.iter().map(|name| String::from_utf8_lossy(name).trim().to_string()).collect();
+    let mut findings = Vec::new();
+    for (index, name) in names.iter().enumerate() {
+        if name.is_empty() {
+            findings.push(Finding { line, message: format!("empty header name in column {}", index + 1) });
+        } else if let Some(first) = names[..index].iter().position(|other| eq_ignore_case(other, name)) {
+            findings.push(Finding { line, message: format!("duplicate header '{}' (columns {} and {})", name, first + 1, index + 1) });
+        }
+    }
+    findings
+}
+
+fn line_of(data: &[u8], offset: usize) -> u64 {
+    let before = &data[..offset.min(data.len())];
+    let lf = before.iter().filter(|&&b| b == b'\n').count();
+    let lone_cr = before.windows(2).filter(|w| w[0] == b'\r' && w[1] != b'\n').count();
+    (lf + lone_cr) as u64 + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(data: &str) -> Vec<String> {
+        let options = ValidateOptions { delimiter: b',', comment: None, check_utf8: true };
+        validate_csv(data.as_bytes(), &options).iter().map(|f| f.to_string()).collect()
+    }
+
+    #[test]
+    fn test_clean_file_has_no_findings() {
+        assert!(check("Name,Note\r\nAnna,\"says \"\"hi\"\"\r\non two lines\"\r\nBo,x\r\n").is_empty());
+    }
+
+    #[test]
+    fn test_findings() {
+        assert_eq!(
+            check("\u{feff}Name,name,\nAnna,1\r\nBo,\"open\n"),
+            vec![
+                "line 1: UTF-8 byte order mark at start of file",
+                "line 1: duplicate header 'name' (columns 1 and 2)",
+                "line 1: empty header name in column 3",
+                "line 2: record has 2 field(s), the header has 3",
+                "line 3: unbalanced quote: quoted field is never closed",
+                "mixed line endings: 1 CRLF, 2 LF",
+            ]
+        );
+        assert_eq!(check("A,B\n1,x\"y\"\n\"2\"z,3\n"), vec!["line 2: quote inside an unquoted field", "line 3: characters after the closing quote of a field"]);
+    }
+}
@@ -0,0 +1,125 @@
+//! Excel and OpenDocument workbooks. As input, one sheet is read and handed to the CSV parser as
+//! CSV text, so the options that apply to CSV files (e.g. `--skip-rows`) apply to sheets too.
+//! As output, `--format xlsx` writes the displayed columns to a single-sheet `.xlsx` file.
+
+use crate::input::display_path;
+use crate::{CsvPeekError, Result};
+use calamine::{open_workbook_auto, Data, Reader};
+use rust_xlsxwriter::{Format, Workbook, XlsxError};
+use std::path::Path;
+
+/// File extensions read as workbooks rather than delimited text.
+pub const WORKBOOK_EXTENSIONS: [&str; 5] = ["xlsx", "xlsm", "xlsb", "xls", "ods"];
+
+/// True when a path names a workbook by its extension.
+pub fn is_workbook(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| WORKBOOK_EXTENSIONS.iter().any(|workbook| ext.eq_ignore_ascii_case(workbook)))
+}
+
+/// Renders a sheet of the workbook at `path` as comma-separated CSV text. `sheet` is matched by
+/// name (exactly, then ignoring ASCII case); without it the first sheet is read.
+pub fn sheet_as_csv(path: &Path, sheet: Option<&str>) -> Result<Vec<u8>> {
+    let workbook_error = |message: String| CsvPeekError::Workbook { path: path.to_path_buf(), message };
+    let mut workbook = open_workbook_auto(path).map_err(|e| workbook_error(e.to_string()))?;
+    let names = workbook.sheet_names();
+    let name = match sheet {
+        Some(sheet) => names.iter().find(|name| *name == sheet)
+            .or_else(|| names.iter().find(|name| name.eq_ignore_ascii_case(sheet)))
+            .cloned()
+            .ok_or_else(|| CsvPeekError::Usage(format!(
+                "Sheet '{}' not found in '{}'. Available sheets: {}",
+                sheet,
+                display_path(path),
+                names.join(", ")
+            )))?,
+        None => names.first().cloned().ok_or(CsvPeekError::MissingHeaders)?,
+    };
+    let range = workbook.worksheet_range(&name).map_err(|e| workbook_error(e.to_string()))?;
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in range.rows() {
+        writer.write_record(row.iter().map(cell_text))?;
+    }
+    writer.into_inner().map_err(|e| CsvPeekError::from(e.into_error()))
+}
+
+/// The text of a cell. Dates are written as `YYYY-MM-DD` (with ` HH:MM:SS` when they have a
+/// time of day) instead of Excel's serial numbers.
+fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::DateTime(value) if value.is_datetime() => match value.as_datetime() {
+            Some(datetime) if datetime.time() == Default::default() => datetime.date().to_string(),
+            Some(datetime) => datetime.to_string(),
+            None => value.to_string(),
+        },
+        other => other.to_string(),
+    }
+}
+
+/// Writes `headers` and `rows` to a new `.xlsx` workbook at `path`. The header row is bold and
+/// stays in view when scrolling, cells holding plain numbers are stored as numbers, and the
+/// columns are sized to fit their contents.
+pub fn write_xlsx(path: &Path, headers: &[String], rows: &[Vec<String>]) -> Result<()> {
+    let write_error = |error: XlsxError| match error {
+        XlsxError::IoError(source) => CsvPeekError::from(source).in_file(path.display()),
+        other => CsvPeekError::Workbook { path: path.to_path_buf(), message: other.to_string() },
+    };
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    let bold = Format::new().set_bold();
+    sheet.write_row_with_format(0, 0, headers, &bold).map_err(write_error)?;
+    sheet.set_freeze_panes(1, 0).map_err(write_error)?;
+    for (row_index, row) in rows.iter().enumerate() {
+        let row_index = u32::try_from(row_index + 1).map_err(|_| write_error(XlsxError::RowColumnLimitError))?;
+        for (column, cell) in (0..).zip(row) {
+            match number(cell) {
+                Some(value) => sheet.write_number(row_index, column, value),
+                None => sheet.write_string(row_index, column, cell),
+            }
+            .map_err(write_error)?;
+        }
+    }
+    sheet.autofit();
+    workbook.save(path).map_err(write_error)
+}
+
+/// The value of a cell written as a plain number, e.g. `42` or `-0.5`. Text that would not be
+/// written back the same way (`007`, `1e3`, long IDs) stays text.
+fn number(cell: &str) -> Option<f64> {
+    cell.parse::<f64>().ok().filter(|value| value.is_finite() && value.to_string() == cell)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_workbook() {
+        assert!(is_workbook(Path::new("report.xlsx")));
+        assert!(is_workbook(Path::new("OLD.XLS")));
+        assert!(is_workbook(Path::new("sheet.ods")));
+        assert!(!is_workbook(Path::new("data.csv")));
+        assert!(!is_workbook(Path::new("xlsx")));
+    }
+
+    #[test]
+    fn test_number_keeps_text_that_would_change() {
+        assert_eq!(number("42"), Some(42.0));
+        assert_eq!(number("-0.5"), Some(-0.5));
+        assert_eq!(number("007"), None);
+        assert_eq!(number("1e3"), None);
+        assert_eq!(number("12345678901234567890"), None);
+        assert_eq!(number("NaN"), None);
+        assert_eq!(number(""), None);
+    }
+
+    #[test]
+    fn test_written_workbook_reads_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.xlsx");
+        let headers = vec!["Zip".to_string(), "Sales".to_string()];
+        write_xlsx(&path, &headers, &[vec!["007".to_string(), "12.5".to_string()]]).unwrap();
+        let csv = sheet_as_csv(&path, None).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "Zip,Sales\n007,12.5\n");
+    }
+}
@@ -6,6 +6,9 @@ use std::io::Write;
 // use std::process::Command; // Används inte längre direkt
 use tempfile::tempdir;
 
+/// What the deprecated --list flag prints on stderr.
+const LIST_DEPRECATION: &str = "Warning: --list is deprecated and will be removed in the next release; use the list subcommand instead.\n";
+
 #[test]
 fn test_list_basic_csv() -> Result<(), Box<dyn Error>> {
     let temp_dir = tempdir()?;
@@ -64,7 +67,7 @@ fn test_single_filter_and_multiple_display_columns() -> Result<(), Box<dyn Error
                 .and(predicate::str::contains("2. Yesterday\t1965"))
                 .and(predicate::str::contains("Bohemian Rhapsody").not()),
         )
-        .stderr(predicate::str::is_empty());
+        .stderr(LIST_DEPRECATION);
     Ok(())
 }
 
@@ -118,7 +121,7 @@ fn test_directory_input_merges_and_skips() -> Result<(), Box<dyn Error>> {
     cmd_filter_artist.args(["-d", ".", "--list", "--filter", "Artist=The Beatles"]);
     
     cmd_filter_artist.assert()
-        .code(1) 
+        .code(2) 
         .stdout(
             predicate::str::contains("Attempting to determine main headers from: ./books_data.csv")
                 .and(predicate::str::contains("Processing file for data: ./books_data.csv")) 
@@ -187,7 +190,7 @@ fn test_list_multiple_filters() -> Result<(), Box<dyn Error>> {
                 .and(predicate::str::contains("1. London\tUK"))
                 .and(predicate::str::contains("Stockholm").not())
         )
-        .stderr(predicate::str::is_empty());
+        .stderr(LIST_DEPRECATION);
     Ok(())
 }
 
@@ -211,14 +214,14 @@ fn test_list_multiple_filters_no_match() -> Result<(), Box<dyn Error>> {
     ]);
 
     cmd.assert()
-        .success()
+        .code(1)
         .stdout(
             predicate::str::contains("Reading CSV file: multi_filter_data.csv")
                 .and(predicate::str::contains("No entries matched your filter."))
                 .and(predicate::str::contains("List from file").not())
                 .and(predicate::str::contains("Number of entries:").not())
         )
-        .stderr(predicate::str::is_empty());
+        .stderr(LIST_DEPRECATION);
     Ok(())
 }
 
@@ -241,7 +244,7 @@ fn test_list_multiple_filters_invalid_column() -> Result<(), Box<dyn Error>> {
     ]);
 
     cmd.assert()
-        .code(1)
+        .code(2)
         .stdout(
             predicate::str::contains("Reading CSV file: data.csv")
         )
@@ -275,7 +278,7 @@ fn test_list_multiple_columns_with_raw_output() -> Result<(), Box<dyn Error>> {
     cmd.assert()
         .success()
         .stdout(expected_output)
-        .stderr(predicate::str::is_empty());
+        .stderr(LIST_DEPRECATION);
     Ok(())
 }
 
@@ -305,7 +308,29 @@ fn test_filter_with_raw_output() -> Result<(), Box<dyn Error>> {
     cmd.assert()
         .success()
         .stdout(expected_output)
-        .stderr(predicate::str::is_empty());
+        .stderr(LIST_DEPRECATION);
+    Ok(())
+}
+
+#[test]
+fn test_filter_on_bracketed_column_name() -> Result<(), Box<dyn Error>> {
+    // [kg] is not a registered filter operator, so it stays part of the column name.
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--filter", "Weight[kg]>10", "--raw"]).write_stdin("Item,Weight[kg]\nA,5\nB,12\nC,30\n");
+    cmd.assert().success().stdout("B\nC\n");
+    Ok(())
+}
+
+#[test]
+fn test_pager_is_skipped_for_piped_output() -> Result<(), Box<dyn Error>> {
+    // A pager that would replace the listing shows whether it was started.
+    let rows: String = (1..=200).map(|n| format!("{}\n", n)).collect();
+    let expected: String = (1..=200).map(|n| format!("{}. {}\n", n, n)).collect();
+    for extra in [None, Some("--no-pager")] {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        cmd.env("PAGER", "echo paged").args(["list", "-q"]).args(extra).write_stdin(format!("n\n{}", rows));
+        cmd.assert().success().stdout(predicate::str::ends_with(expected.as_str()).and(predicate::str::contains("paged").not()));
+    }
     Ok(())
 }
 
@@ -440,3 +465,2142 @@ fn test_random_selection_soundness_repeated_invocation() -> Result<(), Box<dyn E
 
     Ok(())
 }
+
+fn write_zip(path: &std::path::Path, members: &[(&str, &str)]) -> Result<(), Box<dyn Error>> {
+    let mut zip = zip::ZipWriter::new(File::create(path)?);
+    for (name, content) in members {
+        zip.start_file(*name, zip::write::SimpleFileOptions::default())?;
+        zip.write_all(content.as_bytes())?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+#[test]
+fn test_zip_archive_merges_csv_members() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    write_zip(
+        &temp_dir.path().join("export.zip"),
+        &[
+            ("a.csv", "Name,Value\nAlpha,1\n"),
+            ("nested/b.csv", "Name,Value\nBeta,2\n"),
+            ("other.csv", "Title,Author\nMoby Dick,Melville\n"),
+            ("readme.txt", "not csv"),
+        ],
+    )?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path());
+    cmd.args(["-f", "export.zip", "--list"]);
+
+    cmd.assert()
+        .success()
+        .stdout(
+            predicate::str::contains("Processing file for data: export.zip::a.csv")
+                .and(predicate::str::contains("List from zip archive 'export.zip' (displaying column(s): Name)"))
+                .and(predicate::str::contains("Number of entries: 2"))
+                .and(predicate::str::contains("1. Alpha"))
+                .and(predicate::str::contains("2. Beta"))
+                .and(predicate::str::contains("readme.txt").not()),
+        )
+        .stderr(predicate::str::contains(
+            "Warning: Headers in file 'export.zip::other.csv' do not match main headers. Skipping records from this file.",
+        ));
+    Ok(())
+}
+
+#[test]
+fn test_zip_archive_single_member() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    write_zip(
+        &temp_dir.path().join("export.zip"),
+        &[
+            ("a.csv", "Name,Value\nAlpha,1\n"),
+            ("nested/b.csv", "Name,Value\nBeta,2\n"),
+        ],
+    )?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path());
+    cmd.args(["-f", "export.zip::nested/b.csv", "--list", "--raw", "-c", "Name,Value"]);
+
+    cmd.assert()
+        .success()
+        .stdout("Beta\t2\n")
+        .stderr(LIST_DEPRECATION);
+    Ok(())
+}
+
+#[test]
+fn test_http_url_input_sends_headers() -> Result<(), Box<dyn Error>> {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/report.csv", listener.local_addr()?);
+    let server = std::thread::spawn(move || -> Vec<String> {
+        let (stream, _) = listener.accept().expect("accept connection");
+        let mut reader = BufReader::new(stream.try_clone().expect("clone stream"));
+        let mut request_lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).expect("read request line");
+            if line.trim().is_empty() {
+                break;
+            }
+            request_lines.push(line.trim().to_string());
+        }
+        let body = "City,Country\nLondon,UK\nParis,France\n";
+        let mut stream = stream;
+        write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: text/csv\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+            .expect("write response");
+        request_lines
+    });
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["-f", &url, "--header", "Authorization: Bearer secret", "--list", "--filter", "Country=UK", "--raw"]);
+    cmd.assert()
+        .success()
+        .stdout("London\n")
+        .stderr(LIST_DEPRECATION);
+
+    let request_lines = server.join().expect("server thread");
+    assert!(request_lines.iter().any(|l| l.eq_ignore_ascii_case("authorization: Bearer secret")));
+    Ok(())
+}
+
+fn fixtures_dir() -> std::path::PathBuf {
+    std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures")
+}
+
+/// Compares output against `tests/golden/<name>`. Run with `UPDATE_GOLDEN=1` to rewrite the file.
+fn assert_golden(name: &str, actual: &[u8]) -> Result<(), Box<dyn Error>> {
+    let golden_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&golden_path, actual)?;
+        return Ok(());
+    }
+    let expected = std::fs::read_to_string(&golden_path)?;
+    assert_eq!(String::from_utf8_lossy(actual), expected, "output differs from golden file {}", golden_path.display());
+    Ok(())
+}
+
+#[test]
+fn test_golden_filtered_list() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["--render-fixture", "-f", "songs.csv", "--list", "--filter", "Genre=Rock", "--filter", "Rating>=4", "-c", "Title,Artist,Rating"]);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    assert_golden("filtered_list.txt", &output.stdout)
+}
+
+#[test]
+fn test_golden_random_pick_is_reproducible() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["--render-fixture", "-f", "songs.csv", "-c", "Title,Year"]);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    assert_golden("random_pick.txt", &output.stdout)
+}
+
+#[test]
+fn test_golden_directory_merge_with_absolute_path() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.arg("--render-fixture").arg("-d").arg(fixtures_dir().join("merge")).arg("--list");
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    assert_golden("directory_merge.txt", &output.stdout)
+}
+
+#[test]
+fn test_multiple_data_files_are_merged() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("jan.csv"), "Order,Amount\nA-1,10\nA-2,20\n")?;
+    std::fs::write(temp_dir.path().join("feb.csv"), "Order,Amount\nB-1,30\n")?;
+    std::fs::write(temp_dir.path().join("customers.csv"), "Customer,Name\n1,Ann\n")?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path());
+    cmd.args(["-f", "jan.csv", "-f", "feb.csv", "-f", "customers.csv", "--list", "--filter", "Amount>=20"]);
+
+    cmd.assert()
+        .success()
+        .stdout(
+            predicate::str::contains("Attempting to determine main headers from: jan.csv")
+                .and(predicate::str::contains("List from files 'jan.csv', 'feb.csv', 'customers.csv' (displaying column(s): Order)"))
+                .and(predicate::str::contains("Number of entries: 2"))
+                .and(predicate::str::contains("1. A-2"))
+                .and(predicate::str::contains("2. B-1")),
+        )
+        .stderr(predicate::str::contains(
+            "Warning: Headers in file 'customers.csv' do not match main headers. Skipping records from this file.",
+        ));
+    Ok(())
+}
+
+#[test]
+fn test_where_hash_selects_stable_subset() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let mut data = String::from("id,name\n");
+    for i in 0..200 {
+        data.push_str(&format!("{},user{}\n", i, i));
+    }
+    std::fs::write(temp_dir.path().join("users.csv"), data)?;
+
+    let run = || -> Result<String, Box<dyn Error>> {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        cmd.current_dir(temp_dir.path());
+        cmd.args(["-f", "users.csv", "--list", "--where-hash", "id%10<3", "--raw"]);
+        let output = cmd.output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    let first = run()?;
+    let selected = first.lines().count();
+    assert!(selected > 20 && selected < 100, "unexpected sample size {}", selected);
+    assert_eq!(first, run()?);
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path());
+    cmd.args(["-f", "users.csv", "--list", "--where-hash", "id%10<3", "-c", "name"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "List from file 'users.csv' (displaying column(s): name) filtered where hash(id) % 10 < 3\nNumber of entries: {}",
+            selected
+        )));
+    Ok(())
+}
+
+#[test]
+fn test_footer_summary_after_list() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["-f", "songs.csv", "--list", "--filter", "Genre=Rock", "--footer", "min(Year),max(Year),count(),first(Title),avg(Rating)"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "5. Waterloo Sunset\nSummary: min(Year) = 1965 | max(Year) = 1975 | count() = 5 | first(Title) = Bohemian Rhapsody | avg(Rating) = 4.4\n",
+        ));
+
+    let mut raw_cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    raw_cmd.current_dir(fixtures_dir());
+    raw_cmd.args(["-f", "songs.csv", "--list", "--filter", "Genre=Pop", "--footer", "count()", "--raw"]);
+    raw_cmd.assert()
+        .success()
+        .stdout("Imagine\n")
+        .stderr(format!("{}Summary: count() = 1\n", LIST_DEPRECATION));
+    Ok(())
+}
+
+#[test]
+fn test_recursive_directory_with_max_depth() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let root = temp_dir.path().join("data");
+    std::fs::create_dir_all(root.join("2024").join("05"))?;
+    std::fs::write(root.join("top.csv"), "Day,Sales\n2024-04-30,1\n")?;
+    std::fs::write(root.join("2024").join("summary.csv"), "Day,Sales\n2024-05-00,2\n")?;
+    std::fs::write(root.join("2024").join("05").join("01.csv"), "Day,Sales\n2024-05-01,3\n")?;
+
+    let mut flat = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    flat.current_dir(temp_dir.path());
+    flat.args(["-d", "data", "--list", "--raw"]);
+    flat.assert().success().stdout("2024-04-30\n");
+
+    let mut recursive = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    recursive.current_dir(temp_dir.path());
+    recursive.args(["-d", "data", "--recursive", "--list", "--raw"]);
+    recursive.assert().success().stdout("2024-05-01\n2024-05-00\n2024-04-30\n");
+
+    let mut limited = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    limited.current_dir(temp_dir.path());
+    limited.args(["-d", "data", "--recursive", "--max-depth", "1", "--list", "--raw"]);
+    limited.assert().success().stdout("2024-05-00\n2024-04-30\n");
+    Ok(())
+}
+
+#[test]
+fn test_vertical_layout() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["-f", "songs.csv", "--list", "--filter", "Year<1966", "-c", "Title,Artist,Year", "--layout", "vertical"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Number of entries: 1\n\
+             *************************** 1. row ***************************\n\
+             \x20Title: Like a Rolling Stone\n\
+             Artist: Bob Dylan\n\
+             \x20\x20Year: 1965\n",
+        ));
+    Ok(())
+}
+
+#[test]
+fn test_union_merge_strategy_fills_missing_columns() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let dir = temp_dir.path();
+    std::fs::write(dir.join("a.csv"), "Name,Price\nApple,3\n")?;
+    std::fs::write(dir.join("b.csv"), "Name,Origin\nBanana,Ecuador\n")?;
+
+    let mut matching = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    matching.args(["-d", dir.to_str().unwrap(), "--list", "-c", "Name", "--raw"]);
+    matching.assert().success().stdout("Apple\n");
+
+    let mut union = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    union.args(["-d", dir.to_str().unwrap(), "--merge-strategy", "union", "--list", "-c", "Name,Price,Origin", "--raw"]);
+    union.assert().success().stdout("Apple\t3\t\nBanana\t\tEcuador\n");
+
+    let mut headers = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    headers.args(["-d", dir.to_str().unwrap(), "--merge-strategy", "union", "--headers"]);
+    headers.assert().success().stdout(predicate::str::contains("Origin"));
+    Ok(())
+}
+
+#[test]
+fn test_map_by_position_aligns_renamed_headers() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let dir = temp_dir.path();
+    std::fs::write(dir.join("a_export.csv"), "Name,Price\nApple,3\n")?;
+    std::fs::write(dir.join("b_export.csv"), "Product,Cost\nBanana,2\n")?;
+    std::fs::write(dir.join("c_other.csv"), "Product,Cost,Origin\nCherry,5,Chile\n")?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["-d", dir.to_str().unwrap(), "--map-by-position", "--list", "-c", "Name,Price", "--raw"]);
+    cmd.assert().success().stdout("Apple\t3\nBanana\t2\n");
+    Ok(())
+}
+
+#[test]
+fn test_add_source_column_when_merging_files() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["-f", "merge/a_sixties.csv", "-f", "merge/b_nineties.csv", "--add-source-column", "--list",
+        "--filter", "Year=1995", "-c", "Title,source", "--raw"]);
+    cmd.assert().success().stdout("Wonderwall\tmerge/b_nineties.csv\n");
+
+    let mut named = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    named.current_dir(fixtures_dir());
+    named.args(["-f", "merge/a_sixties.csv", "-f", "merge/b_nineties.csv", "--add-source-column", "Title", "--headers"]);
+    named.assert().failure().stderr(predicate::str::contains("already has a column"));
+    Ok(())
+}
+
+#[test]
+fn test_param_substitution_in_filter_and_columns() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.env("CSVPEEK_TEST_COLUMN", "Title");
+    cmd.args(["-f", "songs.csv", "--list", "--param", "artist=Bob Dylan", "--filter", "Artist=${artist}",
+        "-c", "${CSVPEEK_TEST_COLUMN}", "--raw"]);
+    cmd.assert().success().stdout("Like a Rolling Stone\n");
+
+    let mut missing = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    missing.current_dir(fixtures_dir());
+    missing.args(["-f", "songs.csv", "--list", "--filter", "Artist=${csvpeek_undefined}"]);
+    missing.assert().failure().stderr(predicate::str::contains("Parameter 'csvpeek_undefined'"));
+    Ok(())
+}
+
+#[test]
+fn test_batch_runs_queries_from_stdin() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["batch", "-f", "songs.csv"]);
+    cmd.write_stdin("--filter \"Year<1966\" -c Title,Artist --raw\n\n# skipped\n-c Nope --raw\n--filter Year=1995 --raw\n");
+    cmd.assert()
+        .failure()
+        .stdout(
+            "==> query 1: --filter \"Year<1966\" -c Title,Artist --raw\n\
+             Like a Rolling Stone\tBob Dylan\n\
+             <== query 1: ok\n\
+             ==> query 4: -c Nope --raw\n\
+             <== query 4: error: Display column 'Nope' not found in CSV file headers: [\"Title\", \"Artist\", \"Album\", \"Year\", \"Genre\", \"Rating\"]\n\
+             ==> query 5: --filter Year=1995 --raw\n\
+             Wonderwall\n\
+             <== query 5: ok\n",
+        )
+        .stderr(predicate::str::contains("1 batch query failed."));
+    Ok(())
+}
+
+#[test]
+fn test_directory_include_and_exclude_patterns() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let root = temp_dir.path().join("data");
+    std::fs::create_dir_all(root.join("2024"))?;
+    std::fs::write(root.join("sales_jan.csv"), "Day,Sales\njan,1\n")?;
+    std::fs::write(root.join("sales_feb_backup.csv"), "Day,Sales\nfeb-old,2\n")?;
+    std::fs::write(root.join("costs.csv"), "Day,Sales\ncosts,3\n")?;
+    std::fs::write(root.join("2024").join("sales_mar.csv"), "Day,Sales\nmar,4\n")?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path());
+    cmd.args(["-d", "data", "--recursive", "--include", "sales_*.csv", "--exclude", "*_backup.csv", "--list", "--raw"]);
+    cmd.assert().success().stdout("mar\njan\n");
+
+    let mut by_path = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    by_path.current_dir(temp_dir.path());
+    by_path.args(["-d", "data", "--recursive", "--include", "2024/*.csv", "--list", "--raw"]);
+    by_path.assert().success().stdout("mar\n");
+    Ok(())
+}
+
+#[test]
+fn test_directory_extensions_with_delimiter_handling() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let dir = temp_dir.path();
+    std::fs::write(dir.join("a.csv"), "Name,City\nAnna,Oslo\n")?;
+    std::fs::write(dir.join("b.tsv"), "Name\tCity\nBo\tUmeå\n")?;
+    std::fs::write(dir.join("c.txt"), "Name;City\nCeline;Lyon\n")?;
+
+    let mut default_only = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    default_only.args(["-d", dir.to_str().unwrap(), "--list", "-c", "City", "--raw"]);
+    default_only.assert().success().stdout("Oslo\n");
+
+    let mut all = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    all.args(["-d", dir.to_str().unwrap(), "--extensions", "csv,tsv,txt", "--list", "-c", "City", "--raw"]);
+    all.assert().success().stdout("Oslo\nUmeå\nLyon\n");
+
+    let mut explicit = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    explicit.args(["-f", dir.join("c.txt").to_str().unwrap(), "--delimiter", ";", "--list", "-c", "City", "--raw"]);
+    explicit.assert().success().stdout("Lyon\n");
+    Ok(())
+}
+
+#[test]
+fn test_header_map_reconciles_renamed_columns() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let dir = temp_dir.path().join("data");
+    std::fs::create_dir(&dir)?;
+    std::fs::write(dir.join("a.csv"), "CustomerID,Name\n1,Anna\n")?;
+    std::fs::write(dir.join("b.csv"), "Kundnr,Namn\n2,Bo\n")?;
+    let map = temp_dir.path().join("map.toml");
+    std::fs::write(&map, "\"Kundnr\" = \"CustomerID\"\nNamn = \"Name\"\n")?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["-d", dir.to_str().unwrap(), "--header-map", map.to_str().unwrap(), "--list", "-c", "CustomerID,Name", "--raw"]);
+    cmd.assert().success().stdout("1\tAnna\n2\tBo\n");
+
+    std::fs::write(&map, "Kundnr = 3\n")?;
+    let mut invalid = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    invalid.args(["-d", dir.to_str().unwrap(), "--header-map", map.to_str().unwrap(), "--headers"]);
+    invalid.assert().failure().stderr(predicate::str::contains("'Kundnr' must map to a column name"));
+    Ok(())
+}
+
+#[test]
+fn test_strict_merge_fails_on_mismatch_and_bad_files() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let dir = temp_dir.path();
+    std::fs::write(dir.join("a.csv"), "Name,City\nAnna,Oslo\n")?;
+    std::fs::write(dir.join("b.csv"), "Name,Town\nBo,Umeå\n")?;
+
+    let mut lenient = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    lenient.args(["-d", dir.to_str().unwrap(), "--list", "--raw"]);
+    lenient.assert().success().stdout("Anna\n");
+
+    let mut strict = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    strict.args(["-d", dir.to_str().unwrap(), "--strict", "--list", "--raw"]);
+    strict.assert().failure().stdout("").stderr(predicate::str::contains("b.csv' do not match main headers"));
+
+    std::fs::write(dir.join("b.csv"), "Name,City\nBo,Umeå,extra\n")?;
+    let mut ragged = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    ragged.args(["-d", dir.to_str().unwrap(), "--strict", "--list", "--raw"]);
+    ragged.assert().failure().stderr(predicate::str::contains("CSV parse error").and(predicate::str::contains("b.csv' at line 2")));
+    Ok(())
+}
+
+#[test]
+fn test_encoding_transcodes_legacy_input() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let latin1 = temp_dir.path().join("latin1.csv");
+    std::fs::write(&latin1, b"Stad,Land\nG\xf6teborg,Sverige\n")?;
+    let utf16 = temp_dir.path().join("utf16.csv");
+    let utf16_bytes: Vec<u8> = "Stad,Land\nMünchen,Deutschland\n".encode_utf16().flat_map(|unit| unit.to_le_bytes()).collect();
+    std::fs::write(&utf16, utf16_bytes)?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["-f", latin1.to_str().unwrap(), "--encoding", "latin1", "--list", "--raw"]);
+    cmd.assert().success().stdout("Göteborg\n");
+
+    let mut wide = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    wide.args(["-f", utf16.to_str().unwrap(), "--encoding", "utf-16le", "--list", "--raw"]);
+    wide.assert().success().stdout("München\n");
+
+    let mut unknown = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    unknown.args(["-f", latin1.to_str().unwrap(), "--encoding", "klingon", "--list"]);
+    unknown.assert().failure().stderr(predicate::str::contains("Unknown encoding 'klingon'"));
+    Ok(())
+}
+
+#[test]
+fn test_comment_char_skips_metadata_lines() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let data = temp_dir.path().join("samples.txt");
+    std::fs::write(&data, "# instrument: X-200\n# exported; 2024-05-01\nSample\tValue\nA1\t0.5\n# calibration break\nA2\t0.7\n")?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["-f", data.to_str().unwrap(), "--comment-char", "#", "--list", "-c", "Sample,Value", "--raw"]);
+    cmd.assert().success().stdout("A1\t0.5\nA2\t0.7\n");
+    Ok(())
+}
+
+#[test]
+fn test_skip_rows_before_header() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let data = temp_dir.path().join("export.csv");
+    std::fs::write(&data, "Monthly sales export\nGenerated 2024-05-01\nRegion,Sales\nEU,10\nUS,20,extra\n")?;
+
+    let mut headers = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    headers.args(["-f", data.to_str().unwrap(), "--skip-rows", "2", "--headers"]);
+    headers.assert().success().stdout("Region\nSales\n");
+
+    let mut ragged = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    ragged.args(["-f", data.to_str().unwrap(), "--skip-rows", "2", "--list"]);
+    ragged.assert().failure().stderr(predicate::str::contains("at line 5"));
+    Ok(())
+}
+
+#[test]
+fn test_flexible_and_skip_bad_rows() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let data = temp_dir.path().join("ragged.csv");
+    std::fs::write(&data, "Name,City\nAnna,Oslo\nBo\nCeline,Lyon,France\n")?;
+
+    let mut strict = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    strict.args(["-f", data.to_str().unwrap(), "--list", "--raw"]);
+    strict.assert().failure();
+
+    let mut flexible = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    flexible.args(["-f", data.to_str().unwrap(), "--flexible", "--list", "-c", "Name,City", "--raw"]);
+    flexible.assert()
+        .success()
+        .stdout("Anna\tOslo\nBo\t\nCeline\tLyon\n")
+        .stderr(predicate::str::contains("Padded or truncated 2 row(s)").and(predicate::str::contains("first at line 3")));
+
+    let mut skipping = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    skipping.args(["-f", data.to_str().unwrap(), "--skip-bad-rows", "--list", "--raw"]);
+    skipping.assert().success().stdout("Anna\n").stderr(predicate::str::contains("Skipped 2 row(s)"));
+    Ok(())
+}
+
+#[test]
+fn test_report_errors_lists_every_bad_record() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("dirty.csv"), "Name,City\nAnna,Oslo\nBo\nCeline,Lyon,France\nDan,Rome\n")?;
+
+    let mut text = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    text.current_dir(temp_dir.path());
+    text.args(["-f", "dirty.csv", "--report-errors"]);
+    text.assert()
+        .code(2)
+        .stdout(
+            "Found 2 problem(s) in file 'dirty.csv':\n\
+             \x20 dirty.csv:3: found record with 1 fields, but the previous record has 2 fields\n\
+             \x20 dirty.csv:4: found record with 3 fields, but the previous record has 2 fields\n",
+        );
+
+    let mut json = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    json.current_dir(temp_dir.path());
+    json.args(["-f", "dirty.csv", "--report-errors", "json"]);
+    let output = json.output()?;
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(report["problem_count"], 2);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(report["problems"][1]["line"], 4);
+
+    std::fs::write(temp_dir.path().join("clean.csv"), "Name\nAnna\n")?;
+    let mut clean = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    clean.current_dir(temp_dir.path());
+    clean.args(["-f", "clean.csv", "--report-errors"]);
+    clean.assert().success().stdout("No problems found in file 'clean.csv'.\n");
+    Ok(())
+}
+
+#[test]
+fn test_validate_reports_findings_per_file() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let dir = temp_dir.path().join("drop");
+    std::fs::create_dir(&dir)?;
+    std::fs::write(dir.join("a_clean.csv"), "Name,City\r\nAnna,Oslo\r\n")?;
+    std::fs::write(dir.join("b_dirty.csv"), "Name,City,name\nAnna,Oslo\r\nBo,\"Umeå\n")?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path());
+    cmd.args(["-d", "drop", "--validate"]);
+    cmd.assert()
+        .code(2)
+        .stdout(
+            "drop/a_clean.csv: OK\n\
+             drop/b_dirty.csv: 4 finding(s)\n\
+             \x20 line 1: duplicate header 'name' (columns 1 and 3)\n\
+             \x20 line 2: record has 2 field(s), the header has 3\n\
+             \x20 line 3: unbalanced quote: quoted field is never closed\n\
+             \x20 mixed line endings: 1 CRLF, 2 LF\n\
+             4 finding(s) in 1 of 2 input(s).\n",
+        );
+
+    let mut clean = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    clean.current_dir(temp_dir.path());
+    clean.args(["-f", "drop/a_clean.csv", "--validate", "json"]);
+    clean.assert().success().stdout(predicate::str::contains("\"finding_count\": 0"));
+    Ok(())
+}
+
+#[test]
+fn test_infer_schema_text_and_frictionless_json() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("orders.csv"), "Id,Price,Paid,Day\n1,9.5,yes,2024-01-31\n2,,no,2024-02-01\n")?;
+
+    let mut text = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    text.current_dir(temp_dir.path());
+    text.args(["-f", "orders.csv", "--infer-schema"]);
+    text.assert().success().stdout(
+        "Schema of file 'orders.csv' (2 of 2 records examined):\n\
+         Column  Type     Nullable  Examples\n\
+         Id      integer  no        1, 2\n\
+         Price   float    yes       9.5\n\
+         Paid    boolean  no        yes, no\n\
+         Day     date     no        2024-01-31, 2024-02-01\n",
+    );
+
+    let mut json = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    json.current_dir(temp_dir.path());
+    json.args(["-f", "orders.csv", "--infer-schema", "json"]);
+    let schema: serde_json::Value = serde_json::from_slice(&json.output()?.stdout)?;
+    assert_eq!(schema["fields"][1]["type"], "number");
+    assert_eq!(schema["fields"][1]["constraints"]["required"], false);
+    assert_eq!(schema["fields"][3]["type"], "date");
+    Ok(())
+}
+
+#[test]
+fn test_cast_columns_for_typed_filters() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("people.csv"), "Name,Age,Active\nAnna,030,yes\nBo,9,no\nCy,unknown,yes\n")?;
+
+    let mut failing = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    failing.current_dir(temp_dir.path());
+    failing.args(["-f", "people.csv", "--cast", "Age:int", "--list"]);
+    failing.assert().failure().stderr(predicate::str::contains("line 4: cannot cast 'unknown' in column 'Age' to integer"));
+
+    let mut skipping = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    skipping.current_dir(temp_dir.path());
+    skipping.args(["-f", "people.csv", "--cast", "Age:int,Active:bool", "--skip-bad-rows", "--list", "-c", "Name,Age,Active", "--filter", "Active=true", "--raw"]);
+    skipping.assert().success()
+        .stdout("Anna\t30\ttrue\n")
+        .stderr(predicate::str::contains("Skipped 1 row(s) with values that could not be cast"));
+
+    // Streamed queries that stop early warn about the rows they skipped too.
+    let mut streamed = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    streamed.current_dir(temp_dir.path());
+    streamed.args(["-f", "people.csv", "--cast", "Age:int", "--skip-bad-rows", "list", "--max-results", "5", "--raw"]);
+    streamed.assert().success()
+        .stdout("Anna\nBo\n")
+        .stderr("Warning: Skipped 1 row(s) with values that could not be cast.\n");
+
+    let mut bad_filter = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    bad_filter.current_dir(temp_dir.path());
+    bad_filter.args(["-f", "people.csv", "--cast", "Active:bool", "--list", "--filter", "Active=maybe"]);
+    bad_filter.assert().failure().stderr(predicate::str::contains("Filter value 'maybe' for column 'Active' is not a valid boolean."));
+    Ok(())
+}
+
+#[test]
+fn test_trim_padded_cells_and_headers() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("cities.csv"), " Name , City \nAnna, London \nBo,Paris\n")?;
+
+    let mut untrimmed = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    untrimmed.current_dir(temp_dir.path());
+    untrimmed.args(["-f", "cities.csv", "--trim", "headers", "--list", "--filter", "City=London", "--raw"]);
+    untrimmed.assert().code(1).stdout("");
+
+    let mut trimmed = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    trimmed.current_dir(temp_dir.path());
+    trimmed.args(["-f", "cities.csv", "--trim", "--list", "-c", "Name,City", "--filter", "City=London", "--raw"]);
+    trimmed.assert().success().stdout("Anna\tLondon\n");
+    Ok(())
+}
+
+#[test]
+fn test_null_value_and_empty_as_null() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("cities.csv"), "Name,City\nAnna,London\nBo,\n")?;
+
+    let mut placeholder = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    placeholder.current_dir(temp_dir.path());
+    placeholder.args(["-f", "cities.csv", "--list", "-c", "Name,City", "--null-value", "NULL", "--raw"]);
+    placeholder.assert().success().stdout("Anna\tLondon\nBo\tNULL\n");
+
+    let mut not_london = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    not_london.current_dir(temp_dir.path());
+    not_london.args(["-f", "cities.csv", "--list", "--filter", "City!=London", "--raw"]);
+    not_london.assert().success().stdout("Bo\n");
+
+    let mut empty_as_null = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    empty_as_null.current_dir(temp_dir.path());
+    empty_as_null.args(["-f", "cities.csv", "--list", "--filter", "City!=London", "--empty-as-null", "--raw"]);
+    empty_as_null.assert().code(1).stdout("");
+    Ok(())
+}
+
+#[test]
+fn test_fill_empty_cells_before_filtering() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("people.csv"), "Name,Country,Team\nAnna,,\nBo,SE,\n")?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path());
+    cmd.args(["-f", "people.csv", "--fill", "Country=Unknown", "--fill-all", "-", "--list", "-c", "Name,Country,Team", "--filter", "Country!=SE", "--raw"]);
+    cmd.assert().success().stdout("Anna\tUnknown\t-\n");
+    Ok(())
+}
+
+#[test]
+fn test_replace_rewrites_displayed_values() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("phones.csv"), "Name,Phone\nAnna,070-123 45\nBo,070 555\n")?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path());
+    cmd.args(["-f", "phones.csv", "--list", "-c", "Name,Phone", "--replace", "Phone:/[^0-9]+//", "--filter", "Phone>=070-", "--raw"]);
+    cmd.assert().success().stdout("Anna\t07012345\n");
+    Ok(())
+}
+
+#[test]
+fn test_raw_output_delimiter() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["-f", "tests/fixtures/songs.csv", "--list", "-c", "Title,Year", "--filter", "Year=1968", "--raw", "--output-delimiter", "\\x1f"]);
+    cmd.assert().success().stdout("Hey Jude\u{1f}1968\n");
+    Ok(())
+}
+
+#[test]
+fn test_print0_terminates_raw_records_with_nul() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("notes.csv"), "Id,Note\n1,\"two\nlines\"\n2,one line\n")?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path());
+    cmd.args(["-f", "notes.csv", "--list", "-c", "Note", "--raw", "-0"]);
+    cmd.assert().success().stdout("two\nlines\0one line\0");
+    Ok(())
+}
+
+#[test]
+fn test_csv_output_with_quote_style() -> Result<(), Box<dyn Error>> {
+    let mut necessary = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    necessary.args(["-f", "tests/fixtures/songs.csv", "--list", "-c", "Title,Year", "--filter", "Year=1968", "--format", "csv"]);
+    necessary.assert().success().stdout("Title,Year\nHey Jude,1968\n");
+
+    let mut non_numeric = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    non_numeric.args(["-f", "tests/fixtures/songs.csv", "--list", "-c", "Title,Year", "--filter", "Year=1968", "--format", "csv",
+        "--quote-style", "non-numeric", "--quote-char", "'"]);
+    non_numeric.assert().success().stdout("'Title','Year'\n'Hey Jude',1968\n");
+    Ok(())
+}
+
+#[test]
+fn test_crlf_line_endings() -> Result<(), Box<dyn Error>> {
+    let mut csv = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    csv.args(["-f", "tests/fixtures/songs.csv", "--list", "-c", "Title,Year", "--filter", "Year=1968", "--format", "csv", "--crlf"]);
+    csv.assert().success().stdout("Title,Year\r\nHey Jude,1968\r\n");
+
+    let mut text = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    text.args(["-f", "tests/fixtures/songs.csv", "--list", "-c", "Title", "--filter", "Year=1968", "--layout", "table", "--crlf"]);
+    let stdout = String::from_utf8(text.output()?.stdout)?;
+    assert!(stdout.ends_with("Number of entries: 1\r\n1. Hey Jude\r\n"));
+    assert!(!stdout.replace("\r\n", "").contains('\n'));
+    Ok(())
+}
+
+#[test]
+fn test_color_highlights_filtered_cells() -> Result<(), Box<dyn Error>> {
+    let mut always = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    always.args(["-f", "tests/fixtures/songs.csv", "--list", "-c", "Title,Year", "--filter", "Year=1968", "--layout", "table", "--color", "always"]);
+    always.assert().success().stdout(predicate::str::contains("\x1b[2m1.\x1b[0m Hey Jude\t\x1b[1;33m1968\x1b[0m\n"));
+
+    let mut auto = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    auto.args(["-f", "tests/fixtures/songs.csv", "--list", "-c", "Title,Year", "--filter", "Year=1968", "--layout", "table"]);
+    auto.assert().success().stdout(predicate::str::contains("\x1b[").not());
+    Ok(())
+}
+
+#[test]
+fn test_max_col_width_truncates_listing() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["-f", "tests/fixtures/songs.csv", "--list", "-c", "Title", "--filter", "Year=1975", "--layout", "table", "--max-col-width", "8"]);
+    cmd.assert().success().stdout(predicate::str::contains("1. Bohemia…\n"));
+    Ok(())
+}
+
+#[test]
+fn test_wrap_long_cells_within_column() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["-f", "tests/fixtures/songs.csv", "--list", "-c", "Title,Year", "--filter", "Year=1975", "--layout", "table", "--wrap", "--max-col-width", "9"]);
+    cmd.assert().success().stdout(predicate::str::ends_with("1. Bohemian  1975\n   Rhapsody\n"));
+    Ok(())
+}
+
+#[test]
+fn test_row_numbering_control() -> Result<(), Box<dyn Error>> {
+    let mut start = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    start.args(["-f", "tests/fixtures/songs.csv", "--list", "--filter", "Year=1968", "--layout", "table", "--start-index", "41"]);
+    start.assert().success().stdout(predicate::str::ends_with("Number of entries: 1\n41. Hey Jude\n"));
+
+    let mut no_index = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    no_index.args(["-f", "tests/fixtures/songs.csv", "--list", "--filter", "Year=1968", "--layout", "table", "--no-index"]);
+    no_index.assert().success().stdout(predicate::str::ends_with("Number of entries: 1\nHey Jude\n"));
+    Ok(())
+}
+
+#[test]
+fn test_quiet_keeps_formatted_output() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["-f", "tests/fixtures/songs.csv", "--list", "--filter", "Year=1968", "--layout", "table", "-q"]);
+    cmd.assert().success().stdout(
+        "List from file 'tests/fixtures/songs.csv' (displaying column(s): Title) filtered where Year = '1968'\n\
+         Number of entries: 1\n\
+         1. Hey Jude\n",
+    );
+    Ok(())
+}
+
+#[test]
+fn test_verbose_reports_timings_on_stderr() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["-f", "tests/fixtures/songs.csv", "--list", "--filter", "Year=1968", "--raw", "-v"]);
+    cmd.assert().success()
+        .stdout("Hey Jude\n")
+        .stderr(predicate::str::is_match("Verbose: read \\d+ row\\(s\\) and \\d+ byte\\(s\\) from tests/fixtures/songs.csv in ")?)
+        .stderr(predicate::str::contains("Verbose: Loading"))
+        .stderr(predicate::str::contains("Verbose: Filtering to 1 record(s) took"))
+        .stderr(predicate::str::contains("Verbose: Rendering took"));
+    Ok(())
+}
+
+#[test]
+fn test_quiet_exists_sets_exit_status_only() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["-f", "tests/fixtures/songs.csv", "--quiet-exists", "--filter", "Year=1968"]);
+    cmd.assert().success().stdout("").stderr("");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["-f", "tests/fixtures/songs.csv", "--quiet-exists", "--filter", "Year=1800"]);
+    cmd.assert().code(1).stdout("").stderr("");
+    Ok(())
+}
+
+#[test]
+fn test_quiet_exists_stops_at_first_match() -> Result<(), Box<dyn Error>> {
+    // The malformed last row is never read once the first row has matched.
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["--quiet-exists", "--filter", "id=1"]).write_stdin("id,name\n1,a\n2,b,extra\n");
+    cmd.assert().success().stdout("");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["--quiet-exists", "--filter", "id=3"]).write_stdin("id,name\n1,a\n2,b,extra\n");
+    cmd.assert().code(2);
+    Ok(())
+}
+
+#[test]
+fn test_max_results_limits_matches() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["--list", "--filter", "id>1", "--max-results", "2", "--raw"]).write_stdin("id\n1\n2\n3\n4\n");
+    cmd.assert().success().stdout("2\n3\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["-f", "tests/fixtures/songs.csv", "--list", "--first", "--raw"]);
+    cmd.assert().success().stdout(predicate::str::is_match("^[^\n]+\n$")?);
+    Ok(())
+}
+
+#[test]
+fn test_first_stops_reading_after_match() -> Result<(), Box<dyn Error>> {
+    // The malformed last row is never read once the first row has matched.
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["--list", "--first", "-c", "name", "--raw"]).write_stdin("id,name\n1,a\n2,b,extra\n");
+    cmd.assert().success().stdout("a\n");
+    Ok(())
+}
+
+#[test]
+fn test_generate_completions() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["--generate-completions", "bash"]);
+    cmd.assert().success().stdout(predicate::str::contains("complete -F _csvpeek__rs").and(predicate::str::contains("--max-results")));
+    Ok(())
+}
+
+#[test]
+fn test_config_file_defaults() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("data.csv"), "id;name;city\n1;Ann;Oslo\n2;;Rome\n")?;
+    let config_dir = temp_dir.path().join("csvpeek");
+    std::fs::create_dir(&config_dir)?;
+    std::fs::write(
+        config_dir.join("config.toml"),
+        "null_value = \"NULL\"\n\n[datasets.people]\npath = \"../data.csv\"\ncolumns = [\"name\", \"city\"]\ndelimiter = \";\"\n",
+    )?;
+
+    // The default config is found through XDG_CONFIG_HOME; the dataset path is relative to it.
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path()).env("XDG_CONFIG_HOME", temp_dir.path());
+    cmd.args(["-f", "data.csv", "--list", "--raw"]);
+    cmd.assert().success().stdout("Ann\tOslo\nNULL\tRome\n");
+
+    // Command-line options override the config.
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path()).env("XDG_CONFIG_HOME", temp_dir.path());
+    cmd.args(["-f", "data.csv", "--list", "--raw", "-c", "id", "--null-value", "-"]);
+    cmd.assert().success().stdout("1\n2\n");
+
+    // Parameters are substituted in the config settings too.
+    std::fs::write(
+        temp_dir.path().join("params.toml"),
+        "null_value = \"${missing}\"\n\n[datasets.people]\npath = \"${file}\"\ncolumns = [\"${col}\"]\ndelimiter = \";\"\n",
+    )?;
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path());
+    cmd.args(["-f", "data.csv", "--list", "--raw", "--config", "params.toml", "--param", "file=data.csv", "--param", "col=name", "--param", "missing=n/a"]);
+    cmd.assert().success().stdout("Ann\nn/a\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path());
+    cmd.args(["-f", "data.csv", "--list", "--config", "missing.toml"]);
+    cmd.assert().code(2).stderr(predicate::str::contains("Could not read 'missing.toml'"));
+    Ok(())
+}
+
+#[test]
+fn test_environment_variable_defaults() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir()).env("CSVPEEK_DATA_FILE", "songs.csv").env("CSVPEEK_RAW", "1");
+    cmd.args(["--list", "--filter", "Year=1968"]);
+    cmd.assert().success().stdout("Hey Jude\n");
+
+    // CSVPEEK_RAW gives way to other output choices instead of conflicting with them.
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir()).env("CSVPEEK_DATA_FILE", "songs.csv").env("CSVPEEK_RAW", "1");
+    cmd.args(["--list", "--filter", "Year=1968", "--format", "csv"]);
+    cmd.assert().success().stdout("Title\nHey Jude\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.env("CSVPEEK_DELIMITER", ";").env("CSVPEEK_RAW", "false");
+    cmd.args(["--list", "-c", "b", "-q", "--no-index"]).write_stdin("a;b\n1;2\n");
+    cmd.assert().success().stdout(predicate::str::ends_with("2\n").and(predicate::str::contains("Number of entries: 1")));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir()).env("CSVPEEK_DATA_FILE", "songs.csv");
+    cmd.args(["batch"]).write_stdin("--filter Year=1968 --raw\n");
+    cmd.assert().success().stdout("==> query 1: --filter Year=1968 --raw\nHey Jude\n<== query 1: ok\n");
+    Ok(())
+}
+
+#[test]
+fn test_subcommands_select_the_mode() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["list", "-f", "songs.csv", "--filter", "Year=1968", "-c", "Title,Artist", "--raw"]);
+    cmd.assert().success().stdout("Hey Jude\tThe Beatles\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["headers", "-f", "songs.csv"]);
+    cmd.assert().success().stdout("Title\nArtist\nAlbum\nYear\nGenre\nRating\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["random", "-f", "songs.csv", "--raw", "-c", "Year"]);
+    cmd.assert().success().stdout(predicate::str::is_match("^\\d{4}\n$")?);
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["stats", "-f", "songs.csv", "--freq", "Genre", "--filter", "Year<1970", "--raw"]);
+    cmd.assert().success().stdout("Genre\tcount\tpercent\nRock\t3\t100.0\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["stats", "-f", "songs.csv", "--profile", "json"]);
+    cmd.assert().success().stdout(predicate::str::contains("\"candidate_key\""));
+
+    // The old flags still work but say what replaces them.
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["-f", "songs.csv", "--list", "--filter", "Year=1968", "--raw"]);
+    cmd.assert().success().stdout("Hey Jude\n")
+        .stderr(LIST_DEPRECATION);
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["-f", "songs.csv", "--headers"]);
+    cmd.assert().success().stderr(predicate::str::contains("--headers is deprecated"));
+    Ok(())
+}
+
+#[test]
+fn test_join_subcommand() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("orders.csv"), "Order,CustomerID,Total\n1,c1,10\n2,c9,20\n3,c2,30\n")?;
+    std::fs::write(temp_dir.path().join("customers.csv"), "ID,Name,Total\nc1,Ann,100\nc2,Bo,200\n")?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path());
+    cmd.args(["-f", "orders.csv", "join", "--with", "customers.csv", "--on", "CustomerID=ID", "-c", "Order,Name,customers.Total", "--raw"]);
+    cmd.assert().success().stdout("1\tAnn\t100\n3\tBo\t200\n");
+
+    let mut left = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    left.current_dir(temp_dir.path());
+    left.args(["-f", "orders.csv", "join", "--with", "customers.csv", "--on", "CustomerID=ID", "--left", "--filter", "Total>15", "-c", "Order,Name"]);
+    left.assert().success()
+        .stdout(predicate::str::contains("List from file 'orders.csv' joined with 'customers.csv' (displaying column(s): Order, Name) filtered where Total > '15'"))
+        .stdout(predicate::str::contains("1. 2\t\n2. 3\tBo\n"));
+
+    let mut missing = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    missing.current_dir(temp_dir.path());
+    missing.args(["-f", "orders.csv", "join", "--with", "customers.csv", "--on", "Customer=ID"]);
+    missing.assert().code(2).stderr(predicate::str::contains("Join column 'Customer' not found"));
+    Ok(())
+}
+
+#[test]
+fn test_list_subcommand_prints_no_deprecation() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["-f", "songs.csv", "list", "--filter", "Genre=Rock", "--filter", "Rating>=5", "-c", "Title,Year", "--raw"]);
+    cmd.assert().success().stdout("Bohemian Rhapsody\t1975\nStairway to Heaven\t1971\n").stderr("");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["-f", "songs.csv", "list", "--filter", "Genre=Pop", "--footer", "count()", "--raw"]);
+    cmd.assert().success().stdout("Imagine\n").stderr("Summary: count() = 1\n");
+    Ok(())
+}
+
+#[test]
+fn test_large_read_draws_no_progress_bar_off_a_terminal() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let mut data = String::from("id,name\n");
+    while data.len() < 17 * 1024 * 1024 {
+        data.push_str("1,abcdefghijklmnopqrstuvwxyz\n");
+    }
+    data.push_str("2,last\n");
+    std::fs::write(temp_dir.path().join("big.csv"), &data)?;
+
+    let mut text = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    text.current_dir(temp_dir.path()).args(["-d", ".", "list", "--filter", "id=2", "-c", "id,name"]);
+    text.assert().success().stdout(predicate::str::contains("last")).stderr("");
+
+    let mut raw = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    raw.current_dir(temp_dir.path()).args(["-f", "big.csv", "list", "--filter", "id=2", "-c", "id,name", "--raw"]);
+    raw.assert().success().stdout("2\tlast\n").stderr("");
+    Ok(())
+}
+
+#[test]
+fn test_raw_footer_summary_follows_log_options() -> Result<(), Box<dyn Error>> {
+    let mut json = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    json.current_dir(fixtures_dir());
+    json.args(["-f", "songs.csv", "list", "--filter", "Genre=Pop", "--footer", "count()", "--raw", "--log-format", "json"]);
+    let output = json.assert().success().stdout("Imagine\n").get_output().stderr.clone();
+    let line: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(line["level"], "info");
+    assert_eq!(line["message"], "Summary: count() = 1");
+
+    let mut quiet = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    quiet.current_dir(fixtures_dir());
+    quiet.args(["-f", "songs.csv", "list", "--filter", "Genre=Pop", "--footer", "count()", "--raw", "--log-level", "error"]);
+    quiet.assert().success().stdout("Imagine\n").stderr("");
+    Ok(())
+}
+
+#[test]
+fn test_subcommands_reject_options_of_other_modes() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["random", "-f", "songs.csv", "--footer", "count()"]);
+    cmd.assert().code(2).stderr(predicate::str::contains("'--footer' can only be used with the list subcommand"));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["headers", "-f", "songs.csv", "--filter", "Year=1968"]);
+    cmd.assert().code(2).stderr(predicate::str::contains("'--filter' cannot be used with 'headers'"));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["batch", "-f", "songs.csv", "--raw"]);
+    cmd.assert().code(2).stderr(predicate::str::contains("'--raw' cannot be used with 'batch'"));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["stats", "-f", "songs.csv"]);
+    cmd.assert().code(2).stderr(predicate::str::contains("the stats subcommand needs one of '--profile', '--freq'"));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["stats", "-f", "songs.csv", "--profile", "--freq", "Genre"]);
+    cmd.assert().code(2).stderr(predicate::str::contains("'--profile' cannot be used with '--freq'"));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["list", "-f", "songs.csv", "--profile"]);
+    cmd.assert().code(2).stderr(predicate::str::contains("'--profile' cannot be used with 'list'"));
+    Ok(())
+}
+
+#[test]
+fn test_mmap_reads_the_same_records() -> Result<(), Box<dyn Error>> {
+    for mode in ["always", "never"] {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+        cmd.current_dir(fixtures_dir());
+        cmd.args(["list", "-f", "songs.csv", "--filter", "Year<1970", "-c", "Title,Year", "--raw", "--mmap", mode]);
+        cmd.assert().success().stdout("Hey Jude\t1968\nLike a Rolling Stone\t1965\nWaterloo Sunset\t1967\n");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_large_inputs_keep_row_order() -> Result<(), Box<dyn Error>> {
+    // Enough rows to filter and project in parallel.
+    let input: String = std::iter::once("id,parity\n".to_string())
+        .chain((0..30_000).map(|i| format!("{},{}\n", i, if i % 2 == 0 { "even" } else { "odd" })))
+        .collect();
+    let expected: String = (0..30_000).filter(|i| i % 2 == 1).map(|i| format!("{}\n", i)).collect();
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--filter", "parity=odd", "--raw"]).write_stdin(input);
+    cmd.assert().success().stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn test_index_build_and_indexed_filter() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::copy(fixtures_dir().join("songs.csv"), temp_dir.path().join("songs.csv"))?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path());
+    cmd.args(["index", "build", "-f", "songs.csv", "--on", "Artist"]);
+    cmd.assert().success().stdout(
+        "Indexed column 'Artist' of songs.csv: 8 distinct value(s) in 8 row(s).\n\
+         Wrote index songs.csv.csvpeek-index\n",
+    );
+    assert!(temp_dir.path().join("songs.csv.csvpeek-index").is_file());
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path());
+    cmd.args(["list", "-f", "songs.csv", "--filter", "artist=QUEEN", "-c", "Title,Year", "--raw", "-v"]);
+    cmd.assert().success()
+        .stdout("Bohemian Rhapsody\t1975\n")
+        .stderr(predicate::str::contains("Verbose: Using index 'songs.csv.csvpeek-index' to read 1 candidate row(s)."));
+
+    // Once the file changes the index is ignored until it is rebuilt.
+    let mut file = std::fs::OpenOptions::new().append(true).open(temp_dir.path().join("songs.csv"))?;
+    writeln!(file, "Under Pressure,Queen,Hot Space,1982,Rock,5")?;
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path());
+    cmd.args(["list", "-f", "songs.csv", "--filter", "Artist=Queen", "--raw"]);
+    cmd.assert().success()
+        .stdout("Bohemian Rhapsody\nUnder Pressure\n")
+        .stderr(predicate::str::contains("Warning: Ignoring out-of-date index 'songs.csv.csvpeek-index'"));
+    Ok(())
+}
+
+#[test]
+fn test_unused_columns_are_not_decoded() -> Result<(), Box<dyn Error>> {
+    // Only the displayed and filtered columns are copied out, so bad bytes elsewhere are ignored.
+    let input: &[u8] = b"id,name,blob\n1,Ada,\xff\xfe\n2,Bob,\xff\n";
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "-c", "name", "--filter", "id=2", "--raw"]).write_stdin(input);
+    cmd.assert().success().stdout("Bob\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "-c", "blob", "--raw"]).write_stdin(input);
+    cmd.assert().failure().stderr(predicate::str::contains("invalid UTF-8"));
+    Ok(())
+}
+
+#[test]
+fn test_max_memory_spills_matches_to_disk() -> Result<(), Box<dyn Error>> {
+    let input: String = std::iter::once("id,parity\n".to_string())
+        .chain((0..2_000).map(|i| format!("{},{}\n", i, if i % 2 == 0 { "even" } else { "odd" })))
+        .collect();
+    let expected: String = (0..2_000).filter(|i| i % 2 == 1).map(|i| format!("{}\n", i)).collect();
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--filter", "parity=odd", "--raw", "--max-memory", "4K"]).write_stdin(input.clone());
+    cmd.assert().success().stdout(expected);
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--filter", "parity=odd", "-c", "id,parity", "--format", "csv", "--max-memory", "4K"]).write_stdin(input.clone());
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let text = String::from_utf8(output)?;
+    assert_eq!(text.lines().count(), 1_001);
+    assert!(text.starts_with("id,parity\n1,odd\n3,odd\n"));
+    assert!(text.ends_with("1999,odd\n"));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--filter", "parity=odd", "--max-memory", "4K"]).write_stdin(input.clone());
+    cmd.assert().failure().code(2).stderr(predicate::str::contains("The 1000 matching rows need more than --max-memory 4K"));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--filter", "id=7", "--raw", "--max-memory", "1M"]).write_stdin(input);
+    cmd.assert().success().stdout("7\n");
+    Ok(())
+}
+
+#[test]
+fn test_max_memory_guards_merged_inputs() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "-d"]).arg(fixtures_dir().join("merge")).args(["--max-memory", "16"]);
+    cmd.assert().failure().code(2).stderr(predicate::str::contains("needs more than --max-memory 16 bytes"));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "-f", "-", "--max-memory", "lots"]);
+    cmd.assert().failure().stderr(predicate::str::contains("Invalid size 'lots'"));
+    Ok(())
+}
+
+#[test]
+fn test_count_rows() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.arg("-f").arg(fixtures_dir().join("songs.csv")).arg("--count-rows");
+    cmd.assert().success().stdout("8\n");
+
+    // Quoted newlines do not start rows, and skipped ragged rows are not counted.
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["--count-rows", "--skip-bad-rows"]).write_stdin("a,b\n1,\"x\ny\"\n2\n3,z\n");
+    cmd.assert().success().stdout("2\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["--count-rows", "--filter", "a=1"]).write_stdin("a\n1\n");
+    cmd.assert().failure().stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+#[test]
+fn test_vertical_flag() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["list", "-f", "songs.csv", "--filter", "Year<1966", "-c", "Title,Year", "--vertical", "--no-index"]);
+    cmd.assert().success().stdout(predicate::str::contains(
+        "*************************************************************\n\
+         Title: Like a Rolling Stone\n\
+         \x20Year: 1965\n",
+    ));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--vertical", "--layout", "table"]).write_stdin("a\n1\n");
+    cmd.assert().failure().stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+#[test]
+fn test_crosstab() -> Result<(), Box<dyn Error>> {
+    let input = "Region,Status,Total\nEU,open,5\nUS,paid,7\nEU,paid,1\nEU,open,2\n";
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--crosstab", "region,status"]).write_stdin(input);
+    cmd.assert().success().stdout(
+        "No input file specified, reading CSV data from piped stdin...\n\
+         Crosstab of Region by Status (count()) from stdin\n\
+         Region  open  paid\n\
+         EU         2     1\n\
+         US         0     1\n",
+    );
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--crosstab", "Region,Status", "--value", "Total", "--format", "csv"]).write_stdin(input);
+    cmd.assert().success().stdout("Region,open,paid\nEU,7,1\nUS,-,7\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--crosstab", "Region,Status", "--agg", "avg"]).write_stdin(input);
+    cmd.assert().failure().stderr(predicate::str::contains("--value"));
+    Ok(())
+}
+
+#[test]
+fn test_freq_one_and_two_columns() -> Result<(), Box<dyn Error>> {
+    let input = "Region,Status\nEU,open\nUS,paid\nEU,paid\nEU,open\n";
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--freq", "region,status"]).write_stdin(input);
+    cmd.assert().success().stdout(
+        "No input file specified, reading CSV data from piped stdin...\n\
+         Frequencies of Region by Status (count, row % / column %) from stdin\n\
+         Region                open                paid      Total\n\
+         EU      2 (66.7% / 100.0%)   1 (33.3% / 50.0%)  3 (75.0%)\n\
+         US         0 (0.0% / 0.0%)  1 (100.0% / 50.0%)  1 (25.0%)\n\
+         Total            2 (50.0%)           2 (50.0%)          4\n",
+    );
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--freq", "Region,Status", "--filter", "Region=EU", "--format", "csv"]).write_stdin(input);
+    cmd.assert().success().stdout("Region,Status,count,row_percent,column_percent\nEU,open,2,66.7,100.0\nEU,paid,1,33.3,100.0\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--freq", "Status", "--raw"]).write_stdin(input);
+    cmd.assert().success().stdout("Status\tcount\tpercent\nopen\t2\t50.0\npaid\t2\t50.0\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--freq", "Region,Status", "--crosstab", "Region,Status"]).write_stdin(input);
+    cmd.assert().failure().stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+#[test]
+fn test_melt_wide_table() -> Result<(), Box<dyn Error>> {
+    let input = "ID,Name,Jan,Feb\n1,Ada,10,12\n2,Bob,3,7\n";
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--melt", "--id-columns", "ID,Name", "-c", "Name,variable,value", "--format", "csv"]).write_stdin(input);
+    cmd.assert().success().stdout("Name,variable,value\nAda,Jan,10\nAda,Feb,12\nBob,Jan,3\nBob,Feb,7\n");
+
+    // Filters and limits apply to the melted rows.
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--melt", "--id-columns", "name", "--filter", "variable=Feb", "-c", "Name,value", "--first", "--raw"]).write_stdin(input);
+    cmd.assert().success().stdout("Ada\t12\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--id-columns", "ID"]).write_stdin(input);
+    cmd.assert().failure().stderr(predicate::str::contains("--melt"));
+    Ok(())
+}
+
+#[test]
+fn test_explode_multi_value_cells() -> Result<(), Box<dyn Error>> {
+    let input = "Title,Tags\nA,rock;live\nB,\nC,jazz; rock\n";
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--explode", "Tags", "--filter", "Tags=rock", "--raw"]).write_stdin(input);
+    cmd.assert().success().stdout("A\nC\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--explode", "tags", "--split-on", "|", "-c", "Tags", "--raw"]).write_stdin("Title,Tags\nA,x|y\n");
+    cmd.assert().success().stdout("x\ny\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--explode", "Tags", "--split-on", "", "--raw"]).write_stdin("Title,Tags\nA,ab\n");
+    cmd.assert().code(2).stdout("").stderr(predicate::str::contains("The --explode separator cannot be empty."));
+    Ok(())
+}
+
+#[test]
+fn test_concat_builds_new_column() -> Result<(), Box<dyn Error>> {
+    let input = "First,Last\nAda,Lovelace\nAlan,Turing\n";
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--concat", "FullName=First+' '+Last", "--filter", "fullname=Alan Turing", "-c", "FullName", "--raw"]).write_stdin(input);
+    cmd.assert().success().stdout("Alan Turing\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--concat", "Key=Middle+Last"]).write_stdin(input);
+    cmd.assert().failure().stderr(predicate::str::contains("Concat column 'Middle' not found"));
+    Ok(())
+}
+
+#[test]
+fn test_cumulative_running_total() -> Result<(), Box<dyn Error>> {
+    let input = "Account,Date,Amount\nA,2024-01-03,10.50\nB,2024-01-01,7\nA,2024-01-01,-2.25\nA,2024-01-02,\n";
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--cumulative", "Balance=sum(Amount)", "-c", "Account,Balance", "--format", "csv"]).write_stdin(input);
+    cmd.assert().success().stdout("Account,Balance\nA,10.50\nB,17.50\nA,15.25\nA,15.25\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--cumulative", "Balance=sum(Amount)", "--partition-by", "account", "--order-by", "date", "-c", "Date,Balance", "--filter", "Account=A", "--raw"])
+        .write_stdin(input);
+    cmd.assert().success().stdout("2024-01-03\t8.25\n2024-01-01\t-2.25\n2024-01-02\t-2.25\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--cumulative", "Balance=avg(Amount)"]).write_stdin(input);
+    cmd.assert().failure().stderr(predicate::str::contains("Expected NAME=sum(COLUMN)"));
+    Ok(())
+}
+
+#[test]
+fn test_rolling_average() -> Result<(), Box<dyn Error>> {
+    let input = "Date,Site,Visits\n2024-01-03,a,30\n2024-01-01,a,10\n2024-01-02,a,20\n2024-01-01,b,5\n2024-01-04,a,n/a\n";
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--rolling", "Avg2=avg(Visits,2)", "--rolling", "Sum3=sum(Visits,3)", "--order-by", "date", "--partition-by", "site", "-c", "Date,Site,Avg2,Sum3", "--format", "csv"])
+        .write_stdin(input);
+    cmd.assert().success().stdout(
+        "Date,Site,Avg2,Sum3\n2024-01-03,a,25.00,60\n2024-01-01,a,,\n2024-01-02,a,15.00,\n2024-01-01,b,,\n2024-01-04,a,30.00,50\n",
+    );
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--order-by", "Date"]).write_stdin(input);
+    cmd.assert().failure().stderr(predicate::str::contains("'--order-by' can only be used with '--cumulative' or '--rolling'"));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--rolling", "Avg=median(Visits,2)"]).write_stdin(input);
+    cmd.assert().failure().stderr(predicate::str::contains("Expected NAME=FUNC(COLUMN,N)"));
+    Ok(())
+}
+
+#[test]
+fn test_percent_of_total() -> Result<(), Box<dyn Error>> {
+    let input = "Region,Customer,Revenue\nEU,a,50\nUS,b,30\nEU,c,15\nUS,d,5\nEU,e,n/a\n";
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "-c", "Customer", "--percent-of-total", "revenue", "--filter", "Region=EU", "--format", "csv"]).write_stdin(input);
+    cmd.assert().success().stdout("Customer,% of Revenue\na,76.9\nc,23.1\ne,\n");
+
+    // The share is of every matching row, not only of the ones shown.
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "-c", "Customer", "--percent-of-total", "Revenue", "--max-results", "1", "--percent-precision", "2", "--raw"]).write_stdin(input);
+    cmd.assert().success().stdout("a\t50.00\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--group-by", "Region", "--percent-of-total", "Revenue", "--format", "csv"]).write_stdin(input);
+    cmd.assert().success().stdout("Region,count(),% of Revenue\nEU,3,65.0\nUS,2,35.0\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["--percent-of-total", "Revenue"]).write_stdin(input);
+    cmd.assert().failure().stderr(predicate::str::contains("'--percent-of-total' can only be used with the list subcommand"));
+    Ok(())
+}
+
+#[test]
+fn test_round_and_thousands_separator() -> Result<(), Box<dyn Error>> {
+    let input = "Item,Price,Qty\nA,1234.5,3\nB,2.675,1200\nC,n/a,7\n";
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "-c", "Item,Price,Qty", "--round", "price:2", "--thousands-separator", "--filter", "Item!=C"]).write_stdin(input);
+    cmd.assert().success().stdout(predicate::str::ends_with("1. A\t1,234.50\t3\n2. B\t2.68\t1,200\n"));
+
+    // Machine-readable output keeps the exact values unless asked otherwise.
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "-c", "Item,Price", "--round", "Price:0", "--format", "csv"]).write_stdin(input);
+    cmd.assert().success().stdout("Item,Price\nA,1234.5\nB,2.675\nC,n/a\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "-c", "Item,Price", "--round", "Price:0", "--thousands-separator", " ", "--format-numbers-everywhere", "--raw"]).write_stdin(input);
+    cmd.assert().success().stdout("A\t1 235\nB\t3\nC\tn/a\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--format-numbers-everywhere"]).write_stdin(input);
+    cmd.assert().failure().stderr(predicate::str::contains("can only be used with '--round' or '--thousands-separator'"));
+    Ok(())
+}
+
+#[test]
+fn test_scientific_notation_in_filters_and_aggregates() -> Result<(), Box<dyn Error>> {
+    let input = "Id,Amount\n1,1.2e9\n2,500\n3,n/a\n4,2.5E3\n";
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--filter", "Amount>1e3", "--footer", "sum(Amount)"]).write_stdin(input);
+    cmd.assert().success()
+        .stdout(predicate::str::contains("1. 1\n2. 3\n3. 4\n"))
+        .stdout(predicate::str::contains("Summary: sum(Amount) = 1200002500"))
+        .stderr(predicate::str::contains("Column 'Amount' mixes numbers and text: 1 cell(s) such as 'n/a' are compared as text by the filter Amount > '1e3'."));
+
+    // --quiet keeps warnings; only the log level hides them.
+    let mut quiet = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    quiet.args(["list", "--filter", "Amount>1e3", "--quiet"]).write_stdin(input);
+    quiet.assert().success().stderr(predicate::str::contains("mixes numbers"));
+
+    let mut errors_only = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    errors_only.args(["list", "--filter", "Amount>1e3", "--log-level", "error"]).write_stdin(input);
+    errors_only.assert().success().stderr(predicate::str::contains("mixes numbers").not());
+    Ok(())
+}
+
+#[test]
+fn test_decimal_comma() -> Result<(), Box<dyn Error>> {
+    let input = "Item;Price\nA;3,14\nB;1.234,56\nC;12,5\nD;-0,5\n";
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--delimiter", ";", "--decimal-comma", "--filter", "Price>10", "--footer", "sum(Price)", "-c", "Item"]).write_stdin(input);
+    cmd.assert().success()
+        .stdout(predicate::str::contains("1. B\n2. C\n"))
+        .stdout(predicate::str::contains("Summary: sum(Price) = 1247.06"))
+        .stderr(predicate::str::contains("mixes numbers").not());
+
+    let mut sorted = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    sorted.args(["list", "--delimiter", ";", "--decimal-comma", "--top", "3", "--by", "Price", "-c", "Item", "--raw"]).write_stdin(input);
+    sorted.assert().success().stdout("B\nC\nA\n");
+
+    // Without the flag the same cells are text.
+    let mut plain = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    plain.args(["list", "--delimiter", ";", "--top", "3", "--by", "Price", "-c", "Item", "--raw"]).write_stdin(input);
+    plain.assert().success().stdout("A\nC\nB\n");
+
+    // Assertions compare the sums as numbers, not as their printed form.
+    let mut asserted = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    asserted.args(["list", "--delimiter", ";", "--decimal-comma", "--assert", "sum(Price)>200", "--assert", "sum(Price)<1.250", "--raw"])
+        .write_stdin(input);
+    asserted.assert().success();
+
+    let mut failing = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    failing.args(["list", "--delimiter", ";", "--decimal-comma", "--assert", "sum(Price)<200", "--raw"])
+        .write_stdin("Item;Price\nA;123.456\n");
+    failing.assert().code(2).stderr(predicate::str::contains("sum(Price) < 200 (got 123456)"));
+    Ok(())
+}
+
+#[test]
+fn test_with_row_number() -> Result<(), Box<dyn Error>> {
+    // Comment lines are not records, and numbers are counted before filtering.
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--with-row-number", "--comment-char", "#", "--filter", "b>1", "--raw"]).write_stdin("a,b\n# note\nx,1\ny,2\nz,3\n");
+    cmd.assert().success().stdout("2\ty\n3\tz\n");
+
+    // Each merged file is numbered on its own.
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(fixtures_dir());
+    cmd.args(["list", "-d", "merge", "--with-row-number", "line", "-c", "Title", "--raw"]);
+    cmd.assert().success().stdout("1\tHey Jude\n1\tWonderwall\n");
+    Ok(())
+}
+
+#[test]
+fn test_split_rows_writes_numbered_files() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path());
+    cmd.args(["list", "--filter", "n>1", "-c", "n", "--split-rows", "2", "--output-prefix", "out_"]).write_stdin("n\n1\n2\n3\n4\n5\n");
+    cmd.assert().success().stdout(
+        "No input file specified, reading CSV data from piped stdin...\n\
+         Wrote 2 row(s) to out_0001.csv\n\
+         Wrote 2 row(s) to out_0002.csv\n",
+    );
+    assert_eq!(std::fs::read_to_string(temp_dir.path().join("out_0001.csv"))?, "n\n2\n3\n");
+    assert_eq!(std::fs::read_to_string(temp_dir.path().join("out_0002.csv"))?, "n\n4\n5\n");
+    assert!(!temp_dir.path().join("out_0003.csv").exists());
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "--split-rows", "0"]).write_stdin("n\n1\n");
+    cmd.assert().failure();
+    Ok(())
+}
+
+#[test]
+fn test_split_by_writes_one_file_per_value() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path());
+    cmd.args(["list", "-c", "Name", "--split-by", "country", "--output-dir", "out", "-q"])
+        .write_stdin("Name,Country\nAda,UK\nBob,US\nCem,UK\nDan,\nEve,U/S\n");
+    cmd.assert().success().stdout("");
+    let read = |name: &str| std::fs::read_to_string(temp_dir.path().join("out").join(name));
+    assert_eq!(read("UK.csv")?, "Name\nAda\nCem\n");
+    assert_eq!(read("US.csv")?, "Name\nBob\n");
+    assert_eq!(read("empty.csv")?, "Name\nDan\n");
+    assert_eq!(read("U_S.csv")?, "Name\nEve\n");
+    Ok(())
+}
+
+#[test]
+fn test_excel_input_reads_chosen_sheet() -> Result<(), Box<dyn Error>> {
+    let mut first = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    first.args(["-f", "tests/fixtures/report.xlsx", "--list", "-c", "Region,Sales,Closed", "--format", "csv"]);
+    first.assert().success().stdout("Region,Sales,Closed\nNorth,120,2024-04-01\nSouth,80.5,2024-04-08\n");
+
+    let mut named = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    named.args(["-f", "tests/fixtures/report.xlsx", "--sheet", "Q3", "--list", "-c", "Region,Closed", "--filter", "Sales>90", "--raw"]);
+    named.assert().success().stdout("North\t2024-07-01\nSouth\t\n");
+
+    let mut missing = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    missing.args(["-f", "tests/fixtures/report.xlsx", "--sheet", "Q4", "--list"]);
+    missing.assert().failure().code(2).stderr(predicate::str::contains("Sheet 'Q4' not found").and(predicate::str::contains("Available sheets: Q2, Q3")));
+    Ok(())
+}
+
+#[test]
+fn test_xlsx_output_round_trips() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let mut write = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    write.current_dir(temp_dir.path());
+    write.args(["list", "-c", "Zip,Sales", "--filter", "Sales>10", "--format", "xlsx", "--output", "out.xlsx"])
+        .write_stdin("Zip,Sales\n007,12.5\n010,3\n042,40\n");
+    write.assert().success().stdout("Wrote 2 row(s) to out.xlsx\n");
+
+    let mut read = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    read.current_dir(temp_dir.path());
+    read.args(["-f", "out.xlsx", "--list", "-c", "Zip,Sales", "--format", "csv"]);
+    read.assert().success().stdout("Zip,Sales\n007,12.5\n042,40\n");
+
+    let mut missing = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    missing.args(["list", "--format", "xlsx"]).write_stdin("n\n1\n");
+    missing.assert().failure().stderr(predicate::str::contains("--output <PATH>"));
+    Ok(())
+}
+
+#[test]
+fn test_to_sqlite_writes_typed_table() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let db = temp_dir.path().join("results.db");
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["-f", "tests/fixtures/songs.csv", "--list", "-c", "Title,Year", "--filter", "Year<1970", "--to-sqlite"]).arg(&db).args(["--table", "sixties"]);
+    cmd.assert().success().stdout(predicate::str::contains("Wrote 3 row(s) to table 'sixties' in"));
+
+    let connection = rusqlite::Connection::open(&db)?;
+    let declared: Vec<String> = connection.prepare("SELECT type FROM pragma_table_info('sixties')")?
+        .query_map([], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+    assert_eq!(declared, vec!["TEXT", "INTEGER"]);
+    let oldest: (String, i64) = connection.query_row("SELECT Title, Year FROM sixties ORDER BY Year LIMIT 1", [], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    assert_eq!(oldest, ("Like a Rolling Stone".to_string(), 1965));
+    Ok(())
+}
+
+#[test]
+fn test_sqlite_input_reads_table_or_query() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let db = temp_dir.path().join("shop.db");
+    let connection = rusqlite::Connection::open(&db)?;
+    connection.execute_batch(
+        "CREATE TABLE orders (id INTEGER, customer TEXT, total REAL);
+         INSERT INTO orders VALUES (1, 'Ada', 120.5), (2, 'Bo', 40), (3, 'Ada', 99);
+         CREATE TABLE customers (name TEXT);",
+    )?;
+
+    let mut table = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    table.arg("-f").arg(&db).args(["--table", "orders", "--list", "-c", "id,total", "--filter", "customer=Ada", "--raw"]);
+    table.assert().success().stdout("1\t120.5\n3\t99\n");
+
+    let mut query = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    query.arg("-f").arg(&db).args(["--sqlite-query", "SELECT customer, SUM(total) AS spent FROM orders GROUP BY customer", "--list", "-c", "customer,spent", "--format", "csv"]);
+    query.assert().success().stdout("customer,spent\nAda,219.5\nBo,40\n");
+
+    let mut ambiguous = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    ambiguous.arg("-f").arg(&db).arg("--list");
+    ambiguous.assert().failure().code(2).stderr(predicate::str::contains("Tables: customers, orders"));
+    Ok(())
+}
+
+#[test]
+fn test_json_lines_input() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("app.ndjson"),
+        "{\"level\":\"info\",\"msg\":\"started\"}\n{\"level\":\"error\",\"msg\":\"db down\",\"code\":503}\n{\"level\":\"error\",\"msg\":\"retry\"}\n")?;
+    std::fs::write(temp_dir.path().join("people.json"), "[{\"name\":\"Ada\",\"age\":36},{\"name\":\"Bo\"}]")?;
+
+    let mut lines = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    lines.current_dir(temp_dir.path());
+    lines.args(["-f", "app.ndjson", "--list", "-c", "msg,code", "--filter", "level=error", "--raw"]);
+    lines.assert().success().stdout("db down\t503\nretry\t\n");
+
+    let mut array = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    array.current_dir(temp_dir.path());
+    array.args(["-f", "people.json", "--headers"]);
+    array.assert().success().stdout(predicate::str::contains("name").and(predicate::str::contains("age")));
+    Ok(())
+}
+
+#[test]
+fn test_sql_insert_output() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["-f", "tests/fixtures/songs.csv", "--list", "-c", "Title,Year", "--filter", "Year<1970", "--format", "sql", "--table", "songs", "--rows-per-insert", "2"]);
+    cmd.assert().success().stdout(
+        "INSERT INTO \"songs\" (\"Title\", \"Year\") VALUES\n  ('Hey Jude', 1968),\n  ('Like a Rolling Stone', 1965);\n\
+         INSERT INTO \"songs\" (\"Title\", \"Year\") VALUES ('Waterloo Sunset', 1967);\n",
+    );
+
+    let mut escaped = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    escaped.args(["list", "-c", "Name,Note", "--format", "sql"]).write_stdin("Name,Note\nO'Brien,\n");
+    escaped.assert().success().stdout("INSERT INTO \"peek\" (\"Name\", \"Note\") VALUES ('O''Brien', NULL);\n");
+    Ok(())
+}
+
+#[test]
+fn test_latex_output() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "-c", "Item,Share", "--format", "latex"]).write_stdin("Item,Share\nR&D,40\nTools_2,5.5\n");
+    cmd.assert().success().stdout(
+        "\\begin{tabular}{lr}\n\\hline\nItem & Share \\\\\n\\hline\nR\\&D & 40 \\\\\nTools\\_2 & 5.5 \\\\\n\\hline\n\\end{tabular}\n",
+    );
+    Ok(())
+}
+
+#[test]
+fn test_detailed_headers() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["headers", "--detailed"]).write_stdin("City,Pop\nOslo,700\nOslo,\nBergen,290\n");
+    cmd.assert().success().stdout(
+        "#  Column  Type     Non-empty  Distinct  Examples\n\
+         1  City    string           3         2  Oslo, Bergen\n\
+         2  Pop     integer          2         2  700, 290\n",
+    );
+
+    let mut list = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    list.args(["list", "--detailed"]).write_stdin("City\nOslo\n");
+    list.assert().failure().stderr(predicate::str::contains("'--detailed' can only be used with the headers subcommand"));
+    Ok(())
+}
+
+#[test]
+fn test_headers_in_machine_formats() -> Result<(), Box<dyn Error>> {
+    let mut json = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    json.args(["headers", "--format", "json"]).write_stdin("City,Pop\nOslo,700\n");
+    json.assert().success().stdout("[\n  \"City\",\n  \"Pop\"\n]\n");
+
+    let mut csv = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    csv.args(["headers", "--detailed", "--format", "csv"]).write_stdin("City,Pop\nOslo,700\nBergen,290\n");
+    csv.assert().success().stdout("index,name,type,non_empty,distinct,examples\n1,City,string,2,2,Oslo|Bergen\n2,Pop,integer,2,2,700|290\n");
+
+    let mut detailed = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    detailed.args(["headers", "--detailed", "--format", "json"]).write_stdin("City\nOslo\n");
+    let report: serde_json::Value = serde_json::from_slice(&detailed.output()?.stdout)?;
+    assert_eq!(report[0]["type"], "string");
+    assert_eq!(report[0]["examples"], serde_json::json!(["Oslo"]));
+
+    let mut list = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    list.args(["list", "--format", "json"]).write_stdin("City\nOslo\n");
+    list.assert().code(2).stderr(predicate::str::contains("'--format json' can only be used with the headers subcommand"));
+    Ok(())
+}
+
+#[test]
+fn test_profile_report() -> Result<(), Box<dyn Error>> {
+    let data = "Id,Country,Note\n1,SE,\n2,SE,late\n3,SE,\n4,SE,\n";
+    let mut text = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    text.arg("--profile").write_stdin(data);
+    text.assert().success().stdout(
+        "Profile of stdin (4 records):\n\
+         Column   Distinct  Empty  Empty %  Shape     Flags\n\
+         Id              4      0     0.0%  █ █  █ █  candidate key\n\
+         Country         1      0     0.0%            constant\n\
+         Note            1      3    75.0%            constant\n",
+    );
+
+    let mut json = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    json.args(["--profile", "json"]).write_stdin(data);
+    let report: serde_json::Value = serde_json::from_slice(&json.output()?.stdout)?;
+    assert_eq!(report["records"], 4);
+    assert_eq!(report["columns"][2]["empty_percent"], 75.0);
+    assert_eq!(report["columns"][0]["candidate_key"], true);
+    Ok(())
+}
+
+#[test]
+fn test_profile_sparklines() -> Result<(), Box<dyn Error>> {
+    let data = "Price,OrderDate\n10,2024-01-03\n12,2024-01-20\n11,2024-03-02\n90,2024-03-09\n,soon\n";
+    let mut text = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    text.args(["--profile", "--date-column", "orderdate", "--quiet"]).write_stdin(data);
+    text.assert().success()
+        .stdout(predicate::str::contains("Price             4      1    20.0%  █      ▃\n"))
+        .stdout(predicate::str::contains("Rows per week of OrderDate from 2024-01-01 to 2024-03-04: █ █     ██\n"))
+        .stderr(predicate::str::contains("Skipped 1 row(s) where OrderDate is not a date."));
+
+    let mut json = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    json.args(["--profile", "json", "--date-column", "OrderDate"]).write_stdin(data);
+    let report: serde_json::Value = serde_json::from_slice(&json.output()?.stdout)?;
+    assert_eq!(report["columns"][0]["histogram"], serde_json::json!([3, 0, 0, 0, 0, 0, 0, 1]));
+    assert_eq!(report["columns"][1]["histogram"], serde_json::Value::Null);
+    assert_eq!(report["timeline"]["unit"], "week");
+    assert_eq!(report["timeline"]["counts"], serde_json::json!([1, 0, 1, 0, 0, 0, 0, 0, 1, 1]));
+
+    let mut since = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    since.args(["--profile", "--date-column", "OrderDate", "--since", "2024-02-01"]).write_stdin(data);
+    since.assert().code(2).stderr(predicate::str::contains("'--since' cannot be used with '--profile'"));
+    Ok(())
+}
+
+#[test]
+fn test_corr_between_numeric_columns() -> Result<(), Box<dyn Error>> {
+    let data = "Size,Price,Rooms,Note\n50,100,2,a\n70,150,3,b\n90,190,3,c\n110,n/a,4,d\n,300,5,e\n";
+    let mut pair = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    pair.args(["list", "--corr", "Size,Price", "--corr-method", "spearman"]).write_stdin(data);
+    pair.assert().success().stdout(
+        "No input file specified, reading CSV data from piped stdin...\n\
+         Spearman correlation of Size and Price in stdin: 1.0000 (3 row(s))\n\
+         Skipped 2 row(s) where either cell is empty or not a number.\n",
+    );
+
+    let mut all = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    all.args(["list", "--corr", "all", "--filter", "Rooms<5", "--format", "csv"]).write_stdin(data);
+    // Price holds "n/a", so only Size and Rooms are inferred as numeric.
+    all.assert().success().stdout("x,y,coefficient,rows\nSize,Rooms,0.9487,4\n");
+
+    let mut text = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    text.args(["list", "--corr", "Note,Size"]).write_stdin(data);
+    text.assert().success().stdout(predicate::str::contains("Pearson correlation of Note and Size in stdin: undefined (0 row(s))"));
+    Ok(())
+}
+
+#[test]
+fn test_top_and_bottom_rows_by_column() -> Result<(), Box<dyn Error>> {
+    let data = "Name,Score\nana,7\nbob,\nceline,12\ndan,7\neve,3\n";
+    let mut top = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    top.args(["list", "--top", "2", "--by", "score", "--columns", "Name,Score"]).write_stdin(data);
+    top.assert().success().stdout(
+        "No input file specified, reading CSV data from piped stdin...\n\
+         List from stdin (displaying column(s): Name, Score), top 2 by score\n\
+         Number of entries: 2\n\
+         1. celine\t12\n\
+         2. ana\t7\n",
+    );
+
+    let mut bottom = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    bottom.args(["list", "--bottom", "3", "--by", "Score", "--filter", "Name!=eve", "--raw"]).write_stdin(data);
+    bottom.assert().success().stdout("ana\ndan\nceline\n");
+
+    let mut file = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    file.args(["list", "-f", "tests/fixtures/songs.csv", "--top", "1", "--by", "Year", "--format", "csv", "--columns", "Title"]);
+    file.assert().success().stdout("Title\nWonderwall\n");
+
+    let mut by_alone = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    by_alone.args(["list", "-f", "tests/fixtures/songs.csv", "--by", "Year"]);
+    by_alone.assert().failure().stderr(predicate::str::contains("'--by <COLUMN>' can only be used with '--top' or '--bottom'"));
+    Ok(())
+}
+
+#[test]
+fn test_group_by_date_period() -> Result<(), Box<dyn Error>> {
+    let data = "OrderDate,Region,Total\n2024-01-15,EU,100\n2024-01-31T09:00:00,US,50\n2024-02-02,EU,25.5\nnot yet,EU,10\n";
+    let mut monthly = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    monthly.args(["list", "--group-by", "date_trunc(month, OrderDate)", "--value", "Total"]).write_stdin(data);
+    monthly.assert().success()
+        .stdout(
+            "No input file specified, reading CSV data from piped stdin...\n\
+             Groups of stdin by date_trunc(month, OrderDate)\n\
+             month(OrderDate)  count()  sum(Total)\n\
+             2024-01-01              2         150\n\
+             2024-02-01              1        25.5\n",
+        )
+        .stderr("Warning: Skipped 1 row(s) where OrderDate is not a date.\n");
+
+    // --quiet only drops the informational messages.
+    let mut quiet = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    quiet.args(["list", "--group-by", "date_trunc(month, OrderDate)", "--quiet"]).write_stdin(data);
+    quiet.assert().success().stderr("Warning: Skipped 1 row(s) where OrderDate is not a date.\n");
+
+    let mut yearly = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    yearly.args(["list", "--group-by", "date_trunc(year, d)", "--date-format", "%d/%m/%Y", "--format", "csv"])
+        .write_stdin("d,x\n31/12/2023,1\n01/02/2024,2\n29/02/2024,3\n");
+    yearly.assert().success().stdout("year(d),count()\n2023-01-01,1\n2024-01-01,2\n");
+
+    let mut by_region = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    by_region.args(["list", "--group-by", "Region", "--value", "Total", "--agg", "max", "--raw"]).write_stdin(data);
+    by_region.assert().success().stdout("Region\tcount()\tmax(Total)\nEU\t3\t100\nUS\t1\t50\n");
+
+    let mut value_alone = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    value_alone.args(["list", "--value", "Total"]).write_stdin(data);
+    value_alone.assert().failure().stderr(predicate::str::contains("'--value <COLUMN>' can only be used with '--crosstab' or '--group-by'"));
+    Ok(())
+}
+
+#[test]
+fn test_since_and_until_on_date_column() -> Result<(), Box<dyn Error>> {
+    let data = "OrderDate,Total\n2024-01-15,100\n2024-01-31T09:00:00,50\n2024-04-02,25\nnot yet,10\n2999-03-04 12:00,5\n";
+    let mut range = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    range.args(["list", "--date-column", "orderdate", "--since", "2024-01-01", "--until", "2024-03-31", "--columns", "Total"]).write_stdin(data);
+    range.assert().success().stdout(
+        "No input file specified, reading CSV data from piped stdin...\n\
+         List from stdin (displaying column(s): Total) filtered where orderdate from 2024-01-01 to 2024-03-31\n\
+         Number of entries: 2\n\
+         1. 100\n\
+         2. 50\n",
+    );
+
+    let mut relative = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    relative.args(["list", "--date-column", "OrderDate", "--since", "30 days ago", "--raw", "--columns", "Total"]).write_stdin(data);
+    relative.assert().success().stdout("5\n");
+
+    let mut formatted = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    formatted.args(["--quiet-exists", "--date-column", "Day", "--date-format", "%d/%m/%Y", "--until", "yesterday"]).write_stdin("Day\n31/12/2999\n");
+    formatted.assert().code(1);
+
+    let mut invalid = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    invalid.args(["list", "--date-column", "OrderDate", "--since", "tomorrow"]).write_stdin(data);
+    invalid.assert().failure().stderr(predicate::str::contains("Invalid date 'tomorrow'"));
+    Ok(())
+}
+
+#[test]
+fn test_natural_sort() -> Result<(), Box<dyn Error>> {
+    let data = "Name,Version\nold,1.9.0\nnew,1.10.0\nolder,1.2.3\n";
+    let mut lexicographic = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    lexicographic.args(["list", "--filter", "Version>1.9.0", "--raw"]).write_stdin(data);
+    lexicographic.assert().code(1).stdout("");
+
+    let mut natural = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    natural.args(["list", "--filter", "Version>1.9.0", "--natural-sort", "--raw"]).write_stdin(data);
+    natural.assert().success().stdout("new\n");
+
+    let mut top = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    top.args(["list", "--top", "2", "--by", "File", "--natural-sort", "--raw"]).write_stdin("File\nitem2\nitem10\nitem9\n");
+    top.assert().success().stdout("item10\nitem9\n");
+    Ok(())
+}
+
+#[test]
+fn test_unicode_case_insensitive_matching() -> Result<(), Box<dyn Error>> {
+    let data = "Stad,Län\nGöteborg,Västra Götaland\nMalmö,Skåne\n";
+    let mut unicode = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    unicode.args(["list", "--filter", "STAD=GÖTEBORG", "--columns", "LÄN", "--raw"]).write_stdin(data);
+    unicode.assert().success().stdout("Västra Götaland\n");
+
+    let mut folded = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    folded.args(["list", "--filter", "Gata=STRASSE", "--raw"]).write_stdin("Gata\nStraße\nGasse\n");
+    folded.assert().success().stdout("Straße\n");
+
+    let mut ascii = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    ascii.args(["list", "--filter", "Stad=GÖTEBORG", "--ascii-case", "--raw"]).write_stdin(data);
+    ascii.assert().code(1).stdout("");
+
+    let mut ascii_header = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    ascii_header.args(["list", "--columns", "LÄN", "--ascii-case", "--raw"]).write_stdin(data);
+    ascii_header.assert().failure().stderr(predicate::str::contains("'LÄN' not found"));
+    Ok(())
+}
+
+#[test]
+fn test_search_any_column() -> Result<(), Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.args(["list", "-f", "tests/fixtures/songs.csv", "--search", "BEATLES", "--columns", "Title"]);
+    cmd.assert().success().stdout(
+        "Reading CSV file: tests/fixtures/songs.csv\n\
+         List from file 'tests/fixtures/songs.csv' (displaying column(s): Title) filtered where any column contains 'BEATLES'\n\
+         Number of entries: 1\n\
+         1. Hey Jude\n",
+    );
+
+    let mut every_term = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    every_term.args(["list", "--search", "rock", "--search", "197", "--columns", "Title", "--raw"])
+        .write_stdin("Title,Notes\nA,\"Rock, 1975\"\nB,rock\nC,\"1970s, \"\"soft rock\"\"\"\n");
+    every_term.assert().success().stdout("A\nC\n");
+
+    let mut exists = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    exists.args(["--quiet-exists", "-f", "tests/fixtures/songs.csv", "--search", "göteborg"]);
+    exists.assert().code(1);
+    Ok(())
+}
+
+#[test]
+fn test_files_with_matches_in_directory() -> Result<(), Box<dyn Error>> {
+    let mut found = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    found.current_dir(fixtures_dir());
+    found.args(["-d", "merge", "--files-with-matches", "--search", "melville"]);
+    found.assert().success().stdout(format!("{}\n", std::path::Path::new("merge").join("c_books.csv").display()));
+
+    let mut by_column = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    by_column.current_dir(fixtures_dir());
+    by_column.args(["-d", "merge", "--files-with-matches", "--filter", "Year<2000"]);
+    by_column.assert().success().stdout(predicate::str::contains("a_sixties.csv\n").and(predicate::str::contains("b_nineties.csv\n")))
+        .stdout(predicate::str::contains("c_books").not());
+
+    let mut none = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    none.current_dir(fixtures_dir());
+    none.args(["-d", "merge", "--files-with-matches", "--filter", "Year>2000"]);
+    none.assert().code(1).stdout("");
+
+    let temp_dir = tempdir()?;
+    let drop = temp_dir.path().join("drop");
+    std::fs::create_dir(&drop)?;
+    std::fs::write(drop.join("a.csv"), "Id,Qty\n1,x\n2,y\n3,5\n")?;
+    std::fs::write(drop.join("b.csv"), "Id,Qty\n4,1\n")?;
+    let mut cast = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cast.current_dir(temp_dir.path());
+    cast.args(["-d", "drop", "--files-with-matches", "--cast", "Qty:int", "--skip-bad-rows", "--filter", "Qty>2"]);
+    cast.assert().success()
+        .stdout(format!("{}\n", std::path::Path::new("drop").join("a.csv").display()))
+        .stderr(format!("Warning: Skipped 2 row(s) of {} with values that could not be cast.\n", std::path::Path::new("drop").join("a.csv").display()));
+    Ok(())
+}
+
+#[test]
+fn test_escape_line_breaks_and_tabs() -> Result<(), Box<dyn Error>> {
+    let data = "Note,Path\n\"two\nlines\",\"a\tb\\c\"\n";
+    let mut listing = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    listing.args(["list", "--columns", "Note,Path"]).write_stdin(data);
+    listing.assert().success().stdout(predicate::str::contains("1. two\\nlines\ta\\tb\\c\n"));
+
+    let mut raw = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    raw.args(["list", "--columns", "Note,Path", "--raw"]).write_stdin(data);
+    raw.assert().success().stdout("two\nlines\ta\tb\\c\n");
+
+    let mut escaped = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    escaped.args(["list", "--columns", "Note,Path", "--raw", "--escape"]).write_stdin(data);
+    escaped.assert().success().stdout("two\\nlines\ta\\tb\\\\c\n");
+
+    let mut literal = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    literal.args(["list", "--columns", "Note", "--escape", "never"]).write_stdin(data);
+    literal.assert().success().stdout(predicate::str::contains("1. two\nlines\n"));
+    Ok(())
+}
+
+#[test]
+fn test_log_level_and_json_format() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("a.csv"), "id,name\n1,x\n")?;
+    std::fs::write(temp_dir.path().join("b.csv"), "other\n2\n")?;
+
+    let mut json = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    json.current_dir(temp_dir.path()).args(["-d", ".", "--raw", "--columns", "id", "--log-format", "json"]);
+    let output = json.assert().success().stdout("1\n").get_output().stderr.clone();
+    let line: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(line["level"], "warn");
+    assert!(line["message"].as_str().unwrap().contains("b.csv' do not match main headers"));
+    assert!(line["time"].as_str().unwrap().ends_with('Z'));
+
+    let mut quiet = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    quiet.current_dir(temp_dir.path()).args(["-d", ".", "--raw", "--columns", "id", "--log-level", "error"]);
+    quiet.assert().success().stdout("1\n").stderr("");
+
+    let mut info = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    info.current_dir(temp_dir.path()).args(["-d", ".", "--raw", "--columns", "id"]).env("CSVPEEK_LOG_LEVEL", "info");
+    info.assert().success().stderr(predicate::str::contains("Verbose: Loading 1 record(s) took"));
+    Ok(())
+}
+
+#[test]
+fn test_hash_rows_across_files() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("a.csv"), "id,name\n1,Ada\n2,Alan\n")?;
+    std::fs::write(temp_dir.path().join("b.csv"), "id,name\n1,Ada\n2,Grace\n")?;
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    cmd.current_dir(temp_dir.path()).args(["list", "-d", ".", "--add-source-column", "--hash-rows", "-c", "source,id,row_hash", "--raw"]);
+    let output = String::from_utf8(cmd.assert().success().get_output().stdout.clone())?;
+    let rows: Vec<Vec<&str>> = output.lines().map(|line| line.split('\t').collect()).collect();
+    assert_eq!(rows.len(), 4);
+    assert!(rows.iter().all(|row| row[2].len() == 16));
+    assert_eq!(rows[0][2], rows[2][2], "identical rows hash alike across files");
+    assert_ne!(rows[1][2], rows[3][2]);
+
+    // Hashing only the id makes the changed row match its earlier version.
+    let mut by_id = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    by_id.current_dir(temp_dir.path()).args(["list", "-d", ".", "--hash-rows", "id", "-c", "row_hash", "--filter", "id=2", "--raw"]);
+    let output = String::from_utf8(by_id.assert().success().get_output().stdout.clone())?;
+    let hashes: Vec<&str> = output.lines().collect();
+    assert_eq!(hashes.len(), 2);
+    assert_eq!(hashes[0], hashes[1]);
+    Ok(())
+}
+
+#[test]
+fn test_assert_unique_across_merged_files() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("a.csv"), "OrderID,Region\n1001,North\n1002,South\n")?;
+    std::fs::write(temp_dir.path().join("b.csv"), "OrderID,Region\n1003,North\n1001,East\n")?;
+
+    let mut unique = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    unique.current_dir(temp_dir.path()).args(["list", "-d", ".", "--assert-unique", "OrderID,Region", "-c", "OrderID", "--raw"]);
+    unique.assert().success().stdout("1001\n1002\n1003\n1001\n");
+
+    let mut duplicated = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    duplicated.current_dir(temp_dir.path()).args(["list", "-d", ".", "--assert-unique", "orderid", "--filter", "Region=South", "--raw"]);
+    duplicated.assert().code(2).stdout("").stderr(predicate::str::contains(
+        "Error: Column(s) OrderID are not unique: 1 value(s) occur in more than one row: '1001' (2 rows)",
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_assert_checks_aggregates_after_filtering() -> Result<(), Box<dyn Error>> {
+    let data = "Item,Price,Email\nLamp,120,a@example.com\nDesk,12000,\nChair,80,c@example.com\n";
+    let mut passing = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    passing.args(["list", "--filter", "Price<1000", "--assert", "count()>0", "--assert", "max(Price)<=10000", "--assert", "null_count(Email)=0", "--raw"])
+        .write_stdin(data);
+    passing.assert().success().stdout("Lamp\nChair\n");
+
+    let mut failing = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    failing.args(["list", "--assert", "count()>0", "--assert", "max(Price)<=10000", "--assert", "null_count(Email)=0", "--raw"])
+        .write_stdin(data);
+    failing.assert().code(2).stdout("").stderr(predicate::str::contains(
+        "Error: Assertion failed: max(Price) <= 10000 (got 12000); null_count(Email) = 0 (got 1)",
+    ));
+
+    let mut empty = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    empty.args(["list", "--filter", "Item=Sofa", "--assert", "count()>0"]).write_stdin(data);
+    empty.assert().code(2).stderr(predicate::str::contains("count() > 0 (got 0)"));
+
+    let mut invalid = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    invalid.args(["list", "--assert", "median(Price)>1"]).write_stdin(data);
+    invalid.assert().code(2).stderr(predicate::str::contains("Invalid assertion 'median(Price)>1'"));
+    Ok(())
+}
+
+#[test]
+fn test_schema_report_across_directory() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("2024-01.csv"), "id,name\n1,x\n")?;
+    std::fs::write(temp_dir.path().join("2024-02.csv"), "id,name,email\n2,y,e\n")?;
+    std::fs::write(temp_dir.path().join("2024-03.csv"), "id,full_name,email\n3,z,f\n")?;
+
+    let mut text = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    text.current_dir(temp_dir.path()).args(["-d", ".", "--schema-report"]);
+    text.assert().success().stdout(
+        predicate::str::contains("File           id  name  email  full_name  Merge\n")
+            .and(predicate::str::contains("./2024-01.csv  x   x     -      -          main headers\n"))
+            .and(predicate::str::contains("./2024-03.csv  x   -     x      x          skipped: headers differ from the main headers\n"))
+            .and(predicate::str::contains("  ./2024-02.csv: added email\n  ./2024-03.csv: renamed name -> full_name\n"))
+            .and(predicate::str::contains("2 file(s) would not be merged: ./2024-02.csv, ./2024-03.csv")),
+    );
+
+    let mut json = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    json.current_dir(temp_dir.path()).args(["-d", ".", "--schema-report", "json", "--merge-strategy", "union"]);
+    let report: serde_json::Value = serde_json::from_slice(&json.output()?.stdout)?;
+    assert_eq!(report["columns"], serde_json::json!(["id", "name", "email", "full_name"]));
+    assert_eq!(report["files"][1]["merge"], "union");
+    assert_eq!(report["files"][1]["detail"], serde_json::json!(["email"]));
+    assert_eq!(report["files"][2]["changes"]["renamed"], serde_json::json!([{ "from": "name", "to": "full_name" }]));
+    Ok(())
+}
+
+#[test]
+fn test_compare_headers() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("jan.csv"), "id,name,email,city\n1,x,e,c\n")?;
+    std::fs::write(temp_dir.path().join("feb.csv"), "ID,city,name,phone\n2,c,y,p\n")?;
+    std::fs::write(temp_dir.path().join("mar.csv"), "id,name,email,city\n")?;
+
+    let mut differ = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    differ.current_dir(temp_dir.path()).args(["-f", "jan.csv", "--compare-headers", "feb.csv"]);
+    differ.assert().code(1).stdout(
+        "Headers of file 'jan.csv' and 'feb.csv' differ:\n  missing from 'feb.csv': email\n  extra in 'feb.csv': phone\n  \
+         reordered: name (2 -> 3), city (4 -> 2)\n  case differs: id / ID\n",
+    );
+
+    let mut same = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    same.current_dir(temp_dir.path()).args(["--compare-headers", "mar.csv"]).write_stdin("id,name,email,city\n");
+    same.assert().success().stdout("Headers of stdin and 'mar.csv' match (4 column(s)).\n");
+    Ok(())
+}
+
+#[test]
+fn test_dedupe_on_key_when_merging() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("2024-05-01.csv"), "ID,Status\n1,open\n2,open\n")?;
+    std::fs::write(temp_dir.path().join("2024-05-02.csv"), "ID,Status\n2,paid\n3,open\n")?;
+    std::fs::write(temp_dir.path().join("2024-05-03.csv"), "ID,Status\n 3 ,paid\n")?;
+
+    let mut latest = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    latest.current_dir(temp_dir.path()).args(["list", "-d", ".", "--dedupe-on", "id", "--keep", "latest-file", "-c", "ID,Status", "--raw"]);
+    latest.assert().success().stdout("1\topen\n2\tpaid\n 3 \tpaid\n");
+
+    let mut first = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    first.current_dir(temp_dir.path()).args(["list", "-d", ".", "--dedupe-on", "ID", "--keep", "first-file", "-c", "ID,Status", "--raw"]);
+    first.assert().success().stdout("1\topen\n2\topen\n3\topen\n");
+
+    // The oldest name is the most recently modified file.
+    let touched = File::options().append(true).open(temp_dir.path().join("2024-05-01.csv"))?;
+    touched.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))?;
+    let mut mtime = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    mtime.current_dir(temp_dir.path()).args(["list", "-d", ".", "--dedupe-on", "ID", "--keep", "latest-mtime", "-c", "ID,Status", "--raw"]);
+    mtime.assert().success().stdout("1\topen\n2\topen\n 3 \tpaid\n");
+
+    let mut single = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    single.current_dir(temp_dir.path()).args(["list", "-f", "2024-05-01.csv", "--dedupe-on", "ID"]);
+    single.assert().code(2).stderr(predicate::str::contains("'--dedupe-on <COLUMNS>' can only be used when merging"));
+    Ok(())
+}
+
+#[test]
+fn test_check_fk_reports_orphaned_keys() -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("orders.csv"), "OrderID,CustomerID\n1,C9\n2,C1\n3,C7\n4,\n5,C9\n")?;
+    std::fs::write(temp_dir.path().join("customers.csv"), "ID,Name\nC1,Ada\nC2,Alan\n")?;
+    std::fs::write(temp_dir.path().join("returns.csv"), "OrderID\n2\n5\n")?;
+
+    let mut orphans = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    orphans.current_dir(temp_dir.path())
+        .args(["--check-fk", "returns.csv:OrderID -> orders.csv:OrderID", "--check-fk", "orders.csv:customerid -> customers.csv:ID"]);
+    orphans.assert().code(2).stdout(
+        "returns.csv:OrderID -> orders.csv:OrderID: OK\n\
+         orders.csv:customerid -> customers.csv:ID: 3 orphaned row(s) with 2 key(s) missing from customers.csv: 'C9' (2 row(s)), 'C7' (1 row(s))\n",
+    );
+
+    let mut missing_column = Command::cargo_bin(env!("CARGO_PKG_NAME"))?;
+    missing_column.current_dir(temp_dir.path()).args(["--check-fk", "orders.csv:Customer -> customers.csv:ID"]);
+    missing_column.assert().code(2).stderr(predicate::str::contains("Foreign key column 'Customer' not found"));
+    Ok(())
+}
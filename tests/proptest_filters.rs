@@ -0,0 +1,97 @@
+use csvpeek_rs::filter::{natural_cmp, ordered_value_matches, parse_filter_arg, record_matches, value_matches, Operator, ResolvedFilter};
+use csvpeek_rs::input::{parse_csv_from_reader, ReadOptions};
+use proptest::prelude::*;
+
+fn operator() -> impl Strategy<Value = Operator> {
+    prop_oneof![
+        Just(Operator::Eq),
+        Just(Operator::NotEq),
+        Just(Operator::Lt),
+        Just(Operator::Gt),
+        Just(Operator::LtEq),
+        Just(Operator::GtEq),
+    ]
+}
+
+/// Cell values mixing numbers, special float spellings and free text.
+fn cell_value() -> impl Strategy<Value = String> {
+    prop_oneof![
+        any::<i64>().prop_map(|n| n.to_string()),
+        any::<f64>().prop_map(|n| n.to_string()),
+        Just("NaN".to_string()),
+        Just("inf".to_string()),
+        Just("1.2e9".to_string()),
+        Just("-3E-2".to_string()),
+        Just(" 42 ".to_string()),
+        "\\PC{0,12}",
+    ]
+}
+
+proptest! {
+    #[test]
+    fn parse_filter_arg_never_panics(input in "\\PC{0,40}") {
+        let _ = parse_filter_arg(&input);
+    }
+
+    #[test]
+    fn parse_filter_arg_round_trips(
+        column in "[A-Za-z][A-Za-z0-9 _]{0,10}[A-Za-z0-9]",
+        op in operator(),
+        value in "[A-Za-z0-9][A-Za-z0-9 .]{0,10}",
+    ) {
+        let expression = format!("{}{}{}", column, op, value);
+        prop_assert_eq!(parse_filter_arg(&expression), Ok((column.trim().to_string(), op, value.trim().to_string())));
+    }
+
+    #[test]
+    fn ordering_operators_are_complementary(cell in cell_value(), filter_value in cell_value()) {
+        prop_assert_ne!(value_matches(&cell, Operator::Lt, &filter_value), value_matches(&cell, Operator::GtEq, &filter_value));
+        prop_assert_ne!(value_matches(&cell, Operator::Gt, &filter_value), value_matches(&cell, Operator::LtEq, &filter_value));
+        prop_assert_ne!(value_matches(&cell, Operator::Eq, &filter_value), value_matches(&cell, Operator::NotEq, &filter_value));
+    }
+
+    #[test]
+    fn natural_ordering_is_consistent(a in "[a-c0-9.]{0,8}", b in "[a-c0-9.]{0,8}") {
+        prop_assert_eq!(natural_cmp(&a, &b), natural_cmp(&b, &a).reverse());
+        prop_assert_eq!(natural_cmp(&a, &b).is_eq(), a == b);
+        prop_assert_ne!(ordered_value_matches(&a, Operator::Lt, &b, true), ordered_value_matches(&a, Operator::GtEq, &b, true));
+    }
+
+    #[test]
+    fn record_matches_never_panics(
+        cells in prop::collection::vec(cell_value(), 0..5),
+        filters in prop::collection::vec((0usize..8, operator(), cell_value(), any::<bool>()), 0..4),
+    ) {
+        let record = csv::StringRecord::from(cells);
+        let filters: Vec<ResolvedFilter> = filters
+            .into_iter()
+            .map(|(column, op, value, natural)| ResolvedFilter { column, op, value, natural })
+            .collect();
+        let _ = record_matches(&record, &filters);
+    }
+
+    #[test]
+    fn written_csv_parses_back(
+        dialect in (prop::sample::select(vec![b',', b';', b'\t', b'|']), prop::sample::select(vec![b'"', b'\''])),
+        table in (1usize..5).prop_flat_map(|width| {
+            prop::collection::vec(prop::collection::vec("\\PC{0,8}", width), 1..8)
+        }),
+    ) {
+        let (delimiter, quote) = dialect;
+        let mut writer = csv::WriterBuilder::new().delimiter(delimiter).quote(quote).from_writer(Vec::new());
+        for row in &table {
+            writer.write_record(row).unwrap();
+        }
+        let bytes = writer.into_inner().unwrap();
+
+        // Input quoting is fixed to '"'; other quote characters must fail cleanly or parse, never panic.
+        let options = ReadOptions { delimiter: Some(delimiter), ..ReadOptions::default() };
+        let parsed = parse_csv_from_reader(bytes.as_slice(), &options, true);
+        if quote == b'"' {
+            let (headers, records) = parsed.unwrap();
+            prop_assert_eq!(&headers, &table[0]);
+            let parsed_rows: Vec<Vec<String>> = records.iter().map(|r| r.iter().map(String::from).collect()).collect();
+            prop_assert_eq!(parsed_rows.as_slice(), &table[1..]);
+        }
+    }
+}